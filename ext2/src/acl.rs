@@ -0,0 +1,334 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use bitflags::bitflags;
+
+use filesystem::BlockDevice;
+
+use crate::{Error, Ext2Fs, Inode, InodeAddress, Permissions, Type};
+
+/// The version of the ACL format that ext2 stores in extended attributes.
+const ACL_VERSION: u32 = 1;
+
+const TAG_USER_OBJ: u16 = 0x01;
+const TAG_USER: u16 = 0x02;
+const TAG_GROUP_OBJ: u16 = 0x04;
+const TAG_GROUP: u16 = 0x08;
+const TAG_MASK: u16 = 0x10;
+const TAG_OTHER: u16 = 0x20;
+
+/// The kind of a POSIX ACL. Every inode can have an access ACL, which decides
+/// who can access the inode, while only directories can have a default ACL,
+/// which is inherited by the inodes that are created in the directory.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AclType {
+    Access,
+    Default,
+}
+
+impl AclType {
+    fn xattr_name(self) -> &'static str {
+        match self {
+            AclType::Access => "system.posix_acl_access",
+            AclType::Default => "system.posix_acl_default",
+        }
+    }
+}
+
+/// Whom an ACL entry applies to. The order of the variants is the order of the
+/// entries in an ACL.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum AclTag {
+    /// The owner of the inode.
+    UserObj,
+    /// The user with the given id.
+    User(u32),
+    /// The owning group of the inode.
+    GroupObj,
+    /// The group with the given id.
+    Group(u32),
+    /// The maximum permissions for all entries except `UserObj` and `Other`.
+    Mask,
+    /// Everybody else.
+    Other,
+}
+
+bitflags! {
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct AclPerm: u16 {
+        const Execute = 0x1;
+        const Write = 0x2;
+        const Read = 0x4;
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct AclEntry {
+    pub tag: AclTag,
+    pub perm: AclPerm,
+}
+
+/// A valid POSIX ACL. It has exactly one `UserObj`, `GroupObj` and `Other` entry,
+/// and a `Mask` entry if there are entries for specific users or groups.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Acl {
+    entries: Vec<AclEntry>,
+}
+
+impl Acl {
+    /// Creates an ACL from the given entries, which are sorted. Returns
+    /// [`Error::InvalidAcl`] if the entries don't form a valid ACL.
+    pub fn new(mut entries: Vec<AclEntry>) -> Result<Self, Error> {
+        entries.sort_by_key(|e| e.tag);
+        if entries.windows(2).any(|w| w[0].tag == w[1].tag) {
+            return Err(Error::InvalidAcl);
+        }
+
+        let has = |tag| entries.iter().any(|e| e.tag == tag);
+        let has_named = entries.iter().any(|e| matches!(e.tag, AclTag::User(_) | AclTag::Group(_)));
+        if !has(AclTag::UserObj) || !has(AclTag::GroupObj) || !has(AclTag::Other) || has_named && !has(AclTag::Mask) {
+            return Err(Error::InvalidAcl);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Creates the ACL that is equivalent to the given permissions.
+    pub fn from_perm(perm: Permissions) -> Self {
+        let bits = perm.bits();
+        let entry = |tag, shift: u16| AclEntry {
+            tag,
+            perm: AclPerm::from_bits_truncate((bits >> shift) & 7),
+        };
+        Self {
+            entries: vec![entry(AclTag::UserObj, 6), entry(AclTag::GroupObj, 3), entry(AclTag::Other, 0)],
+        }
+    }
+
+    pub fn entries(&self) -> &[AclEntry] {
+        &self.entries
+    }
+
+    /// Whether the ACL can be represented by the permission bits of an inode alone.
+    pub fn is_minimal(&self) -> bool {
+        self.entries.len() == 3
+    }
+
+    fn perm_of(&self, tag: AclTag) -> Option<AclPerm> {
+        self.entries.iter().find(|e| e.tag == tag).map(|e| e.perm)
+    }
+
+    /// The permission bits of an inode with this access ACL. The group bits
+    /// are taken from the mask, if there is one.
+    pub fn perm(&self) -> Permissions {
+        let user = self.perm_of(AclTag::UserObj).unwrap_or(AclPerm::empty());
+        let group = self.perm_of(AclTag::Mask)
+            .or(self.perm_of(AclTag::GroupObj))
+            .unwrap_or(AclPerm::empty());
+        let other = self.perm_of(AclTag::Other).unwrap_or(AclPerm::empty());
+        Permissions::from_bits_truncate(user.bits() << 6 | group.bits() << 3 | other.bits())
+    }
+
+    /// Restricts the ACL to the given permissions, as it happens when an inode with
+    /// the given permissions inherits this ACL as its access ACL. Returns the
+    /// permissions that the inode ends up with.
+    fn restrict_to(&mut self, perm: Permissions) -> Permissions {
+        let bits = perm.bits();
+        let has_mask = self.perm_of(AclTag::Mask).is_some();
+        for entry in &mut self.entries {
+            let shift = match entry.tag {
+                AclTag::UserObj => 6,
+                AclTag::GroupObj if !has_mask => 3,
+                AclTag::Mask => 3,
+                AclTag::Other => 0,
+                _ => continue,
+            };
+            entry.perm &= AclPerm::from_bits_truncate((bits >> shift) & 7);
+        }
+        let special = perm & (Permissions::SetUID | Permissions::SetGID | Permissions::Sticky);
+        self.perm() | special
+    }
+
+    /// Decodes an ACL from the format that is stored in extended attributes.
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let version = data.get(0..4).ok_or(Error::InvalidAcl)?;
+        if u32::from_le_bytes(version.try_into().unwrap()) != ACL_VERSION {
+            return Err(Error::InvalidAcl);
+        }
+
+        let mut entries = Vec::new();
+        let mut data = &data[4..];
+        while !data.is_empty() {
+            let short = data.get(0..4).ok_or(Error::InvalidAcl)?;
+            let tag = u16::from_le_bytes([short[0], short[1]]);
+            let perm = AclPerm::from_bits(u16::from_le_bytes([short[2], short[3]])).ok_or(Error::InvalidAcl)?;
+
+            // only the entries for specific users and groups contain an id
+            let (tag, len) = match tag {
+                TAG_USER_OBJ => (AclTag::UserObj, 4),
+                TAG_GROUP_OBJ => (AclTag::GroupObj, 4),
+                TAG_MASK => (AclTag::Mask, 4),
+                TAG_OTHER => (AclTag::Other, 4),
+                TAG_USER | TAG_GROUP => {
+                    let id = data.get(4..8).ok_or(Error::InvalidAcl)?;
+                    let id = u32::from_le_bytes(id.try_into().unwrap());
+                    (if tag == TAG_USER { AclTag::User(id) } else { AclTag::Group(id) }, 8)
+                }
+                _ => return Err(Error::InvalidAcl),
+            };
+            entries.push(AclEntry { tag, perm });
+            data = &data[len..];
+        }
+        Self::new(entries)
+    }
+
+    /// Encodes the ACL into the format that is stored in extended attributes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(4 + self.entries.len() * 8);
+        result.extend_from_slice(&ACL_VERSION.to_le_bytes());
+        for entry in &self.entries {
+            let (tag, id) = match entry.tag {
+                AclTag::UserObj => (TAG_USER_OBJ, None),
+                AclTag::User(id) => (TAG_USER, Some(id)),
+                AclTag::GroupObj => (TAG_GROUP_OBJ, None),
+                AclTag::Group(id) => (TAG_GROUP, Some(id)),
+                AclTag::Mask => (TAG_MASK, None),
+                AclTag::Other => (TAG_OTHER, None),
+            };
+            result.extend_from_slice(&tag.to_le_bytes());
+            result.extend_from_slice(&entry.perm.bits().to_le_bytes());
+            if let Some(id) = id {
+                result.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+        result
+    }
+}
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Returns the ACL of the given type of the given inode. An inode without an
+    /// access ACL is only governed by its permission bits.
+    pub fn get_acl(&self, inode: &Inode, typ: AclType) -> Result<Option<Acl>, Error> {
+        self.get_xattr(inode, typ.xattr_name())?
+            .map(|data| Acl::decode(&data))
+            .transpose()
+    }
+
+    /// Sets or removes the ACL of the given type of the given inode, and writes the inode.
+    ///
+    /// Setting an access ACL also sets the permission bits of the inode. An access ACL
+    /// that is equivalent to permission bits is not stored. Default ACLs can only be
+    /// set on directories.
+    pub fn set_acl(&mut self, inode_address: InodeAddress, inode: &mut Inode, typ: AclType, acl: Option<&Acl>) -> Result<(), Error> {
+        if typ == AclType::Default && inode.typ() != Type::Directory {
+            return Err(Error::NotDirectory);
+        }
+
+        if let (AclType::Access, Some(acl)) = (typ, acl) {
+            let special = inode.perm() & (Permissions::SetUID | Permissions::SetGID | Permissions::Sticky);
+            inode.set_perm(acl.perm() | special);
+        }
+
+        match acl {
+            Some(acl) if typ == AclType::Default || !acl.is_minimal() => {
+                self.set_xattr(inode_address, inode, typ.xattr_name(), &acl.encode())
+            }
+            _ => match self.remove_xattr(inode_address, inode, typ.xattr_name()) {
+                Err(Error::NotFound) => self.write_inode(inode_address, inode),
+                result => result,
+            },
+        }
+    }
+
+    /// Applies the default ACL of the given parent directory to the given new inode. The
+    /// permissions of the new inode restrict the inherited access ACL, and are updated
+    /// to match it. New directories also inherit the default ACL itself.
+    pub(crate) fn inherit_acl(&mut self, parent: &Inode, inode_address: InodeAddress, inode: &mut Inode) -> Result<(), Error> {
+        // symlinks don't have ACLs
+        if inode.typ() == Type::SymLink {
+            return Ok(());
+        }
+        let Some(default_acl) = self.get_acl(parent, AclType::Default)? else {
+            return Ok(());
+        };
+
+        if inode.typ() == Type::Directory {
+            self.set_acl(inode_address, inode, AclType::Default, Some(&default_acl))?;
+        }
+
+        let mut access_acl = default_acl;
+        let perm = access_acl.restrict_to(inode.perm());
+        inode.set_perm(perm);
+        self.set_acl(inode_address, inode, AclType::Access, Some(&access_acl))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(tag: AclTag, perm: AclPerm) -> AclEntry {
+        AclEntry { tag, perm }
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let acl = Acl::new(vec![
+            entry(AclTag::Other, AclPerm::Read),
+            entry(AclTag::UserObj, AclPerm::all()),
+            entry(AclTag::Group(100), AclPerm::Read | AclPerm::Write),
+            entry(AclTag::GroupObj, AclPerm::Read),
+            entry(AclTag::Mask, AclPerm::Read | AclPerm::Write),
+            entry(AclTag::User(1000), AclPerm::Read),
+        ]).unwrap();
+        assert_eq!(AclTag::UserObj, acl.entries()[0].tag);
+        assert_eq!(AclTag::Other, acl.entries()[5].tag);
+
+        let encoded = acl.encode();
+        // header, four short entries and two entries with an id
+        assert_eq!(4 + 4 * 4 + 2 * 8, encoded.len());
+        assert_eq!(acl, Acl::decode(&encoded).unwrap());
+        assert_eq!(Permissions::from_bits_truncate(0o764), acl.perm());
+    }
+
+    #[test]
+    fn test_invalid_acls() {
+        // named entries need a mask
+        assert_eq!(Err(Error::InvalidAcl), Acl::new(vec![
+            entry(AclTag::UserObj, AclPerm::all()),
+            entry(AclTag::User(1000), AclPerm::Read),
+            entry(AclTag::GroupObj, AclPerm::Read),
+            entry(AclTag::Other, AclPerm::Read),
+        ]));
+        // the required entries must be present exactly once
+        assert_eq!(Err(Error::InvalidAcl), Acl::new(vec![entry(AclTag::UserObj, AclPerm::all())]));
+        assert_eq!(Err(Error::InvalidAcl), Acl::new(vec![
+            entry(AclTag::UserObj, AclPerm::all()),
+            entry(AclTag::UserObj, AclPerm::all()),
+            entry(AclTag::GroupObj, AclPerm::Read),
+            entry(AclTag::Other, AclPerm::Read),
+        ]));
+        assert_eq!(Err(Error::InvalidAcl), Acl::decode(&[2, 0, 0, 0]));
+        assert_eq!(Err(Error::InvalidAcl), Acl::decode(&[1, 0, 0, 0, 0x02, 0, 4, 0]));
+    }
+
+    #[test]
+    fn test_restrict_to() {
+        let mut acl = Acl::new(vec![
+            entry(AclTag::UserObj, AclPerm::all()),
+            entry(AclTag::User(1000), AclPerm::all()),
+            entry(AclTag::GroupObj, AclPerm::all()),
+            entry(AclTag::Mask, AclPerm::all()),
+            entry(AclTag::Other, AclPerm::Read | AclPerm::Execute),
+        ]).unwrap();
+        let perm = acl.restrict_to(Permissions::from_bits_truncate(0o666));
+        assert_eq!(Permissions::from_bits_truncate(0o664), perm);
+        // the mask restricts the group, the named entries keep their permissions
+        assert_eq!(Some(AclPerm::Read | AclPerm::Write), acl.perm_of(AclTag::Mask));
+        assert_eq!(Some(AclPerm::all()), acl.perm_of(AclTag::GroupObj));
+        assert_eq!(Some(AclPerm::all()), acl.perm_of(AclTag::User(1000)));
+        assert_eq!(Some(AclPerm::Read), acl.perm_of(AclTag::Other));
+    }
+}
@@ -1,7 +1,8 @@
+use core::fmt::{Display, Formatter};
 use core::num::NonZeroU32;
 use core::ops::Deref;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 #[repr(transparent)]
 pub struct InodeAddress(NonZeroU32);
 
@@ -19,6 +20,12 @@ impl From<NonZeroU32> for InodeAddress {
     }
 }
 
+impl Display for InodeAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
 impl From<InodeAddress> for u32 {
     fn from(value: InodeAddress) -> u32 {
         value.0.get()
@@ -35,7 +42,7 @@ impl InodeAddress {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 #[repr(transparent)]
 pub struct BlockAddress(NonZeroU32);
 
@@ -53,6 +60,12 @@ impl From<NonZeroU32> for BlockAddress {
     }
 }
 
+impl Display for BlockAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
 impl BlockAddress {
     pub const fn new(n: u32) -> Option<Self> {
         let nzu32 = NonZeroU32::new(n);
@@ -4,6 +4,8 @@ use core::ops::{Deref, DerefMut};
 use crate::{bytefield, bytefield_field_read, bytefield_field_write, check_is_implemented};
 
 bytefield! {
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct BlockGroupDescriptor ([u8; 32]) {
         block_usage_bitmap_block: u32 = 0,
         inode_usage_bitmap_block: u32 = 4,
@@ -15,6 +17,18 @@ bytefield! {
 }
 
 impl BlockGroupDescriptor {
+    /// Creates the descriptor of a group of a new file system, without directories.
+    pub(crate) fn new(block_usage_bitmap_block: u32, inode_usage_bitmap_block: u32, inode_table_starting_block: u32, num_unallocated_blocks: u16, num_unallocated_inodes: u16) -> Self {
+        Self {
+            block_usage_bitmap_block,
+            inode_usage_bitmap_block,
+            inode_table_starting_block,
+            num_unallocated_blocks,
+            num_unallocated_inodes,
+            num_directories: 0,
+        }
+    }
+
     pub fn block_usage_bitmap_block(&self) -> u32 {
         self.block_usage_bitmap_block
     }
@@ -39,9 +53,17 @@ impl BlockGroupDescriptor {
         self.num_unallocated_inodes
     }
 
+    pub fn num_unallocated_inodes_mut(&mut self) -> &mut u16 {
+        &mut self.num_unallocated_inodes
+    }
+
     pub fn num_directories(&self) -> u16 {
         self.num_directories
     }
+
+    pub fn num_directories_mut(&mut self) -> &mut u16 {
+        &mut self.num_directories
+    }
 }
 
 pub type Inner = Vec<BlockGroupDescriptor>;
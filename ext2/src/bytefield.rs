@@ -27,8 +27,8 @@ macro_rules! bytefield_field_read {
     ([u8; $len:literal], $offset:literal, $source:expr) => { $crate::bytefield_field_read!(u8_array [u8; $len], $offset, $source) };
     (u8_array $typ:ty, $offset:literal, $source:expr) => {
         {
-            check_is_implemented!($typ, Default);
-            let mut t: $typ = Default::default();
+            // arrays of any length can be zeroed, but only short ones implement `Default`
+            let mut t: $typ = [0; core::mem::size_of::<$typ>()];
             t.copy_from_slice(&$source[$offset..$offset + core::mem::size_of::<$typ>()]);
             t
         }
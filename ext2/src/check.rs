@@ -0,0 +1,776 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::ops::Range;
+
+use filesystem::BlockDevice;
+
+use crate::dir::parse_dir_block;
+use crate::xattr::xattr_block_refcount;
+use crate::{validate_name, BlockAddress, BlockUse, DirEntry, DirType, Error, Ext2Fs, Inode, InodeAddress, OptionalFeatures, RequiredFeatures, Type, ROOT_DIR_INODE_ADDRESS};
+
+/// The reserved inode that owns the blocks that are reserved for growing the
+/// block group descriptor table.
+pub(crate) const RESIZE_INODE_ADDRESS: InodeAddress = InodeAddress::new(7).unwrap();
+
+/// What a block is used for, as found by [`Ext2Fs::check`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BlockOwner {
+    /// The block is a superblock copy, part of a block group descriptor table,
+    /// a bitmap or part of an inode table.
+    Metadata,
+    /// The block is a data block, an indirect block or the extended attribute
+    /// block of the inode.
+    Inode(InodeAddress),
+}
+
+/// An inconsistency that was found by [`Ext2Fs::check`]. The checks are done in
+/// the same passes as `e2fsck`, see [`Finding::pass`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Finding {
+    /// A bitmap or the inode table of the group is not inside the file system.
+    InvalidGroupDescriptor { group: u32 },
+    /// The inode is in use, but its mode doesn't have a valid file type.
+    InvalidInodeType { inode: InodeAddress, mode: u16 },
+    /// The inode is in use, but has a deletion time.
+    DeletionTimeSet { inode: InodeAddress },
+    /// The inode points to a block that is not inside the file system.
+    InvalidBlockPointer { inode: InodeAddress, block: u32 },
+    /// The block is used more than once.
+    DuplicateBlock { block: BlockAddress, owners: Vec<BlockOwner> },
+    /// The number of 512 byte sectors of the inode doesn't match the blocks it uses.
+    WrongSectorCount { inode: InodeAddress, stored: u32, actual: u32 },
+    /// The size of the inode doesn't match the blocks it uses.
+    WrongSize { inode: InodeAddress, size: u64, expected: u64 },
+    /// The extended attribute block of the inode is not valid.
+    InvalidXattrBlock { inode: InodeAddress, block: BlockAddress },
+    /// The refcount of the extended attribute block doesn't match the number of inodes that use it.
+    WrongXattrRefcount { block: BlockAddress, stored: u32, actual: u32 },
+
+    /// The entries in the block of the directory are not valid.
+    CorruptedDirectory { dir: InodeAddress, block_index: u32 },
+    /// The first entry of the directory is not a `.` entry that points to the directory.
+    MissingDot { dir: InodeAddress },
+    /// The second entry of the directory is not a `..` entry.
+    MissingDotDot { dir: InodeAddress },
+    /// The entry points to an inode that doesn't exist or is reserved.
+    InvalidEntryInode { dir: InodeAddress, name: Vec<u8>, inode: u32 },
+    /// The entry points to an inode that is not in use.
+    EntryToUnusedInode { dir: InodeAddress, name: Vec<u8>, inode: InodeAddress },
+    /// The name of the entry is not valid, or it is a `.` or `..` entry in the wrong place.
+    InvalidEntryName { dir: InodeAddress, name: Vec<u8> },
+    /// The type that is stored in the entry doesn't match the type of the inode.
+    WrongEntryType { dir: InodeAddress, name: Vec<u8>, inode: InodeAddress, stored: DirType, actual: DirType },
+    /// The entry points to a directory that already has another entry.
+    DirectoryHardLink { dir: InodeAddress, name: Vec<u8>, inode: InodeAddress },
+
+    /// The root inode is not a directory that is in use.
+    RootNotDirectory,
+    /// The directory can't be reached from the root directory. `parent` is the
+    /// target of its `..` entry.
+    UnconnectedDirectory { dir: InodeAddress, parent: Option<InodeAddress> },
+    /// The `..` entry of the directory doesn't point to the directory that contains it.
+    WrongParent { dir: InodeAddress, stored: InodeAddress, actual: InodeAddress },
+
+    /// The inode is in use, but no directory entry points to it.
+    UnattachedInode { inode: InodeAddress },
+    /// The link count of the inode doesn't match the number of entries that point to it.
+    WrongLinkCount { inode: InodeAddress, stored: u16, actual: u32 },
+
+    /// The blocks are marked as free in the bitmap, but used (`used == true`), or the
+    /// other way round.
+    BlockBitmapDifference { blocks: Range<u32>, used: bool },
+    /// The inodes are marked as free in the bitmap, but used (`used == true`), or the
+    /// other way round.
+    InodeBitmapDifference { inodes: Range<u32>, used: bool },
+    /// The number of free blocks in the block group descriptor is wrong.
+    WrongGroupFreeBlocks { group: u32, stored: u32, actual: u32 },
+    /// The number of free inodes in the block group descriptor is wrong.
+    WrongGroupFreeInodes { group: u32, stored: u32, actual: u32 },
+    /// The number of directories in the block group descriptor is wrong.
+    WrongGroupDirectories { group: u32, stored: u32, actual: u32 },
+    /// The number of free blocks in the superblock is wrong.
+    WrongFreeBlocks { stored: u32, actual: u32 },
+    /// The number of free inodes in the superblock is wrong.
+    WrongFreeInodes { stored: u32, actual: u32 },
+}
+
+impl Finding {
+    /// The pass of the check that reports this finding. Pass 1 checks inodes and
+    /// their blocks, pass 2 the directory entries, pass 3 the connectivity of the
+    /// directories, pass 4 the link counts, and pass 5 the bitmaps and counters.
+    pub fn pass(&self) -> u8 {
+        match self {
+            Finding::InvalidGroupDescriptor { .. }
+            | Finding::InvalidInodeType { .. }
+            | Finding::DeletionTimeSet { .. }
+            | Finding::InvalidBlockPointer { .. }
+            | Finding::DuplicateBlock { .. }
+            | Finding::WrongSectorCount { .. }
+            | Finding::WrongSize { .. }
+            | Finding::InvalidXattrBlock { .. }
+            | Finding::WrongXattrRefcount { .. } => 1,
+            Finding::CorruptedDirectory { .. }
+            | Finding::MissingDot { .. }
+            | Finding::MissingDotDot { .. }
+            | Finding::InvalidEntryInode { .. }
+            | Finding::EntryToUnusedInode { .. }
+            | Finding::InvalidEntryName { .. }
+            | Finding::WrongEntryType { .. }
+            | Finding::DirectoryHardLink { .. } => 2,
+            Finding::RootNotDirectory
+            | Finding::UnconnectedDirectory { .. }
+            | Finding::WrongParent { .. } => 3,
+            Finding::UnattachedInode { .. } | Finding::WrongLinkCount { .. } => 4,
+            Finding::BlockBitmapDifference { .. }
+            | Finding::InodeBitmapDifference { .. }
+            | Finding::WrongGroupFreeBlocks { .. }
+            | Finding::WrongGroupFreeInodes { .. }
+            | Finding::WrongGroupDirectories { .. }
+            | Finding::WrongFreeBlocks { .. }
+            | Finding::WrongFreeInodes { .. } => 5,
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let name = |name: &[u8]| String::from_utf8_lossy(name).into_owned();
+        match self {
+            Finding::InvalidGroupDescriptor { group } => write!(f, "block group {} has a bitmap or inode table outside of the file system", group),
+            Finding::InvalidInodeType { inode, mode } => write!(f, "inode {} has an invalid mode {:#o}", inode, mode),
+            Finding::DeletionTimeSet { inode } => write!(f, "inode {} is in use, but has a deletion time", inode),
+            Finding::InvalidBlockPointer { inode, block } => write!(f, "inode {} points to invalid block {}", inode, block),
+            Finding::DuplicateBlock { block, owners } => {
+                write!(f, "block {} is used by", block)?;
+                for (i, owner) in owners.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    match owner {
+                        BlockOwner::Metadata => write!(f, "{} the file system metadata", separator)?,
+                        BlockOwner::Inode(inode) => write!(f, "{} inode {}", separator, inode)?,
+                    }
+                }
+                Ok(())
+            }
+            Finding::WrongSectorCount { inode, stored, actual } => write!(f, "inode {} has {} sectors, but uses {}", inode, stored, actual),
+            Finding::WrongSize { inode, size, expected } => write!(f, "inode {} has size {}, but should have size {}", inode, size, expected),
+            Finding::InvalidXattrBlock { inode, block } => write!(f, "inode {} has an invalid extended attribute block {}", inode, block),
+            Finding::WrongXattrRefcount { block, stored, actual } => write!(f, "extended attribute block {} has refcount {}, but is used {} times", block, stored, actual),
+            Finding::CorruptedDirectory { dir, block_index } => write!(f, "directory inode {} has corrupted entries in block {}", dir, block_index),
+            Finding::MissingDot { dir } => write!(f, "directory inode {} has no '.' entry", dir),
+            Finding::MissingDotDot { dir } => write!(f, "directory inode {} has no '..' entry", dir),
+            Finding::InvalidEntryInode { dir, name: n, inode } => write!(f, "entry '{}' in directory inode {} points to invalid inode {}", name(n), dir, inode),
+            Finding::EntryToUnusedInode { dir, name: n, inode } => write!(f, "entry '{}' in directory inode {} points to unused inode {}", name(n), dir, inode),
+            Finding::InvalidEntryName { dir, name: n } => write!(f, "entry '{}' in directory inode {} has an invalid name", name(n), dir),
+            Finding::WrongEntryType { dir, name: n, inode, stored, actual } => write!(
+                f,
+                "entry '{}' in directory inode {} has type {}, but inode {} has type {}",
+                name(n),
+                dir,
+                stored.bits(),
+                inode,
+                actual.bits()
+            ),
+            Finding::DirectoryHardLink { dir, name: n, inode } => write!(f, "entry '{}' in directory inode {} is another link to directory inode {}", name(n), dir, inode),
+            Finding::RootNotDirectory => write!(f, "the root inode is not a directory"),
+            Finding::UnconnectedDirectory { dir, parent: Some(parent) } => write!(f, "directory inode {} is not connected, its parent is inode {}", dir, parent),
+            Finding::UnconnectedDirectory { dir, parent: None } => write!(f, "directory inode {} is not connected", dir),
+            Finding::WrongParent { dir, stored, actual } => write!(f, "'..' in directory inode {} points to inode {}, but should point to inode {}", dir, stored, actual),
+            Finding::UnattachedInode { inode } => write!(f, "inode {} is not in any directory", inode),
+            Finding::WrongLinkCount { inode, stored, actual } => write!(f, "inode {} has link count {}, but {} links", inode, stored, actual),
+            Finding::BlockBitmapDifference { blocks, used } => write_bitmap_difference(f, "block", blocks, *used),
+            Finding::InodeBitmapDifference { inodes, used } => write_bitmap_difference(f, "inode", inodes, *used),
+            Finding::WrongGroupFreeBlocks { group, stored, actual } => write!(f, "block group {} has {} free blocks, but the count is {}", group, actual, stored),
+            Finding::WrongGroupFreeInodes { group, stored, actual } => write!(f, "block group {} has {} free inodes, but the count is {}", group, actual, stored),
+            Finding::WrongGroupDirectories { group, stored, actual } => write!(f, "block group {} has {} directories, but the count is {}", group, actual, stored),
+            Finding::WrongFreeBlocks { stored, actual } => write!(f, "the file system has {} free blocks, but the count is {}", actual, stored),
+            Finding::WrongFreeInodes { stored, actual } => write!(f, "the file system has {} free inodes, but the count is {}", actual, stored),
+        }
+    }
+}
+
+fn write_bitmap_difference(f: &mut Formatter<'_>, what: &str, range: &Range<u32>, used: bool) -> core::fmt::Result {
+    let state = if used { "used, but marked as free" } else { "free, but marked as used" };
+    if range.len() == 1 {
+        write!(f, "{} {} is {}", what, range.start, state)
+    } else {
+        write!(f, "{}s {}-{} are {}", what, range.start, range.end - 1, state)
+    }
+}
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Checks the consistency of the file system, like `e2fsck -n`, and returns all
+    /// inconsistencies that were found. The file system is not changed. Blocks that are
+    /// preallocated for open files count as used blocks without an owner, so the file
+    /// system should be synced before it is checked.
+    ///
+    /// Errors are only returned if the device can't be read.
+    pub fn check(&self) -> Result<Vec<Finding>, Error> {
+        let mut checker = Checker::new(self);
+        checker.run()?;
+        Ok(checker.findings)
+    }
+}
+
+/// What the checker knows about an inode.
+#[derive(Debug, Clone)]
+pub(crate) struct InodeState {
+    /// Whether the inode is in use, i.e. has links. Reserved inodes other than the root
+    /// directory are never in use, since no directory entry can point to them.
+    pub(crate) used: bool,
+    pub(crate) typ: Type,
+    pub(crate) links: u16,
+    /// The number of directory entries that point to the inode.
+    pub(crate) references: u32,
+    /// The directory with the first entry that points to this directory, not counting
+    /// `.` and `..` entries.
+    pub(crate) parent: Option<InodeAddress>,
+    /// The target of the `..` entry of this directory.
+    pub(crate) dotdot: Option<InodeAddress>,
+    /// Whether the inode has invalid block pointers, so that its content can't be read.
+    pub(crate) bad_blocks: bool,
+}
+
+/// The block pointers of an inode, as found while walking its block tree.
+#[derive(Debug, Default)]
+struct BlockWalk {
+    /// The number of blocks, including indirect blocks.
+    count: u32,
+    /// The highest index of a data block.
+    last_index: Option<u64>,
+    invalid: bool,
+}
+
+/// The state of a check, which is also used to repair the file system.
+pub(crate) struct Checker<'a, T> {
+    fs: &'a Ext2Fs<T>,
+    pub(crate) findings: Vec<Finding>,
+    /// The state of every inode, by inode number - 1.
+    pub(crate) inodes: Vec<InodeState>,
+    /// The first owner of every block, by block number.
+    pub(crate) block_owners: Vec<Option<BlockOwner>>,
+    /// All owners of blocks that are used more than once.
+    pub(crate) duplicates: BTreeMap<u32, Vec<BlockOwner>>,
+    /// The number of inodes that use an extended attribute block, and the first of them.
+    pub(crate) xattr_blocks: BTreeMap<u32, (InodeAddress, u32)>,
+    /// The directories that can't be reached from the root, and that are not in an
+    /// unreachable directory themselves.
+    pub(crate) unconnected: BTreeSet<InodeAddress>,
+    /// The groups whose bitmaps or inode table are not inside the file system.
+    invalid_groups: BTreeSet<u32>,
+}
+
+impl<'a, T> Checker<'a, T>
+where
+    T: BlockDevice,
+{
+    pub(crate) fn new(fs: &'a Ext2Fs<T>) -> Self {
+        let num_inodes = fs.superblock.num_inodes() as usize;
+        let num_blocks = fs.superblock.num_blocks() as usize;
+        Self {
+            fs,
+            findings: Vec::new(),
+            inodes: vec![
+                InodeState {
+                    used: false,
+                    typ: Type::empty(),
+                    links: 0,
+                    references: 0,
+                    parent: None,
+                    dotdot: None,
+                    bad_blocks: false,
+                };
+                num_inodes
+            ],
+            block_owners: vec![None; num_blocks],
+            duplicates: BTreeMap::new(),
+            xattr_blocks: BTreeMap::new(),
+            unconnected: BTreeSet::new(),
+            invalid_groups: BTreeSet::new(),
+        }
+    }
+
+    pub(crate) fn run(&mut self) -> Result<(), Error> {
+        self.check_inodes()?;
+        self.check_dirs()?;
+        self.check_connectivity();
+        self.check_link_counts();
+        self.check_bitmaps()
+    }
+
+    fn state(&self, inode: InodeAddress) -> &InodeState {
+        &self.inodes[inode.get() as usize - 1]
+    }
+
+    fn state_mut(&mut self, inode: InodeAddress) -> &mut InodeState {
+        &mut self.inodes[inode.get() as usize - 1]
+    }
+
+    /// Whether a directory entry may point to the given inode number.
+    fn is_valid_entry_target(&self, inode: u32) -> bool {
+        inode == ROOT_DIR_INODE_ADDRESS.get()
+            || (inode >= self.fs.superblock.first_non_reserved_inode() && inode <= self.fs.superblock.num_inodes())
+    }
+
+    /// Marks the given block as used by the given owner. Returns `false` if the block
+    /// is not inside the file system.
+    fn claim(&mut self, block: u32, owner: BlockOwner) -> bool {
+        let first_data_block = self.fs.superblock.superblock_block_number();
+        if block < first_data_block || block >= self.fs.superblock.num_blocks() {
+            if let BlockOwner::Inode(inode) = owner {
+                self.findings.push(Finding::InvalidBlockPointer { inode, block });
+            }
+            return false;
+        }
+
+        match self.block_owners[block as usize] {
+            Some(first_owner) => self.duplicates.entry(block).or_insert_with(|| vec![first_owner]).push(owner),
+            None => self.block_owners[block as usize] = Some(owner),
+        }
+        true
+    }
+
+    /// The metadata blocks of every group, which are the superblock and block group descriptor
+    /// table copies with the reserved descriptor blocks, the bitmaps and the inode table.
+    fn claim_metadata(&mut self) {
+        let superblock = &self.fs.superblock;
+        let block_size = superblock.block_size();
+        let num_groups = self.fs.bgdt.len() as u32;
+        let gdt_blocks = (num_groups * crate::BGD_SIZE as u32).div_ceil(block_size);
+        let inode_table_blocks = (superblock.inodes_per_group() * superblock.inode_size() as u32).div_ceil(block_size);
+        let reserved_gdt_blocks = superblock.num_reserved_gdt_blocks() as u32;
+
+        for group_index in 0..num_groups {
+            let group_start = superblock.superblock_block_number() + group_index * superblock.blocks_per_group();
+            let mut blocks = Vec::new();
+            if superblock.group_has_superblock(group_index) {
+                blocks.extend(group_start..group_start + 1 + gdt_blocks + reserved_gdt_blocks);
+            }
+            let descriptor = &self.fs.bgdt[group_index as usize];
+            blocks.push(descriptor.block_usage_bitmap_block());
+            blocks.push(descriptor.inode_usage_bitmap_block());
+            let inode_table = descriptor.inode_table_starting_block();
+            blocks.extend(inode_table..inode_table.saturating_add(inode_table_blocks));
+
+            let mut valid = true;
+            for block in blocks {
+                valid &= self.claim(block, BlockOwner::Metadata);
+            }
+            if !valid {
+                self.findings.push(Finding::InvalidGroupDescriptor { group: group_index });
+                self.invalid_groups.insert(group_index);
+            }
+        }
+    }
+
+    /// Pass 1: the inodes and the blocks they use.
+    fn check_inodes(&mut self) -> Result<(), Error> {
+        self.claim_metadata();
+
+        let superblock = &self.fs.superblock;
+        let first_inode = superblock.first_non_reserved_inode();
+        let can_resize = superblock.optional_features().contains(OptionalFeatures::CAN_RESIZE);
+        for number in 1..=superblock.num_inodes() {
+            // the inode table of an invalid group can't be read, so its inodes count as unused
+            if self.invalid_groups.contains(&((number - 1) / superblock.inodes_per_group())) {
+                continue;
+            }
+            let address = InodeAddress::new(number).unwrap(); // we start at 1
+            let (_, inode) = self.fs.read_inode(address)?;
+
+            if number < first_inode && address != ROOT_DIR_INODE_ADDRESS {
+                // The blocks of the resize inode are the reserved descriptor blocks, which are
+                // already part of the metadata, except for its double indirect block.
+                if address == RESIZE_INODE_ADDRESS && can_resize {
+                    if let Some(block) = inode.double_indirect_ptr() {
+                        self.claim(block.get(), BlockOwner::Inode(address));
+                    }
+                } else {
+                    self.walk_blocks(address, &inode)?;
+                }
+                continue;
+            }
+            if inode.num_hard_links() == 0 {
+                continue;
+            }
+
+            let typ = inode.typ();
+            *self.state_mut(address) = InodeState {
+                used: true,
+                typ,
+                links: inode.num_hard_links(),
+                ..self.state(address).clone()
+            };
+            if !is_valid_type(typ) {
+                self.findings.push(Finding::InvalidInodeType { inode: address, mode: typ.bits() });
+                continue;
+            }
+            if inode.deletion_time() != 0 {
+                self.findings.push(Finding::DeletionTimeSet { inode: address });
+            }
+            self.check_inode_blocks(address, &inode)?;
+        }
+
+        self.check_xattr_blocks()
+    }
+
+    fn check_inode_blocks(&mut self, address: InodeAddress, inode: &Inode) -> Result<(), Error> {
+        let typ = inode.typ();
+        let has_blocks = typ == Type::RegularFile || typ == Type::Directory || (typ == Type::SymLink && !self.fs.is_fast_symlink(inode));
+        let mut walk = if has_blocks { self.walk_blocks(address, inode)? } else { BlockWalk::default() };
+
+        if let Some(block) = inode.extended_attribute_block() {
+            if let Some((_, count)) = self.xattr_blocks.get_mut(&block.get()) {
+                // attribute blocks can be shared
+                *count += 1;
+                walk.count += 1;
+            } else if self.claim(block.get(), BlockOwner::Inode(address)) {
+                self.xattr_blocks.insert(block.get(), (address, 1));
+                walk.count += 1;
+            } else {
+                walk.invalid = true;
+            }
+        }
+        self.state_mut(address).bad_blocks = walk.invalid;
+
+        let block_size = self.fs.superblock.block_size();
+        let sectors = walk.count * (block_size / 512);
+        if sectors != inode.num_disk_sectors() {
+            self.findings.push(Finding::WrongSectorCount { inode: address, stored: inode.num_disk_sectors(), actual: sectors });
+        }
+
+        // directories end with their last block, other files may end with a hole
+        let size = inode.len();
+        let blocks_len = walk.last_index.map_or(0, |index| (index + 1) * block_size as u64);
+        let wrong_size = if typ == Type::Directory {
+            size != blocks_len
+        } else {
+            has_blocks && walk.last_index.is_some_and(|index| size <= index * block_size as u64)
+        };
+        if wrong_size {
+            self.findings.push(Finding::WrongSize { inode: address, size, expected: blocks_len });
+        }
+        Ok(())
+    }
+
+    /// Claims all blocks of the given inode, including the indirect blocks.
+    fn walk_blocks(&mut self, address: InodeAddress, inode: &Inode) -> Result<BlockWalk, Error> {
+        let mut walk = BlockWalk::default();
+        // the blocks come in the order of the block tree, so the last data block has the highest index
+        for (block, usage) in self.fs.block_tree(inode)? {
+            if !self.claim(block.get(), BlockOwner::Inode(address)) {
+                walk.invalid = true;
+                continue;
+            }
+            walk.count += 1;
+            if let BlockUse::Data(index) = usage {
+                walk.last_index = Some(index);
+            }
+        }
+        Ok(walk)
+    }
+
+    fn check_xattr_blocks(&mut self) -> Result<(), Error> {
+        let mut data = vec![0_u8; self.fs.superblock.block_size() as usize];
+        for (&block, &(inode, count)) in &self.xattr_blocks {
+            let block = BlockAddress::new(block).unwrap(); // we claimed the block, so it is not zero
+            self.fs.read_block(block, &mut data)?;
+            match xattr_block_refcount(&data) {
+                None => self.findings.push(Finding::InvalidXattrBlock { inode, block }),
+                Some(refcount) if refcount != count => self.findings.push(Finding::WrongXattrRefcount { block, stored: refcount, actual: count }),
+                Some(_) => {}
+            }
+        }
+
+        for (&block, owners) in &self.duplicates {
+            let block = BlockAddress::new(block).unwrap(); // we claimed the block, so it is not zero
+            self.findings.push(Finding::DuplicateBlock { block, owners: owners.clone() });
+        }
+        Ok(())
+    }
+
+    /// Pass 2: the entries of all directories.
+    fn check_dirs(&mut self) -> Result<(), Error> {
+        let block_size = self.fs.superblock.block_size() as usize;
+        let dir_entries_have_type = self.fs.superblock.required_features().contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE);
+        let mut data = vec![0_u8; block_size];
+
+        for number in 1..=self.inodes.len() as u32 {
+            let dir = InodeAddress::new(number).unwrap(); // we start at 1
+            let state = self.state(dir);
+            if !state.used || state.typ != Type::Directory || state.bad_blocks {
+                continue;
+            }
+
+            let (_, inode) = self.fs.read_inode(dir)?;
+            let mut entry_index = 0;
+            for block_index in 0..(inode.len() / block_size as u64) as u32 {
+                let Some(block) = self.fs.resolve_block_index(&inode, block_index)? else {
+                    continue;
+                };
+                self.fs.read_block(block, &mut data)?;
+                let Ok(entries) = parse_dir_block(dir_entries_have_type, &data) else {
+                    self.findings.push(Finding::CorruptedDirectory { dir, block_index });
+                    continue;
+                };
+                for (_, entry) in entries {
+                    self.check_dir_entry(dir, entry_index, &entry, dir_entries_have_type);
+                    entry_index += 1;
+                }
+            }
+
+            if entry_index == 0 {
+                self.findings.push(Finding::MissingDot { dir });
+            }
+            if entry_index <= 1 {
+                self.findings.push(Finding::MissingDotDot { dir });
+            }
+        }
+        Ok(())
+    }
+
+    fn check_dir_entry(&mut self, dir: InodeAddress, entry_index: usize, entry: &DirEntry, dir_entries_have_type: bool) {
+        let name = entry.name_bytes();
+        let target = entry.inode();
+
+        match entry_index {
+            0 if name == b"." && target == dir => {
+                self.state_mut(dir).references += 1;
+                return;
+            }
+            0 => self.findings.push(Finding::MissingDot { dir }),
+            1 if name == b".." => {
+                if let Some(target) = self.check_entry_target(dir, entry, false) {
+                    self.state_mut(dir).dotdot = Some(target);
+                    self.state_mut(target).references += 1;
+                }
+                return;
+            }
+            1 => self.findings.push(Finding::MissingDotDot { dir }),
+            _ => {}
+        }
+
+        if validate_name(name).is_err() {
+            self.findings.push(Finding::InvalidEntryName { dir, name: name.to_vec() });
+            return;
+        }
+        let Some(target) = self.check_entry_target(dir, entry, dir_entries_have_type) else {
+            return;
+        };
+        self.state_mut(target).references += 1;
+
+        if self.state(target).typ == Type::Directory {
+            if target == ROOT_DIR_INODE_ADDRESS || self.state(target).parent.is_some() {
+                self.findings.push(Finding::DirectoryHardLink { dir, name: name.to_vec(), inode: target });
+            } else {
+                self.state_mut(target).parent = Some(dir);
+            }
+        }
+    }
+
+    /// Checks that the given entry points to an inode that is in use, and returns the inode.
+    /// The type of `..` entries is not checked, like `e2fsck` does.
+    fn check_entry_target(&mut self, dir: InodeAddress, entry: &DirEntry, check_type: bool) -> Option<InodeAddress> {
+        let name = entry.name_bytes();
+        let target = entry.inode();
+        if !self.is_valid_entry_target(target.get()) {
+            self.findings.push(Finding::InvalidEntryInode { dir, name: name.to_vec(), inode: target.get() });
+            return None;
+        }
+        let state = self.state(target);
+        if !state.used {
+            self.findings.push(Finding::EntryToUnusedInode { dir, name: name.to_vec(), inode: target });
+            return None;
+        }
+
+        if check_type && is_valid_type(state.typ) {
+            let stored = entry.typ().unwrap_or(DirType::empty());
+            let actual = DirType::from(state.typ);
+            if stored != actual {
+                self.findings.push(Finding::WrongEntryType { dir, name: name.to_vec(), inode: target, stored, actual });
+            }
+        }
+        Some(target)
+    }
+
+    /// Pass 3: every directory must be reachable from the root, and its `..` entry
+    /// must point to the directory that contains it.
+    fn check_connectivity(&mut self) {
+        let root = self.state(ROOT_DIR_INODE_ADDRESS);
+        if !root.used || root.typ != Type::Directory {
+            self.findings.push(Finding::RootNotDirectory);
+            return;
+        }
+        if let Some(dotdot) = root.dotdot.filter(|&dotdot| dotdot != ROOT_DIR_INODE_ADDRESS) {
+            self.findings.push(Finding::WrongParent { dir: ROOT_DIR_INODE_ADDRESS, stored: dotdot, actual: ROOT_DIR_INODE_ADDRESS });
+        }
+
+        // `true` for directories that are known to be reachable, `false` for unreachable ones
+        let mut reachable = BTreeMap::from([(ROOT_DIR_INODE_ADDRESS, true)]);
+        for number in 1..=self.inodes.len() as u32 {
+            let dir = InodeAddress::new(number).unwrap(); // we start at 1
+            let state = self.state(dir);
+            if !state.used || state.typ != Type::Directory || reachable.contains_key(&dir) {
+                continue;
+            }
+
+            // follow the parents up to the root, a directory without parent, or a loop
+            let mut path = Vec::new();
+            let mut current = dir;
+            let is_reachable = loop {
+                if let Some(&known) = reachable.get(&current) {
+                    break known;
+                }
+                if path.contains(&current) {
+                    // the directories form a loop, which is unreachable as a whole, so the
+                    // directory with the lowest inode number is the one to reconnect
+                    let start = path.iter().position(|&d| d == current).unwrap();
+                    self.unconnected.insert(*path[start..].iter().min().unwrap());
+                    break false;
+                }
+                path.push(current);
+                match self.state(current).parent {
+                    Some(parent) => current = parent,
+                    None => {
+                        self.unconnected.insert(current);
+                        break false;
+                    }
+                }
+            };
+            for d in path {
+                reachable.insert(d, is_reachable);
+            }
+        }
+
+        // The link counts are checked as if the `..` entries were fixed, i.e. unconnected
+        // directories were moved to `lost+found`, and wrong `..` entries were corrected.
+        for (dir, is_reachable) in reachable {
+            let state = self.state(dir);
+            let dotdot = state.dotdot;
+            if !is_reachable {
+                if self.unconnected.contains(&dir) {
+                    self.findings.push(Finding::UnconnectedDirectory { dir, parent: dotdot });
+                    if let Some(dotdot) = dotdot {
+                        self.state_mut(dotdot).references -= 1;
+                    }
+                }
+                continue;
+            }
+            if let (Some(stored), Some(actual)) = (dotdot, state.parent) {
+                if stored != actual {
+                    self.findings.push(Finding::WrongParent { dir, stored, actual });
+                    self.state_mut(stored).references -= 1;
+                    self.state_mut(actual).references += 1;
+                }
+            }
+        }
+    }
+
+    /// Pass 4: the link count of every inode must match the number of entries that point to it.
+    fn check_link_counts(&mut self) {
+        for number in 1..=self.inodes.len() as u32 {
+            let inode = InodeAddress::new(number).unwrap(); // we start at 1
+            let state = self.state(inode);
+            // unconnected directories get another link when they are reconnected
+            if !state.used || self.unconnected.contains(&inode) {
+                continue;
+            }
+            if state.references == 0 {
+                self.findings.push(Finding::UnattachedInode { inode });
+            } else if state.references != state.links as u32 {
+                self.findings.push(Finding::WrongLinkCount { inode, stored: state.links, actual: state.references });
+            }
+        }
+    }
+
+    /// Pass 5: the bitmaps and the counters in the block group descriptors and the superblock.
+    fn check_bitmaps(&mut self) -> Result<(), Error> {
+        let superblock = &self.fs.superblock;
+        let first_data_block = superblock.superblock_block_number();
+        let blocks_per_group = superblock.blocks_per_group();
+        let inodes_per_group = superblock.inodes_per_group();
+        let first_inode = superblock.first_non_reserved_inode();
+        let mut bitmap = vec![0_u8; superblock.block_size() as usize];
+
+        let mut total_free_blocks = 0;
+        let mut total_free_inodes = 0;
+        for group_index in 0..self.fs.bgdt.len() {
+            let descriptor = &self.fs.bgdt[group_index];
+            let group = group_index as u32;
+
+            // the bitmaps can only be read if the descriptor is valid
+            let block_bitmap = BlockAddress::new(descriptor.block_usage_bitmap_block()).filter(|b| b.get() < superblock.num_blocks());
+            let inode_bitmap = BlockAddress::new(descriptor.inode_usage_bitmap_block()).filter(|b| b.get() < superblock.num_blocks());
+
+            let first_block = first_data_block + group * blocks_per_group;
+            let blocks = first_block..first_block + self.fs.blocks_in_group(group_index);
+            let used = |block: u32| self.block_owners[block as usize].is_some();
+            let free_blocks = blocks.clone().filter(|&block| !used(block)).count() as u32;
+            if let Some(block_bitmap) = block_bitmap {
+                self.fs.read_block(block_bitmap, &mut bitmap)?;
+                let differences = bitmap_differences(&bitmap, blocks, used, |block| block - first_block);
+                self.findings.extend(differences.into_iter().map(|(blocks, used)| Finding::BlockBitmapDifference { blocks, used }));
+            }
+
+            let first_inode_in_group = group * inodes_per_group + 1;
+            let inodes = first_inode_in_group..first_inode_in_group + inodes_per_group;
+            let used = |inode: u32| inode < first_inode || self.inodes[inode as usize - 1].used;
+            let free_inodes = inodes.clone().filter(|&inode| !used(inode)).count() as u32;
+            let dirs = inodes.clone().filter(|&inode| used(inode) && self.inodes[inode as usize - 1].typ == Type::Directory).count() as u32;
+            if let Some(inode_bitmap) = inode_bitmap {
+                self.fs.read_block(inode_bitmap, &mut bitmap)?;
+                let differences = bitmap_differences(&bitmap, inodes, used, |inode| inode - first_inode_in_group);
+                self.findings.extend(differences.into_iter().map(|(inodes, used)| Finding::InodeBitmapDifference { inodes, used }));
+            }
+
+            if descriptor.num_unallocated_blocks() as u32 != free_blocks {
+                self.findings.push(Finding::WrongGroupFreeBlocks { group, stored: descriptor.num_unallocated_blocks() as u32, actual: free_blocks });
+            }
+            if descriptor.num_unallocated_inodes() as u32 != free_inodes {
+                self.findings.push(Finding::WrongGroupFreeInodes { group, stored: descriptor.num_unallocated_inodes() as u32, actual: free_inodes });
+            }
+            if descriptor.num_directories() as u32 != dirs {
+                self.findings.push(Finding::WrongGroupDirectories { group, stored: descriptor.num_directories() as u32, actual: dirs });
+            }
+            total_free_blocks += free_blocks;
+            total_free_inodes += free_inodes;
+        }
+
+        if superblock.num_unallocated_blocks() != total_free_blocks {
+            self.findings.push(Finding::WrongFreeBlocks { stored: superblock.num_unallocated_blocks(), actual: total_free_blocks });
+        }
+        if superblock.num_unallocated_inodes() != total_free_inodes {
+            self.findings.push(Finding::WrongFreeInodes { stored: superblock.num_unallocated_inodes(), actual: total_free_inodes });
+        }
+        Ok(())
+    }
+}
+
+/// Whether the given type is exactly one of the file types.
+pub(crate) fn is_valid_type(typ: Type) -> bool {
+    [Type::FIFO, Type::CharacterDevice, Type::Directory, Type::BlockDevice, Type::RegularFile, Type::SymLink, Type::UnixSocket].contains(&typ)
+}
+
+/// Compares the given bitmap with the expected state of the given items, and returns the
+/// ranges of items where they differ, and whether the items are used.
+fn bitmap_differences(bitmap: &[u8], items: Range<u32>, used: impl Fn(u32) -> bool, bit_index: impl Fn(u32) -> u32) -> Vec<(Range<u32>, bool)> {
+    let mut differences: Vec<(Range<u32>, bool)> = Vec::new();
+    for item in items {
+        let index = bit_index(item) as usize;
+        let marked = bitmap[index / 8] & (1 << (index % 8)) != 0;
+        let used = used(item);
+        if marked == used {
+            continue;
+        }
+        match differences.last_mut() {
+            Some((range, last_used)) if range.end == item && *last_used == used => range.end += 1,
+            _ => differences.push((item..item + 1, used)),
+        }
+    }
+    differences
+}
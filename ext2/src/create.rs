@@ -1,24 +1,79 @@
 use filesystem::BlockDevice;
 
-use crate::{Directory, Error, Ext2Fs, Inode, InodeAddress, RegularFile, Type};
+use crate::{validate_name, DirType, Directory, Error, Ext2Fs, Inode, InodeAddress, Permissions, RegularFile, Type, DEFAULT_EXTRA_ISIZE};
 
 impl<T> Ext2Fs<T>
 where
     T: BlockDevice,
 {
-    pub fn create_inode(&mut self, parent: &mut Directory, name: &str, typ: Type) -> Result<(InodeAddress, Inode), Error> {
+    pub fn create_inode(&mut self, parent: &mut Directory, name: impl AsRef<[u8]>, typ: Type) -> Result<(InodeAddress, Inode), Error> {
+        self.create_inode_with_perm(parent, name, typ, Permissions::empty())
+    }
+
+    /// Creates an inode with the given permissions. If the parent directory has a
+    /// default ACL, the new inode inherits it, and the permissions are restricted
+    /// by the ACL, like the mode that is passed to `creat(2)`.
+    pub fn create_inode_with_perm(&mut self, parent: &mut Directory, name: impl AsRef<[u8]>, typ: Type, perm: Permissions) -> Result<(InodeAddress, Inode), Error> {
+        // the name is checked before an inode, and maybe an attribute block for an
+        // inherited ACL, is allocated for it
+        let name = name.as_ref();
+        validate_name(name)?;
+        if self.find_entry_by_name(parent, name)?.is_some() {
+            return Err(Error::EntryExists);
+        }
         let inode_address = self.allocate_inode()?.ok_or(Error::NoSpace)?;
-        let inode = Inode::new(typ);
+        let mut inode = self.new_inode(typ);
+        inode.set_perm(perm);
+        *inode.num_hard_links_mut() = 1;
 
         self.write_inode(inode_address, &inode)?;
+        self.inherit_acl(parent, inode_address, &mut inode)?;
 
         self.add_entry_to_dir(parent, name, inode_address, inode.typ().into())?;
 
         Ok((inode_address, inode))
     }
 
-    pub fn create_regular_file(&mut self, parent: &mut Directory, name: &str) -> Result<RegularFile, Error> {
+    /// Creates an inode with the given type, with extra fields if the inodes of
+    /// the file system are large enough.
+    pub(crate) fn new_inode(&self, typ: Type) -> Inode {
+        let mut inode = Inode::new(typ);
+        let extra_isize = match self.superblock.want_extra_inode_size() {
+            0 => DEFAULT_EXTRA_ISIZE,
+            size => size,
+        };
+        inode.init_extra(self.superblock.inode_size() as usize, extra_isize);
+        inode
+    }
+
+    pub fn create_regular_file(&mut self, parent: &mut Directory, name: impl AsRef<[u8]>) -> Result<RegularFile, Error> {
         self.create_inode(parent, name, Type::RegularFile)
             .map(|v| v.try_into().unwrap()) // if we don't get an inode with type RegularFile, something is really broken
     }
-}
\ No newline at end of file
+
+    /// Creates an empty directory with the given name in the given directory. The
+    /// parent gets another hard link, because of the `..` entry of the new directory.
+    pub fn create_dir(&mut self, parent: &mut Directory, name: impl AsRef<[u8]>) -> Result<Directory, Error> {
+        let (inode_address, inode) = self.create_inode(parent, name, Type::Directory)?;
+        let mut dir: Directory = (inode_address, inode).try_into().unwrap(); // we just created a directory, so this can't fail
+        self.init_dir(&mut dir, parent.inode_address())?;
+
+        *parent.inode_mut().num_hard_links_mut() += 1;
+        self.write_inode(parent.inode_address(), parent)?;
+        Ok(dir)
+    }
+
+    /// Adds the `.` and `..` entries to the given empty directory, and counts it
+    /// in the block group of its inode.
+    pub(crate) fn init_dir(&mut self, dir: &mut Directory, parent: InodeAddress) -> Result<(), Error> {
+        let block = self.append_dir_blocks(dir, 1)?;
+        self.try_add_entry_to_block(block, b".", dir.inode_address(), DirType::Directory)?;
+        self.try_add_entry_to_block(block, b"..", parent, DirType::Directory)?;
+        *dir.inode_mut().num_hard_links_mut() = 2;
+        self.write_inode(dir.inode_address(), dir)?;
+
+        let group_index = ((dir.inode_address().get() - 1) / self.superblock.inodes_per_group()) as usize;
+        *self.bgdt[group_index].num_directories_mut() += 1;
+        self.write_block_group_descriptor(group_index)
+    }
+}
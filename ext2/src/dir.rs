@@ -1,3 +1,5 @@
+use alloc::borrow::Cow;
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt::{Debug, Formatter};
@@ -7,8 +9,8 @@ use bitflags::bitflags;
 use filesystem::BlockDevice;
 
 use crate::{
-    bytefield, bytefield_field_read, bytefield_field_write, check_is_implemented, Directory,
-    Ext2Fs, Inode, InodeAddress, Type,
+    bytefield, bytefield_field_read, bytefield_field_write, check_is_implemented, BlockAddress,
+    Directory, Ext2Fs, Flags, Inode, InodeAddress, OptionalFeatures, Revision, Type,
 };
 use crate::error::Error;
 use crate::superblock::RequiredFeatures;
@@ -17,49 +19,47 @@ impl<T> Ext2Fs<T>
 where
     T: BlockDevice,
 {
-    // TODO: make this return some Result<impl Iterator<Item=DirEntry>, Error>
     pub fn list_dir(&self, dir: &Inode) -> Result<Vec<DirEntry>, Error> {
-        if dir.typ() != Type::Directory {
-            return Err(Error::NotDirectory);
-        }
+        self.read_dir(dir)?.collect()
+    }
 
-        let mut entries = Vec::new();
-        let block_size = self.superblock.block_size() as usize;
-        let dir_entries_have_type = self
-            .superblock
-            .required_features()
-            .contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE);
+    /// Returns an iterator over the entries of the given directory.
+    /// Directory blocks are read lazily, one at a time.
+    pub fn read_dir<'a>(&'a self, dir: &'a Inode) -> Result<ReadDir<'a, T>, Error> {
+        self.read_dir_at(dir, DirPosition::START)
+    }
 
-        for addr in dir.direct_ptrs().filter_map(|v| v) {
-            let mut data = vec![0_u8; block_size];
-            self.read_block(addr, &mut data)
-                .map_err(|_| Error::DeviceRead)?;
-
-            let mut offset = 0;
-            while offset < block_size - 8 {
-                let dir_entry = DirEntry::from(dir_entries_have_type, &data[offset..]);
-                // we don't need to align the offset, as there must be no space between entries
-                offset += dir_entry.total_size as usize;
-                entries.push(dir_entry);
-            }
+    /// Returns an iterator over the entries of the given directory, starting
+    /// at the given position. The position must have been obtained from
+    /// [`ReadDir::position`] for the same directory.
+    pub fn read_dir_at<'a>(&'a self, dir: &'a Inode, position: DirPosition) -> Result<ReadDir<'a, T>, Error> {
+        if dir.typ() != Type::Directory {
+            return Err(Error::NotDirectory);
         }
 
-        // TODO: handle indirect ptrsa
-
-        Ok(entries)
+        Ok(ReadDir {
+            fs: self,
+            dir,
+            position: position.0,
+            block: None,
+        })
     }
 
     pub fn find_entry<P>(
         &self,
         dir: &Directory,
-        p: P,
+        mut p: P,
     ) -> Result<Option<DirEntry>, Error>
     where
         P: FnMut(&DirEntry) -> bool,
     {
-        Ok(self.list_dir(dir)?
-            .into_iter()
-            .find(p))
+        for entry in self.read_dir(dir)? {
+            let entry = entry?;
+            if p(&entry) {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
     }
 
     pub fn find_and_resolve_entry<P>(
@@ -79,80 +79,513 @@ where
         self.read_inode(entry.inode)
     }
 
+    /// Returns the type of the file that the given entry points to. If the file system
+    /// doesn't store types in directory entries, or the stored type is unknown, the
+    /// type is read from the inode.
+    pub fn dir_entry_type(&self, entry: &DirEntry) -> Result<DirType, Error> {
+        match entry.typ() {
+            Some(typ) if !typ.is_empty() => Ok(typ),
+            _ => Ok(DirType::from(self.read_inode(entry.inode)?.1.typ())),
+        }
+    }
+
+    /// Enables the [`RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE`] feature, and stores
+    /// the type of the file in every entry of every directory. Does nothing if the
+    /// feature is already enabled. Revision 0 file systems don't support features.
+    ///
+    /// All directories are checked before anything is written, so that a directory with
+    /// a name that is too long for an entry with a type leaves the file system unchanged.
+    pub fn enable_dir_entry_types(&mut self) -> Result<(), Error> {
+        if self.superblock.revision() == Revision::GoodOld {
+            return Err(Error::NotSupported);
+        }
+        let features = self.superblock.required_features();
+        if features.contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE) {
+            return Ok(());
+        }
+
+        // the offsets and types of the used entries of every directory block
+        let block_size = self.superblock.block_size() as usize;
+        let mut block_data = vec![0_u8; block_size];
+        let mut blocks = Vec::new();
+        for inode_address in self.used_inodes()? {
+            let (_, inode) = self.read_inode(inode_address)?;
+            if inode.typ() != Type::Directory {
+                continue;
+            }
+            for block_index in 0..(inode.len() / block_size as u64) as u32 {
+                let Some(block) = self.resolve_block_index(&inode, block_index)? else {
+                    continue;
+                };
+                self.read_block(block, &mut block_data)?;
+
+                let mut types = Vec::new();
+                for (offset, entry) in parse_dir_block(false, &block_data)? {
+                    if entry.name_bytes().len() > u8::MAX as usize {
+                        return Err(Error::NotSupported);
+                    }
+                    types.push((offset, self.dir_entry_type(&entry)?));
+                }
+                blocks.push((block, types));
+            }
+        }
+
+        // the high byte of the name length becomes the type
+        for (block, types) in blocks {
+            self.read_block(block, &mut block_data)?;
+            for (offset, typ) in types {
+                let mut header = DirEntryNoName::try_from(&block_data[offset..offset + 8].try_into().unwrap()).unwrap();
+                header.type_indicator_or_name_length_msb = typ.bits();
+                block_data[offset..offset + 8].copy_from_slice(&Into::<[u8; 8]>::into(&header));
+            }
+            self.write_block(block, &block_data)?;
+        }
+
+        self.superblock.set_required_features(features | RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE);
+        self.write_superblock()
+    }
+
+    /// Adds an entry with the given name to the given directory. The name is a byte
+    /// string, which doesn't have to be valid UTF-8, but it must be a valid file name,
+    /// see [`validate_name`].
     pub fn add_entry_to_dir(
         &mut self,
         dir: &mut Directory,
-        name: &str,
+        name: impl AsRef<[u8]>,
         inode_address: InodeAddress,
         typ: DirType,
     ) -> Result<(), Error> {
-        // TODO: make this more efficient once we have indexed lookups implemented
-        if self.find_entry(dir, |e| e.name() == Some(name))?.is_some() {
+        let name = name.as_ref();
+        validate_name(name)?;
+        if self.find_entry_by_name(dir, name)?.is_some() {
             return Err(Error::EntryExists);
         }
 
+        if dir.flags().contains(Flags::HashIndexedDirectory) {
+            if self.is_indexed_dir(dir) && self.add_entry_to_indexed_dir(dir, name, inode_address, typ)? {
+                return Ok(());
+            }
+            // The index can't be used, so it is dropped. The directory stays valid,
+            // since index blocks look like blocks with unused entries.
+            self.drop_dir_index(dir)?;
+        }
+
+        let block_size = self.superblock.block_size();
+        let num_blocks = (dir.len() / block_size as u64) as u32;
+
+        // find a free slot in the existing blocks and insert the entry
+        for block_index in 0..num_blocks {
+            let Some(block) = self.resolve_block_index(dir, block_index)? else {
+                continue;
+            };
+            if self.try_add_entry_to_block(block, name, inode_address, typ)? {
+                return Ok(());
+            }
+        }
+
+        // a directory that outgrows its first block gets an index, if the file system supports it
+        if self.make_indexed_dir(dir)? {
+            if self.add_entry_to_indexed_dir(dir, name, inode_address, typ)? {
+                return Ok(());
+            }
+            self.drop_dir_index(dir)?;
+        }
+
+        // there is no free slot, so the directory has to grow
+        let block = self.grow_dir(dir)?;
+        let added = self.try_add_entry_to_block(block, name, inode_address, typ)?;
+        debug_assert!(added, "a new directory block must have space for a new entry");
+        Ok(())
+    }
+
+    /// Removes the entry with the given name from the given directory, and returns it.
+    /// The inode that the entry points to is not changed, so updating its link count
+    /// is up to the caller. The `.` and `..` entries can't be removed.
+    ///
+    /// Removing an entry from a hash indexed directory doesn't change the index.
+    pub fn remove_entry_from_dir(&mut self, dir: &mut Directory, name: impl AsRef<[u8]>) -> Result<DirEntry, Error> {
+        let name = name.as_ref();
+        if name == b"." || name == b".." {
+            return Err(Error::NotSupported);
+        }
+
+        let (block_index, entry) = self.find_entry_location(dir, name)?.ok_or(Error::NotFound)?;
+        let block = self.resolve_block_index(dir, block_index)?.ok_or(Error::CorruptedDirectory)?;
         let block_size = self.superblock.block_size() as usize;
         let dir_entries_have_type = self
             .superblock
             .required_features()
             .contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE);
 
-        let inode = &mut dir.inode_mut();
+        let mut block_data = vec![0_u8; block_size];
+        self.read_block(block, &mut block_data)?;
+
+        let mut previous_offset = None;
+        let mut offset = 0;
+        while offset < block_size - 8 {
+            let header = DirEntryNoName::try_from(&block_data[offset..offset + 8].try_into().unwrap()).unwrap();
+            if header.total_size < DirEntry::size(0) {
+                return Err(Error::CorruptedDirectory);
+            }
+            if header.inode != 0 && DirEntry::parse(dir_entries_have_type, &block_data[offset..])?.name_bytes() == name {
+                match previous_offset {
+                    // the previous entry takes over the space of the removed entry
+                    Some(previous_offset) => {
+                        let mut previous = DirEntryNoName::try_from(&block_data[previous_offset..previous_offset + 8].try_into().unwrap()).unwrap();
+                        previous.total_size += header.total_size;
+                        block_data[previous_offset..previous_offset + 8].copy_from_slice(&Into::<[u8; 8]>::into(&previous));
+                    }
+                    // the first entry of a block can't be merged, so it is marked as unused
+                    None => {
+                        let mut header = header;
+                        header.inode = 0;
+                        block_data[offset..offset + 8].copy_from_slice(&Into::<[u8; 8]>::into(&header));
+                    }
+                }
+                self.write_block(block, &block_data)?;
+                return Ok(entry);
+            }
+
+            previous_offset = Some(offset);
+            offset += header.total_size as usize;
+        }
+
+        Err(Error::CorruptedDirectory)
+    }
+
+    /// Removes the hash index flag from the given directory, so that it is used as
+    /// a linear directory.
+    pub(crate) fn drop_dir_index(&mut self, dir: &mut Directory) -> Result<(), Error> {
+        let flags = dir.flags() - Flags::HashIndexedDirectory;
+        dir.inode_mut().set_flags(flags);
+        self.write_inode(dir.inode_address(), dir)
+    }
+
+    /// Serializes the given entries into a directory block. The entries are packed
+    /// at the start of the block, and the last entry spans the rest of the block.
+    pub(crate) fn serialize_dir_block(&self, entries: Vec<DirEntry>) -> Vec<u8> {
+        let block_size = self.superblock.block_size() as usize;
+        let dir_entries_have_type = self
+            .superblock
+            .required_features()
+            .contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE);
+
+        let mut block_data = vec![0_u8; block_size];
+        if entries.is_empty() {
+            let mut header = DirEntryNoName::try_from([0; 8]).unwrap();
+            header.total_size = block_size as u16;
+            block_data[..8].copy_from_slice(&Into::<[u8; 8]>::into(&header));
+            return block_data;
+        }
+
+        let num_entries = entries.len();
+        let mut offset = 0;
+        for (i, mut entry) in entries.into_iter().enumerate() {
+            entry.total_size = if i == num_entries - 1 {
+                (block_size - offset) as u16
+            } else {
+                DirEntry::size(entry.name_length)
+            };
+            let total_size = entry.total_size as usize;
+            let entry_serialized = entry.serialize(dir_entries_have_type);
+            block_data[offset..offset + entry_serialized.len()].copy_from_slice(&entry_serialized);
+            offset += total_size;
+        }
+        block_data
+    }
+
+    /// Inserts the entry into the given directory block, if the block has a
+    /// slot that is big enough. Returns whether the entry was inserted.
+    pub(crate) fn try_add_entry_to_block(
+        &mut self,
+        block: BlockAddress,
+        name: &[u8],
+        inode_address: InodeAddress,
+        typ: DirType,
+    ) -> Result<bool, Error> {
+        let block_size = self.superblock.block_size() as usize;
+        let dir_entries_have_type = self
+            .superblock
+            .required_features()
+            .contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE);
 
         // compute the size of the directory entry that we need
         let required_size = DirEntry::size(name.len() as u16);
 
-        // find a free slot and insert the entry
-        for block in inode.direct_ptrs().filter_map(|v| v) {
-            let mut block_data = vec![0_u8; block_size];
-            self.read_block(block, &mut block_data)?;
+        let mut block_data = vec![0_u8; block_size];
+        self.read_block(block, &mut block_data)?;
 
-            // In the block, we need to find the first entry, where
-            // entry.total_size - DirEntry::size(..., entry.name_length) >= required_size,
-            // adapt that entry and store our entry there.
-            let mut offset = 0;
-            while offset < block_size - 8 {
-                debug_assert_eq!(offset % 4, 0, "offset is not aligned");
-
-                let mut entry = DirEntry::from(dir_entries_have_type, &block_data[offset..]);
-                let entry_size = DirEntry::size(entry.name_length);
-                if entry.total_size >= required_size + entry_size {
-                    // we found a slot that is big enough
-
-                    let old_total_size = entry.total_size;
-                    entry.total_size = entry_size; // resize the old entry
-
-                    // merge the old entry back into the block data
-                    let entry_serialized = entry.serialize(dir_entries_have_type);
-                    block_data[offset..offset + entry_serialized.len()].copy_from_slice(&entry_serialized);
-
-                    let new_entry_total_size = old_total_size - entry_size;
-                    let new_entry_offset = offset + entry_size as usize;
-                    debug_assert_eq!(new_entry_offset % 4, 0, "new entry offset is not aligned");
-
-                    let new_entry = DirEntry {
-                        inode: inode_address,
-                        total_size: new_entry_total_size,
-                        name_length: name.len() as u16,
-                        type_indicator: if dir_entries_have_type { Some(typ) } else { None },
-                        name_bytes: name.as_bytes().to_vec(),
-                    };
-
-                    // merge the new entry into the block data
-                    let new_entry_serialized = new_entry.serialize(dir_entries_have_type);
-                    block_data[new_entry_offset..new_entry_offset + new_entry_serialized.len()].copy_from_slice(&new_entry_serialized);
-
-                    // write the block back to the device
-                    self.write_block(block, &block_data)?;
-
-                    return Ok(());
-                }
+        // In the block, we need to find the first entry, where
+        // entry.total_size - DirEntry::size(..., entry.name_length) >= required_size,
+        // adapt that entry and store our entry there.
+        let mut offset = 0;
+        while offset < block_size - 8 {
+            debug_assert_eq!(offset % 4, 0, "offset is not aligned");
 
-                offset += entry.total_size as usize;
+            let header = DirEntryNoName::try_from(&block_data[offset..offset + 8].try_into().unwrap()).unwrap();
+            if header.total_size < DirEntry::size(0) {
+                return Err(Error::CorruptedDirectory);
             }
+            if header.inode == 0 && header.total_size >= required_size {
+                // the entry is unused, so we can take its place
+                let new_entry = DirEntry {
+                    inode: inode_address,
+                    total_size: header.total_size,
+                    name_length: name.len() as u16,
+                    type_indicator: if dir_entries_have_type { Some(typ) } else { None },
+                    name_bytes: name.to_vec(),
+                };
+                let new_entry_serialized = new_entry.serialize(dir_entries_have_type);
+                block_data[offset..offset + new_entry_serialized.len()].copy_from_slice(&new_entry_serialized);
+                self.write_block(block, &block_data)?;
+
+                return Ok(true);
+            }
+            if header.inode == 0 {
+                offset += header.total_size as usize;
+                continue;
+            }
+
+            let mut entry = DirEntry::parse(dir_entries_have_type, &block_data[offset..])?;
+            let entry_size = DirEntry::size(entry.name_length);
+            if entry.total_size >= required_size + entry_size {
+                // we found a slot that is big enough
+
+                let old_total_size = entry.total_size;
+                entry.total_size = entry_size; // resize the old entry
+
+                // merge the old entry back into the block data
+                let entry_serialized = entry.serialize(dir_entries_have_type);
+                block_data[offset..offset + entry_serialized.len()].copy_from_slice(&entry_serialized);
+
+                let new_entry_total_size = old_total_size - entry_size;
+                let new_entry_offset = offset + entry_size as usize;
+                debug_assert_eq!(new_entry_offset % 4, 0, "new entry offset is not aligned");
+
+                let new_entry = DirEntry {
+                    inode: inode_address,
+                    total_size: new_entry_total_size,
+                    name_length: name.len() as u16,
+                    type_indicator: if dir_entries_have_type { Some(typ) } else { None },
+                    name_bytes: name.to_vec(),
+                };
+
+                // merge the new entry into the block data
+                let new_entry_serialized = new_entry.serialize(dir_entries_have_type);
+                block_data[new_entry_offset..new_entry_offset + new_entry_serialized.len()].copy_from_slice(&new_entry_serialized);
+
+                // write the block back to the device
+                self.write_block(block, &block_data)?;
+
+                return Ok(true);
+            }
+
+            offset += entry.total_size as usize;
+        }
+
+        Ok(false)
+    }
+
+    /// Appends empty blocks to the given directory and returns the first new block.
+    /// If the file system has [`OptionalFeatures::PREALLOCATE_FOR_DIRECTORY`] set,
+    /// the directory grows by the number of blocks that the superblock asks to
+    /// preallocate for directories, otherwise by a single block.
+    fn grow_dir(&mut self, dir: &mut Directory) -> Result<BlockAddress, Error> {
+        let preallocate = self.superblock.num_preallocate_blocks_directory() as u32;
+        let num_new_blocks = if self.superblock.optional_features().contains(OptionalFeatures::PREALLOCATE_FOR_DIRECTORY) {
+            preallocate.max(1)
+        } else {
+            1
+        };
+        self.append_dir_blocks(dir, num_new_blocks)
+    }
+
+    /// Appends the given number of empty blocks to the given directory and returns the
+    /// first new block. Only the first block is required, the others are allocated if
+    /// there is enough space.
+    pub(crate) fn append_dir_blocks(&mut self, dir: &mut Directory, num_new_blocks: u32) -> Result<BlockAddress, Error> {
+        let block_size = self.superblock.block_size();
+
+        // every new block consists of a single unused entry that spans the whole block
+        let mut empty_block = vec![0_u8; block_size as usize];
+        let mut header = DirEntryNoName::try_from([0; 8]).unwrap();
+        header.total_size = block_size as u16;
+        empty_block[..8].copy_from_slice(&Into::<[u8; 8]>::into(&header));
+
+        let inode_address = dir.inode_address();
+        let inode = dir.inode_mut();
+        let first_block_index = (inode.len() / block_size as u64) as u32;
+        let mut first_block = None;
+        let mut num_new_allocated_blocks = 0;
+        for block_index in first_block_index..first_block_index + num_new_blocks {
+            // only the first block is required, the others are a best effort
+            let block = match self.allocate_block()? {
+                Some(block) => block,
+                None if first_block.is_none() => return Err(Error::NoSpace),
+                None => break,
+            };
+            self.write_block(block, &empty_block)?;
+            num_new_allocated_blocks += 1 + self.set_block_index(inode, block_index, block)?;
+            first_block.get_or_insert(block);
+
+            let new_size = (block_index + 1) as u64 * block_size as u64;
+            inode.set_len(new_size);
         }
 
-        todo!("add_entry_to_dir with indirect pointers")
+        *inode.num_disk_sectors_mut() += num_new_allocated_blocks * (block_size / 512);
+        self.write_inode(inode_address, inode)?;
+        Ok(first_block.unwrap()) // we return early if no block could be allocated
+    }
+}
+
+/// Parses the used entries of the given directory block, and returns them with
+/// their offsets in the block.
+pub(crate) fn parse_dir_block(dir_entries_have_type: bool, data: &[u8]) -> Result<Vec<(usize, DirEntry)>, Error> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset + DirEntry::size(0) as usize <= data.len() {
+        let header = DirEntryNoName::try_from(&data[offset..offset + 8].try_into().unwrap()).unwrap();
+        let total_size = header.total_size as usize;
+        if total_size < DirEntry::size(0) as usize || !total_size.is_multiple_of(4) || offset + total_size > data.len() {
+            return Err(Error::CorruptedDirectory);
+        }
+        if header.inode != 0 {
+            entries.push((offset, DirEntry::parse(dir_entries_have_type, &data[offset..])?));
+        }
+        offset += total_size;
+    }
+    Ok(entries)
+}
+
+/// The maximum length of a file name in bytes.
+pub const MAX_NAME_LEN: usize = 255;
+
+/// Checks that the given name can be used for a new directory entry. File names are
+/// byte strings that don't have to be valid UTF-8, but they must not be empty, must not
+/// be longer than [`MAX_NAME_LEN`] bytes, must not contain `/` or NUL, and must not be
+/// `.` or `..`.
+pub fn validate_name(name: &[u8]) -> Result<(), Error> {
+    if name.is_empty()
+        || name.len() > MAX_NAME_LEN
+        || name.contains(&b'/')
+        || name.contains(&0)
+        || name == b"."
+        || name == b".."
+    {
+        return Err(Error::InvalidName);
+    }
+    Ok(())
+}
+
+/// An opaque position in a directory, which can be used to continue
+/// reading a directory at a later point, e.g. for `getdents`-style calls.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct DirPosition(u64);
+
+impl DirPosition {
+    /// The position of the first entry of a directory.
+    pub const START: Self = Self(0);
+
+    /// The position after the last entry of a directory.
+    pub const END: Self = Self(u64::MAX);
+
+    pub const fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+
+    pub const fn into_raw(self) -> u64 {
+        self.0
+    }
+}
+
+/// An iterator over the entries of a directory, created by [`Ext2Fs::read_dir`].
+///
+/// Entries that are not in use (with an inode number of `0`) are skipped.
+pub struct ReadDir<'a, T> {
+    fs: &'a Ext2Fs<T>,
+    dir: &'a Inode,
+    position: u64,
+    block: Option<(u32, Vec<u8>)>,
+}
+
+impl<T> ReadDir<'_, T>
+where
+    T: BlockDevice,
+{
+    /// The position of the next entry that this iterator will return.
+    pub fn position(&self) -> DirPosition {
+        DirPosition(self.position)
+    }
+
+    fn fail(&mut self, error: Error) -> Option<Result<DirEntry, Error>> {
+        self.position = DirPosition::END.0;
+        Some(Err(error))
+    }
+}
+
+impl<T> Iterator for ReadDir<'_, T>
+where
+    T: BlockDevice,
+{
+    type Item = Result<DirEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let block_size = self.fs.superblock.block_size() as u64;
+        let dir_entries_have_type = self
+            .fs
+            .superblock
+            .required_features()
+            .contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE);
+
+        loop {
+            if self.position >= self.dir.len() {
+                return None;
+            }
+
+            let block_index = (self.position / block_size) as u32;
+            let offset = (self.position % block_size) as usize;
+
+            if self.block.as_ref().map(|(index, _)| *index) != Some(block_index) {
+                let block_address = match self.fs.resolve_block_index(self.dir, block_index) {
+                    Ok(Some(v)) => v,
+                    Ok(None) => {
+                        // directories should not have holes, but if they do, there are no entries in there
+                        self.position = (block_index as u64 + 1) * block_size;
+                        continue;
+                    }
+                    Err(e) => return self.fail(e),
+                };
+                let mut data = vec![0_u8; block_size as usize];
+                if let Err(e) = self.fs.read_block(block_address, &mut data) {
+                    return self.fail(e);
+                }
+                self.block = Some((block_index, data));
+            }
+            let data = &self.block.as_ref().unwrap().1;
+
+            if offset + DirEntry::size(0) as usize > data.len() {
+                self.position = (block_index as u64 + 1) * block_size;
+                continue;
+            }
+
+            let header = DirEntryNoName::try_from(&data[offset..offset + 8].try_into().unwrap()).unwrap();
+            let total_size = header.total_size as usize;
+            if total_size < DirEntry::size(0) as usize || !total_size.is_multiple_of(4) || offset + total_size > data.len() {
+                return self.fail(Error::CorruptedDirectory);
+            }
+            let name_length = header.name_length(dir_entries_have_type) as usize;
+            if DirEntry::size(0) as usize + name_length > total_size {
+                return self.fail(Error::CorruptedDirectory);
+            }
+            self.position += total_size as u64;
+
+            if header.inode == 0 {
+                // unused entry
+                continue;
+            }
+
+            return Some(DirEntry::parse(dir_entries_have_type, &data[offset..]));
+        }
     }
 }
 
@@ -165,7 +598,7 @@ pub struct DirEntry {
 }
 
 impl DirEntry {
-    const fn size(name_length: u16) -> u16 {
+    pub(crate) const fn size(name_length: u16) -> u16 {
         let unaligned_size = 4 + // inode
             2 + // total_size
             2 + // name_length and type_indicator
@@ -174,20 +607,16 @@ impl DirEntry {
         (unaligned_size + 3) & !3
     }
 
-    fn from(dir_entries_have_type: bool, value: &[u8]) -> Self {
-        let required_size = Self::size(0);
-        debug_assert!(
-            value.len() >= required_size as usize,
-            "need at least {required_size} byte, but got {}",
-            value.len()
-        );
+    /// Parses the entry at the start of the given bytes. Without the
+    /// [`RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE`] feature, the name length
+    /// has 16 bits and the entry has no type.
+    fn parse(dir_entries_have_type: bool, value: &[u8]) -> Result<Self, Error> {
+        if value.len() < Self::size(0) as usize {
+            return Err(Error::CorruptedDirectory);
+        }
 
         let arr = DirEntryNoName::try_from(&value[0..8].try_into().unwrap()).unwrap();
-        let name_length = if dir_entries_have_type {
-            arr.name_length_lsb as u16
-        } else {
-            arr.name_length_lsb as u16 | ((arr.type_indicator_or_name_length_msb as u16) << 8)
-        };
+        let name_length = arr.name_length(dir_entries_have_type);
         let type_indicator = if dir_entries_have_type {
             Some(DirType::from_bits_truncate(
                 arr.type_indicator_or_name_length_msb,
@@ -196,17 +625,20 @@ impl DirEntry {
             None
         };
 
-        let required_size = Self::size(name_length); // now that we have the name length, we can compute the actual required size
-        debug_assert!(value.len() >= Self::size(name_length) as usize, "need at least {} byte, but have only {}", required_size, value.len());
-        
-        let name_bytes = value[8..8 + name_length as usize].to_vec();
-        Self {
-            inode: InodeAddress::new(arr.inode).unwrap(),
+        // the name must fit into the entry and the given bytes
+        let name_end = 8 + name_length as usize;
+        if name_end > arr.total_size as usize || name_end > value.len() {
+            return Err(Error::CorruptedDirectory);
+        }
+
+        let name_bytes = value[8..name_end].to_vec();
+        Ok(Self {
+            inode: InodeAddress::new(arr.inode).ok_or(Error::CorruptedDirectory)?,
             total_size: arr.total_size,
             name_length,
             type_indicator,
             name_bytes,
-        }
+        })
     }
 
     pub fn serialize(self, dir_entries_have_type: bool) -> Vec<u8> {
@@ -217,9 +649,11 @@ impl DirEntry {
         entry_no_name.total_size = self.total_size;
         entry_no_name.name_length_lsb = self.name_length as u8;
         if dir_entries_have_type {
-            entry_no_name.type_indicator_or_name_length_msb = self.type_indicator.unwrap().bits();
+            // entries that were read without a type are stored with an unknown type
+            debug_assert!(self.name_length <= u8::MAX as u16, "name is too long for an entry with a type");
+            entry_no_name.type_indicator_or_name_length_msb = self.type_indicator.map_or(0, |typ| typ.bits());
         } else {
-            entry_no_name.type_indicator_or_name_length_msb = (self.name_length >> 8) as u8; // TODO: check for data loss
+            entry_no_name.type_indicator_or_name_length_msb = (self.name_length >> 8) as u8;
         }
 
         result.extend_from_slice(&<[u8; 8]>::from(entry_no_name));
@@ -228,12 +662,21 @@ impl DirEntry {
     }
 
     pub fn name(&self) -> Option<&str> {
-        match core::str::from_utf8(&self.name_bytes) {
-            Ok(s) => Some(s),
-            Err(_) => None,
-        }
+        core::str::from_utf8(&self.name_bytes).ok()
     }
 
+    pub fn name_bytes(&self) -> &[u8] {
+        &self.name_bytes
+    }
+
+    /// The name of the entry, with invalid UTF-8 sequences replaced by `U+FFFD`.
+    pub fn name_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.name_bytes)
+    }
+
+    /// The type that is stored in the entry. This is `None` if the file system
+    /// doesn't store types in directory entries, and may be empty if the type is
+    /// unknown. Use [`Ext2Fs::dir_entry_type`] to get the type in any case.
     pub fn typ(&self) -> Option<DirType> {
         self.type_indicator
     }
@@ -272,6 +715,16 @@ bytefield! {
     }
 }
 
+impl DirEntryNoName {
+    fn name_length(&self, dir_entries_have_type: bool) -> u16 {
+        if dir_entries_have_type {
+            self.name_length_lsb as u16
+        } else {
+            self.name_length_lsb as u16 | ((self.type_indicator_or_name_length_msb as u16) << 8)
+        }
+    }
+}
+
 bitflags! {
     #[derive(Debug, Copy, Clone, Eq, PartialEq)]
     pub struct DirType: u8 {
@@ -295,7 +748,8 @@ impl From<Type> for DirType {
             Type::FIFO => Self::FIFO,
             Type::UnixSocket => Self::UnixSocket,
             Type::SymLink => Self::SymLink,
-            _ => panic!("invalid type")
+            // an empty type means that the type is unknown
+            _ => Self::empty(),
         }
     }
 }
\ No newline at end of file
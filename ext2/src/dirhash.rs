@@ -0,0 +1,248 @@
+/// The hash functions that can be used for hash indexed directories.
+///
+/// The unsigned variants are never stored in a directory index. Instead, the
+/// superblock flags decide whether the bytes of a name are treated as signed
+/// or unsigned characters, which only makes a difference for non-ASCII names.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum HashVersion {
+    Legacy,
+    HalfMd4,
+    Tea,
+    LegacyUnsigned,
+    HalfMd4Unsigned,
+    TeaUnsigned,
+}
+
+/// The hash that marks the end of the hash range, which can't be used
+/// as the hash of a name.
+const EOF_HASH: u32 = 0x7FFF_FFFF << 1;
+
+impl HashVersion {
+    /// Converts the hash version as it is stored on disk. `unsigned` selects
+    /// the unsigned variant, as indicated by the superblock flags.
+    pub fn from_raw(raw: u8, unsigned: bool) -> Option<Self> {
+        Some(match (raw, unsigned) {
+            (0, false) => Self::Legacy,
+            (1, false) => Self::HalfMd4,
+            (2, false) => Self::Tea,
+            (0, true) | (3, _) => Self::LegacyUnsigned,
+            (1, true) | (4, _) => Self::HalfMd4Unsigned,
+            (2, true) | (5, _) => Self::TeaUnsigned,
+            _ => return None,
+        })
+    }
+
+    /// The value that is stored in the root of a directory index. The unsigned
+    /// variants are stored like their signed counterparts, since the superblock
+    /// flags select them.
+    pub(crate) fn index_raw(self) -> u8 {
+        match self {
+            Self::Legacy | Self::LegacyUnsigned => 0,
+            Self::HalfMd4 | Self::HalfMd4Unsigned => 1,
+            Self::Tea | Self::TeaUnsigned => 2,
+        }
+    }
+
+    /// Hashes the given name with the given seed, and returns the major and the minor
+    /// hash. A seed that consists only of zeros is replaced with the default seed.
+    pub fn hash(self, name: &[u8], seed: [u32; 4]) -> (u32, u32) {
+        let mut buf = if seed.iter().any(|&s| s != 0) {
+            seed
+        } else {
+            [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476]
+        };
+
+        let (hash, minor_hash) = match self {
+            Self::Legacy => (legacy_hash(name, false), 0),
+            Self::LegacyUnsigned => (legacy_hash(name, true), 0),
+            Self::HalfMd4 | Self::HalfMd4Unsigned => {
+                let unsigned = self == Self::HalfMd4Unsigned;
+                let mut input = [0_u32; 8];
+                for start in (0..name.len()).step_by(32) {
+                    str_to_hash_buf(&name[start..], unsigned, &mut input);
+                    half_md4_transform(&mut buf, &input);
+                }
+                (buf[1], buf[2])
+            }
+            Self::Tea | Self::TeaUnsigned => {
+                let unsigned = self == Self::TeaUnsigned;
+                let mut input = [0_u32; 4];
+                for start in (0..name.len()).step_by(16) {
+                    str_to_hash_buf(&name[start..], unsigned, &mut input);
+                    tea_transform(&mut buf, &input);
+                }
+                (buf[0], buf[1])
+            }
+        };
+
+        let hash = hash & !1;
+        if hash == EOF_HASH {
+            ((0x7FFF_FFFF - 1) << 1, minor_hash)
+        } else {
+            (hash, minor_hash)
+        }
+    }
+}
+
+fn char_value(c: u8, unsigned: bool) -> u32 {
+    if unsigned {
+        c as u32
+    } else {
+        c as i8 as i32 as u32
+    }
+}
+
+fn legacy_hash(name: &[u8], unsigned: bool) -> u32 {
+    let mut hash0: u32 = 0x12A3_FE2D;
+    let mut hash1: u32 = 0x37AB_E8F9;
+    for &c in name {
+        let mut hash = hash1.wrapping_add(hash0 ^ char_value(c, unsigned).wrapping_mul(7_152_373));
+        if hash & 0x8000_0000 != 0 {
+            hash = hash.wrapping_sub(0x7FFF_FFFF);
+        }
+        hash1 = hash0;
+        hash0 = hash;
+    }
+    hash0 << 1
+}
+
+/// Packs the start of the remaining part of a name into the input words of a hash
+/// transform. The padding depends on the length of the whole remaining part.
+fn str_to_hash_buf(remaining: &[u8], unsigned: bool, buf: &mut [u32]) {
+    let mut pad = remaining.len() as u32 | ((remaining.len() as u32) << 8);
+    pad |= pad << 16;
+
+    let chunk = &remaining[..remaining.len().min(buf.len() * 4)];
+    let mut words = buf.iter_mut();
+    let mut val = pad;
+    for (i, &c) in chunk.iter().enumerate() {
+        val = char_value(c, unsigned).wrapping_add(val << 8);
+        if i % 4 == 3 {
+            *words.next().unwrap() = val;
+            val = pad;
+        }
+    }
+    if !chunk.len().is_multiple_of(4) {
+        *words.next().unwrap() = val;
+    }
+    for word in words {
+        *word = pad;
+    }
+}
+
+fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+    const K2: u32 = 0x5A82_7999;
+    const K3: u32 = 0x6ED9_EBA1;
+
+    fn f(x: u32, y: u32, z: u32) -> u32 {
+        z ^ (x & (y ^ z))
+    }
+    fn g(x: u32, y: u32, z: u32) -> u32 {
+        (x & y).wrapping_add((x ^ y) & z)
+    }
+    fn h(x: u32, y: u32, z: u32) -> u32 {
+        x ^ y ^ z
+    }
+
+    let [mut a, mut b, mut c, mut d] = *buf;
+    macro_rules! round {
+        ($f:ident, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:literal) => {
+            $a = $a.wrapping_add($f($b, $c, $d)).wrapping_add($x).rotate_left($s);
+        };
+    }
+
+    round!(f, a, b, c, d, input[0], 3);
+    round!(f, d, a, b, c, input[1], 7);
+    round!(f, c, d, a, b, input[2], 11);
+    round!(f, b, c, d, a, input[3], 19);
+    round!(f, a, b, c, d, input[4], 3);
+    round!(f, d, a, b, c, input[5], 7);
+    round!(f, c, d, a, b, input[6], 11);
+    round!(f, b, c, d, a, input[7], 19);
+
+    round!(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+    round!(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+    round!(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+    round!(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+    round!(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+
+    round!(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+    round!(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+    round!(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+    round!(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+    round!(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+
+    buf[0] = buf[0].wrapping_add(a);
+    buf[1] = buf[1].wrapping_add(b);
+    buf[2] = buf[2].wrapping_add(c);
+    buf[3] = buf[3].wrapping_add(d);
+}
+
+fn tea_transform(buf: &mut [u32; 4], input: &[u32; 4]) {
+    const DELTA: u32 = 0x9E37_79B9;
+
+    let [a, b, c, d] = *input;
+    let (mut b0, mut b1) = (buf[0], buf[1]);
+    let mut sum: u32 = 0;
+    for _ in 0..16 {
+        sum = sum.wrapping_add(DELTA);
+        b0 = b0.wrapping_add((b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b));
+        b1 = b1.wrapping_add((b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d));
+    }
+    buf[0] = buf[0].wrapping_add(b0);
+    buf[1] = buf[1].wrapping_add(b1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: [u32; 4] = [0x6745_2301, 0xEFCD_AB89, 0x6745_2301, 0xEFCD_AB89];
+
+    // the expected values were obtained with `debugfs -R "dx_hash -h <version> -s <seed> <name>"`
+    const LONG_NAME: &[u8] = b"aaaaaaaaaaaaaaaaaaaa\xFF\x80xyz0123456789abcdefghij";
+
+    #[test]
+    fn test_legacy() {
+        assert_eq!((0x3225_2546, 0), HashVersion::Legacy.hash(b"hello", [0; 4]));
+        assert_eq!((0xA01D_631E, 0), HashVersion::Legacy.hash(b"h\xE4llo", [0; 4]));
+        assert_eq!((0xA1A8_791A, 0), HashVersion::Legacy.hash(LONG_NAME, SEED));
+        assert_eq!((0x3225_2546, 0), HashVersion::LegacyUnsigned.hash(b"hello", [0; 4]));
+        assert_eq!((0x967B_5542, 0), HashVersion::LegacyUnsigned.hash(b"h\xE4llo", [0; 4]));
+        assert_eq!((0x8CBD_FD10, 0), HashVersion::LegacyUnsigned.hash(LONG_NAME, SEED));
+    }
+
+    #[test]
+    fn test_half_md4() {
+        assert_eq!((0x1746_DA32, 0x4200_13B5), HashVersion::HalfMd4.hash(b"hello", [0; 4]));
+        assert_eq!((0x709E_DF1E, 0x4058_8515), HashVersion::HalfMd4.hash(b"h\xE4llo", [0; 4]));
+        assert_eq!((0x8E4F_ED6E, 0x720C_D5D6), HashVersion::HalfMd4.hash(LONG_NAME, [0; 4]));
+        assert_eq!((0x48C8_8356, 0xD330_E5B5), HashVersion::HalfMd4.hash(LONG_NAME, SEED));
+        assert_eq!((0x2CFE_F244, 0x0423_674C), HashVersion::HalfMd4Unsigned.hash(b"h\xE4llo", [0; 4]));
+        assert_eq!((0xAC13_8C36, 0xAE24_A73F), HashVersion::HalfMd4Unsigned.hash(LONG_NAME, [0; 4]));
+        assert_eq!((0x18F8_C466, 0x4EFA_D49E), HashVersion::HalfMd4Unsigned.hash(LONG_NAME, SEED));
+    }
+
+    #[test]
+    fn test_tea() {
+        assert_eq!((0x6F5B_B1A8, 0x2319_17C2), HashVersion::Tea.hash(b"hello", [0; 4]));
+        assert_eq!((0xF873_18F6, 0x0AE2_A1F5), HashVersion::Tea.hash(b"h\xE4llo", [0; 4]));
+        assert_eq!((0x69EB_B710, 0x4D83_8703), HashVersion::Tea.hash(LONG_NAME, [0; 4]));
+        assert_eq!((0xF9CA_26FA, 0x345A_EFD9), HashVersion::TeaUnsigned.hash(b"h\xE4llo", [0; 4]));
+        assert_eq!((0x4579_99DA, 0xA37C_B7C0), HashVersion::TeaUnsigned.hash(LONG_NAME, [0; 4]));
+    }
+
+    #[test]
+    fn test_from_raw() {
+        assert_eq!(Some(HashVersion::HalfMd4), HashVersion::from_raw(1, false));
+        assert_eq!(Some(HashVersion::HalfMd4Unsigned), HashVersion::from_raw(1, true));
+        assert_eq!(Some(HashVersion::TeaUnsigned), HashVersion::from_raw(5, false));
+        assert_eq!(None, HashVersion::from_raw(6, false));
+    }
+}
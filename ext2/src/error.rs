@@ -12,9 +12,21 @@ pub enum Error {
     DeviceRead,
     DeviceWrite,
     InvalidInodeAddress(u32),
+    InvalidBlockAddress(u32),
     NoSpace,
     NotSupported,
     EntryExists,
+    InvalidName,
+    CorruptedDirectory,
+    CorruptedXattrBlock,
+    InvalidAcl,
+    NotFound,
+    TooManySymLinks,
+    InvalidSymLinkTarget,
+    InvalidSeek,
+    FileTooLarge,
+    Inconsistent,
+    InvalidArgument,
 }
 
 impl Display for Error {
@@ -24,3 +36,38 @@ impl Display for Error {
 }
 
 impl core::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(value: Error) -> Self {
+        use std::io::ErrorKind;
+
+        let kind = match value {
+            Error::NotFound => ErrorKind::NotFound,
+            Error::EntryExists => ErrorKind::AlreadyExists,
+            Error::NotDirectory => ErrorKind::NotADirectory,
+            Error::NoSpace => ErrorKind::StorageFull,
+            Error::NotSupported => ErrorKind::Unsupported,
+            Error::InvalidSeek | Error::InvalidArgument => ErrorKind::InvalidInput,
+            Error::InvalidName => ErrorKind::InvalidFilename,
+            Error::FileTooLarge => ErrorKind::FileTooLarge,
+            _ => ErrorKind::Other,
+        };
+        std::io::Error::new(kind, value)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        use embedded_io::ErrorKind;
+
+        match self {
+            Error::NotFound => ErrorKind::NotFound,
+            Error::EntryExists => ErrorKind::AlreadyExists,
+            Error::NotSupported => ErrorKind::Unsupported,
+            Error::InvalidSeek | Error::InvalidName | Error::InvalidArgument => ErrorKind::InvalidInput,
+            _ => ErrorKind::Other,
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use filesystem::BlockDevice;
+
+use crate::{Error, Ext2Fs, RegularFile};
+
+/// A position to seek to in an [`Ext2File`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SeekFrom {
+    /// Seek to an offset from the start of the file.
+    Start(u64),
+    /// Seek to an offset from the end of the file.
+    End(i64),
+    /// Seek to an offset from the current position.
+    Current(i64),
+}
+
+/// A handle to an open regular file, that keeps track of a position in
+/// the file. Reads and writes start at that position and advance it.
+///
+/// With the `std` feature, this implements [`std::io::Read`],
+/// [`std::io::Write`] and [`std::io::Seek`], and with the `embedded-io`
+/// feature, the respective `embedded-io` traits.
+pub struct Ext2File<'a, T> {
+    fs: &'a mut Ext2Fs<T>,
+    file: RegularFile,
+    position: u64,
+}
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Opens the regular file at the given path as a file handle, positioned
+    /// at the start of the file.
+    pub fn open_file_handle(&mut self, path: impl AsRef<[u8]>) -> Result<Ext2File<'_, T>, Error> {
+        let file = self.open_file(path)?;
+        Ok(self.file_handle(file))
+    }
+
+    /// Creates a file handle for the given file, positioned at the start of the file.
+    pub fn file_handle(&mut self, file: RegularFile) -> Ext2File<'_, T> {
+        Ext2File {
+            fs: self,
+            file,
+            position: 0,
+        }
+    }
+}
+
+impl<T> Ext2File<'_, T>
+where
+    T: BlockDevice,
+{
+    /// Reads from the current position into the given buffer, and advances
+    /// the position by the amount of bytes read. Returns `0` at the end of the file.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = self.fs.read_from_file(&self.file, self.position, buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+
+    /// Writes the given buffer at the current position, and advances the
+    /// position by the amount of bytes written.
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let written = self.fs.write_to_file(&mut self.file, self.position, buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    /// Moves the position of this handle and returns the new position, measured
+    /// from the start of the file. Seeking beyond the end of the file is allowed.
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let new_position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.file.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = new_position.ok_or(Error::InvalidSeek)?;
+        Ok(self.position)
+    }
+
+    /// Moves the position to the next data at or after the current position,
+    /// see [`Ext2Fs::seek_data`]. If there is no more data, the position is
+    /// not changed and `None` is returned.
+    pub fn seek_data(&mut self) -> Result<Option<u64>, Error> {
+        let data = self.fs.seek_data(&self.file, self.position)?;
+        if let Some(data) = data {
+            self.position = data;
+        }
+        Ok(data)
+    }
+
+    /// Moves the position to the next hole at or after the current position,
+    /// see [`Ext2Fs::seek_hole`]. If the position is beyond the end of the file,
+    /// the position is not changed and `None` is returned.
+    pub fn seek_hole(&mut self) -> Result<Option<u64>, Error> {
+        let hole = self.fs.seek_hole(&self.file, self.position)?;
+        if let Some(hole) = hole {
+            self.position = hole;
+        }
+        Ok(hole)
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn len(&self) -> u64 {
+        self.file.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn file(&self) -> &RegularFile {
+        &self.file
+    }
+
+    pub fn into_inner(self) -> RegularFile {
+        self.file
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::SeekFrom> for SeekFrom {
+    fn from(value: std::io::SeekFrom) -> Self {
+        match value {
+            std::io::SeekFrom::Start(offset) => Self::Start(offset),
+            std::io::SeekFrom::End(offset) => Self::End(offset),
+            std::io::SeekFrom::Current(offset) => Self::Current(offset),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> std::io::Read for Ext2File<'_, T>
+where
+    T: BlockDevice,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ext2File::read(self, buf).map_err(Into::into)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> std::io::Write for Ext2File<'_, T>
+where
+    T: BlockDevice,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ext2File::write(self, buf).map_err(Into::into)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        // writes go directly to the block device
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> std::io::Seek for Ext2File<'_, T>
+where
+    T: BlockDevice,
+{
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        Ext2File::seek(self, pos.into()).map_err(Into::into)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl From<embedded_io::SeekFrom> for SeekFrom {
+    fn from(value: embedded_io::SeekFrom) -> Self {
+        match value {
+            embedded_io::SeekFrom::Start(offset) => Self::Start(offset),
+            embedded_io::SeekFrom::End(offset) => Self::End(offset),
+            embedded_io::SeekFrom::Current(offset) => Self::Current(offset),
+        }
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<T> embedded_io::ErrorType for Ext2File<'_, T> {
+    type Error = Error;
+}
+
+#[cfg(feature = "embedded-io")]
+impl<T> embedded_io::Read for Ext2File<'_, T>
+where
+    T: BlockDevice,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ext2File::read(self, buf)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<T> embedded_io::Write for Ext2File<'_, T>
+where
+    T: BlockDevice,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ext2File::write(self, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        // writes go directly to the block device
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<T> embedded_io::Seek for Ext2File<'_, T>
+where
+    T: BlockDevice,
+{
+    fn seek(&mut self, pos: embedded_io::SeekFrom) -> Result<u64, Self::Error> {
+        Ext2File::seek(self, pos.into())
+    }
+}
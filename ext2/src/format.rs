@@ -0,0 +1,243 @@
+use alloc::vec;
+
+use filesystem::BlockDevice;
+
+use crate::block_group::BlockGroupDescriptor;
+use crate::superblock::has_superblock_copy;
+use crate::{Directory, Error, Ext2Fs, Inode, Permissions, Revision, State, Superblock, SuperblockArray, Type, BGD_SIZE, ROOT_DIR_INODE_ADDRESS, SUPERBLOCK_OFFSET};
+
+/// The options for [`Ext2Fs::format`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FormatOptions {
+    /// The revision of the new file system. Revision 0 file systems have no
+    /// features, which makes them readable by the most implementations.
+    pub revision: Revision,
+    /// The block size, which must be a power of two between 1KiB and 64KiB.
+    pub block_size: u32,
+    /// The number of bytes of the device per inode, which determines the number of inodes.
+    pub bytes_per_inode: u32,
+    /// The size of an inode, which must be a power of two between 128 and the block size.
+    /// Revision 0 file systems always have 128 byte inodes.
+    pub inode_size: u16,
+    /// The percentage of blocks that is reserved for the superuser.
+    pub reserved_blocks_percent: u8,
+    /// The id of the new file system. Not stored by revision 0 file systems.
+    pub fsid: [u8; 16],
+    /// The name of the new file system. Not stored by revision 0 file systems.
+    pub volume_name: [u8; 16],
+    /// The time in seconds since the unix epoch, which is stored as the creation time.
+    pub time: u32,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            revision: Revision::Dynamic,
+            block_size: 1024,
+            bytes_per_inode: 4096,
+            inode_size: 256,
+            reserved_blocks_percent: 5,
+            fsid: [0; 16],
+            volume_name: [0; 16],
+            time: 0,
+        }
+    }
+}
+
+/// The number of blocks that the root directory and `lost+found` need.
+const MIN_DATA_BLOCKS: u32 = 2;
+
+/// The positions of the metadata of a new file system.
+struct Layout {
+    num_blocks: u32,
+    num_groups: u32,
+    inodes_per_group: u32,
+    inode_table_blocks: u32,
+    gdt_blocks: u32,
+    sparse_superblocks: bool,
+}
+
+impl Layout {
+    fn new(options: &FormatOptions, device_size: u64) -> Result<Self, Error> {
+        let block_size = options.block_size;
+        if !block_size.is_power_of_two() || !(1024..=65536).contains(&block_size) || options.bytes_per_inode == 0 {
+            return Err(Error::NotSupported);
+        }
+        let inode_size = match options.revision {
+            Revision::GoodOld => Revision::GOOD_OLD_INODE_SIZE,
+            Revision::Dynamic => options.inode_size,
+        } as u32;
+        if !inode_size.is_power_of_two() || inode_size < Revision::GOOD_OLD_INODE_SIZE as u32 || inode_size > block_size {
+            return Err(Error::NotSupported);
+        }
+
+        let first_data_block = if block_size == 1024 { 1 } else { 0 };
+        let blocks_per_group = block_size * 8;
+        let inodes_per_block = block_size / inode_size;
+        let mut num_blocks = (device_size / block_size as u64).min(u32::MAX as u64) as u32;
+        loop {
+            if num_blocks <= first_data_block {
+                return Err(Error::NoSpace);
+            }
+            let num_groups = (num_blocks - first_data_block).div_ceil(blocks_per_group);
+            let num_inodes = (num_blocks as u64 * block_size as u64 / options.bytes_per_inode as u64)
+                .max(Revision::GOOD_OLD_FIRST_INODE as u64 + 1);
+            // the inodes of a group fill whole blocks of the inode table, and whole bytes of the bitmap
+            let inodes_per_group = (num_inodes.div_ceil(num_groups as u64) as u32)
+                .next_multiple_of(inodes_per_block)
+                .next_multiple_of(8)
+                .min(blocks_per_group / inodes_per_block * inodes_per_block);
+            let layout = Self {
+                num_blocks,
+                num_groups,
+                inodes_per_group,
+                inode_table_blocks: inodes_per_group / inodes_per_block,
+                gdt_blocks: (num_groups * BGD_SIZE as u32).div_ceil(block_size),
+                sparse_superblocks: options.revision == Revision::Dynamic,
+            };
+            if inodes_per_group * num_groups < Revision::GOOD_OLD_FIRST_INODE {
+                return Err(Error::NoSpace);
+            }
+
+            // the last group must have space for its metadata and at least one data block,
+            // otherwise it is dropped
+            let last_group = num_groups - 1;
+            let last_group_blocks = layout.blocks_in_group(last_group, first_data_block, blocks_per_group);
+            let min_blocks = layout.metadata_blocks(last_group) + if last_group == 0 { MIN_DATA_BLOCKS } else { 1 };
+            if last_group_blocks >= min_blocks {
+                return Ok(layout);
+            }
+            if last_group == 0 {
+                return Err(Error::NoSpace);
+            }
+            num_blocks -= last_group_blocks;
+        }
+    }
+
+    fn blocks_in_group(&self, group_index: u32, first_data_block: u32, blocks_per_group: u32) -> u32 {
+        let first_block = first_data_block + group_index * blocks_per_group;
+        (self.num_blocks - first_block).min(blocks_per_group)
+    }
+
+    /// Whether the group with the given index has a copy of the superblock and the
+    /// block group descriptor table.
+    fn has_superblock(&self, group_index: u32) -> bool {
+        has_superblock_copy(self.sparse_superblocks, group_index)
+    }
+
+    /// The number of blocks at the start of the group with the given index that are used by metadata.
+    fn metadata_blocks(&self, group_index: u32) -> u32 {
+        let superblock_blocks = if self.has_superblock(group_index) { 1 + self.gdt_blocks } else { 0 };
+        superblock_blocks + 2 + self.inode_table_blocks
+    }
+}
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Creates a new, empty file system on the given device, which uses the whole device.
+    /// The file system has a root directory and an empty `lost+found` directory. Blocks
+    /// at the end of the device that are too few for another block group are not used.
+    ///
+    /// Revision 1 file systems use sparse superblocks and store file types in directory
+    /// entries, revision 0 file systems store the superblock in every group.
+    pub fn format(block_device: T, options: &FormatOptions) -> Result<Self, Error> {
+        let mut block_device = block_device;
+        let device_size = block_device.sector_size() as u64 * block_device.sector_count() as u64;
+        let layout = Layout::new(options, device_size)?;
+
+        let block_size = options.block_size;
+        let first_data_block = if block_size == 1024 { 1 } else { 0 };
+        let blocks_per_group = block_size * 8;
+        let first_inode = Revision::GOOD_OLD_FIRST_INODE;
+
+        let mut superblock = Superblock::new(options, layout.num_blocks, layout.inodes_per_group);
+        let mut descriptors = vec![0_u8; (layout.gdt_blocks * block_size) as usize];
+        let mut num_unallocated_blocks = 0;
+        let mut num_unallocated_inodes = 0;
+        let write = |device: &mut T, block: u32, data: &[u8]| {
+            device.write_at(block as usize * block_size as usize, data).map_err(|_| Error::DeviceWrite)
+        };
+
+        for group_index in 0..layout.num_groups {
+            let group_start = first_data_block + group_index * blocks_per_group;
+            let blocks_in_group = layout.blocks_in_group(group_index, first_data_block, blocks_per_group);
+            let metadata_blocks = layout.metadata_blocks(group_index);
+            let block_bitmap = group_start + metadata_blocks - layout.inode_table_blocks - 2;
+            let inode_bitmap = block_bitmap + 1;
+            let inode_table = block_bitmap + 2;
+
+            // the bits after the end of the group are set, so that they are never allocated
+            let mut bitmap = vec![0_u8; block_size as usize];
+            set_bits(&mut bitmap, 0..metadata_blocks);
+            set_bits(&mut bitmap, blocks_in_group..blocks_per_group);
+            write(&mut block_device, block_bitmap, &bitmap)?;
+
+            // the reserved inodes are in the first group
+            bitmap.fill(0);
+            let reserved_inodes = if group_index == 0 { first_inode - 1 } else { 0 };
+            set_bits(&mut bitmap, 0..reserved_inodes);
+            set_bits(&mut bitmap, layout.inodes_per_group..block_size * 8);
+            write(&mut block_device, inode_bitmap, &bitmap)?;
+
+            bitmap.fill(0);
+            for block in inode_table..inode_table + layout.inode_table_blocks {
+                write(&mut block_device, block, &bitmap)?;
+            }
+
+            let free_blocks = blocks_in_group - metadata_blocks;
+            let free_inodes = layout.inodes_per_group - reserved_inodes;
+            num_unallocated_blocks += free_blocks;
+            num_unallocated_inodes += free_inodes;
+            let descriptor = BlockGroupDescriptor::new(block_bitmap, inode_bitmap, inode_table, free_blocks as u16, free_inodes as u16);
+            let offset = group_index as usize * BGD_SIZE;
+            descriptors[offset..offset + BGD_SIZE].copy_from_slice(&Into::<[u8; BGD_SIZE]>::into(&descriptor));
+        }
+        *superblock.num_unallocated_blocks_mut() = num_unallocated_blocks;
+        *superblock.num_unallocated_inodes_mut() = num_unallocated_inodes;
+
+        // the copies of the superblock and the descriptor table
+        for group_index in (0..layout.num_groups).filter(|&g| layout.has_superblock(g)) {
+            let group_start = first_data_block + group_index * blocks_per_group;
+            superblock.set_this_superblock_block_group(group_index as u16);
+            let superblock_data = Into::<SuperblockArray>::into(&superblock);
+            let offset = if group_index == 0 { SUPERBLOCK_OFFSET } else { (group_start * block_size) as usize };
+            block_device.write_at(offset, superblock_data.as_slice()).map_err(|_| Error::UnableToWriteSuperblock)?;
+            write(&mut block_device, group_start + 1, &descriptors).map_err(|_| Error::UnableToWriteBlockGroupDescriptorTable)?;
+        }
+
+        let mut fs = Self::try_new(block_device)?;
+        // the new file system is not mounted, so creating the directories must not mark it as not clean
+        fs.mount_state = Some(State::CLEAN);
+
+        // the root directory is its own parent
+        let mut root = fs.new_inode(Type::Directory);
+        root.set_perm(Permissions::from_bits_truncate(0o755));
+        set_times(&mut root, options.time);
+        fs.write_inode(ROOT_DIR_INODE_ADDRESS, &root)?;
+        let mut root = Directory::try_from((ROOT_DIR_INODE_ADDRESS, root)).unwrap(); // the inode is a directory
+        fs.init_dir(&mut root, ROOT_DIR_INODE_ADDRESS)?;
+
+        let mut lost_and_found = fs.create_dir(&mut root, "lost+found")?;
+        lost_and_found.inode_mut().set_perm(Permissions::from_bits_truncate(0o700));
+        set_times(lost_and_found.inode_mut(), options.time);
+        fs.write_inode(lost_and_found.inode_address(), &lost_and_found)?;
+        fs.mount_state = None;
+        Ok(fs)
+    }
+}
+
+fn set_times(inode: &mut Inode, time: u32) {
+    *inode.last_access_time_mut() = time;
+    *inode.last_modification_time_mut() = time;
+    *inode.creation_time_mut() = time;
+}
+
+/// Sets the bits in the given range in the given bitmap. Bits after the end of the bitmap are ignored.
+pub(crate) fn set_bits(bitmap: &mut [u8], range: core::ops::Range<u32>) {
+    let num_bits = bitmap.len() as u32 * 8;
+    for index in range.start..range.end.min(num_bits) {
+        bitmap[index as usize / 8] |= 1 << (index % 8);
+    }
+}
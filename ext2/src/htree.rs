@@ -0,0 +1,514 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use filesystem::BlockDevice;
+
+use crate::{
+    DirEntry, DirPosition, DirType, Directory, Error, Ext2Fs, Flags, HashVersion, Inode, InodeAddress,
+    OptionalFeatures, SuperblockFlags,
+};
+
+/// The offset of the index information in the first block of a hash indexed
+/// directory, right after the `.` and `..` entries.
+const ROOT_INFO_OFFSET: usize = 24;
+/// The size of the index information in the first block.
+const ROOT_INFO_LEN: u8 = 8;
+/// The offset of the index entries in the first block of a hash indexed directory.
+const ROOT_ENTRIES_OFFSET: usize = ROOT_INFO_OFFSET + ROOT_INFO_LEN as usize;
+/// The offset of the index entries in an interior index block, right after an
+/// unused directory entry that spans the whole block.
+const NODE_ENTRIES_OFFSET: usize = 8;
+/// The size of an index entry, which consists of a hash and a block index.
+const ENTRY_SIZE: usize = 8;
+/// Without the `largedir` feature, there can be at most one level of interior
+/// index blocks between the root and the leaves.
+const MAX_INDIRECT_LEVELS: u8 = 1;
+
+/// An entry in an index block of a hash indexed directory. All names with a hash
+/// that is at least `hash` (and smaller than the hash of the next entry) are
+/// stored in the directory block with the index `block`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) struct DxEntry {
+    pub(crate) hash: u32,
+    pub(crate) block: u32,
+}
+
+/// The root of the index of a hash indexed directory, which is stored in
+/// the first block of the directory.
+#[derive(Debug, Clone)]
+pub(crate) struct DxRoot {
+    pub(crate) hash_version: HashVersion,
+    pub(crate) indirect_levels: u8,
+    pub(crate) entries: Vec<DxEntry>,
+}
+
+/// The path from the root of an index to a leaf. Every element contains the entries of
+/// an index block, and the index of the entry that leads to the next level.
+type DxPath = Vec<(Vec<DxEntry>, usize)>;
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Finds the entry with the given name in the given directory. If the directory is
+    /// hash indexed, only the directory blocks that can contain the name are read,
+    /// otherwise all entries are searched.
+    pub fn find_entry_by_name(&self, dir: &Directory, name: &[u8]) -> Result<Option<DirEntry>, Error> {
+        Ok(self.find_entry_location(dir, name)?.map(|(_, entry)| entry))
+    }
+
+    /// Like [`Ext2Fs::find_entry_by_name`], but also returns the index of the
+    /// directory block that contains the entry.
+    pub(crate) fn find_entry_location(&self, dir: &Directory, name: &[u8]) -> Result<Option<(u32, DirEntry)>, Error> {
+        if let Some(location) = self.find_entry_in_index(dir, name)? {
+            return Ok(location);
+        }
+
+        let block_size = self.superblock.block_size() as u64;
+        let mut entries = self.read_dir(dir)?;
+        while let Some(entry) = entries.next() {
+            let entry = entry?;
+            if entry.name_bytes() == name {
+                // the position is already behind the entry
+                let block_index = ((entries.position().into_raw() - 1) / block_size) as u32;
+                return Ok(Some((block_index, entry)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether the given directory is hash indexed, and the file system
+    /// supports hash indexed directories.
+    pub(crate) fn is_indexed_dir(&self, dir: &Inode) -> bool {
+        self.superblock.optional_features().contains(OptionalFeatures::DIRECTORIES_USE_HASH_INDEX)
+            && dir.flags().contains(Flags::HashIndexedDirectory)
+    }
+
+    /// Hashes the given name with the given hash version and the seed of the file system.
+    pub(crate) fn dir_hash(&self, hash_version: HashVersion, name: &[u8]) -> u32 {
+        hash_version.hash(name, self.superblock.hash_seed()).0
+    }
+
+    /// Looks up the given name through the index of the given directory. Returns `None`
+    /// if the directory has no index, or if the index is not valid, in which case the
+    /// directory has to be searched linearly. Directories are still valid without
+    /// their index, since the index blocks look like blocks with unused entries.
+    fn find_entry_in_index(&self, dir: &Directory, name: &[u8]) -> Result<Option<Option<(u32, DirEntry)>>, Error> {
+        let Some(root) = self.read_dx_root(dir)? else {
+            return Ok(None);
+        };
+        let hash = self.dir_hash(root.hash_version, name);
+        let Some(mut path) = self.dx_probe(dir, root, hash)? else {
+            return Ok(None);
+        };
+
+        loop {
+            let (entries, index) = path.last().unwrap();
+            let block_index = entries[*index].block;
+            if let Some(entry) = self.find_entry_in_dir_block(dir, block_index, name)? {
+                return Ok(Some(Some((block_index, entry))));
+            }
+
+            // Names with the same hash can continue in the next leaf, which is marked
+            // by setting the lowest bit in the hash of the next leaf.
+            match self.dx_next_leaf(dir, &mut path)? {
+                Some(next_hash) if next_hash & !1 == hash => continue,
+                _ => return Ok(Some(None)),
+            }
+        }
+    }
+
+    /// Walks down the index from the root to the leaf that contains the given hash.
+    /// Returns `None` if an index block on the way is not valid.
+    pub(crate) fn dx_probe(&self, dir: &Inode, root: DxRoot, hash: u32) -> Result<Option<DxPath>, Error> {
+        let mut path = Vec::with_capacity(root.indirect_levels as usize + 1);
+        let mut entries = root.entries;
+        loop {
+            // the first entry covers all hashes below the second entry, so this is never 0
+            let index = entries.partition_point(|e| e.hash <= hash).max(1) - 1;
+            let block = entries[index].block;
+            path.push((entries, index));
+            if path.len() > root.indirect_levels as usize {
+                return Ok(Some(path));
+            }
+
+            entries = match self.read_dx_node(dir, block)? {
+                Some(entries) => entries,
+                None => return Ok(None),
+            };
+        }
+    }
+
+    /// Moves the given path to the next leaf, and returns the hash of the index entry
+    /// that points to the new leaf. Returns `None` if there is no next leaf.
+    pub(crate) fn dx_next_leaf(&self, dir: &Inode, path: &mut DxPath) -> Result<Option<u32>, Error> {
+        let depth = path.len();
+
+        // find the innermost index block that has another entry
+        let mut level = depth;
+        loop {
+            if level == 0 {
+                return Ok(None);
+            }
+            level -= 1;
+            let (entries, index) = &mut path[level];
+            if *index + 1 < entries.len() {
+                *index += 1;
+                break;
+            }
+        }
+        let (entries, index) = &path[level];
+        let hash = entries[*index].hash;
+
+        // descend to the first leaf below the new entry
+        path.truncate(level + 1);
+        while path.len() < depth {
+            let (entries, index) = path.last().unwrap();
+            let entries = self.read_dx_node(dir, entries[*index].block)?
+                .ok_or(Error::CorruptedDirectory)?;
+            path.push((entries, 0));
+        }
+        Ok(Some(hash))
+    }
+
+    /// Searches the directory block with the given index for an entry with the given name.
+    fn find_entry_in_dir_block(&self, dir: &Inode, block_index: u32, name: &[u8]) -> Result<Option<DirEntry>, Error> {
+        Ok(self.read_dir_block(dir, block_index)?.into_iter().find(|e| e.name_bytes() == name))
+    }
+
+    /// Reads all used entries of the directory block with the given index.
+    fn read_dir_block(&self, dir: &Inode, block_index: u32) -> Result<Vec<DirEntry>, Error> {
+        let block_size = self.superblock.block_size() as u64;
+        let end = (block_index as u64 + 1) * block_size;
+
+        let mut result = Vec::new();
+        let mut entries = self.read_dir_at(dir, DirPosition::from_raw(block_index as u64 * block_size))?;
+        while entries.position().into_raw() < end {
+            match entries.next() {
+                Some(entry) => result.push(entry?),
+                None => break,
+            }
+        }
+        Ok(result)
+    }
+
+    /// Adds an entry to the given hash indexed directory. If the leaf for the name is
+    /// full, it is split in two, and the index grows as needed. Returns `false` if the
+    /// index is not valid or full, in which case the entry was not added.
+    pub(crate) fn add_entry_to_indexed_dir(
+        &mut self,
+        dir: &mut Directory,
+        name: &[u8],
+        inode_address: InodeAddress,
+        typ: DirType,
+    ) -> Result<bool, Error> {
+        loop {
+            let Some(root) = self.read_dx_root(dir)? else {
+                return Ok(false);
+            };
+            let hash_version = root.hash_version;
+            let hash = self.dir_hash(hash_version, name);
+            let Some(path) = self.dx_probe(dir, root, hash)? else {
+                return Ok(false);
+            };
+
+            let (entries, index) = path.last().unwrap();
+            let Some(leaf) = self.resolve_block_index(dir, entries[*index].block)? else {
+                return Ok(false);
+            };
+            if self.try_add_entry_to_block(leaf, name, inode_address, typ)? {
+                return Ok(true);
+            }
+
+            // The leaf is full and has to be split, which needs a free slot in the index
+            // block above it. After every change to the index, the lookup starts over.
+            let level = path.len() - 1;
+            if entries.len() < self.dx_limit(level) {
+                if !self.split_dx_leaf(dir, hash_version, &path)? {
+                    return Ok(false);
+                }
+            } else if level == 0 {
+                self.add_dx_level(dir, &path[0].0)?;
+            } else if path[0].0.len() < self.dx_limit(0) {
+                self.split_dx_node(dir, &path)?;
+            } else {
+                // there can't be more levels, so the index is full
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Turns the given linear directory into a hash indexed directory, if the file system
+    /// supports it. Only directories that consist of a single block are converted. The
+    /// entries are moved to a new block, and the first block becomes the root of the index.
+    /// Returns whether the directory was converted.
+    pub(crate) fn make_indexed_dir(&mut self, dir: &mut Directory) -> Result<bool, Error> {
+        let block_size = self.superblock.block_size() as usize;
+        if !self.superblock.optional_features().contains(OptionalFeatures::DIRECTORIES_USE_HASH_INDEX)
+            || dir.len() != block_size as u64 {
+            return Ok(false);
+        }
+        let Some(hash_version) = self.superblock.default_hash_version() else {
+            return Ok(false);
+        };
+        let Some(root_block) = self.resolve_block_index(dir, 0)? else {
+            return Ok(false);
+        };
+
+        let mut data = vec![0_u8; block_size];
+        self.read_block(root_block, &mut data)?;
+
+        // the block must start with `.` and `..`, which stay in the root
+        let dot_len = u16::from_le_bytes([data[4], data[5]]);
+        if dot_len != 12 || data[6] != 1 || data[8] != b'.' || data[18] != 2 || &data[20..22] != b".." {
+            return Ok(false);
+        }
+
+        let entries = self.read_dir_block(dir, 0)?
+            .into_iter()
+            .filter(|e| e.name_bytes() != b"." && e.name_bytes() != b"..")
+            .collect::<Vec<_>>();
+        let leaf_data = self.serialize_dir_block(entries);
+        let leaf = self.append_dir_blocks(dir, 1)?;
+        self.write_block(leaf, &leaf_data)?;
+
+        // `..` spans the rest of the block, so that the index is hidden in unused space
+        data[16..18].copy_from_slice(&((block_size - 12) as u16).to_le_bytes());
+        data[ROOT_INFO_OFFSET..].fill(0);
+        data[ROOT_INFO_OFFSET + 4] = hash_version.index_raw();
+        data[ROOT_INFO_OFFSET + 5] = ROOT_INFO_LEN;
+        encode_dx_entries(&mut data, ROOT_ENTRIES_OFFSET, &[DxEntry { hash: 0, block: 1 }]);
+        self.write_block(root_block, &data)?;
+
+        let flags = dir.flags() | Flags::HashIndexedDirectory;
+        dir.inode_mut().set_flags(flags);
+        self.write_inode(dir.inode_address(), dir)?;
+        Ok(true)
+    }
+
+    /// The maximum number of entries in an index block at the given level.
+    fn dx_limit(&self, level: usize) -> usize {
+        let offset = if level == 0 { ROOT_ENTRIES_OFFSET } else { NODE_ENTRIES_OFFSET };
+        (self.superblock.block_size() as usize - offset) / ENTRY_SIZE
+    }
+
+    /// Moves the upper half of the entries of the leaf at the end of the given path to a
+    /// new block, and adds the new block to the index block above the leaf, which must
+    /// have a free slot. Returns `false` if the leaf can't be split.
+    fn split_dx_leaf(&mut self, dir: &mut Directory, hash_version: HashVersion, path: &DxPath) -> Result<bool, Error> {
+        let (entries, index) = path.last().unwrap();
+        let leaf_index = entries[*index].block;
+        let leaf = self.resolve_block_index(dir, leaf_index)?.ok_or(Error::CorruptedDirectory)?;
+
+        let seed = self.superblock.hash_seed();
+        let mut leaf_entries = self.read_dir_block(dir, leaf_index)?
+            .into_iter()
+            .map(|e| (hash_version.hash(e.name_bytes(), seed), e))
+            .collect::<Vec<_>>();
+        if leaf_entries.len() < 2 {
+            return Ok(false);
+        }
+        leaf_entries.sort_by_key(|(hash, _)| *hash);
+
+        // split the entries by their size, not by their number
+        let sizes = leaf_entries.iter()
+            .map(|(_, e)| DirEntry::size(e.name_bytes().len() as u16) as usize)
+            .collect::<Vec<_>>();
+        let half = sizes.iter().sum::<usize>() / 2;
+        let mut split = 0;
+        let mut size = 0;
+        while split < sizes.len() - 1 && size + sizes[split] <= half {
+            size += sizes[split];
+            split += 1;
+        }
+        let split = split.max(1);
+
+        // if names with the same hash end up in both blocks, the lowest bit of the
+        // hash in the index marks that the previous block has to be searched as well
+        let split_hash = leaf_entries[split].0.0;
+        let continued = leaf_entries[split - 1].0.0 == split_hash;
+        let upper = leaf_entries.split_off(split);
+
+        let new_leaf_index = (dir.len() / self.superblock.block_size() as u64) as u32;
+        let new_leaf = self.append_dir_blocks(dir, 1)?;
+        let lower_data = self.serialize_dir_block(leaf_entries.into_iter().map(|(_, e)| e).collect());
+        let upper_data = self.serialize_dir_block(upper.into_iter().map(|(_, e)| e).collect());
+        self.write_block(leaf, &lower_data)?;
+        self.write_block(new_leaf, &upper_data)?;
+
+        let mut entries = entries.clone();
+        entries.insert(index + 1, DxEntry {
+            hash: split_hash | continued as u32,
+            block: new_leaf_index,
+        });
+        self.write_dx_parent(dir, path, path.len() - 1, &entries)?;
+        Ok(true)
+    }
+
+    /// Moves the upper half of the entries of the interior index block in the given path to
+    /// a new index block, and adds the new block to the root, which must have a free slot.
+    fn split_dx_node(&mut self, dir: &mut Directory, path: &DxPath) -> Result<(), Error> {
+        let (root_entries, root_index) = &path[0];
+        let node_index = root_entries[*root_index].block;
+
+        let mut lower = path[1].0.clone();
+        let upper = lower.split_off(lower.len() / 2);
+        let new_node_index = (dir.len() / self.superblock.block_size() as u64) as u32;
+        self.append_dir_blocks(dir, 1)?;
+        self.write_dx_node(dir, node_index, &lower)?;
+        self.write_dx_node(dir, new_node_index, &upper)?;
+
+        let mut root_entries = root_entries.clone();
+        root_entries.insert(root_index + 1, DxEntry {
+            hash: upper[0].hash,
+            block: new_node_index,
+        });
+        self.write_dx_root(dir, 1, &root_entries)
+    }
+
+    /// Moves all entries of the root to a new interior index block, which becomes
+    /// the only entry of the root.
+    fn add_dx_level(&mut self, dir: &mut Directory, root_entries: &[DxEntry]) -> Result<(), Error> {
+        let new_node_index = (dir.len() / self.superblock.block_size() as u64) as u32;
+        self.append_dir_blocks(dir, 1)?;
+        self.write_dx_node(dir, new_node_index, root_entries)?;
+        self.write_dx_root(dir, 1, &[DxEntry { hash: 0, block: new_node_index }])
+    }
+
+    /// Writes the given entries to the index block at the given level of the given path.
+    fn write_dx_parent(&mut self, dir: &Inode, path: &DxPath, level: usize, entries: &[DxEntry]) -> Result<(), Error> {
+        if level == 0 {
+            self.write_dx_root(dir, (path.len() - 1) as u8, entries)
+        } else {
+            let (parent_entries, parent_index) = &path[level - 1];
+            self.write_dx_node(dir, parent_entries[*parent_index].block, entries)
+        }
+    }
+
+    /// Writes the given entries and number of indirect levels to the root of the index.
+    fn write_dx_root(&mut self, dir: &Inode, indirect_levels: u8, entries: &[DxEntry]) -> Result<(), Error> {
+        let block = self.resolve_block_index(dir, 0)?.ok_or(Error::CorruptedDirectory)?;
+        let mut data = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(block, &mut data)?;
+        data[ROOT_INFO_OFFSET + 6] = indirect_levels;
+        encode_dx_entries(&mut data, ROOT_ENTRIES_OFFSET, entries);
+        self.write_block(block, &data)?;
+        Ok(())
+    }
+
+    /// Writes the given entries to the interior index block with the given index.
+    fn write_dx_node(&mut self, dir: &Inode, block_index: u32, entries: &[DxEntry]) -> Result<(), Error> {
+        let block = self.resolve_block_index(dir, block_index)?.ok_or(Error::CorruptedDirectory)?;
+        let block_size = self.superblock.block_size() as usize;
+
+        // interior index blocks start with an unused entry that spans the whole block
+        let mut data = vec![0_u8; block_size];
+        data[4..6].copy_from_slice(&(block_size as u16).to_le_bytes());
+        encode_dx_entries(&mut data, NODE_ENTRIES_OFFSET, entries);
+        self.write_block(block, &data)?;
+        Ok(())
+    }
+
+    /// Reads the root of the index of the given directory. Returns `None` if the
+    /// directory is not hash indexed, or if the root is not valid.
+    pub(crate) fn read_dx_root(&self, dir: &Inode) -> Result<Option<DxRoot>, Error> {
+        if !self.is_indexed_dir(dir) {
+            return Ok(None);
+        }
+        let Some(block) = self.resolve_block_index(dir, 0)? else {
+            return Ok(None);
+        };
+
+        let block_size = self.superblock.block_size() as usize;
+        let mut data = vec![0_u8; block_size];
+        self.read_block(block, &mut data)?;
+
+        // the first block starts with `.` (12 bytes), followed by `..`, which spans the rest of the block
+        let dot_dot_len = u16::from_le_bytes([data[16], data[17]]) as usize;
+        if &data[8..10] != b".\0" || &data[20..23] != b"..\0" || dot_dot_len != block_size - 12 {
+            return Ok(None);
+        }
+
+        let info = &data[ROOT_INFO_OFFSET..ROOT_ENTRIES_OFFSET];
+        let reserved_zero = u32::from_le_bytes(info[0..4].try_into().unwrap());
+        let unsigned = self.superblock.flags().contains(SuperblockFlags::UNSIGNED_HASH);
+        let hash_version = HashVersion::from_raw(info[4], unsigned);
+        let info_length = info[5];
+        let indirect_levels = info[6];
+        let Some(hash_version) = hash_version else {
+            return Ok(None);
+        };
+        if reserved_zero != 0 || info_length != ROOT_INFO_LEN || indirect_levels > MAX_INDIRECT_LEVELS {
+            return Ok(None);
+        }
+
+        Ok(parse_dx_entries(&data, ROOT_ENTRIES_OFFSET).map(|entries| DxRoot {
+            hash_version,
+            indirect_levels,
+            entries,
+        }))
+    }
+
+    /// Reads the entries of the interior index block with the given index in the given
+    /// directory. Returns `None` if the block is not a valid index block.
+    pub(crate) fn read_dx_node(&self, dir: &Inode, block_index: u32) -> Result<Option<Vec<DxEntry>>, Error> {
+        let Some(block) = self.resolve_block_index(dir, block_index)? else {
+            return Ok(None);
+        };
+
+        let block_size = self.superblock.block_size() as usize;
+        let mut data = vec![0_u8; block_size];
+        self.read_block(block, &mut data)?;
+
+        // interior index blocks start with an unused entry that spans the whole block
+        let inode = u32::from_le_bytes(data[0..4].try_into().unwrap());
+        let rec_len = u16::from_le_bytes([data[4], data[5]]) as usize;
+        if inode != 0 || rec_len != block_size {
+            return Ok(None);
+        }
+
+        Ok(parse_dx_entries(&data, NODE_ENTRIES_OFFSET))
+    }
+}
+
+/// Writes the given index entries to the given offset in the given block, including the
+/// limit and the count, and clears the unused slots.
+fn encode_dx_entries(data: &mut [u8], offset: usize, entries: &[DxEntry]) {
+    let limit = (data.len() - offset) / ENTRY_SIZE;
+    debug_assert!(entries.len() <= limit, "too many index entries");
+
+    data[offset..].fill(0);
+    for (i, entry) in entries.iter().enumerate() {
+        let slot = &mut data[offset + i * ENTRY_SIZE..offset + (i + 1) * ENTRY_SIZE];
+        if i == 0 {
+            slot[0..2].copy_from_slice(&(limit as u16).to_le_bytes());
+            slot[2..4].copy_from_slice(&(entries.len() as u16).to_le_bytes());
+        } else {
+            slot[0..4].copy_from_slice(&entry.hash.to_le_bytes());
+        }
+        slot[4..8].copy_from_slice(&entry.block.to_le_bytes());
+    }
+}
+
+/// Parses the index entries that start at the given offset in the given block. The
+/// first entry doesn't have a hash, its place is taken by the limit and the count
+/// of the entries. Returns `None` if the entries are not valid.
+fn parse_dx_entries(data: &[u8], offset: usize) -> Option<Vec<DxEntry>> {
+    let limit = u16::from_le_bytes([data[offset], data[offset + 1]]) as usize;
+    let count = u16::from_le_bytes([data[offset + 2], data[offset + 3]]) as usize;
+    if limit != (data.len() - offset) / ENTRY_SIZE || count == 0 || count > limit {
+        return None;
+    }
+
+    let entries = data[offset..offset + count * ENTRY_SIZE]
+        .chunks_exact(ENTRY_SIZE)
+        .enumerate()
+        .map(|(i, entry)| DxEntry {
+            hash: if i == 0 { 0 } else { u32::from_le_bytes(entry[0..4].try_into().unwrap()) },
+            block: u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+        })
+        .collect::<Vec<_>>();
+
+    // the entries must be sorted by their hashes, otherwise the lookup doesn't work
+    entries.is_sorted_by_key(|e| e.hash).then_some(entries)
+}
@@ -1,3 +1,5 @@
+use alloc::vec;
+use alloc::vec::Vec;
 use core::ops::{Deref, DerefMut};
 
 use bitflags::bitflags;
@@ -101,8 +103,11 @@ impl DerefMut for InodeRawArray {
 }
 
 bytefield! {
+    /// The fields of an inode that are present in every revision, which are
+    /// the first 128 bytes of an inode.
     #[derive(Debug)]
-    pub struct Inode (InodeRawArray) {
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct InodeBase (InodeRawArray) {
         type_and_perm: u16 = 0,
         user_id: u16 = 2,
         byte_size_lower: u32 = 4,
@@ -127,11 +132,194 @@ bytefield! {
     }
 }
 
+/// The size of the fields that every inode has.
+pub(crate) const GOOD_OLD_INODE_SIZE: usize = 128;
+
+/// The offsets of the extra fields, relative to the end of the first 128 bytes.
+const EXTRA_ISIZE_OFFSET: usize = 0;
+const CHANGE_TIME_EXTRA_OFFSET: usize = 4;
+const MODIFICATION_TIME_EXTRA_OFFSET: usize = 8;
+const ACCESS_TIME_EXTRA_OFFSET: usize = 12;
+const BIRTH_TIME_OFFSET: usize = 16;
+const BIRTH_TIME_EXTRA_OFFSET: usize = 20;
+
+/// The offsets of the upper halves of the user and group id in the second OS dependent value.
+const UID_HIGH_OFFSET: usize = 4;
+const GID_HIGH_OFFSET: usize = 6;
+
+/// The extra fields that this crate knows about. New inodes reserve this much
+/// space, unless the superblock asks for more.
+pub(crate) const DEFAULT_EXTRA_ISIZE: u16 = 32;
+
+/// An inode. Inodes that are larger than 128 bytes have an extra area, which starts
+/// with the size of the extra fields (`i_extra_isize`), followed by the extra fields
+/// and in-inode extended attributes. The extra area is kept as it was read, so that
+/// fields that this crate doesn't know about survive when the inode is written back.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Inode {
+    base: InodeBase,
+    extra: Vec<u8>,
+}
+
+impl Deref for Inode {
+    type Target = InodeBase;
+
+    fn deref(&self) -> &Self::Target {
+        &self.base
+    }
+}
+
+impl DerefMut for Inode {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.base
+    }
+}
+
+/// A point in time with nanosecond precision, as stored in the extra fields of
+/// large inodes. Without extra fields, timestamps only have a precision of seconds,
+/// and can't be after 2038.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Timestamp {
+    /// The seconds since the unix epoch.
+    pub seconds: i64,
+    pub nanoseconds: u32,
+}
+
+impl Timestamp {
+    /// Decodes a timestamp from the 32 bit seconds field and its extra field, which
+    /// contains two more bits for the seconds and the nanoseconds.
+    fn decode(seconds: u32, extra: u32) -> Self {
+        Self {
+            seconds: seconds as i32 as i64 + (((extra & 0b11) as i64) << 32),
+            nanoseconds: extra >> 2,
+        }
+    }
+
+    /// Encodes the timestamp into the 32 bit seconds field and its extra field.
+    fn encode(self) -> (u32, u32) {
+        let epoch = ((self.seconds - self.seconds as i32 as i64) >> 32) as u32 & 0b11;
+        (self.seconds as u32, epoch | (self.nanoseconds << 2))
+    }
+}
+
 impl Inode {
+    /// Reads an inode from the given bytes, which must be at least 128 bytes long.
+    /// Everything after the first 128 bytes is the extra area.
+    pub(crate) fn from_bytes(data: &[u8]) -> Self {
+        let base: [u8; GOOD_OLD_INODE_SIZE] = data[..GOOD_OLD_INODE_SIZE].try_into().unwrap();
+        Self {
+            base: InodeBase::try_from(InodeRawArray::from(base)).expect("inode conversion can't fail. if it does, the logic has changed and this should propagate the error"),
+            extra: data[GOOD_OLD_INODE_SIZE..].to_vec(),
+        }
+    }
+
+    /// Serializes the inode into the given buffer, which has the size of an on-disk
+    /// inode. The extra area is truncated or padded with zeros to fit.
+    pub(crate) fn to_bytes(&self, buf: &mut [u8]) {
+        buf[..GOOD_OLD_INODE_SIZE].copy_from_slice(InodeRawArray::from(&self.base).as_slice());
+        let extra = &mut buf[GOOD_OLD_INODE_SIZE..];
+        let len = extra.len().min(self.extra.len());
+        extra[..len].copy_from_slice(&self.extra[..len]);
+        extra[len..].fill(0);
+    }
+
+    /// Initializes the extra area of a new inode, for an on-disk inode size of
+    /// `inode_size`, with extra fields of the given size.
+    pub(crate) fn init_extra(&mut self, inode_size: usize, extra_isize: u16) {
+        self.extra = vec![0; inode_size.saturating_sub(GOOD_OLD_INODE_SIZE)];
+        if self.extra.len() >= 4 {
+            let extra_isize = extra_isize.min(self.extra.len() as u16) & !3;
+            self.extra[EXTRA_ISIZE_OFFSET..EXTRA_ISIZE_OFFSET + 2].copy_from_slice(&extra_isize.to_le_bytes());
+        }
+    }
+
+    /// The size of the extra fields, which is `0` if the inode has no extra area.
+    pub fn extra_isize(&self) -> u16 {
+        match self.extra.get(EXTRA_ISIZE_OFFSET..EXTRA_ISIZE_OFFSET + 2) {
+            Some(bytes) => u16::from_le_bytes(bytes.try_into().unwrap()).min(self.extra.len() as u16),
+            None => 0,
+        }
+    }
+
+    /// The space after the extra fields, which holds the in-inode extended attributes.
+    pub(crate) fn xattr_area(&self) -> &[u8] {
+        &self.extra[self.extra_isize() as usize..]
+    }
+
+    pub(crate) fn xattr_area_mut(&mut self) -> &mut [u8] {
+        let extra_isize = self.extra_isize() as usize;
+        &mut self.extra[extra_isize..]
+    }
+
+    /// Returns the extra field at the given offset, if the extra fields of the inode include it.
+    fn extra_field(&self, offset: usize) -> Option<u32> {
+        if offset + 4 > self.extra_isize() as usize {
+            return None;
+        }
+        Some(u32::from_le_bytes(self.extra[offset..offset + 4].try_into().unwrap()))
+    }
+
+    fn set_extra_field(&mut self, offset: usize, value: u32) {
+        if offset + 4 <= self.extra_isize() as usize {
+            self.extra[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    pub fn last_access_timestamp(&self) -> Timestamp {
+        Timestamp::decode(self.last_access_time, self.extra_field(ACCESS_TIME_EXTRA_OFFSET).unwrap_or(0))
+    }
+
+    /// Sets the last access time. The nanoseconds and the time after 2038 are
+    /// only stored if the inode has the extra fields for them.
+    pub fn set_last_access_timestamp(&mut self, timestamp: Timestamp) {
+        let (seconds, extra) = timestamp.encode();
+        self.last_access_time = seconds;
+        self.set_extra_field(ACCESS_TIME_EXTRA_OFFSET, extra);
+    }
+
+    pub fn last_modification_timestamp(&self) -> Timestamp {
+        Timestamp::decode(self.last_modification_time, self.extra_field(MODIFICATION_TIME_EXTRA_OFFSET).unwrap_or(0))
+    }
+
+    /// Sets the last modification time. The nanoseconds and the time after 2038
+    /// are only stored if the inode has the extra fields for them.
+    pub fn set_last_modification_timestamp(&mut self, timestamp: Timestamp) {
+        let (seconds, extra) = timestamp.encode();
+        self.last_modification_time = seconds;
+        self.set_extra_field(MODIFICATION_TIME_EXTRA_OFFSET, extra);
+    }
+
+    pub fn creation_timestamp(&self) -> Timestamp {
+        Timestamp::decode(self.creation_time, self.extra_field(CHANGE_TIME_EXTRA_OFFSET).unwrap_or(0))
+    }
+
+    /// Sets the time that is stored in the `creation_time` field. The nanoseconds and
+    /// the time after 2038 are only stored if the inode has the extra fields for them.
+    pub fn set_creation_timestamp(&mut self, timestamp: Timestamp) {
+        let (seconds, extra) = timestamp.encode();
+        self.creation_time = seconds;
+        self.set_extra_field(CHANGE_TIME_EXTRA_OFFSET, extra);
+    }
+
+    /// The time when the inode was created, which is only stored in the extra fields.
+    pub fn birth_timestamp(&self) -> Option<Timestamp> {
+        let seconds = self.extra_field(BIRTH_TIME_OFFSET)?;
+        Some(Timestamp::decode(seconds, self.extra_field(BIRTH_TIME_EXTRA_OFFSET).unwrap_or(0)))
+    }
+
+    /// Sets the time when the inode was created. Does nothing if the inode
+    /// doesn't have the extra fields for it.
+    pub fn set_birth_timestamp(&mut self, timestamp: Timestamp) {
+        let (seconds, extra) = timestamp.encode();
+        self.set_extra_field(BIRTH_TIME_OFFSET, seconds);
+        self.set_extra_field(BIRTH_TIME_EXTRA_OFFSET, extra);
+    }
+
     /// Creates a new inode with the given type.
     /// All other fields are uninitialized (zeroed).
     pub fn new(typ: Type) -> Self {
-        Self {
+        let base = InodeBase {
             type_and_perm: typ.bits(),
             user_id: 0,
             byte_size_lower: 0,
@@ -153,6 +341,10 @@ impl Inode {
             byte_size_upper_or_dir_acl: 0,
             fragment_block_address: 0,
             os_val_2: [0; 12],
+        };
+        Self {
+            base,
+            extra: Vec::new(),
         }
     }
 
@@ -160,20 +352,49 @@ impl Inode {
         Type::from_bits_truncate(self.type_and_perm)
     }
 
+    /// Changes the type of the inode, without converting its contents.
+    pub fn set_typ(&mut self, typ: Type) {
+        self.type_and_perm = typ.bits() | self.perm().bits();
+    }
+
     pub fn perm(&self) -> Permissions {
         Permissions::from_bits_truncate(self.type_and_perm)
     }
 
+    pub fn set_perm(&mut self, perm: Permissions) {
+        self.type_and_perm = self.typ().bits() | perm.bits();
+    }
+
     pub fn flags(&self) -> Flags {
-        Flags::from_bits_truncate(self.flags)
+        // keep unknown flags, so that they survive when the flags are written back
+        Flags::from_bits_retain(self.flags)
+    }
+
+    pub fn set_flags(&mut self, flags: Flags) {
+        self.flags = flags.bits();
     }
 
     pub fn set_file_size_lower(&mut self, size: u32) {
         self.byte_size_lower = size;
     }
 
+    /// Sets the upper 32 bits of the size. For directories, the field holds the
+    /// directory ACL of old revisions instead, which is not used, since ACLs are
+    /// stored in extended attributes. It must stay `0`, so this does nothing.
     pub fn set_file_size_upper(&mut self, size: u32) {
-        self.byte_size_upper_or_dir_acl = size;
+        if self.typ() != Type::Directory {
+            self.byte_size_upper_or_dir_acl = size;
+        }
+    }
+
+    /// The time in seconds since the unix epoch at which the inode was deleted,
+    /// or zero if the inode is in use.
+    pub fn deletion_time(&self) -> u32 {
+        self.deletion_time
+    }
+
+    pub fn deletion_time_mut(&mut self) -> &mut u32 {
+        &mut self.deletion_time
     }
 
     pub fn num_disk_sectors(&self) -> u32 {
@@ -192,6 +413,47 @@ impl Inode {
         &mut self.num_hard_links
     }
 
+    /// The owner of the inode, including the upper 16 bits that Linux stores in the
+    /// OS dependent values.
+    pub fn user_id(&self) -> u32 {
+        self.user_id as u32 | (u16::from_le_bytes([self.os_val_2[UID_HIGH_OFFSET], self.os_val_2[UID_HIGH_OFFSET + 1]]) as u32) << 16
+    }
+
+    pub fn set_user_id(&mut self, user_id: u32) {
+        self.user_id = user_id as u16;
+        self.os_val_2[UID_HIGH_OFFSET..UID_HIGH_OFFSET + 2].copy_from_slice(&((user_id >> 16) as u16).to_le_bytes());
+    }
+
+    /// The group of the inode, including the upper 16 bits that Linux stores in the
+    /// OS dependent values.
+    pub fn group_id(&self) -> u32 {
+        self.group_id as u32 | (u16::from_le_bytes([self.os_val_2[GID_HIGH_OFFSET], self.os_val_2[GID_HIGH_OFFSET + 1]]) as u32) << 16
+    }
+
+    pub fn set_group_id(&mut self, group_id: u32) {
+        self.group_id = group_id as u16;
+        self.os_val_2[GID_HIGH_OFFSET..GID_HIGH_OFFSET + 2].copy_from_slice(&((group_id >> 16) as u16).to_le_bytes());
+    }
+
+    /// The version of the file, as used by NFS.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn generation_mut(&mut self) -> &mut u32 {
+        &mut self.generation
+    }
+
+    pub fn fragment_block_address(&self) -> u32 {
+        self.fragment_block_address
+    }
+
+    /// The raw values whose meaning depends on the operating system that created
+    /// the file system.
+    pub fn os_dependent_values(&self) -> (&[u8; 4], &[u8; 12]) {
+        (&self.os_val_1, &self.os_val_2)
+    }
+
     pub fn last_access_time(&self) -> u32 {
         self.last_access_time
     }
@@ -228,19 +490,68 @@ impl Inode {
         BlockAddress::new(self.singly_indirect_block_ptr)
     }
 
+    pub fn set_single_indirect_ptr(&mut self, ptr: Option<BlockAddress>) {
+        self.singly_indirect_block_ptr = ptr.map_or(0, |v| v.into_u32());
+    }
+
     pub fn double_indirect_ptr(&self) -> Option<BlockAddress> {
         BlockAddress::new(self.doubly_indirect_block_ptr)
     }
 
+    pub fn set_double_indirect_ptr(&mut self, ptr: Option<BlockAddress>) {
+        self.doubly_indirect_block_ptr = ptr.map_or(0, |v| v.into_u32());
+    }
+
     pub fn triple_indirect_ptr(&self) -> Option<BlockAddress> {
         BlockAddress::new(self.triply_indirect_block_ptr)
     }
 
-    pub fn len(&self) -> usize {
+    pub fn set_triple_indirect_ptr(&mut self, ptr: Option<BlockAddress>) {
+        self.triply_indirect_block_ptr = ptr.map_or(0, |v| v.into_u32());
+    }
+
+    pub fn extended_attribute_block(&self) -> Option<BlockAddress> {
+        BlockAddress::new(self.extended_attribute_block)
+    }
+
+    pub fn set_extended_attribute_block(&mut self, block: Option<BlockAddress>) {
+        self.extended_attribute_block = block.map_or(0, |v| v.into_u32());
+    }
+
+    /// Returns the raw bytes of all block pointers (direct and indirect).
+    /// Fast symlinks store their target in these bytes instead of using
+    /// data blocks.
+    pub(crate) fn block_ptr_bytes(&self) -> [u8; 60] {
+        let mut result = [0_u8; 60];
+        self.direct_block_ptr.iter()
+            .chain([self.singly_indirect_block_ptr, self.doubly_indirect_block_ptr, self.triply_indirect_block_ptr].iter())
+            .zip(result.chunks_exact_mut(4))
+            .for_each(|(ptr, chunk)| chunk.copy_from_slice(&ptr.to_le_bytes()));
+        result
+    }
+
+    pub(crate) fn set_block_ptr_bytes(&mut self, bytes: [u8; 60]) {
+        let mut ptrs = bytes.chunks_exact(4).map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()));
+        self.direct_block_ptr.iter_mut().for_each(|ptr| *ptr = ptrs.next().unwrap());
+        self.singly_indirect_block_ptr = ptrs.next().unwrap();
+        self.doubly_indirect_block_ptr = ptrs.next().unwrap();
+        self.triply_indirect_block_ptr = ptrs.next().unwrap();
+    }
+
+    pub fn len(&self) -> u64 {
         if self.typ() == Type::Directory {
-            self.byte_size_lower as usize
+            self.byte_size_lower as u64
         } else {
-            self.byte_size_lower as usize | ((self.byte_size_upper_or_dir_acl as usize) << 32)
+            self.byte_size_lower as u64 | ((self.byte_size_upper_or_dir_acl as u64) << 32)
+        }
+    }
+
+    /// Sets the size of the inode. For directories, only the lower 32 bits
+    /// are stored, since the upper half of the size field holds the directory ACL.
+    pub fn set_len(&mut self, len: u64) {
+        self.byte_size_lower = len as u32;
+        if self.typ() != Type::Directory {
+            self.byte_size_upper_or_dir_acl = (len >> 32) as u32;
         }
     }
 
@@ -263,6 +574,7 @@ bitflags! {
 }
 
 bitflags! {
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
     pub struct Permissions: u16 {
         const OtherExec = 0x001;
         const OtherWrite = 0x002;
@@ -289,8 +601,8 @@ bitflags! {
         const AppendOnly = 0x00000020;
         const ExcludeFromDump = 0x00000040;
         const KeepLastAccessedTime = 0x00000080;
-        const HashIndexedDirectory = 0x00010000;
-        const AfsDirectory = 0x00020000;
-        const JournalFileData = 0x00040000;
+        const HashIndexedDirectory = 0x00001000;
+        const AfsDirectory = 0x00002000;
+        const JournalFileData = 0x00004000;
     }
 }
@@ -1,40 +1,90 @@
 #![no_std]
-#![feature(const_option)]
 #![feature(iter_array_chunks)]
 
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
+use alloc::collections::BTreeMap;
 use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
 
+pub use acl::*;
 pub use address::*;
+pub use block_group::BlockGroupDescriptor;
+pub use check::{BlockOwner, Finding};
 pub use dir::*;
+pub use dirhash::HashVersion;
 pub use error::*;
+pub use file::*;
+pub use format::*;
 use filesystem::BlockDevice;
 pub use inode::*;
+pub use repair::Repair;
+pub use reverse::{BlockRef, BlockUse};
 pub use superblock::*;
+pub use tune::{Feature, TuneOptions};
 
-use crate::block_group::{BlockGroupDescriptor, BlockGroupDescriptorTable};
+use crate::block_group::BlockGroupDescriptorTable;
 
+mod acl;
 mod address;
 mod block_group;
 mod bytefield;
+mod check;
 mod create;
 mod dir;
+mod dirhash;
 mod error;
+mod file;
+mod format;
+mod htree;
 mod inode;
+mod mount;
+mod path;
+mod prealloc;
 mod read;
+mod remove;
+mod repair;
+mod resize;
+mod reverse;
+mod sparse;
 mod superblock;
+mod symlink;
+mod tune;
 mod write;
+mod xattr;
 
 const ROOT_DIR_INODE_ADDRESS: InodeAddress = InodeAddress::new(2).unwrap();
 
 /// An ext2 filesystem over a block device.
+///
+/// All changes are written to the device right away, so dropping the file system
+/// without [`Ext2Fs::sync`] doesn't lose data or leak blocks. The first change mounts
+/// the file system for writing, if it isn't already, and it stays marked as not clean
+/// on disk until it is unmounted with [`Ext2Fs::unmount`].
 pub struct Ext2Fs<T> {
     block_device: T,
     superblock: Superblock,
     bgdt: BlockGroupDescriptorTable,
+    clock: Clock,
+    mount_state: Option<State>,
+    skip_zero_blocks: bool,
+    /// Blocks that are reserved for regular files according to the preallocation
+    /// hint in the superblock, but not used yet. They are still free on disk, and
+    /// the reservations are dropped on sync.
+    preallocated_blocks: BTreeMap<InodeAddress, Vec<BlockAddress>>,
+    /// Extended attribute blocks that were read or written, by their hash, so that
+    /// inodes with the same attributes can share a block.
+    xattr_block_cache: BTreeMap<u32, Vec<BlockAddress>>,
 }
 
+/// A source for the current time, in seconds since the unix epoch.
+/// `no_std` environments don't have a clock, so the time has to be provided
+/// by the user of this crate.
+pub type Clock = fn() -> u32;
+
 const SUPERBLOCK_OFFSET: usize = 1024;
 const BGD_SIZE: usize = 32; // 32 bytes per block group descriptor
 
@@ -49,12 +99,17 @@ where
             .map_err(|_| Error::UnableToReadSuperblock)?;
 
         let superblock = Superblock::try_from(SuperblockArray::from(superblock_data)).unwrap();
-        let number_of_block_groups = (superblock.num_blocks() + superblock.blocks_per_group() - 1)
-            / superblock.blocks_per_group();
+        superblock.validate()?;
+        let number_of_block_groups = superblock.num_blocks()
+            .checked_sub(superblock.superblock_block_number())
+            .filter(|&num_blocks| num_blocks > 0)
+            .ok_or(Error::InvalidSuperblock)?
+            .div_ceil(superblock.blocks_per_group());
 
         let bgdt_offset = if superblock.block_size() == 1024 { 2048 } else { superblock.block_size() } as usize;
 
-        let mut bgdt_data = vec![0_u8; superblock.block_size() as usize];
+        let bgdt_len = (number_of_block_groups as usize * BGD_SIZE).next_multiple_of(superblock.block_size() as usize);
+        let mut bgdt_data = vec![0_u8; bgdt_len];
         block_device
             .read_at(bgdt_offset, &mut bgdt_data)
             .map_err(|_| Error::UnableToReadBlockGroupDescriptorTable)?;
@@ -71,9 +126,31 @@ where
             block_device,
             superblock,
             bgdt,
+            clock: || 0,
+            mount_state: None,
+            skip_zero_blocks: false,
+            preallocated_blocks: BTreeMap::new(),
+            xattr_block_cache: BTreeMap::new(),
         })
     }
 
+    /// Sets the clock that is used for timestamps that are written to the
+    /// file system. Without a clock, all timestamps are `0`.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// If enabled, writes of blocks that consist only of zeros don't allocate
+    /// a block, but leave a hole in the file instead. Blocks that are already
+    /// allocated are still overwritten. Disabled by default.
+    pub fn set_skip_zero_blocks(&mut self, skip_zero_blocks: bool) {
+        self.skip_zero_blocks = skip_zero_blocks;
+    }
+
+    pub(crate) fn now(&self) -> u32 {
+        (self.clock)()
+    }
+
     fn bgdt_offset(&self) -> usize {
         let block_size = self.superblock.block_size() as usize;
         if block_size == 1024 {
@@ -83,10 +160,18 @@ where
         }
     }
 
+    pub fn block_device(&self) -> &T {
+        &self.block_device
+    }
+
     pub fn superblock(&self) -> &Superblock {
         &self.superblock
     }
 
+    pub fn block_group_descriptors(&self) -> &[BlockGroupDescriptor] {
+        &self.bgdt
+    }
+
     pub fn read_root_inode(&self) -> Result<Directory, Error> {
         self.read_inode(ROOT_DIR_INODE_ADDRESS)
             .and_then(|inode| Directory::try_from(inode).map_err(|_| Error::NotDirectory))
@@ -94,81 +179,233 @@ where
 
     pub fn read_inode(&self, addr: InodeAddress) -> Result<(InodeAddress, Inode), Error> {
         // FIXME: reading the inode will create multiple copies that alias the data in the block device, there needs to be some kind of cache that centralizes the inodes (and their data)
-        let inodes_per_group = self.superblock.inodes_per_group();
-        let block_group_index = (addr.get() - 1) / inodes_per_group;
-        let block_group = &self.bgdt[block_group_index as usize];
-        let itable_start_block =
-            BlockAddress::new(block_group.inode_table_starting_block()).unwrap();
-
-        let index = (addr.get() - 1) % inodes_per_group;
+        let address = self.resolve_inode_offset(addr)?;
         let inode_size = self.superblock.inode_size();
-        let address =
-            self.resolve_block_offset(itable_start_block) + (index * inode_size as u32) as usize;
 
-        let mut inode_buffer = [0_u8; 128]; // inode size can vary, but the specified fields are always between 0 and 128, and we don't need more
+        // the extra area after the first 128 bytes is read as well, so that it can be written back unchanged
+        let mut inode_buffer = vec![0_u8; inode_size as usize];
         self.block_device
             .read_at(address, &mut inode_buffer)
             .map_err(|_| Error::DeviceRead)?;
 
-        Ok((addr, Inode::try_from(InodeRawArray::from(inode_buffer)).expect("inode conversion can't fail. if it does, the logic has changed and this should propagate the error")))
+        Ok((addr, Inode::from_bytes(&inode_buffer)))
     }
 
     pub fn write_inode(&mut self, addr: InodeAddress, inode: &Inode) -> Result<(), Error> {
-        let inodes_per_group = self.superblock.inodes_per_group();
-        let block_group_index = (addr.get() - 1) / inodes_per_group;
-        let block_group = &self.bgdt[block_group_index as usize];
-        let itable_start_block =
-            BlockAddress::new(block_group.inode_table_starting_block()).unwrap();
-
-        let index = (addr.get() - 1) % inodes_per_group;
+        let address = self.resolve_inode_offset(addr)?;
+        self.mark_dirty()?;
         let inode_size = self.superblock.inode_size();
-        let address =
-            self.resolve_block_offset(itable_start_block) + (index * inode_size as u32) as usize;
 
-        let inode_raw = InodeRawArray::from(inode);
+        let mut inode_buffer = vec![0_u8; inode_size as usize];
+        inode.to_bytes(&mut inode_buffer);
         self.block_device
-            .write_at(address, &inode_raw.as_slice())
+            .write_at(address, &inode_buffer)
             .map_err(|_| Error::DeviceWrite)
             .map(|_| ())
     }
 
+    /// Writes the in-memory copy of the superblock to the device.
+    /// Only the fields known to [`Superblock`] are written, all other
+    /// bytes of the on-disk superblock are preserved.
+    pub(crate) fn write_superblock(&mut self) -> Result<(), Error> {
+        let mut superblock_data = [0_u8; 1024];
+        self.block_device
+            .read_at(SUPERBLOCK_OFFSET, &mut superblock_data)
+            .map_err(|_| Error::UnableToReadSuperblock)?;
+        // merge the known fields into the on-disk superblock
+        let known = Into::<SuperblockArray>::into(&self.superblock);
+        superblock_data[..Superblock::LEN].copy_from_slice(&known[..Superblock::LEN]);
+        self.block_device
+            .write_at(SUPERBLOCK_OFFSET, &superblock_data)
+            .map_err(|_| Error::UnableToWriteSuperblock)
+            .map(|_| ())
+    }
+
+    /// Writes the in-memory copy of the superblock to the device, and to every group
+    /// that has a copy of it. The copies of revision 1 file systems store the index of
+    /// their group, otherwise they are the same as the primary superblock.
+    pub(crate) fn write_superblock_copies(&mut self) -> Result<(), Error> {
+        self.write_superblock()?;
+        let mut superblock_data = [0_u8; 1024];
+        self.block_device
+            .read_at(SUPERBLOCK_OFFSET, &mut superblock_data)
+            .map_err(|_| Error::UnableToReadSuperblock)?;
+
+        let block_size = self.superblock.block_size() as usize;
+        let first_data_block = self.superblock.superblock_block_number();
+        let blocks_per_group = self.superblock.blocks_per_group();
+        for group_index in (1..self.bgdt.len() as u32).filter(|&group| self.superblock.group_has_superblock(group)) {
+            let mut copy = self.superblock.clone();
+            copy.set_this_superblock_block_group(group_index as u16);
+            let known = Into::<SuperblockArray>::into(&copy);
+            superblock_data[..Superblock::LEN].copy_from_slice(&known[..Superblock::LEN]);
+            let group_start = (first_data_block + group_index * blocks_per_group) as usize;
+            self.block_device
+                .write_at(group_start * block_size, &superblock_data)
+                .map_err(|_| Error::UnableToWriteSuperblock)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the in-memory copy of the block group descriptor with the
+    /// given index to the device.
+    pub(crate) fn write_block_group_descriptor(&mut self, group_index: usize) -> Result<(), Error> {
+        let block_size = self.superblock.block_size() as usize;
+        // the block of the table that contains the descriptor
+        let bgdt_offset = self.bgdt_offset() + group_index * BGD_SIZE / block_size * block_size;
+
+        // read the block group descriptor table
+        let mut bgdt_data = vec![0_u8; block_size];
+        self.block_device
+            .read_at(bgdt_offset, &mut bgdt_data)
+            .map_err(|_| Error::UnableToReadBlockGroupDescriptorTable)?;
+        // merge the changed descriptor back into the table
+        let bgd_offset = group_index * BGD_SIZE % block_size;
+        let bgd_end = bgd_offset + BGD_SIZE;
+        let bgd_data = Into::<[u8; BGD_SIZE]>::into(&self.bgdt[group_index]);
+        bgdt_data[bgd_offset..bgd_end].copy_from_slice(&bgd_data);
+        // write the block group descriptor table back
+        self.block_device
+            .write_at(bgdt_offset, &bgdt_data)
+            .map_err(|_| Error::UnableToWriteBlockGroupDescriptorTable)
+            .map(|_| ())
+    }
+
     pub fn read_block(&self, addr: BlockAddress, buf: &mut [u8]) -> Result<usize, Error> {
-        let offset = self.resolve_block_offset(addr);
+        let offset = self.resolve_block_offset(addr)?;
         self.block_device
             .read_at(offset, buf)
             .map_err(|_| Error::DeviceRead)
     }
 
     pub fn write_block(&mut self, addr: BlockAddress, buf: &[u8]) -> Result<usize, Error> {
-        let offset = self.resolve_block_offset(addr);
+        self.mark_dirty()?;
+        let offset = self.resolve_block_offset(addr)?;
         self.block_device
             .write_at(offset, buf)
             .map_err(|_| Error::DeviceWrite)
     }
 
-    fn resolve_block_offset(&self, addr: BlockAddress) -> usize {
-        (addr.get() * self.superblock.block_size()) as usize
+    /// The offset of the given block on the device. Fails if the block is not on the device,
+    /// which happens for corrupted block pointers and block group descriptors.
+    fn resolve_block_offset(&self, addr: BlockAddress) -> Result<usize, Error> {
+        let block_size = self.superblock.block_size() as u64;
+        let device_size = self.block_device.sector_size() as u64 * self.block_device.sector_count() as u64;
+        let offset = addr.get() as u64 * block_size;
+        if offset + block_size > device_size {
+            return Err(Error::InvalidBlockAddress(addr.get()));
+        }
+        Ok(offset as usize)
+    }
+
+    /// The offset of the given inode in the inode table of its group on the device.
+    fn resolve_inode_offset(&self, addr: InodeAddress) -> Result<usize, Error> {
+        let inodes_per_group = self.superblock.inodes_per_group();
+        let block_group_index = (addr.get() - 1) / inodes_per_group;
+        let block_group = self.bgdt.get(block_group_index as usize)
+            .ok_or(Error::InvalidInodeAddress(addr.get()))?;
+
+        // inodes don't span blocks, so only the block that contains the inode has to be on the device
+        let block_size = self.superblock.block_size();
+        let index_offset = (addr.get() - 1) % inodes_per_group * self.superblock.inode_size() as u32;
+        let block = block_group.inode_table_starting_block()
+            .checked_add(index_offset / block_size)
+            .and_then(BlockAddress::new)
+            .ok_or(Error::InvalidInodeAddress(addr.get()))?;
+        Ok(self.resolve_block_offset(block)? + (index_offset % block_size) as usize)
     }
 
     pub fn allocate_block(&mut self) -> Result<Option<BlockAddress>, Error> {
         let blocks_per_group = self.superblock.blocks_per_group();
+        // block bitmaps start at the first data block, which is not block 0 for 1KiB blocks
+        let first_data_block = self.superblock.superblock_block_number();
         self.allocate_resource(blocks_per_group, Self::try_reserve_block_in_group)
-            .map(|block| block.map(BlockAddress::new).flatten())
+            .map(|block| block.and_then(|block| BlockAddress::new(block + first_data_block)))
     }
 
     pub fn allocate_inode(&mut self) -> Result<Option<InodeAddress>, Error> {
         let inodes_per_group = self.superblock.inodes_per_group();
+        // inode numbers start at 1
         self.allocate_resource(inodes_per_group, Self::try_reserve_inode_in_group)
-            .map(|inode| inode.map(InodeAddress::new).flatten())
+            .map(|inode| inode.and_then(|inode| InodeAddress::new(inode + 1)))
+    }
+
+    /// Marks the given block as free in the block bitmap of its group.
+    /// Fails if the block is not part of the file system or already free.
+    pub fn free_block(&mut self, addr: BlockAddress) -> Result<(), Error> {
+        let (group_index, bitmap_block, index) = self.block_bitmap_position(addr)?;
+        if !self.try_release_in_group_with_bitmap(bitmap_block, index)? {
+            return Err(Error::InvalidBlockAddress(addr.get()));
+        }
+
+        *self.bgdt[group_index].num_unallocated_blocks_mut() += 1;
+        *self.superblock.num_unallocated_blocks_mut() += 1;
+        self.write_block_group_descriptor(group_index)?;
+        self.write_superblock()
+    }
+
+    /// Marks the given block as used in the block bitmap of its group, without
+    /// giving it to an inode. Fails if the block is not part of the file system
+    /// or already used.
+    pub fn reserve_block(&mut self, addr: BlockAddress) -> Result<(), Error> {
+        let (group_index, bitmap_block, index) = self.block_bitmap_position(addr)?;
+        if !self.try_reserve_index_with_bitmap(bitmap_block, index)? {
+            return Err(Error::InvalidBlockAddress(addr.get()));
+        }
+
+        *self.bgdt[group_index].num_unallocated_blocks_mut() -= 1;
+        *self.superblock.num_unallocated_blocks_mut() -= 1;
+        self.write_block_group_descriptor(group_index)?;
+        self.write_superblock()
+    }
+
+    /// Returns the index of the group of the given block, the block bitmap of the
+    /// group and the index of the block in the bitmap.
+    fn block_bitmap_position(&self, addr: BlockAddress) -> Result<(usize, BlockAddress, usize), Error> {
+        let blocks_per_group = self.superblock.blocks_per_group();
+        let first_data_block = self.superblock.superblock_block_number();
+        if addr.get() < first_data_block || addr.get() >= self.superblock.num_blocks() {
+            return Err(Error::InvalidBlockAddress(addr.get()));
+        }
+
+        let index = addr.get() - first_data_block;
+        let group_index = (index / blocks_per_group) as usize;
+        let bitmap_block = self.bgdt[group_index].block_usage_bitmap_block();
+        let bitmap_block_address = BlockAddress::new(bitmap_block).expect("bgdt does not have valid block address for bitmap block");
+        Ok((group_index, bitmap_block_address, (index % blocks_per_group) as usize))
+    }
+
+    /// Marks the given inode as free in the inode bitmap of its group, and counts
+    /// the directory as removed if it is one. Fails if the inode is already free.
+    pub(crate) fn free_inode(&mut self, addr: InodeAddress, is_dir: bool) -> Result<(), Error> {
+        let inodes_per_group = self.superblock.inodes_per_group();
+        if addr.get() > self.superblock.num_inodes() {
+            return Err(Error::InvalidInodeAddress(addr.get()));
+        }
+
+        let index = addr.get() - 1;
+        let group_index = (index / inodes_per_group) as usize;
+        let bitmap_block = self.bgdt[group_index].inode_usage_bitmap_block();
+        let bitmap_block_address = BlockAddress::new(bitmap_block).expect("bgdt does not have valid block address for bitmap block");
+        if !self.try_release_in_group_with_bitmap(bitmap_block_address, (index % inodes_per_group) as usize)? {
+            return Err(Error::InvalidInodeAddress(addr.get()));
+        }
+
+        let descriptor = &mut self.bgdt[group_index];
+        *descriptor.num_unallocated_inodes_mut() += 1;
+        if is_dir {
+            *descriptor.num_directories_mut() -= 1;
+        }
+        *self.superblock.num_unallocated_inodes_mut() += 1;
+        self.write_block_group_descriptor(group_index)?;
+        self.write_superblock()
     }
 
     fn allocate_resource<F>(&mut self, resource_per_group: u32, try_reserve_in_group: F) -> Result<Option<u32>, Error>
     where
         F: Fn(&mut Self, usize) -> Result<Option<usize>, Error>,
     {
-        let block_size = self.superblock.block_size();
         let num_groups = self.bgdt.len();
-        let bgdt_offset = self.bgdt_offset();
 
         for group_index in 0..num_groups {
             let first_free_resource_index = try_reserve_in_group(self, group_index)?;
@@ -177,29 +414,8 @@ where
             }
             let first_free_resource_index = first_free_resource_index.unwrap();
 
-            let descriptor = &mut self.bgdt[group_index];
-            *descriptor.num_unallocated_blocks_mut() -= 1;
-
-            // read the block group descriptor table
-            let mut bgdt_data = vec![0_u8; block_size as usize];
-            self.block_device
-                .read_at(bgdt_offset, &mut bgdt_data)
-                .map_err(|_| Error::UnableToReadBlockGroupDescriptorTable)?;
-            // merge the changed descriptor back into the table
-            let bgd_offset = group_index * BGD_SIZE;
-            let bgd_end = bgd_offset + BGD_SIZE;
-            let bgd_data = Into::<[u8; BGD_SIZE]>::into(&*descriptor);
-            bgdt_data[bgd_offset..bgd_end].copy_from_slice(&bgd_data);
-            // write the block group descriptor table back
-            self.block_device
-                .write_at(bgdt_offset, &bgdt_data)
-                .map_err(|_| Error::UnableToWriteBlockGroupDescriptorTable)?;
-
-            *self.superblock.num_unallocated_blocks_mut() -= 1;
-            let superblock_data = Into::<SuperblockArray>::into(&self.superblock);
-            self.block_device
-                .write_at(SUPERBLOCK_OFFSET, superblock_data.as_slice())
-                .map_err(|_| Error::UnableToWriteSuperblock)?;
+            self.write_block_group_descriptor(group_index)?;
+            self.write_superblock()?;
 
             let global_resource_num = group_index as u32 * resource_per_group + first_free_resource_index as u32;
             return Ok(Some(global_resource_num));
@@ -208,31 +424,139 @@ where
         Ok(None)
     }
 
+    /// Returns the addresses of all inodes that are marked as used in the inode
+    /// bitmaps, including the reserved inodes.
+    pub fn used_inodes(&self) -> Result<Vec<InodeAddress>, Error> {
+        let inodes_per_group = self.superblock.inodes_per_group();
+        let mut bitmap = vec![0_u8; self.superblock.block_size() as usize];
+        let mut result = Vec::new();
+        for (group_index, descriptor) in self.bgdt.iter().enumerate() {
+            let bitmap_block = BlockAddress::new(descriptor.inode_usage_bitmap_block()).expect("bgdt does not have valid block address for bitmap block");
+            self.read_block(bitmap_block, &mut bitmap)?;
+            let used = (0..inodes_per_group)
+                .filter(|index| bitmap[*index as usize / 8] & (1 << (index % 8)) != 0)
+                .filter_map(|index| InodeAddress::new(group_index as u32 * inodes_per_group + index + 1));
+            result.extend(used);
+        }
+        Ok(result)
+    }
+
+    /// Returns the ranges of blocks that are marked as free in the block bitmap of the
+    /// group with the given index.
+    pub fn free_block_ranges(&self, group_index: usize) -> Result<Vec<Range<u32>>, Error> {
+        let bitmap_block = BlockAddress::new(self.bgdt[group_index].block_usage_bitmap_block()).expect("bgdt does not have valid block address for bitmap block");
+        let first_block = self.superblock.superblock_block_number() + group_index as u32 * self.superblock.blocks_per_group();
+        self.free_ranges(bitmap_block, self.blocks_in_group(group_index), first_block)
+    }
+
+    /// Returns the ranges of inodes that are marked as free in the inode bitmap of the
+    /// group with the given index.
+    pub fn free_inode_ranges(&self, group_index: usize) -> Result<Vec<Range<u32>>, Error> {
+        let bitmap_block = BlockAddress::new(self.bgdt[group_index].inode_usage_bitmap_block()).expect("bgdt does not have valid block address for bitmap block");
+        let inodes_per_group = self.superblock.inodes_per_group();
+        self.free_ranges(bitmap_block, inodes_per_group, group_index as u32 * inodes_per_group + 1)
+    }
+
+    /// Returns the ranges of clear bits among the first `count` bits of the given bitmap,
+    /// offset by `first`.
+    fn free_ranges(&self, bitmap_block: BlockAddress, count: u32, first: u32) -> Result<Vec<Range<u32>>, Error> {
+        let mut bitmap = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(bitmap_block, &mut bitmap)?;
+        let mut ranges: Vec<Range<u32>> = Vec::new();
+        for index in (0..count).filter(|index| bitmap[*index as usize / 8] & (1 << (index % 8)) == 0) {
+            match ranges.last_mut() {
+                Some(range) if range.end == first + index => range.end += 1,
+                _ => ranges.push(first + index..first + index + 1),
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// The number of blocks in the block group with the given index. This is
+    /// only different from the blocks per group for the last group.
+    pub(crate) fn blocks_in_group(&self, group_index: usize) -> u32 {
+        let blocks_per_group = self.superblock.blocks_per_group();
+        let first_block = self.superblock.superblock_block_number() + group_index as u32 * blocks_per_group;
+        (self.superblock.num_blocks() - first_block).min(blocks_per_group)
+    }
+
     fn try_reserve_block_in_group(&mut self, group_index: usize) -> Result<Option<usize>, Error> {
+        if self.bgdt[group_index].num_unallocated_blocks() == 0 {
+            return Ok(None);
+        }
+
         let bitmap_block = self.bgdt[group_index].block_usage_bitmap_block();
         let bitmap_block_address = BlockAddress::new(bitmap_block).expect("bgdt does not have valid block address for bitmap block");
-        self.try_reserve_in_group_with_bitmap(bitmap_block_address)
+        let blocks_in_group = self.blocks_in_group(group_index);
+        let index = self.try_reserve_in_group_with_bitmap(bitmap_block_address, blocks_in_group as usize)?;
+        if index.is_some() {
+            *self.bgdt[group_index].num_unallocated_blocks_mut() -= 1;
+            *self.superblock.num_unallocated_blocks_mut() -= 1;
+        }
+        Ok(index)
     }
 
     fn try_reserve_inode_in_group(&mut self, group_index: usize) -> Result<Option<usize>, Error> {
+        if self.bgdt[group_index].num_unallocated_inodes() == 0 {
+            return Ok(None);
+        }
+
         let bitmap_block = self.bgdt[group_index].inode_usage_bitmap_block();
         let bitmap_block_address = BlockAddress::new(bitmap_block).expect("bgdt does not have valid block address for bitmap block");
-        self.try_reserve_in_group_with_bitmap(bitmap_block_address)
+        let inodes_in_group = self.superblock.inodes_per_group();
+        let index = self.try_reserve_in_group_with_bitmap(bitmap_block_address, inodes_in_group as usize)?;
+        if index.is_some() {
+            *self.bgdt[group_index].num_unallocated_inodes_mut() -= 1;
+            *self.superblock.num_unallocated_inodes_mut() -= 1;
+        }
+        Ok(index)
     }
 
-    fn try_reserve_in_group_with_bitmap(&mut self, bitmap_block: BlockAddress) -> Result<Option<usize>, Error> {
-        let mut inode_bitmap = vec![0_u8; self.superblock.block_size() as usize];
-        self.read_block(bitmap_block, &mut inode_bitmap)?;
-
-        for (i, byte) in inode_bitmap.iter_mut().enumerate() {
-            for bit_index in 0..8 {
-                if *byte & (1_u8 << bit_index) == 0 {
-                    *byte |= 1 << bit_index;
-                    return Ok(Some(i * 8 + bit_index));
-                }
+    fn try_reserve_in_group_with_bitmap(&mut self, bitmap_block: BlockAddress, count: usize) -> Result<Option<usize>, Error> {
+        let mut bitmap = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(bitmap_block, &mut bitmap)?;
+
+        for index in 0..count {
+            let byte = &mut bitmap[index / 8];
+            let bit = 1_u8 << (index % 8);
+            if *byte & bit == 0 {
+                *byte |= bit;
+                self.write_block(bitmap_block, &bitmap)?;
+                return Ok(Some(index));
             }
         }
-        self.write_block(bitmap_block, &inode_bitmap)?;
         Ok(None)
     }
+
+    /// Sets the bit with the given index in the given bitmap. Returns `false`
+    /// if the bit was already set.
+    fn try_reserve_index_with_bitmap(&mut self, bitmap_block: BlockAddress, index: usize) -> Result<bool, Error> {
+        let mut bitmap = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(bitmap_block, &mut bitmap)?;
+
+        let byte = &mut bitmap[index / 8];
+        let bit = 1_u8 << (index % 8);
+        if *byte & bit != 0 {
+            return Ok(false);
+        }
+        *byte |= bit;
+        self.write_block(bitmap_block, &bitmap)?;
+        Ok(true)
+    }
+
+    /// Clears the bit with the given index in the given bitmap. Returns `false`
+    /// if the bit was not set.
+    fn try_release_in_group_with_bitmap(&mut self, bitmap_block: BlockAddress, index: usize) -> Result<bool, Error> {
+        let mut bitmap = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(bitmap_block, &mut bitmap)?;
+
+        let byte = &mut bitmap[index / 8];
+        let bit = 1_u8 << (index % 8);
+        if *byte & bit == 0 {
+            return Ok(false);
+        }
+        *byte &= !bit;
+        self.write_block(bitmap_block, &bitmap)?;
+        Ok(true)
+    }
 }
@@ -0,0 +1,80 @@
+use filesystem::BlockDevice;
+
+use crate::{Error, Ext2Fs, State};
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Marks the file system as mounted for writing. This happens implicitly
+    /// before the first change to the file system.
+    ///
+    /// The file system is marked as not clean on disk until it is unmounted
+    /// with [`Ext2Fs::unmount`], so that a crash while it is mounted can be
+    /// detected. This also increments the mount counter and updates the
+    /// last mount time.
+    ///
+    /// Mounting an already mounted file system does nothing.
+    pub fn mount_read_write(&mut self) -> Result<(), Error> {
+        if self.mount_state.is_some() {
+            return Ok(());
+        }
+
+        let state = self.superblock.state();
+        self.mount_state = Some(state);
+
+        let now = self.now();
+        self.superblock.set_state(state.difference(State::CLEAN));
+        *self.superblock.mounts_since_fsck_mut() = self.superblock.mounts_since_fsck().saturating_add(1);
+        *self.superblock.last_mount_time_mut() = now;
+        *self.superblock.last_written_time_mut() = now;
+        self.write_superblock()
+    }
+
+    /// Mounts the file system for writing before the first change, so that the
+    /// change marks it as not clean on disk, even if it was never mounted
+    /// explicitly.
+    pub(crate) fn mark_dirty(&mut self) -> Result<(), Error> {
+        if self.mount_state.is_some() {
+            return Ok(());
+        }
+        self.mount_read_write()
+    }
+
+    /// Returns whether the file system is mounted for writing, see
+    /// [`Ext2Fs::mount_read_write`].
+    pub fn is_mounted_read_write(&self) -> bool {
+        self.mount_state.is_some()
+    }
+
+    /// Writes the in-memory copies of the superblock and the block group
+    /// descriptor table to the device. Reservations of blocks for files
+    /// because of the preallocation hint, that are not used yet, are dropped.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        self.discard_all_preallocated_blocks();
+        if self.is_mounted_read_write() {
+            *self.superblock.last_written_time_mut() = self.now();
+        }
+
+        for group_index in 0..self.bgdt.len() {
+            self.write_block_group_descriptor(group_index)?;
+        }
+        self.write_superblock()
+    }
+
+    /// Syncs and unmounts the file system, and returns the underlying
+    /// block device.
+    ///
+    /// If the file system was mounted for writing, the state that it had
+    /// when it was mounted is restored. This means that a file system that
+    /// was clean when it was mounted is marked as clean again, but a file
+    /// system with errors keeps its errors until it is checked.
+    pub fn unmount(mut self) -> Result<T, Error> {
+        if let Some(state) = self.mount_state.take() {
+            *self.superblock.last_written_time_mut() = self.now();
+            self.superblock.set_state(state);
+        }
+        self.sync()?;
+        Ok(self.block_device)
+    }
+}
@@ -0,0 +1,112 @@
+use alloc::vec::Vec;
+
+use filesystem::BlockDevice;
+
+use crate::{Directory, Error, Ext2Fs, Inode, InodeAddress, RegularFile, SymLink, Type, ROOT_DIR_INODE_ADDRESS};
+
+/// The maximum number of symlinks that are followed while resolving a
+/// single path, before resolving fails with [`Error::TooManySymLinks`].
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Resolves the given path to an inode, following symlinks.
+    ///
+    /// Paths are always resolved relative to the root directory, so
+    /// `a/b` and `/a/b` refer to the same file. Paths are byte strings, so
+    /// they may contain names that are not valid UTF-8.
+    pub fn resolve_path(&self, path: impl AsRef<[u8]>) -> Result<(InodeAddress, Inode), Error> {
+        self.resolve_path_bytes(path.as_ref(), true)
+    }
+
+    /// Resolves the given path to an inode like [`Ext2Fs::resolve_path`], but
+    /// doesn't follow a symlink in the last component of the path.
+    pub fn resolve_path_no_follow(&self, path: impl AsRef<[u8]>) -> Result<(InodeAddress, Inode), Error> {
+        self.resolve_path_bytes(path.as_ref(), false)
+    }
+
+    pub fn open_file(&self, path: impl AsRef<[u8]>) -> Result<RegularFile, Error> {
+        self.resolve_path(path)?
+            .try_into()
+            .map_err(|_| Error::NotRegularFile)
+    }
+
+    pub fn open_dir(&self, path: impl AsRef<[u8]>) -> Result<Directory, Error> {
+        self.resolve_path(path)?
+            .try_into()
+            .map_err(|_| Error::NotDirectory)
+    }
+
+    /// Returns the inode of the file at the given path, following symlinks.
+    pub fn metadata(&self, path: impl AsRef<[u8]>) -> Result<Inode, Error> {
+        self.resolve_path(path).map(|(_, inode)| inode)
+    }
+
+    /// Returns the inode of the file at the given path, without following
+    /// a symlink in the last component of the path.
+    pub fn symlink_metadata(&self, path: impl AsRef<[u8]>) -> Result<Inode, Error> {
+        self.resolve_path_no_follow(path).map(|(_, inode)| inode)
+    }
+
+    pub(crate) fn resolve_path_bytes(&self, path: &[u8], follow_last: bool) -> Result<(InodeAddress, Inode), Error> {
+        let mut symlinks_followed = 0;
+        let mut current = self.read_inode(ROOT_DIR_INODE_ADDRESS)?;
+
+        // the components that still need to be resolved, in reverse order
+        let mut components = Vec::new();
+        push_components(&mut components, path);
+
+        while let Some(component) = components.pop() {
+            if component == b"." {
+                if current.1.typ() != Type::Directory {
+                    return Err(Error::NotDirectory);
+                }
+                continue;
+            }
+
+            let dir = Directory::try_from(current).map_err(|_| Error::NotDirectory)?;
+            // `..` doesn't need special handling, since every directory has an entry for it
+            let entry = self.find_entry_by_name(&dir, &component)?.ok_or(Error::NotFound)?;
+            let next = self.resolve_dir_entry(entry)?;
+
+            if next.1.typ() == Type::SymLink && (follow_last || !components.is_empty()) {
+                symlinks_followed += 1;
+                if symlinks_followed > MAX_SYMLINK_FOLLOWS {
+                    return Err(Error::TooManySymLinks);
+                }
+
+                let link = SymLink::try_from(next).unwrap(); // we checked the type above
+                let target = self.read_link(&link)?;
+                current = if target.first() == Some(&b'/') {
+                    self.read_inode(ROOT_DIR_INODE_ADDRESS)?
+                } else {
+                    // relative targets are resolved relative to the directory that contains the link
+                    dir.into_inner()
+                };
+                push_components(&mut components, &target);
+                continue;
+            }
+
+            current = next;
+        }
+
+        Ok(current)
+    }
+}
+
+/// Pushes the components of the given path onto the stack of components that still
+/// need to be resolved, in reverse order. A trailing `/` becomes a final `.` component,
+/// so that the path only resolves to a directory, and a symlink before it is followed.
+fn push_components(components: &mut Vec<Vec<u8>>, path: &[u8]) {
+    if path.ends_with(b"/") {
+        components.push(b".".to_vec());
+    }
+    components.extend(split_path(path).rev().map(<[u8]>::to_vec));
+}
+
+fn split_path(path: &[u8]) -> impl DoubleEndedIterator<Item=&[u8]> {
+    path.split(|&b| b == b'/')
+        .filter(|component| !component.is_empty())
+}
@@ -0,0 +1,222 @@
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use filesystem::BlockDevice;
+
+use crate::{BlockAddress, Error, Ext2Fs, Inode, InodeAddress, RegularFile, Type};
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Allocates all blocks in the given byte range of the file, so that writes
+    /// to that range can't fail because the file system is full. Holes in the
+    /// range are filled with zeroed blocks, data in the range is not changed.
+    ///
+    /// If `keep_size` is `false`, the file grows to include the range. Otherwise,
+    /// the size of the file stays the same, even if blocks after the end of the
+    /// file are allocated. Note that e2fsck considers allocated blocks after the
+    /// end of a file an error, so these blocks should either be written to or
+    /// freed again with [`Ext2Fs::punch_hole`].
+    ///
+    /// If the file system runs out of space, the blocks that were allocated until then
+    /// stay in the file, and the file only grows to include them.
+    pub fn fallocate(&mut self, file: &mut RegularFile, offset: u64, len: u64, keep_size: bool) -> Result<(), Error> {
+        if len == 0 {
+            return Ok(());
+        }
+
+        let block_size = self.superblock.block_size() as u64;
+        let end = offset.checked_add(len).ok_or(Error::FileTooLarge)?;
+        if end > self.max_file_size() {
+            return Err(Error::FileTooLarge);
+        }
+        if !keep_size && end > file.len() {
+            self.ensure_large_file_support(end)?;
+        }
+        let start_block = offset / block_size;
+        let end_block = (end - 1) / block_size;
+
+        let inode = file.inode_mut();
+        let mut num_new_allocated_blocks = 0;
+        let mut result = Ok(());
+        let mut allocated_end = end;
+        for block_index in start_block as u32..=end_block as u32 {
+            match self.fallocate_block(inode, block_index) {
+                Ok(num_allocated) => num_new_allocated_blocks += num_allocated,
+                Err(e) => {
+                    result = Err(e);
+                    allocated_end = block_index as u64 * block_size;
+                    break;
+                }
+            }
+        }
+
+        // the number of disk sectors is counted in 512 byte sectors, not in blocks
+        let sectors_per_block = block_size as u32 / 512;
+        if result.is_ok() {
+            *inode.num_disk_sectors_mut() += num_new_allocated_blocks * sectors_per_block;
+        } else {
+            // the blocks that were allocated before the error stay in the file, including
+            // indirect blocks that were added for a block that couldn't be allocated
+            let xattr_blocks = inode.extended_attribute_block().is_some() as u32;
+            *inode.num_disk_sectors_mut() = (self.inode_blocks(inode)?.len() as u32 + xattr_blocks) * sectors_per_block;
+        }
+        if !keep_size && inode.len() < allocated_end {
+            inode.set_len(allocated_end);
+        }
+        self.write_inode(file.inode_address(), file)?;
+        result
+    }
+
+    /// Allocates a zeroed block for the given index, unless the index already has a block.
+    /// Returns the number of allocated blocks, including indirect blocks.
+    fn fallocate_block(&mut self, inode: &mut Inode, block_index: u32) -> Result<u32, Error> {
+        if self.resolve_block_index(inode, block_index)?.is_some() {
+            return Ok(0);
+        }
+        let block = self.allocate_zeroed_block()?;
+        match self.set_block_index(inode, block_index, block) {
+            Ok(num_indirect_blocks) => Ok(1 + num_indirect_blocks),
+            Err(e) => {
+                self.free_block(block)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Frees all blocks that are completely inside the given byte range of the file,
+    /// and zeroes the parts of the range that only cover a block partially. The size
+    /// of the file doesn't change, the range reads as zeros afterwards.
+    pub fn punch_hole(&mut self, file: &mut RegularFile, offset: u64, len: u64) -> Result<(), Error> {
+        if len == 0 {
+            return Ok(());
+        }
+
+        let inode_address = file.inode_address();
+        self.discard_preallocated_blocks(inode_address);
+
+        let block_size = self.superblock.block_size() as u64;
+        let end = offset.saturating_add(len);
+        let inode = file.inode_mut();
+
+        // the blocks that are completely covered by the range are [first_full_block, end_full_block)
+        let first_full_block = offset.div_ceil(block_size);
+        let end_full_block = end / block_size;
+        if first_full_block > end_full_block {
+            // the range is within a single block
+            self.zero_block_range(inode, offset / block_size, offset % block_size, end % block_size)?;
+        } else {
+            if !offset.is_multiple_of(block_size) {
+                self.zero_block_range(inode, offset / block_size, offset % block_size, block_size)?;
+            }
+            if !end.is_multiple_of(block_size) {
+                self.zero_block_range(inode, end_full_block, 0, end % block_size)?;
+            }
+        }
+
+        // no block after the maximum file size can be allocated
+        let end_full_block = end_full_block.min(self.max_file_size().div_ceil(block_size)).min(u32::MAX as u64) as u32;
+        let mut block_index = first_full_block.min(u32::MAX as u64) as u32;
+        let mut num_freed_blocks = 0;
+        while block_index < end_full_block {
+            match self.resolve_block_index_or_next(inode, block_index)? {
+                Ok(_) => {
+                    let (block, num_freed_indirect_blocks) = self.clear_block_index(inode, block_index)?;
+                    if let Some(block) = block {
+                        self.free_block(block)?;
+                        num_freed_blocks += 1;
+                    }
+                    num_freed_blocks += num_freed_indirect_blocks;
+                    block_index += 1;
+                }
+                Err(next) => block_index = next,
+            }
+        }
+
+        *inode.num_disk_sectors_mut() = inode.num_disk_sectors().saturating_sub(num_freed_blocks * (block_size as u32 / 512));
+        self.write_inode(inode_address, file)
+    }
+
+    /// Zeroes the given range within the block with the given index, if the block is allocated.
+    fn zero_block_range(&mut self, inode: &Inode, block_index: u64, start: u64, end: u64) -> Result<(), Error> {
+        let Ok(block_index) = u32::try_from(block_index) else {
+            return Ok(());
+        };
+        let Some(block) = self.resolve_block_index(inode, block_index)? else {
+            return Ok(());
+        };
+
+        let mut data = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(block, &mut data)?;
+        data[start as usize..end as usize].fill(0);
+        self.write_block(block, &data)?;
+        Ok(())
+    }
+
+    /// Allocates a block for the data of the given inode. For regular files, this
+    /// honours the preallocation hint of the superblock: if the hint asks for more than
+    /// one block, the following free blocks are reserved for the same file, so that its
+    /// data stays close together. Reservations only exist in memory, a reserved block is
+    /// marked as used once the file uses it. Reservations that are not used until the
+    /// next [`Ext2Fs::sync`] are dropped.
+    pub(crate) fn allocate_data_block(&mut self, inode_address: InodeAddress, inode: &Inode) -> Result<BlockAddress, Error> {
+        let preallocate = self.superblock.num_preallocate_blocks_file() as usize;
+        if inode.typ() != Type::RegularFile || preallocate <= 1 {
+            return self.allocate_block()?.ok_or(Error::NoSpace);
+        }
+
+        while let Some(block) = self.preallocated_blocks.get_mut(&inode_address).and_then(Vec::pop) {
+            match self.reserve_block(block) {
+                Ok(()) => return Ok(block),
+                // the block was used for something else in the meantime
+                Err(Error::InvalidBlockAddress(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut blocks = self.find_unreserved_free_blocks(preallocate)?;
+        if blocks.is_empty() {
+            // the only free blocks left are reserved for other files
+            return self.allocate_block()?.ok_or(Error::NoSpace);
+        }
+        let block = blocks.remove(0);
+        self.reserve_block(block)?;
+        // blocks are taken from the back, so the lowest block must be the last
+        blocks.reverse();
+        self.preallocated_blocks.insert(inode_address, blocks);
+        Ok(block)
+    }
+
+    /// Finds up to `count` free blocks, in ascending order, that are not reserved for any file.
+    fn find_unreserved_free_blocks(&self, count: usize) -> Result<Vec<BlockAddress>, Error> {
+        let reserved = self.preallocated_blocks.values().flatten().copied().collect::<BTreeSet<_>>();
+        let mut blocks = Vec::with_capacity(count);
+        for group_index in 0..self.bgdt.len() {
+            if self.bgdt[group_index].num_unallocated_blocks() == 0 {
+                continue;
+            }
+            let free_blocks = self.free_block_ranges(group_index)?
+                .into_iter()
+                .flatten()
+                .filter_map(BlockAddress::new)
+                .filter(|block| !reserved.contains(block));
+            blocks.extend(free_blocks.take(count - blocks.len()));
+            if blocks.len() == count {
+                break;
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Drops the reservations of blocks for the given inode.
+    pub(crate) fn discard_preallocated_blocks(&mut self, inode_address: InodeAddress) {
+        self.preallocated_blocks.remove(&inode_address);
+    }
+
+    /// Drops the reservations of blocks for all inodes.
+    pub(crate) fn discard_all_preallocated_blocks(&mut self) {
+        self.preallocated_blocks.clear();
+    }
+}
@@ -14,57 +14,55 @@ where
     pub fn read_from_file(
         &self,
         file: &RegularFile,
-        offset: usize,
+        offset: u64,
         buf: &mut [u8],
     ) -> Result<usize, Error> {
-        let file_size = file.len();
-        if offset >= file_size {
+        self.read_from_inode(file, offset, buf)
+    }
+
+    pub(crate) fn read_from_inode(
+        &self,
+        inode: &Inode,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        let file_size = inode.len();
+        if offset >= file_size || buf.is_empty() {
             return Ok(0);
         }
 
-        let block_size = self.superblock.block_size();
-        let offset = offset as u32;
+        // only read up to the file size, which also keeps the block indices within u32
+        let len = (buf.len() as u64).min(file_size - offset);
+        let block_size = self.superblock.block_size() as u64;
 
-        let start_block = offset / block_size;
-        let end_block = (offset + buf.len() as u32 - 1) / block_size;
+        let start_block = (offset / block_size) as u32;
+        let end_block = ((offset + len - 1) / block_size) as u32;
         let relative_offset = (offset % block_size) as usize;
         let block_count = (end_block - start_block + 1) as usize;
 
         // read blocks
         let mut data: Vec<u8> = vec![0_u8; block_count * block_size as usize]; // TODO: avoid allocation - maybe try to only allocate the first and last block if the read is not aligned, but read the rest directly into the buffer
-        let res = self.read_blocks_from_inode(file, start_block as usize, end_block as usize, &mut data)?;
-        // copy the data into buf, but only the requested part and only up to the file size
-        let total_read = res.min(file_size - offset as usize).min(buf.len());
+        let res = self.read_blocks_from_inode(inode, start_block, end_block, &mut data)?;
+        // copy the data into buf, but only the requested part
+        let total_read = (res - relative_offset).min(len as usize);
         buf[..total_read].copy_from_slice(&data[relative_offset..relative_offset + total_read]);
 
-
         Ok(total_read)
     }
 
-    pub(crate) fn read_blocks_from_inode(&self, inode: &Inode, start_block: usize, end_block: usize, buf: &mut [u8]) -> Result<usize, Error> {
+    pub(crate) fn read_blocks_from_inode(&self, inode: &Inode, start_block: u32, end_block: u32, buf: &mut [u8]) -> Result<usize, Error> {
         let block_size = self.superblock.block_size() as usize;
-        assert_eq!(buf.len(), (end_block - start_block + 1) * block_size, "buf.len() must be equal to the number of blocks you want to read");
-
-        let (direct_limit, indirect_limit, double_indirect_limit) = self.indirect_pointer_limits();
+        assert_eq!(buf.len(), (end_block - start_block + 1) as usize * block_size, "buf.len() must be equal to the number of blocks you want to read");
 
         let mut total_read = 0;
 
-        for (i, block) in (start_block..=end_block).enumerate() {
-            let block_data = &mut buf[i * block_size..(i + 1) * block_size];
-            let block_pointer = if block < direct_limit as usize {
-                inode.direct_ptrs().nth(block).flatten()
-            } else if block < indirect_limit as usize {
-                self.resolve_indirect_ptr(inode.single_indirect_ptr(), block as u32 - direct_limit)?
-            } else if block < double_indirect_limit as usize {
-                self.resolve_double_indirect_ptr(inode.double_indirect_ptr(), block as u32 - indirect_limit)?
-            } else {
-                self.resolve_triple_indirect_ptr(inode.triple_indirect_ptr(), block as u32 - double_indirect_limit)?
-            };
-            if let Some(block_pointer) = block_pointer {
+        for (block, block_data) in (start_block..=end_block).zip(buf.chunks_exact_mut(block_size)) {
+            if let Some(block_pointer) = self.resolve_block_index(inode, block)? {
                 total_read += self.read_block(block_pointer, block_data)?;
             } else {
+                // holes read as zeros, the caller limits the read to the file size
                 block_data.fill(0);
-                total_read += block_size; // FIXME: what if the last block is sparse?
+                total_read += block_size;
             }
         }
 
@@ -72,9 +70,10 @@ where
     }
 
     pub fn indirect_pointer_limits(&self) -> (u32, u32, u32) {
+        let pointers_per_block = self.superblock.block_size() / 4;
         let direct_limit = 12;
-        let indirect_limit = direct_limit + self.superblock.block_size() / 4;
-        let double_indirect_limit = indirect_limit + indirect_limit * indirect_limit;
+        let indirect_limit = direct_limit + pointers_per_block;
+        let double_indirect_limit = indirect_limit + pointers_per_block * pointers_per_block;
         (direct_limit, indirect_limit, double_indirect_limit)
     }
 
@@ -132,7 +131,9 @@ where
     pub fn resolve_triple_indirect_ptr(&self, triple_indirect_block: Option<BlockAddress>, block_index: u32) -> Result<Option<BlockAddress>, Error> {
         let block_size = self.superblock.block_size();
 
-        let double_indirect_block_size = block_size / 4;
+        // every pointer in the triple indirect block covers a whole double indirect block
+        let pointers_per_block = block_size / 4;
+        let double_indirect_block_size = pointers_per_block * pointers_per_block;
         let double_indirect_index = block_index / double_indirect_block_size;
 
         self.resolve_indirect_ptr(triple_indirect_block, double_indirect_index)
@@ -0,0 +1,52 @@
+use filesystem::BlockDevice;
+
+use crate::{Directory, Error, Ext2Fs, Inode, InodeAddress, Type};
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Removes the entry with the given name from the given directory, like `unlink(2)`.
+    /// When the last link is removed, the inode is deleted and its blocks are freed.
+    /// Directories can't be unlinked, which fails with [`Error::NotSupported`].
+    pub fn unlink(&mut self, dir: &mut Directory, name: impl AsRef<[u8]>) -> Result<(), Error> {
+        let name = name.as_ref();
+        let entry = self.find_entry_by_name(dir, name)?.ok_or(Error::NotFound)?;
+        let (inode_address, mut inode) = self.resolve_dir_entry(entry)?;
+        if inode.typ() == Type::Directory {
+            return Err(Error::NotSupported);
+        }
+
+        self.remove_entry_from_dir(dir, name)?;
+        let links = inode.num_hard_links().saturating_sub(1);
+        if links > 0 {
+            *inode.num_hard_links_mut() = links;
+            return self.write_inode(inode_address, &inode);
+        }
+        self.delete_inode(inode_address, inode)
+    }
+
+    /// Frees the blocks and the attribute block of the given inode, marks it as
+    /// deleted and frees it in the inode bitmap. Nothing may link to the inode anymore.
+    fn delete_inode(&mut self, inode_address: InodeAddress, mut inode: Inode) -> Result<(), Error> {
+        self.discard_preallocated_blocks(inode_address);
+        for (block, _) in self.inode_blocks(&inode)? {
+            self.free_block(block)?;
+        }
+        if let Some(block) = inode.extended_attribute_block() {
+            self.change_xattr_refcount(block, -1)?;
+        }
+
+        // e2fsck expects a deletion time for inodes without links, even without a clock
+        let deletion_time = self.now().max(1);
+        let is_dir = inode.typ() == Type::Directory;
+        *inode.num_hard_links_mut() = 0;
+        *inode.deletion_time_mut() = deletion_time;
+        inode.set_block_ptr_bytes([0; 60]);
+        inode.set_extended_attribute_block(None);
+        *inode.num_disk_sectors_mut() = 0;
+        inode.set_len(0);
+        self.write_inode(inode_address, &inode)?;
+        self.free_inode(inode_address, is_dir)
+    }
+}
@@ -0,0 +1,525 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::ops::Range;
+
+use filesystem::BlockDevice;
+
+use crate::dir::parse_dir_block;
+use crate::{BlockAddress, BlockOwner, DirEntry, DirType, Directory, Error, Ext2Fs, Finding, Flags, Inode, InodeAddress, Permissions, RequiredFeatures, Type, ROOT_DIR_INODE_ADDRESS};
+
+/// The maximum number of times that the file system is checked and repaired. Every
+/// round repairs the findings that other repairs depend on, so a few rounds are enough.
+const MAX_REPAIR_ROUNDS: usize = 16;
+
+/// A change that was made by [`Ext2Fs::repair`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Repair {
+    /// The inode was cleared, so that it is not in use anymore, and its blocks are freed.
+    ClearInode { inode: InodeAddress },
+    ClearDeletionTime { inode: InodeAddress },
+    SetSectorCount { inode: InodeAddress, sectors: u32 },
+    SetSize { inode: InodeAddress, size: u64 },
+    /// The pointer to a block that is used by something else was removed from the inode.
+    RemoveBlockPointer { inode: InodeAddress, block: BlockAddress },
+    RemoveXattrBlock { inode: InodeAddress, block: BlockAddress },
+    SetXattrRefcount { block: BlockAddress, refcount: u32 },
+    RemoveEntry { dir: InodeAddress, name: Vec<u8> },
+    SetEntryType { dir: InodeAddress, name: Vec<u8>, typ: DirType },
+    /// All entries in the block of the directory were removed. The first block keeps
+    /// its `.` and `..` entries.
+    ClearDirectoryBlock { dir: InodeAddress, block_index: u32 },
+    /// The `..` entry of the directory was changed to point to the given parent.
+    SetParent { dir: InodeAddress, parent: InodeAddress },
+    CreateLostAndFound { inode: InodeAddress },
+    /// The inode was added to `lost+found` with the given name.
+    Reconnect { inode: InodeAddress, name: Vec<u8> },
+    SetLinkCount { inode: InodeAddress, links: u16 },
+    MarkBlocks { blocks: Range<u32>, used: bool },
+    MarkInodes { inodes: Range<u32>, used: bool },
+    SetGroupFreeBlocks { group: u32, count: u32 },
+    SetGroupFreeInodes { group: u32, count: u32 },
+    SetGroupDirectories { group: u32, count: u32 },
+    SetFreeBlocks { count: u32 },
+    SetFreeInodes { count: u32 },
+}
+
+impl Display for Repair {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let name = |name: &[u8]| alloc::string::String::from_utf8_lossy(name).into_owned();
+        let range = |f: &mut Formatter<'_>, what: &str, range: &Range<u32>, used: bool| {
+            let state = if used { "used" } else { "free" };
+            if range.len() == 1 {
+                write!(f, "mark {} {} as {}", what, range.start, state)
+            } else {
+                write!(f, "mark {}s {}-{} as {}", what, range.start, range.end - 1, state)
+            }
+        };
+        match self {
+            Repair::ClearInode { inode } => write!(f, "clear inode {}", inode),
+            Repair::ClearDeletionTime { inode } => write!(f, "clear the deletion time of inode {}", inode),
+            Repair::SetSectorCount { inode, sectors } => write!(f, "set the sector count of inode {} to {}", inode, sectors),
+            Repair::SetSize { inode, size } => write!(f, "set the size of inode {} to {}", inode, size),
+            Repair::RemoveBlockPointer { inode, block } => write!(f, "remove block {} from inode {}", block, inode),
+            Repair::RemoveXattrBlock { inode, block } => write!(f, "remove extended attribute block {} from inode {}", block, inode),
+            Repair::SetXattrRefcount { block, refcount } => write!(f, "set the refcount of extended attribute block {} to {}", block, refcount),
+            Repair::RemoveEntry { dir, name: n } => write!(f, "remove entry '{}' from directory inode {}", name(n), dir),
+            Repair::SetEntryType { dir, name: n, typ } => write!(f, "set the type of entry '{}' in directory inode {} to {}", name(n), dir, typ.bits()),
+            Repair::ClearDirectoryBlock { dir, block_index } => write!(f, "clear block {} of directory inode {}", block_index, dir),
+            Repair::SetParent { dir, parent } => write!(f, "set '..' in directory inode {} to inode {}", dir, parent),
+            Repair::CreateLostAndFound { inode } => write!(f, "create /lost+found as inode {}", inode),
+            Repair::Reconnect { inode, name: n } => write!(f, "connect inode {} to /lost+found as '{}'", inode, name(n)),
+            Repair::SetLinkCount { inode, links } => write!(f, "set the link count of inode {} to {}", inode, links),
+            Repair::MarkBlocks { blocks, used } => range(f, "block", blocks, *used),
+            Repair::MarkInodes { inodes, used } => range(f, "inode", inodes, *used),
+            Repair::SetGroupFreeBlocks { group, count } => write!(f, "set the free blocks of block group {} to {}", group, count),
+            Repair::SetGroupFreeInodes { group, count } => write!(f, "set the free inodes of block group {} to {}", group, count),
+            Repair::SetGroupDirectories { group, count } => write!(f, "set the directories of block group {} to {}", group, count),
+            Repair::SetFreeBlocks { count } => write!(f, "set the free blocks to {}", count),
+            Repair::SetFreeInodes { count } => write!(f, "set the free inodes to {}", count),
+        }
+    }
+}
+
+/// The order in which findings are repaired, or `None` if the finding can't be repaired.
+/// Inodes are cleared before their blocks are looked at, the blocks and directory entries
+/// are fixed before the bitmaps are recomputed, and the bitmaps are correct before blocks
+/// are allocated for `lost+found`.
+fn repair_priority(finding: &Finding) -> Option<u8> {
+    match finding {
+        Finding::InvalidInodeType { .. } | Finding::InvalidBlockPointer { .. } => Some(0),
+        Finding::DuplicateBlock { .. } | Finding::InvalidXattrBlock { .. } => Some(1),
+        Finding::DeletionTimeSet { .. } | Finding::WrongSectorCount { .. } | Finding::WrongSize { .. } | Finding::WrongXattrRefcount { .. } => Some(2),
+        Finding::CorruptedDirectory { .. }
+        | Finding::InvalidEntryInode { .. }
+        | Finding::EntryToUnusedInode { .. }
+        | Finding::InvalidEntryName { .. }
+        | Finding::WrongEntryType { .. }
+        | Finding::DirectoryHardLink { .. } => Some(3),
+        Finding::BlockBitmapDifference { .. }
+        | Finding::InodeBitmapDifference { .. }
+        | Finding::WrongGroupFreeBlocks { .. }
+        | Finding::WrongGroupFreeInodes { .. }
+        | Finding::WrongGroupDirectories { .. }
+        | Finding::WrongFreeBlocks { .. }
+        | Finding::WrongFreeInodes { .. } => Some(4),
+        Finding::UnconnectedDirectory { .. } | Finding::WrongParent { .. } => Some(5),
+        Finding::UnattachedInode { .. } | Finding::WrongLinkCount { .. } => Some(6),
+        Finding::InvalidGroupDescriptor { .. } | Finding::MissingDot { .. } | Finding::MissingDotDot { .. } | Finding::RootNotDirectory => None,
+    }
+}
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Repairs the inconsistencies that [`Ext2Fs::check`] finds, like `e2fsck -p`, and
+    /// returns the changes that were made. Inodes with invalid block pointers are cleared,
+    /// blocks that are used more than once are removed from all but their first owner,
+    /// invalid directory entries are removed, and unconnected inodes are moved to
+    /// `lost+found`, which is created if it doesn't exist. The bitmaps and counters are
+    /// recomputed from the blocks and inodes that are in use.
+    ///
+    /// Some findings, like a missing `.` entry, can't be repaired, so the file system
+    /// should be checked again afterwards. Nothing is repaired if a block group descriptor
+    /// is invalid, since the inodes in the inode table of the group are unknown. The file
+    /// system must be synced, like for [`Ext2Fs::check`].
+    pub fn repair(&mut self) -> Result<Vec<Repair>, Error> {
+        let mut repairs = Vec::new();
+        for _ in 0..MAX_REPAIR_ROUNDS {
+            let findings = self.check()?;
+            if findings.iter().any(|finding| matches!(finding, Finding::InvalidGroupDescriptor { .. })) {
+                break;
+            }
+            let mut priorities = findings.iter().filter_map(repair_priority).collect::<Vec<_>>();
+            priorities.sort();
+            priorities.dedup();
+
+            // Only the findings with the lowest priority are repaired before the file system is
+            // checked again, because repairing them changes the other findings.
+            let num_repairs = repairs.len();
+            for priority in priorities {
+                for finding in findings.iter().filter(|finding| repair_priority(finding) == Some(priority)) {
+                    self.repair_finding(finding, &mut repairs)?;
+                }
+                if repairs.len() > num_repairs {
+                    break;
+                }
+            }
+            if repairs.len() == num_repairs {
+                break;
+            }
+        }
+
+        // cached attribute blocks may have been changed or freed
+        self.xattr_block_cache.clear();
+        Ok(repairs)
+    }
+
+    /// Recomputes the bitmaps and the counters of free blocks, free inodes and directories
+    /// from the blocks and inodes that are in use, like pass 5 of the repair.
+    pub(crate) fn rebuild_bitmaps(&mut self) -> Result<(), Error> {
+        let mut repairs = Vec::new();
+        for finding in self.check()?.iter().filter(|finding| finding.pass() == 5) {
+            self.repair_finding(finding, &mut repairs)?;
+        }
+        Ok(())
+    }
+
+    fn repair_finding(&mut self, finding: &Finding, repairs: &mut Vec<Repair>) -> Result<(), Error> {
+        match *finding {
+            Finding::InvalidInodeType { inode, .. } | Finding::InvalidBlockPointer { inode, .. } => {
+                // an inode can have more than one invalid block pointer
+                if !repairs.contains(&Repair::ClearInode { inode }) {
+                    self.clear_inode(inode)?;
+                    repairs.push(Repair::ClearInode { inode });
+                }
+            }
+            Finding::DuplicateBlock { block, ref owners } => {
+                // the first owner keeps the block, unless it is used by the metadata
+                let first = if owners[0] == BlockOwner::Metadata { 0 } else { 1 };
+                for owner in &owners[first..] {
+                    if let BlockOwner::Inode(inode) = *owner {
+                        if self.remove_block_pointer(inode, block)? {
+                            repairs.push(Repair::RemoveBlockPointer { inode, block });
+                        }
+                    }
+                }
+            }
+            Finding::InvalidXattrBlock { inode, block } => {
+                let (_, mut data) = self.read_inode(inode)?;
+                data.set_extended_attribute_block(None);
+                self.write_inode(inode, &data)?;
+                repairs.push(Repair::RemoveXattrBlock { inode, block });
+            }
+            Finding::DeletionTimeSet { inode } => {
+                self.update_inode(inode, |inode| *inode.deletion_time_mut() = 0)?;
+                repairs.push(Repair::ClearDeletionTime { inode });
+            }
+            Finding::WrongSectorCount { inode, actual, .. } => {
+                self.update_inode(inode, |inode| *inode.num_disk_sectors_mut() = actual)?;
+                repairs.push(Repair::SetSectorCount { inode, sectors: actual });
+            }
+            Finding::WrongSize { inode, expected, .. } => {
+                self.update_inode(inode, |inode| inode.set_len(expected))?;
+                repairs.push(Repair::SetSize { inode, size: expected });
+            }
+            Finding::WrongXattrRefcount { block, actual, .. } => {
+                let mut data = vec![0_u8; self.superblock.block_size() as usize];
+                self.read_block(block, &mut data)?;
+                data[4..8].copy_from_slice(&actual.to_le_bytes());
+                self.write_block(block, &data)?;
+                repairs.push(Repair::SetXattrRefcount { block, refcount: actual });
+            }
+
+            Finding::CorruptedDirectory { dir, block_index } => {
+                self.clear_dir_block(dir, block_index)?;
+                repairs.push(Repair::ClearDirectoryBlock { dir, block_index });
+            }
+            Finding::InvalidEntryInode { dir, ref name, inode } => self.repair_entry_target(dir, name, Some(inode), repairs)?,
+            Finding::EntryToUnusedInode { dir, ref name, inode } => self.repair_entry_target(dir, name, Some(inode.get()), repairs)?,
+            Finding::InvalidEntryName { dir, ref name } => self.repair_entry_target(dir, name, None, repairs)?,
+            Finding::DirectoryHardLink { dir, ref name, inode } => self.repair_entry_target(dir, name, Some(inode.get()), repairs)?,
+            Finding::WrongEntryType { dir, ref name, inode, actual, .. } => {
+                let found = self.modify_dir_entry(
+                    dir,
+                    |index, entry| !is_dot_entry(index, entry) && entry.name_bytes() == name.as_slice() && entry.inode() == inode,
+                    |header| header[7] = actual.bits(),
+                )?;
+                if found {
+                    repairs.push(Repair::SetEntryType { dir, name: name.clone(), typ: actual });
+                }
+            }
+
+            Finding::BlockBitmapDifference { ref blocks, used } => {
+                let first_data_block = self.superblock.superblock_block_number();
+                let blocks_per_group = self.superblock.blocks_per_group();
+                let bitmaps = self.bgdt.iter().map(|descriptor| descriptor.block_usage_bitmap_block()).collect::<Vec<_>>();
+                self.mark_in_bitmaps(blocks.start - first_data_block..blocks.end - first_data_block, blocks_per_group, &bitmaps, used)?;
+                repairs.push(Repair::MarkBlocks { blocks: blocks.clone(), used });
+            }
+            Finding::InodeBitmapDifference { ref inodes, used } => {
+                let inodes_per_group = self.superblock.inodes_per_group();
+                let bitmaps = self.bgdt.iter().map(|descriptor| descriptor.inode_usage_bitmap_block()).collect::<Vec<_>>();
+                self.mark_in_bitmaps(inodes.start - 1..inodes.end - 1, inodes_per_group, &bitmaps, used)?;
+                repairs.push(Repair::MarkInodes { inodes: inodes.clone(), used });
+            }
+            Finding::WrongGroupFreeBlocks { group, actual, .. } => {
+                *self.bgdt[group as usize].num_unallocated_blocks_mut() = actual as u16;
+                self.write_block_group_descriptor(group as usize)?;
+                repairs.push(Repair::SetGroupFreeBlocks { group, count: actual });
+            }
+            Finding::WrongGroupFreeInodes { group, actual, .. } => {
+                *self.bgdt[group as usize].num_unallocated_inodes_mut() = actual as u16;
+                self.write_block_group_descriptor(group as usize)?;
+                repairs.push(Repair::SetGroupFreeInodes { group, count: actual });
+            }
+            Finding::WrongGroupDirectories { group, actual, .. } => {
+                *self.bgdt[group as usize].num_directories_mut() = actual as u16;
+                self.write_block_group_descriptor(group as usize)?;
+                repairs.push(Repair::SetGroupDirectories { group, count: actual });
+            }
+            Finding::WrongFreeBlocks { actual, .. } => {
+                *self.superblock.num_unallocated_blocks_mut() = actual;
+                self.write_superblock()?;
+                repairs.push(Repair::SetFreeBlocks { count: actual });
+            }
+            Finding::WrongFreeInodes { actual, .. } => {
+                *self.superblock.num_unallocated_inodes_mut() = actual;
+                self.write_superblock()?;
+                repairs.push(Repair::SetFreeInodes { count: actual });
+            }
+
+            Finding::UnconnectedDirectory { dir, .. } => {
+                let Some(mut lost_and_found) = self.lost_and_found(repairs)? else {
+                    return Ok(());
+                };
+                if lost_and_found.inode_address() == dir {
+                    return Ok(());
+                }
+                let name = self.reconnect(&mut lost_and_found, dir, DirType::Directory)?;
+                self.set_parent(dir, lost_and_found.inode_address())?;
+                *lost_and_found.inode_mut().num_hard_links_mut() += 1;
+                self.write_inode(lost_and_found.inode_address(), &lost_and_found)?;
+                repairs.push(Repair::Reconnect { inode: dir, name });
+            }
+            Finding::WrongParent { dir, actual, .. } => {
+                if self.set_parent(dir, actual)? {
+                    repairs.push(Repair::SetParent { dir, parent: actual });
+                }
+            }
+
+            Finding::UnattachedInode { inode } => {
+                let (_, data) = self.read_inode(inode)?;
+                // like e2fsck, empty regular files are not worth keeping
+                if data.typ() == Type::RegularFile && data.num_disk_sectors() == 0 {
+                    self.clear_inode(inode)?;
+                    repairs.push(Repair::ClearInode { inode });
+                    return Ok(());
+                }
+                let Some(mut lost_and_found) = self.lost_and_found(repairs)? else {
+                    return Ok(());
+                };
+                let name = self.reconnect(&mut lost_and_found, inode, data.typ().into())?;
+                repairs.push(Repair::Reconnect { inode, name });
+            }
+            Finding::WrongLinkCount { inode, actual, .. } => {
+                let links = actual.min(u16::MAX as u32) as u16;
+                self.update_inode(inode, |inode| *inode.num_hard_links_mut() = links)?;
+                repairs.push(Repair::SetLinkCount { inode, links });
+            }
+
+            Finding::InvalidGroupDescriptor { .. } | Finding::MissingDot { .. } | Finding::MissingDotDot { .. } | Finding::RootNotDirectory => {}
+        }
+        Ok(())
+    }
+
+    fn update_inode(&mut self, address: InodeAddress, update: impl FnOnce(&mut Inode)) -> Result<(), Error> {
+        let (_, mut inode) = self.read_inode(address)?;
+        update(&mut inode);
+        self.write_inode(address, &inode)
+    }
+
+    /// Marks the inode as deleted, and removes all its blocks. The bitmaps are not changed.
+    fn clear_inode(&mut self, address: InodeAddress) -> Result<(), Error> {
+        // e2fsck expects a deletion time for inodes without links, even without a clock
+        let deletion_time = self.now().max(1);
+        self.update_inode(address, |inode| {
+            *inode.num_hard_links_mut() = 0;
+            *inode.deletion_time_mut() = deletion_time;
+            inode.set_block_ptr_bytes([0; 60]);
+            inode.set_extended_attribute_block(None);
+            *inode.num_disk_sectors_mut() = 0;
+            inode.set_len(0);
+        })
+    }
+
+    /// Removes the first pointer to the given block from the block tree or the attribute
+    /// block of the inode. Returns whether the inode points to the block.
+    fn remove_block_pointer(&mut self, address: InodeAddress, block: BlockAddress) -> Result<bool, Error> {
+        let (_, mut inode) = self.read_inode(address)?;
+        let direct_index = inode.direct_ptrs().position(|ptr| ptr == Some(block));
+        let found = if inode.extended_attribute_block() == Some(block) {
+            inode.set_extended_attribute_block(None);
+            true
+        } else if let Some(index) = direct_index {
+            inode.set_direct_ptr(index, None);
+            true
+        } else if inode.single_indirect_ptr() == Some(block) {
+            inode.set_single_indirect_ptr(None);
+            true
+        } else if inode.double_indirect_ptr() == Some(block) {
+            inode.set_double_indirect_ptr(None);
+            true
+        } else if inode.triple_indirect_ptr() == Some(block) {
+            inode.set_triple_indirect_ptr(None);
+            true
+        } else {
+            let tables = [(inode.single_indirect_ptr(), 1), (inode.double_indirect_ptr(), 2), (inode.triple_indirect_ptr(), 3)];
+            for (table, level) in tables {
+                if let Some(table) = table {
+                    if self.remove_indirect_block_pointer(table, level, block)? {
+                        return Ok(true);
+                    }
+                }
+            }
+            false
+        };
+        if found {
+            self.write_inode(address, &inode)?;
+        }
+        Ok(found)
+    }
+
+    fn remove_indirect_block_pointer(&mut self, table: BlockAddress, level: u32, block: BlockAddress) -> Result<bool, Error> {
+        if table.get() >= self.superblock.num_blocks() {
+            return Ok(false);
+        }
+        let mut data = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(table, &mut data)?;
+        let ptrs = data.chunks_exact(4).map(|ptr| u32::from_le_bytes(ptr.try_into().unwrap())).collect::<Vec<_>>();
+        if let Some(index) = ptrs.iter().position(|&ptr| ptr == block.get()) {
+            data[index * 4..index * 4 + 4].fill(0);
+            self.write_block(table, &data)?;
+            return Ok(true);
+        }
+        if level > 1 {
+            for ptr in ptrs.into_iter().filter_map(BlockAddress::new) {
+                if self.remove_indirect_block_pointer(ptr, level - 1, block)? {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Removes an entry that points to an invalid or unused inode, or that has an invalid name.
+    /// A `..` entry can't be removed, so it points to the root directory until the parent of
+    /// the directory is known.
+    fn repair_entry_target(&mut self, dir: InodeAddress, name: &[u8], inode: Option<u32>, repairs: &mut Vec<Repair>) -> Result<(), Error> {
+        let matches = |entry: &DirEntry| entry.name_bytes() == name && inode.is_none_or(|inode| entry.inode().get() == inode);
+        if self.modify_dir_entry(dir, |index, entry| index == 1 && entry.name_bytes() == b".." && matches(entry), |header| {
+            header[0..4].copy_from_slice(&ROOT_DIR_INODE_ADDRESS.get().to_le_bytes())
+        })? {
+            repairs.push(Repair::SetParent { dir, parent: ROOT_DIR_INODE_ADDRESS });
+        } else if self.modify_dir_entry(dir, |index, entry| !is_dot_entry(index, entry) && matches(entry), |header| header[0..4].fill(0))? {
+            repairs.push(Repair::RemoveEntry { dir, name: name.to_vec() });
+        }
+        Ok(())
+    }
+
+    /// Finds the first entry of the given directory for which `matches` returns `true`, with
+    /// the index of the entry in the directory, and changes its 8 byte header with `modify`.
+    /// Returns whether an entry was found. Blocks with corrupted entries are skipped.
+    fn modify_dir_entry(&mut self, dir: InodeAddress, matches: impl Fn(usize, &DirEntry) -> bool, modify: impl FnOnce(&mut [u8])) -> Result<bool, Error> {
+        let (_, inode) = self.read_inode(dir)?;
+        let block_size = self.superblock.block_size() as usize;
+        let dir_entries_have_type = self.superblock.required_features().contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE);
+        let mut data = vec![0_u8; block_size];
+
+        let mut entry_index = 0;
+        for block_index in 0..(inode.len() / block_size as u64) as u32 {
+            let Some(block) = self.resolve_block_index(&inode, block_index)? else {
+                continue;
+            };
+            self.read_block(block, &mut data)?;
+            let Ok(entries) = parse_dir_block(dir_entries_have_type, &data) else {
+                continue;
+            };
+            for (offset, entry) in entries {
+                if matches(entry_index, &entry) {
+                    modify(&mut data[offset..offset + 8]);
+                    self.write_block(block, &data)?;
+                    return Ok(true);
+                }
+                entry_index += 1;
+            }
+        }
+        Ok(false)
+    }
+
+    /// Changes the `..` entry of the given directory. Returns whether the directory has one.
+    fn set_parent(&mut self, dir: InodeAddress, parent: InodeAddress) -> Result<bool, Error> {
+        self.modify_dir_entry(dir, |index, entry| index == 1 && entry.name_bytes() == b"..", |header| {
+            header[0..4].copy_from_slice(&parent.get().to_le_bytes())
+        })
+    }
+
+    /// Replaces the given block of the directory with an empty block. The first block gets
+    /// new `.` and `..` entries, where `..` points to the root directory until the parent
+    /// of the directory is known.
+    fn clear_dir_block(&mut self, dir: InodeAddress, block_index: u32) -> Result<(), Error> {
+        let (_, mut inode) = self.read_inode(dir)?;
+        let Some(block) = self.resolve_block_index(&inode, block_index)? else {
+            return Ok(());
+        };
+        let data = self.serialize_dir_block(Vec::new());
+        self.write_block(block, &data)?;
+        if block_index == 0 {
+            self.try_add_entry_to_block(block, b".", dir, DirType::Directory)?;
+            self.try_add_entry_to_block(block, b"..", ROOT_DIR_INODE_ADDRESS, DirType::Directory)?;
+        }
+
+        // the index may point to the cleared block
+        if inode.flags().contains(Flags::HashIndexedDirectory) {
+            inode.set_flags(inode.flags() - Flags::HashIndexedDirectory);
+            self.write_inode(dir, &inode)?;
+        }
+        Ok(())
+    }
+
+    /// Sets or clears the bits for the given items, which are numbered from zero, in the
+    /// bitmaps of the groups.
+    fn mark_in_bitmaps(&mut self, items: Range<u32>, items_per_group: u32, bitmaps: &[u32], used: bool) -> Result<(), Error> {
+        let mut data = vec![0_u8; self.superblock.block_size() as usize];
+        let mut current = None;
+        for item in items {
+            let bitmap = BlockAddress::new(bitmaps[(item / items_per_group) as usize]).ok_or(Error::InvalidBlockAddress(0))?;
+            if current != Some(bitmap) {
+                if let Some(current) = current {
+                    self.write_block(current, &data)?;
+                }
+                self.read_block(bitmap, &mut data)?;
+                current = Some(bitmap);
+            }
+            let index = (item % items_per_group) as usize;
+            if used {
+                data[index / 8] |= 1 << (index % 8);
+            } else {
+                data[index / 8] &= !(1 << (index % 8));
+            }
+        }
+        if let Some(current) = current {
+            self.write_block(current, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the `lost+found` directory, and creates it if it doesn't exist. Returns
+    /// `None` if there is an entry with that name that is not a directory.
+    fn lost_and_found(&mut self, repairs: &mut Vec<Repair>) -> Result<Option<Directory>, Error> {
+        let mut root = self.read_root_inode()?;
+        if let Some(entry) = self.find_entry_by_name(&root, b"lost+found")? {
+            return Ok(Directory::try_from(self.read_inode(entry.inode())?).ok());
+        }
+
+        let mut lost_and_found = self.create_dir(&mut root, "lost+found")?;
+        lost_and_found.inode_mut().set_perm(Permissions::from_bits_truncate(0o700));
+        self.write_inode(lost_and_found.inode_address(), &lost_and_found)?;
+        repairs.push(Repair::CreateLostAndFound { inode: lost_and_found.inode_address() });
+        Ok(Some(lost_and_found))
+    }
+
+    /// Adds an entry for the inode to `lost+found`, named after the inode number, like
+    /// e2fsck does. The link count of the inode is not changed.
+    fn reconnect(&mut self, lost_and_found: &mut Directory, inode: InodeAddress, typ: DirType) -> Result<Vec<u8>, Error> {
+        let name = alloc::format!("#{}", inode).into_bytes();
+        self.add_entry_to_dir(lost_and_found, &name, inode, typ)?;
+        Ok(name)
+    }
+}
+
+/// Whether the entry is the `.` or `..` entry at the start of a directory.
+fn is_dot_entry(index: usize, entry: &DirEntry) -> bool {
+    (index == 0 && entry.name_bytes() == b".") || (index == 1 && entry.name_bytes() == b"..")
+}
@@ -0,0 +1,460 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use filesystem::BlockDevice;
+
+use crate::block_group::BlockGroupDescriptor;
+use crate::check::{Checker, InodeState, RESIZE_INODE_ADDRESS};
+use crate::dir::parse_dir_block;
+use crate::format::set_bits;
+use crate::{BlockAddress, BlockOwner, Error, Ext2Fs, Inode, InodeAddress, OptionalFeatures, RequiredFeatures, Type, BGD_SIZE};
+
+/// The size of a resized file system, and the size of its block group descriptor table.
+struct Geometry {
+    num_blocks: u32,
+    num_groups: u32,
+    gdt_blocks: u32,
+    reserved_gdt_blocks: u32,
+}
+
+/// The blocks and inodes that are in use, as found by the checker.
+struct Usage {
+    block_owners: Vec<Option<BlockOwner>>,
+    inodes: Vec<InodeState>,
+}
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Changes the number of blocks of the file system, like `resize2fs`, and returns the new
+    /// number of blocks. Blocks at the end that are too few for another block group are not
+    /// used, so the result may be smaller than the requested number.
+    ///
+    /// Growing extends the last group and adds new groups, which the device must have space
+    /// for. Shrinking moves the blocks and inodes out of the removed part of the file system,
+    /// and fails with [`Error::NoSpace`] if they don't fit into the remaining part. The device
+    /// is not truncated. In both cases, the block group descriptor table can only grow into
+    /// the blocks that are reserved for it.
+    ///
+    /// The file system must be consistent, otherwise [`Error::Inconsistent`] is returned, and
+    /// it is not changed if resizing fails before any blocks are moved.
+    pub fn resize(&mut self, num_blocks: u32) -> Result<u32, Error> {
+        self.discard_all_preallocated_blocks();
+        let usage = self.usage()?;
+        let geometry = self.geometry(num_blocks)?;
+        match geometry.num_blocks.cmp(&self.superblock.num_blocks()) {
+            core::cmp::Ordering::Greater => self.grow(&geometry)?,
+            core::cmp::Ordering::Less => self.shrink(&geometry, usage)?,
+            core::cmp::Ordering::Equal => return Ok(geometry.num_blocks),
+        }
+
+        // the descriptor table may have grown into the reserved blocks, or the other way around
+        let reserved_blocks = self.superblock.num_superuser_reserved_blocks() as u64 * geometry.num_blocks as u64 / self.superblock.num_blocks() as u64;
+        self.superblock.set_size(geometry.num_blocks, geometry.num_groups * self.superblock.inodes_per_group());
+        self.superblock.set_num_superuser_reserved_blocks(reserved_blocks as u32);
+        self.superblock.set_num_reserved_gdt_blocks(geometry.reserved_gdt_blocks as u16);
+        self.set_bitmap_padding(geometry.num_groups as usize - 1)?;
+        if self.superblock.optional_features().contains(OptionalFeatures::CAN_RESIZE) {
+            self.write_resize_inode(&geometry)?;
+        }
+
+        self.rebuild_bitmaps()?;
+        self.write_metadata_copies(&geometry)?;
+        // cached attribute blocks may have been moved
+        self.xattr_block_cache.clear();
+        Ok(geometry.num_blocks)
+    }
+
+    /// Returns the smallest number of blocks that the file system can be shrunk to with
+    /// [`Ext2Fs::resize`], so that all blocks and inodes that are in use still fit.
+    ///
+    /// The file system must be consistent and synced, like for [`Ext2Fs::check`].
+    pub fn min_num_blocks(&self) -> Result<u32, Error> {
+        let usage = self.usage()?;
+        let superblock = &self.superblock;
+        let first_data_block = superblock.superblock_block_number();
+        let blocks_per_group = superblock.blocks_per_group();
+        let used_blocks = usage.block_owners.iter().filter(|owner| matches!(owner, Some(BlockOwner::Inode(_)))).count() as u32;
+        let used_inodes = usage.inodes.iter().filter(|state| state.used).count() as u32 + superblock.first_non_reserved_inode() - 1;
+
+        for num_groups in 1..=self.bgdt.len() as u32 {
+            if num_groups * superblock.inodes_per_group() < used_inodes {
+                continue;
+            }
+            let Ok(geometry) = self.geometry_with_groups(num_groups, first_data_block + num_groups * blocks_per_group) else {
+                continue;
+            };
+            // the data blocks that fit into the full groups, the rest goes into the last group
+            let capacity = (0..num_groups - 1).map(|group| blocks_per_group - self.metadata_blocks(&geometry, group)).sum::<u32>();
+            let last_group_blocks = self.metadata_blocks(&geometry, num_groups - 1) + used_blocks.saturating_sub(capacity).max(1);
+            if last_group_blocks <= blocks_per_group {
+                let num_blocks = first_data_block + (num_groups - 1) * blocks_per_group + last_group_blocks;
+                return Ok(num_blocks.min(superblock.num_blocks()));
+            }
+        }
+        Ok(superblock.num_blocks())
+    }
+
+    /// Checks the file system, and returns the blocks and inodes that are in use.
+    fn usage(&self) -> Result<Usage, Error> {
+        let mut checker = Checker::new(self);
+        checker.run()?;
+        if !checker.findings.is_empty() {
+            return Err(Error::Inconsistent);
+        }
+        Ok(Usage {
+            block_owners: checker.block_owners,
+            inodes: checker.inodes,
+        })
+    }
+
+    /// The layout of the file system with the given number of blocks. The last group must
+    /// have space for its metadata and at least one data block, otherwise it is dropped.
+    fn geometry(&self, num_blocks: u32) -> Result<Geometry, Error> {
+        let first_data_block = self.superblock.superblock_block_number();
+        let blocks_per_group = self.superblock.blocks_per_group();
+        let mut num_blocks = num_blocks;
+        loop {
+            if num_blocks <= first_data_block {
+                return Err(Error::NoSpace);
+            }
+            let num_groups = (num_blocks - first_data_block).div_ceil(blocks_per_group);
+            let geometry = self.geometry_with_groups(num_groups, num_blocks)?;
+            let last_group = num_groups - 1;
+            let last_group_blocks = num_blocks - (first_data_block + last_group * blocks_per_group);
+            if last_group_blocks > self.metadata_blocks(&geometry, last_group) {
+                return Ok(geometry);
+            }
+            if last_group == 0 {
+                return Err(Error::NoSpace);
+            }
+            num_blocks -= last_group_blocks;
+        }
+    }
+
+    /// The layout of the file system with the given number of groups. The descriptor table
+    /// can only grow into the blocks that are reserved for it, since it is followed by the
+    /// bitmaps in every group with a superblock.
+    fn geometry_with_groups(&self, num_groups: u32, num_blocks: u32) -> Result<Geometry, Error> {
+        let block_size = self.superblock.block_size();
+        num_groups.checked_mul(self.superblock.inodes_per_group()).ok_or(Error::NotSupported)?;
+        let old_gdt_blocks = (self.bgdt.len() as u32 * BGD_SIZE as u32).div_ceil(block_size);
+        let old_reserved_gdt_blocks = self.superblock.num_reserved_gdt_blocks() as u32;
+        let gdt_blocks = (num_groups * BGD_SIZE as u32).div_ceil(block_size);
+        if gdt_blocks > old_gdt_blocks + old_reserved_gdt_blocks {
+            return Err(Error::NotSupported);
+        }
+
+        // blocks of the table that are not needed anymore are reserved, as far as the resize
+        // inode can point to them, and free otherwise
+        let reserved_gdt_blocks = if self.superblock.optional_features().contains(OptionalFeatures::CAN_RESIZE) {
+            (old_gdt_blocks + old_reserved_gdt_blocks - gdt_blocks).min(block_size / 4)
+        } else {
+            old_reserved_gdt_blocks.saturating_sub(gdt_blocks.saturating_sub(old_gdt_blocks))
+        };
+        Ok(Geometry {
+            num_blocks,
+            num_groups,
+            gdt_blocks,
+            reserved_gdt_blocks,
+        })
+    }
+
+    /// The number of blocks at the start of the group with the given index that are used by metadata.
+    fn metadata_blocks(&self, geometry: &Geometry, group_index: u32) -> u32 {
+        let superblock_blocks = if self.superblock.group_has_superblock(group_index) {
+            1 + geometry.gdt_blocks + geometry.reserved_gdt_blocks
+        } else {
+            0
+        };
+        superblock_blocks + 2 + self.inode_table_blocks()
+    }
+
+    fn inode_table_blocks(&self) -> u32 {
+        (self.superblock.inodes_per_group() * self.superblock.inode_size() as u32).div_ceil(self.superblock.block_size())
+    }
+
+    /// Adds the new groups, with empty bitmaps and inode tables. Their free blocks and inodes
+    /// are counted when the bitmaps are rebuilt.
+    fn grow(&mut self, geometry: &Geometry) -> Result<(), Error> {
+        let block_size = self.superblock.block_size();
+        let device_size = self.block_device.sector_size() as u64 * self.block_device.sector_count() as u64;
+        if device_size < geometry.num_blocks as u64 * block_size as u64 {
+            return Err(Error::NoSpace);
+        }
+
+        let first_data_block = self.superblock.superblock_block_number();
+        let blocks_per_group = self.superblock.blocks_per_group();
+        let inodes_per_group = self.superblock.inodes_per_group();
+        let zeros = vec![0_u8; block_size as usize];
+        for group_index in self.bgdt.len() as u32..geometry.num_groups {
+            let group_start = first_data_block + group_index * blocks_per_group;
+            let block_bitmap = group_start + self.metadata_blocks(geometry, group_index) - self.inode_table_blocks() - 2;
+            let inode_bitmap = block_bitmap + 1;
+            let inode_table = block_bitmap + 2;
+            // the superblock and the descriptor table are written later, but the reserved blocks are cleared
+            for block in (group_start..block_bitmap).chain(inode_table..inode_table + self.inode_table_blocks()) {
+                self.write_block(BlockAddress::new(block).unwrap(), &zeros)?; // the first group already exists
+            }
+
+            let mut bitmap = vec![0_u8; block_size as usize];
+            self.write_block(BlockAddress::new(block_bitmap).unwrap(), &bitmap)?;
+            set_bits(&mut bitmap, inodes_per_group..block_size * 8);
+            self.write_block(BlockAddress::new(inode_bitmap).unwrap(), &bitmap)?;
+            self.bgdt.push(BlockGroupDescriptor::new(block_bitmap, inode_bitmap, inode_table, 0, 0));
+        }
+        Ok(())
+    }
+
+    /// Moves the blocks and inodes that are in use out of the removed groups, and removes
+    /// the groups. Nothing is changed if they don't fit into the remaining groups.
+    fn shrink(&mut self, geometry: &Geometry, usage: Usage) -> Result<(), Error> {
+        let superblock = &self.superblock;
+        let first_data_block = superblock.superblock_block_number();
+        let first_inode = superblock.first_non_reserved_inode();
+        let num_inodes = geometry.num_groups * superblock.inodes_per_group();
+        let inode_table_blocks = self.inode_table_blocks();
+        // the metadata of the remaining groups must be inside the file system
+        let metadata_fits = self.bgdt[..geometry.num_groups as usize].iter().all(|descriptor| {
+            descriptor.block_usage_bitmap_block() < geometry.num_blocks
+                && descriptor.inode_usage_bitmap_block() < geometry.num_blocks
+                && descriptor.inode_table_starting_block() + inode_table_blocks <= geometry.num_blocks
+        });
+        if !metadata_fits {
+            return Err(Error::NotSupported);
+        }
+
+        // the new locations of the inodes and blocks that are moved
+        let mut free_inodes = (first_inode..=num_inodes).filter(|&inode| !usage.inodes[inode as usize - 1].used);
+        let mut inode_map = BTreeMap::new();
+        for inode in num_inodes + 1..=superblock.num_inodes() {
+            if usage.inodes[inode as usize - 1].used {
+                inode_map.insert(inode, free_inodes.next().ok_or(Error::NoSpace)?);
+            }
+        }
+        let mut free_blocks = (first_data_block..geometry.num_blocks).filter(|&block| usage.block_owners[block as usize].is_none());
+        let mut block_map = BTreeMap::new();
+        for block in geometry.num_blocks..superblock.num_blocks() {
+            if let Some(BlockOwner::Inode(_)) = usage.block_owners[block as usize] {
+                block_map.insert(block, free_blocks.next().ok_or(Error::NoSpace)?);
+            }
+        }
+
+        let mut data = vec![0_u8; superblock.block_size() as usize];
+        for (&old, &new) in &block_map {
+            self.read_block(BlockAddress::new(old).unwrap(), &mut data)?; // blocks after the first group are not zero
+            self.write_block(BlockAddress::new(new).unwrap(), &data)?;
+        }
+        for (&old, &new) in &inode_map {
+            let (_, inode) = self.read_inode(InodeAddress::new(old).unwrap())?; // inodes after the first group are not zero
+            self.write_inode(InodeAddress::new(new).unwrap(), &inode)?;
+        }
+
+        let can_resize = self.superblock.optional_features().contains(OptionalFeatures::CAN_RESIZE);
+        for number in 1..=num_inodes {
+            let address = InodeAddress::new(number).unwrap(); // we start at 1
+            let (_, mut inode) = self.read_inode(address)?;
+            let changed = if address == RESIZE_INODE_ADDRESS && can_resize {
+                // the resize inode is rebuilt later, only its double indirect block may be moved
+                let moved = remap(inode.double_indirect_ptr(), &block_map);
+                if moved.is_some() {
+                    inode.set_double_indirect_ptr(moved);
+                }
+                moved.is_some()
+            } else if number < first_inode || self.has_blocks(&inode) {
+                self.remap_blocks(&mut inode, &block_map)?
+            } else if inode.num_hard_links() != 0 {
+                // devices and fast symlinks only have an attribute block
+                let moved = remap(inode.extended_attribute_block(), &block_map);
+                if moved.is_some() {
+                    inode.set_extended_attribute_block(moved);
+                }
+                moved.is_some()
+            } else {
+                false
+            };
+            if changed {
+                self.write_inode(address, &inode)?;
+            }
+        }
+        if !inode_map.is_empty() {
+            self.remap_dir_entries(num_inodes, &inode_map)?;
+        }
+
+        self.bgdt.truncate(geometry.num_groups as usize);
+        Ok(())
+    }
+
+    /// Whether the inode is in use and has a block tree.
+    fn has_blocks(&self, inode: &Inode) -> bool {
+        let typ = inode.typ();
+        inode.num_hard_links() != 0 && (typ == Type::RegularFile || typ == Type::Directory || (typ == Type::SymLink && !self.is_fast_symlink(inode)))
+    }
+
+    /// Changes the pointers to moved blocks in the block tree and the attribute block of
+    /// the inode. The inode itself is not written, returns whether it was changed.
+    fn remap_blocks(&mut self, inode: &mut Inode, block_map: &BTreeMap<u32, u32>) -> Result<bool, Error> {
+        let mut changed = false;
+        let direct_ptrs = inode.direct_ptrs().collect::<Vec<_>>();
+        for (index, ptr) in direct_ptrs.into_iter().enumerate() {
+            if let Some(moved) = remap(ptr, block_map) {
+                inode.set_direct_ptr(index, Some(moved));
+                changed = true;
+            }
+        }
+        if let Some(moved) = remap(inode.extended_attribute_block(), block_map) {
+            inode.set_extended_attribute_block(Some(moved));
+            changed = true;
+        }
+
+        let tables = [
+            (inode.single_indirect_ptr(), 1, Inode::set_single_indirect_ptr as fn(&mut Inode, Option<BlockAddress>)),
+            (inode.double_indirect_ptr(), 2, Inode::set_double_indirect_ptr),
+            (inode.triple_indirect_ptr(), 3, Inode::set_triple_indirect_ptr),
+        ];
+        for (table, level, set_ptr) in tables {
+            let Some(mut table) = table else {
+                continue;
+            };
+            if let Some(moved) = remap(Some(table), block_map) {
+                set_ptr(inode, Some(moved));
+                table = moved;
+                changed = true;
+            }
+            self.remap_indirect_block(table, level, block_map)?;
+        }
+        Ok(changed)
+    }
+
+    fn remap_indirect_block(&mut self, table: BlockAddress, level: u32, block_map: &BTreeMap<u32, u32>) -> Result<(), Error> {
+        let mut data = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(table, &mut data)?;
+        let mut changed = false;
+        for index in 0..data.len() / 4 {
+            let ptr = BlockAddress::new(u32::from_le_bytes(data[index * 4..index * 4 + 4].try_into().unwrap()));
+            let Some(mut ptr) = ptr else {
+                continue;
+            };
+            if let Some(moved) = remap(Some(ptr), block_map) {
+                data[index * 4..index * 4 + 4].copy_from_slice(&moved.get().to_le_bytes());
+                ptr = moved;
+                changed = true;
+            }
+            if level > 1 {
+                self.remap_indirect_block(ptr, level - 1, block_map)?;
+            }
+        }
+        if changed {
+            self.write_block(table, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Changes the entries of all directories that point to moved inodes, including the
+    /// `.` and `..` entries.
+    fn remap_dir_entries(&mut self, num_inodes: u32, inode_map: &BTreeMap<u32, u32>) -> Result<(), Error> {
+        let block_size = self.superblock.block_size() as usize;
+        let dir_entries_have_type = self.superblock.required_features().contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE);
+        let mut data = vec![0_u8; block_size];
+        for number in 1..=num_inodes {
+            let (_, inode) = self.read_inode(InodeAddress::new(number).unwrap())?; // we start at 1
+            if inode.num_hard_links() == 0 || inode.typ() != Type::Directory {
+                continue;
+            }
+            for block_index in 0..(inode.len() / block_size as u64) as u32 {
+                let Some(block) = self.resolve_block_index(&inode, block_index)? else {
+                    continue;
+                };
+                self.read_block(block, &mut data)?;
+                let mut changed = false;
+                for (offset, entry) in parse_dir_block(dir_entries_have_type, &data)? {
+                    if let Some(&moved) = inode_map.get(&entry.inode().get()) {
+                        data[offset..offset + 4].copy_from_slice(&moved.to_le_bytes());
+                        changed = true;
+                    }
+                }
+                if changed {
+                    self.write_block(block, &data)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the blocks after the end of the last group as used, so that they are never allocated.
+    fn set_bitmap_padding(&mut self, last_group: usize) -> Result<(), Error> {
+        let block_size = self.superblock.block_size();
+        let mut bitmap = vec![0_u8; block_size as usize];
+        let block_bitmap = BlockAddress::new(self.bgdt[last_group].block_usage_bitmap_block()).ok_or(Error::InvalidBlockAddress(0))?;
+        self.read_block(block_bitmap, &mut bitmap)?;
+        set_bits(&mut bitmap, self.blocks_in_group(last_group)..block_size * 8);
+        self.write_block(block_bitmap, &bitmap)?;
+        Ok(())
+    }
+
+    /// Points the resize inode to the reserved blocks of the descriptor table. Its double
+    /// indirect block points to the reserved blocks after the primary table, like an
+    /// indirect block, and every reserved block lists its copies in the other groups with
+    /// a superblock.
+    fn write_resize_inode(&mut self, geometry: &Geometry) -> Result<(), Error> {
+        let (_, mut inode) = self.read_inode(RESIZE_INODE_ADDRESS)?;
+        let Some(dind) = inode.double_indirect_ptr() else {
+            return Ok(());
+        };
+        let block_size = self.superblock.block_size();
+        let first_data_block = self.superblock.superblock_block_number();
+        let blocks_per_group = self.superblock.blocks_per_group();
+        let ptrs_per_block = block_size / 4;
+        let backups = (1..geometry.num_groups).filter(|&group| self.superblock.group_has_superblock(group)).collect::<Vec<_>>();
+
+        let mut dind_data = vec![0_u8; block_size as usize];
+        let mut data = vec![0_u8; block_size as usize];
+        for i in 0..geometry.reserved_gdt_blocks {
+            let reserved_block = first_data_block + 1 + geometry.gdt_blocks + i;
+            let index = ((geometry.gdt_blocks + i) % ptrs_per_block) as usize;
+            dind_data[index * 4..index * 4 + 4].copy_from_slice(&reserved_block.to_le_bytes());
+
+            data.fill(0);
+            for (index, group) in backups.iter().enumerate() {
+                let copy = reserved_block + group * blocks_per_group;
+                data[index * 4..index * 4 + 4].copy_from_slice(&copy.to_le_bytes());
+            }
+            self.write_block(BlockAddress::new(reserved_block).unwrap(), &data)?; // the block is after the superblock
+        }
+        self.write_block(dind, &dind_data)?;
+
+        let num_blocks = 1 + geometry.reserved_gdt_blocks * (1 + backups.len() as u32);
+        *inode.num_disk_sectors_mut() = num_blocks * (block_size / 512);
+        self.write_inode(RESIZE_INODE_ADDRESS, &inode)
+    }
+
+    /// Writes the superblock and the block group descriptor table to every group with a
+    /// superblock.
+    fn write_metadata_copies(&mut self, geometry: &Geometry) -> Result<(), Error> {
+        self.write_superblock_copies()?;
+
+        let block_size = self.superblock.block_size() as usize;
+        let mut descriptors = vec![0_u8; geometry.gdt_blocks as usize * block_size];
+        for (group_index, descriptor) in self.bgdt.iter().enumerate() {
+            let offset = group_index * BGD_SIZE;
+            descriptors[offset..offset + BGD_SIZE].copy_from_slice(&Into::<[u8; BGD_SIZE]>::into(descriptor));
+        }
+
+        let first_data_block = self.superblock.superblock_block_number();
+        let blocks_per_group = self.superblock.blocks_per_group();
+        for group_index in (0..geometry.num_groups).filter(|&group| self.superblock.group_has_superblock(group)) {
+            let group_start = (first_data_block + group_index * blocks_per_group) as usize;
+            self.block_device
+                .write_at((group_start + 1) * block_size, &descriptors)
+                .map_err(|_| Error::UnableToWriteBlockGroupDescriptorTable)?;
+        }
+        Ok(())
+    }
+}
+
+/// The new location of the given block, if it was moved.
+fn remap(block: Option<BlockAddress>, block_map: &BTreeMap<u32, u32>) -> Option<BlockAddress> {
+    block.and_then(|block| block_map.get(&block.get())).and_then(|&moved| BlockAddress::new(moved))
+}
@@ -0,0 +1,139 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use filesystem::BlockDevice;
+
+use crate::{BlockAddress, Error, Ext2Fs, Inode, InodeAddress, Type};
+
+/// What an inode uses a block for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BlockUse {
+    /// A data block, with its index in the file.
+    Data(u64),
+    /// An indirect block, with its level, which is `1` for single indirect blocks.
+    Indirect(u32),
+    /// The extended attribute block, which can be shared by several inodes.
+    Xattr,
+}
+
+/// A use of a block by an inode, as found by [`Ext2Fs::block_map`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BlockRef {
+    pub inode: InodeAddress,
+    pub usage: BlockUse,
+}
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Returns the data and indirect blocks of the given inode, in the order of its block
+    /// tree, where every indirect block comes before the blocks that it points to. Devices
+    /// and fast symlinks store other data in their block pointers, so they have no blocks.
+    /// Indirect blocks outside of the file system are returned, but not read.
+    pub fn inode_blocks(&self, inode: &Inode) -> Result<Vec<(BlockAddress, BlockUse)>, Error> {
+        let has_blocks = match inode.typ() {
+            Type::RegularFile | Type::Directory => true,
+            Type::SymLink => !self.is_fast_symlink(inode),
+            _ => false,
+        };
+        if !has_blocks {
+            return Ok(Vec::new());
+        }
+        self.block_tree(inode)
+    }
+
+    /// Returns the blocks that the block pointers of the given inode point to, like
+    /// [`Ext2Fs::inode_blocks`], but whatever the type of the inode is. Reserved inodes
+    /// like the bad blocks inode have blocks, but no file type.
+    pub(crate) fn block_tree(&self, inode: &Inode) -> Result<Vec<(BlockAddress, BlockUse)>, Error> {
+        let mut blocks = inode
+            .direct_ptrs()
+            .enumerate()
+            .filter_map(|(index, ptr)| ptr.map(|ptr| (ptr, BlockUse::Data(index as u64))))
+            .collect::<Vec<_>>();
+        let (direct_limit, indirect_limit, double_indirect_limit) = self.indirect_pointer_limits();
+        let indirect_ptrs = [
+            (inode.single_indirect_ptr(), 1, direct_limit),
+            (inode.double_indirect_ptr(), 2, indirect_limit),
+            (inode.triple_indirect_ptr(), 3, double_indirect_limit),
+        ];
+        for (block, level, first_index) in indirect_ptrs {
+            if let Some(block) = block {
+                self.walk_indirect_block(block, level, first_index as u64, &mut blocks)?;
+            }
+        }
+        Ok(blocks)
+    }
+
+    fn walk_indirect_block(&self, block: BlockAddress, level: u32, first_index: u64, blocks: &mut Vec<(BlockAddress, BlockUse)>) -> Result<(), Error> {
+        blocks.push((block, BlockUse::Indirect(level)));
+        if block.get() >= self.superblock.num_blocks() {
+            return Ok(());
+        }
+
+        let block_size = self.superblock.block_size() as usize;
+        let mut data = vec![0_u8; block_size];
+        self.read_block(block, &mut data)?;
+
+        // the number of data blocks that every pointer in this block covers
+        let span = (block_size as u64 / 4).pow(level - 1);
+        for (i, ptr) in data.chunks_exact(4).map(|ptr| u32::from_le_bytes(ptr.try_into().unwrap())).enumerate() {
+            let Some(ptr) = BlockAddress::new(ptr) else {
+                continue;
+            };
+            let index = first_index + i as u64 * span;
+            if level > 1 {
+                self.walk_indirect_block(ptr, level - 1, index, blocks)?;
+            } else {
+                blocks.push((ptr, BlockUse::Data(index)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the inodes that use every block, like `debugfs icheck`, by walking the block
+    /// trees of all used inodes. Blocks that are used more than once, like shared extended
+    /// attribute blocks, have all their uses. Free blocks and blocks that only contain
+    /// metadata of the file system are missing.
+    pub fn block_map(&self) -> Result<BTreeMap<BlockAddress, Vec<BlockRef>>, Error> {
+        let mut map: BTreeMap<BlockAddress, Vec<BlockRef>> = BTreeMap::new();
+        for inode_address in self.used_inodes()? {
+            let (_, inode) = self.read_inode(inode_address)?;
+            let xattr_block = inode.extended_attribute_block().map(|block| (block, BlockUse::Xattr));
+            for (block, usage) in self.inode_blocks(&inode)?.into_iter().chain(xattr_block) {
+                map.entry(block).or_default().push(BlockRef { inode: inode_address, usage });
+            }
+        }
+        Ok(map)
+    }
+
+    /// Finds all paths that lead from the root directory to every inode, like `debugfs ncheck`.
+    /// Inodes with more than one hard link have more than one path, in sorted order. Inodes
+    /// that can't be reached from the root directory are missing.
+    pub fn inode_paths(&self) -> Result<BTreeMap<InodeAddress, Vec<Vec<u8>>>, Error> {
+        let (root_address, root) = self.read_root_inode()?.into_inner();
+        let mut paths = BTreeMap::from([(root_address, vec![b"/".to_vec()])]);
+        // directories are only entered once, in case the file system is corrupted
+        let mut visited = BTreeSet::from([root_address]);
+        let mut pending = vec![(Vec::new(), root)];
+        while let Some((path, dir)) = pending.pop() {
+            for entry in self.list_dir(&dir)? {
+                if matches!(entry.name_bytes(), b"." | b"..") {
+                    continue;
+                }
+                let child_path = [path.as_slice(), b"/", entry.name_bytes()].concat();
+                paths.entry(entry.inode()).or_insert_with(Vec::new).push(child_path.clone());
+                let (inode_address, inode) = self.read_inode(entry.inode())?;
+                if inode.typ() == Type::Directory && visited.insert(inode_address) {
+                    pending.push((child_path, inode));
+                }
+            }
+        }
+        for paths in paths.values_mut() {
+            paths.sort();
+        }
+        Ok(paths)
+    }
+}
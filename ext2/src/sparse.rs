@@ -0,0 +1,95 @@
+use filesystem::BlockDevice;
+
+use crate::{BlockAddress, Error, Ext2Fs, Inode};
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Returns the first offset at or after the given offset, that is backed
+    /// by an allocated block, or `None` if there is no more data after the offset.
+    pub fn seek_data(&self, inode: &Inode, offset: u64) -> Result<Option<u64>, Error> {
+        let len = inode.len();
+        if offset >= len {
+            return Ok(None);
+        }
+
+        let block_size = self.superblock.block_size() as u64;
+        let end_block = len.div_ceil(block_size) as u32;
+        let mut block_index = (offset / block_size) as u32;
+        while block_index < end_block {
+            match self.resolve_block_index_or_next(inode, block_index)? {
+                Ok(_) => return Ok(Some(offset.max(block_index as u64 * block_size))),
+                Err(next) => block_index = next,
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the first offset at or after the given offset, that is inside a
+    /// hole. The end of the file counts as a hole, so this returns the length of
+    /// the file if there are no more holes, and `None` only if the offset is
+    /// beyond the end of the file.
+    pub fn seek_hole(&self, inode: &Inode, offset: u64) -> Result<Option<u64>, Error> {
+        let len = inode.len();
+        if offset >= len {
+            return Ok(None);
+        }
+
+        let block_size = self.superblock.block_size() as u64;
+        let end_block = len.div_ceil(block_size) as u32;
+        let mut block_index = (offset / block_size) as u32;
+        while block_index < end_block {
+            match self.resolve_block_index_or_next(inode, block_index)? {
+                Ok(_) => block_index += 1,
+                Err(_) => return Ok(Some(offset.max(block_index as u64 * block_size))),
+            }
+        }
+        Ok(Some(len))
+    }
+
+    /// Resolves the block index like [`Ext2Fs::resolve_block_index`], but if the block
+    /// is not allocated, returns the index of the next block that may be allocated.
+    /// This allows skipping over missing indirect blocks without looking at every
+    /// single block index that they would contain.
+    pub(crate) fn resolve_block_index_or_next(&self, inode: &Inode, block_index: u32) -> Result<Result<BlockAddress, u32>, Error> {
+        let (direct_limit, indirect_limit, double_indirect_limit) = self.indirect_pointer_limits();
+        let pointers_per_block = self.superblock.block_size() / 4;
+
+        if block_index < direct_limit {
+            return Ok(inode.direct_ptrs().nth(block_index as usize).flatten().ok_or(block_index + 1));
+        }
+
+        // the number of blocks that are covered by a single pointer in the outermost indirect block
+        let (root, first_index, blocks_per_pointer) = if block_index < indirect_limit {
+            (inode.single_indirect_ptr(), direct_limit, 1)
+        } else if block_index < double_indirect_limit {
+            (inode.double_indirect_ptr(), indirect_limit, pointers_per_block)
+        } else {
+            (inode.triple_indirect_ptr(), double_indirect_limit, pointers_per_block * pointers_per_block)
+        };
+
+        let Some(root) = root else {
+            // skip everything that this indirect block would have pointed to
+            let covered = blocks_per_pointer as u64 * pointers_per_block as u64;
+            return Ok(Err((first_index as u64 + covered).min(u32::MAX as u64) as u32));
+        };
+
+        let relative_index = block_index - first_index;
+        let mut table = root;
+        let mut blocks_per_pointer = blocks_per_pointer;
+        loop {
+            let pointer_index = (relative_index / blocks_per_pointer) % pointers_per_block;
+            let Some(next) = self.resolve_indirect_ptr(Some(table), pointer_index)? else {
+                // skip everything that the missing pointer would have pointed to
+                let next_index = (block_index - relative_index % blocks_per_pointer) as u64 + blocks_per_pointer as u64;
+                return Ok(Err(next_index.min(u32::MAX as u64) as u32));
+            };
+            if blocks_per_pointer == 1 {
+                return Ok(Ok(next));
+            }
+            table = next;
+            blocks_per_pointer /= pointers_per_block;
+        }
+    }
+}
@@ -1,8 +1,12 @@
 use core::ops::{Deref, DerefMut, Shl};
 
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
 use bitflags::bitflags;
 
-use crate::{bytefield, bytefield_field_read, bytefield_field_write, check_is_implemented};
+use crate::{bytefield, bytefield_field_read, bytefield_field_write, check_is_implemented, Error, FormatOptions, HashVersion};
 
 pub struct SuperblockArray([u8; 1024]);
 
@@ -39,7 +43,8 @@ impl DerefMut for SuperblockArray {
 }
 
 bytefield! {
-    #[derive(Debug, Eq, PartialEq)]
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Superblock (SuperblockArray) {
         num_inodes: u32 = 0,
         num_blocks: u32 = 4,
@@ -77,14 +82,162 @@ bytefield! {
         write_required_features: u32 = 100,
         fsid: [u8; 16] = 104,
         volume_name: [u8; 16] = 120,
-        last_mount_path: [u8; 16] = 136,
+        #[cfg_attr(feature = "serde", serde(with = "serde_array"))]
+        last_mount_path: [u8; 64] = 136,
         compression: u32 = 200,
         num_preallocate_blocks_file: u8 = 204,
         num_preallocate_blocks_directory: u8 = 205,
+        num_reserved_gdt_blocks: u16 = 206,
+        journal_id: [u8; 16] = 208,
+        journal_inode: u32 = 224,
+        journal_device: u32 = 228,
+        orphan_inode_list_head: u32 = 232,
+        hash_seed: [u32; 4] = 236,
+        default_hash_version: u8 = 252,
+        journal_backup_type: u8 = 253,
+        group_descriptor_size: u16 = 254,
+        default_mount_options: u32 = 256,
+        first_meta_block_group: u32 = 260,
+        creation_time: u32 = 264,
+        journal_blocks: [u32; 17] = 268,
+        num_blocks_upper: u32 = 336,
+        num_superuser_reserved_blocks_upper: u32 = 340,
+        num_unallocated_blocks_upper: u32 = 344,
+        min_extra_inode_size: u16 = 348,
+        want_extra_inode_size: u16 = 350,
+        flags: u32 = 352,
+    }
+}
+
+/// The revision of a file system, which determines whether the superblock
+/// has the fields after `gid_for_reserved_blocks`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Revision {
+    /// The original format, with 128 byte inodes, 11 as the first non-reserved
+    /// inode and no feature flags.
+    GoodOld,
+    /// The format with variable inode sizes and feature flags.
+    Dynamic,
+}
+
+impl Revision {
+    /// The inode size of revision 0 file systems.
+    pub const GOOD_OLD_INODE_SIZE: u16 = 128;
+    /// The first non-reserved inode of revision 0 file systems.
+    pub const GOOD_OLD_FIRST_INODE: u32 = 11;
+}
+
+impl From<Revision> for u32 {
+    fn from(value: Revision) -> Self {
+        match value {
+            Revision::GoodOld => 0,
+            Revision::Dynamic => 1,
+        }
     }
 }
 
 impl Superblock {
+    /// The number of bytes at the start of the on-disk superblock that are
+    /// covered by the fields of this struct.
+    pub const LEN: usize = 356;
+
+    pub const MAGIC: u16 = 0xEF53;
+
+    /// Creates the superblock of a new file system with the given number of blocks and
+    /// inodes per group. All blocks and inodes are unallocated. The fields of the dynamic
+    /// revision are only set for revision 1 file systems, and are zero otherwise.
+    pub(crate) fn new(options: &FormatOptions, num_blocks: u32, inodes_per_group: u32) -> Self {
+        let first_data_block = if options.block_size == 1024 { 1 } else { 0 };
+        let blocks_per_group = options.block_size * 8;
+        let num_groups = (num_blocks - first_data_block).div_ceil(blocks_per_group);
+        let log2_block_size = options.block_size.trailing_zeros() - 10;
+        let dynamic = options.revision == Revision::Dynamic;
+        let extra_inode_size = if dynamic {
+            options.inode_size.saturating_sub(Revision::GOOD_OLD_INODE_SIZE).min(32)
+        } else {
+            0
+        };
+
+        Self {
+            num_inodes: inodes_per_group * num_groups,
+            num_blocks,
+            num_superuser_reserved_blocks: (num_blocks as u64 * options.reserved_blocks_percent as u64 / 100) as u32,
+            num_unallocated_blocks: num_blocks - first_data_block,
+            num_unallocated_inodes: inodes_per_group * num_groups,
+            superblock_block_number: first_data_block,
+            log2_block_size,
+            log2_fragment_size: log2_block_size,
+            blocks_per_group,
+            fragments_per_group: blocks_per_group,
+            inodes_per_group,
+            last_mount_time: 0,
+            last_written_time: options.time,
+            mounts_since_fsck: 0,
+            mounts_allowed_before_fsck: u16::MAX, // no limit
+            magic_number: Self::MAGIC,
+            state: State::CLEAN.bits(),
+            error_policy: ErrorPolicy::IGNORE.bits(),
+            version_minor: 0,
+            last_fsck: options.time,
+            fsck_force_interval: 0,
+            os_id: 0, // Linux
+            version_major: options.revision.into(),
+            uid_for_reserved_blocks: 0,
+            gid_for_reserved_blocks: 0,
+            first_non_reserved_inode: if dynamic { Revision::GOOD_OLD_FIRST_INODE } else { 0 },
+            inode_size: if dynamic { options.inode_size } else { 0 },
+            this_superblock_block_group: 0,
+            optional_features: 0,
+            required_features: if dynamic { RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE.bits() } else { 0 },
+            write_required_features: if dynamic { ReadOnlyFeatures::SPARSE_SUPERBLOCK_AND_GDTS.bits() } else { 0 },
+            fsid: if dynamic { options.fsid } else { [0; 16] },
+            volume_name: if dynamic { options.volume_name } else { [0; 16] },
+            last_mount_path: [0; 64],
+            compression: 0,
+            num_preallocate_blocks_file: 0,
+            num_preallocate_blocks_directory: 0,
+            num_reserved_gdt_blocks: 0,
+            journal_id: [0; 16],
+            journal_inode: 0,
+            journal_device: 0,
+            orphan_inode_list_head: 0,
+            hash_seed: [0; 4],
+            default_hash_version: 0,
+            journal_backup_type: 0,
+            group_descriptor_size: 0,
+            default_mount_options: 0,
+            first_meta_block_group: 0,
+            creation_time: if dynamic { options.time } else { 0 },
+            journal_blocks: [0; 17],
+            num_blocks_upper: 0,
+            num_superuser_reserved_blocks_upper: 0,
+            num_unallocated_blocks_upper: 0,
+            min_extra_inode_size: extra_inode_size,
+            want_extra_inode_size: extra_inode_size,
+            flags: 0,
+        }
+    }
+
+    /// Checks the fields that the layout of the file system is computed from, so that
+    /// a corrupted superblock fails with [`Error::InvalidSuperblock`] instead of making
+    /// these computations overflow or divide by zero.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        // ext2 supports blocks of up to 64KiB
+        let valid = self.log2_block_size <= 6
+            && self.blocks_per_group != 0
+            && self.inodes_per_group != 0;
+        if !valid {
+            return Err(Error::InvalidSuperblock);
+        }
+
+        // inodes contain at least the fields of revision 0, and don't span blocks
+        let inode_size = self.inode_size() as u32;
+        if inode_size < Revision::GOOD_OLD_INODE_SIZE as u32 || !inode_size.is_power_of_two() || inode_size > self.block_size() {
+            return Err(Error::InvalidSuperblock);
+        }
+        Ok(())
+    }
+
     pub fn num_inodes(&self) -> u32 {
         self.num_inodes
     }
@@ -93,6 +246,16 @@ impl Superblock {
         self.num_blocks
     }
 
+    /// Sets the number of blocks and inodes, after the file system was resized.
+    pub(crate) fn set_size(&mut self, num_blocks: u32, num_inodes: u32) {
+        self.num_blocks = num_blocks;
+        self.num_inodes = num_inodes;
+    }
+
+    pub fn set_num_superuser_reserved_blocks(&mut self, num_blocks: u32) {
+        self.num_superuser_reserved_blocks = num_blocks;
+    }
+
     pub fn num_superuser_reserved_blocks(&self) -> u32 {
         self.num_superuser_reserved_blocks
     }
@@ -141,18 +304,34 @@ impl Superblock {
         self.last_mount_time
     }
 
+    pub fn last_mount_time_mut(&mut self) -> &mut u32 {
+        &mut self.last_mount_time
+    }
+
     pub fn last_written_time(&self) -> u32 {
         self.last_written_time
     }
 
+    pub fn last_written_time_mut(&mut self) -> &mut u32 {
+        &mut self.last_written_time
+    }
+
     pub fn mounts_since_fsck(&self) -> u16 {
         self.mounts_since_fsck
     }
 
+    pub fn mounts_since_fsck_mut(&mut self) -> &mut u16 {
+        &mut self.mounts_since_fsck
+    }
+
     pub fn mounts_allowed_before_fsck(&self) -> u16 {
         self.mounts_allowed_before_fsck
     }
 
+    pub fn mounts_allowed_before_fsck_mut(&mut self) -> &mut u16 {
+        &mut self.mounts_allowed_before_fsck
+    }
+
     pub fn magic_number(&self) -> u16 {
         self.magic_number
     }
@@ -161,10 +340,18 @@ impl Superblock {
         State::from_bits_truncate(self.state)
     }
 
+    pub fn set_state(&mut self, state: State) {
+        self.state = state.bits();
+    }
+
     pub fn error_policy(&self) -> ErrorPolicy {
         ErrorPolicy::from_bits_truncate(self.error_policy)
     }
 
+    pub fn set_error_policy(&mut self, error_policy: ErrorPolicy) {
+        self.error_policy = error_policy.bits();
+    }
+
     pub fn version_minor(&self) -> u16 {
         self.version_minor
     }
@@ -177,6 +364,10 @@ impl Superblock {
         self.fsck_force_interval
     }
 
+    pub fn fsck_force_interval_mut(&mut self) -> &mut u32 {
+        &mut self.fsck_force_interval
+    }
+
     pub fn os_id(&self) -> u32 {
         self.os_id
     }
@@ -185,48 +376,127 @@ impl Superblock {
         self.version_major
     }
 
+    /// The revision of the file system. For revision 0 file systems, the fields of
+    /// the dynamic revision are not valid, and their getters return the fixed values
+    /// of revision 0 instead.
+    pub fn revision(&self) -> Revision {
+        if self.version_major == 0 {
+            Revision::GoodOld
+        } else {
+            Revision::Dynamic
+        }
+    }
+
     pub fn uid_for_reserved_blocks(&self) -> u16 {
         self.uid_for_reserved_blocks
     }
 
+    pub fn uid_for_reserved_blocks_mut(&mut self) -> &mut u16 {
+        &mut self.uid_for_reserved_blocks
+    }
+
     pub fn gid_for_reserved_blocks(&self) -> u16 {
         self.gid_for_reserved_blocks
     }
 
+    pub fn gid_for_reserved_blocks_mut(&mut self) -> &mut u16 {
+        &mut self.gid_for_reserved_blocks
+    }
+
     pub fn first_non_reserved_inode(&self) -> u32 {
-        self.first_non_reserved_inode
+        match self.revision() {
+            Revision::GoodOld => Revision::GOOD_OLD_FIRST_INODE,
+            Revision::Dynamic => self.first_non_reserved_inode,
+        }
     }
 
     pub fn inode_size(&self) -> u16 {
-        self.inode_size
+        match self.revision() {
+            Revision::GoodOld => Revision::GOOD_OLD_INODE_SIZE,
+            Revision::Dynamic => self.inode_size,
+        }
     }
 
     pub fn this_superblock_block_group(&self) -> u16 {
         self.this_superblock_block_group
     }
 
+    /// Sets the group that this copy of the superblock is stored in, which
+    /// is only stored by revision 1 file systems.
+    pub(crate) fn set_this_superblock_block_group(&mut self, group_index: u16) {
+        if self.revision() == Revision::Dynamic {
+            self.this_superblock_block_group = group_index;
+        }
+    }
+
     pub fn optional_features(&self) -> OptionalFeatures {
-        OptionalFeatures::from_bits_truncate(self.optional_features)
+        match self.revision() {
+            Revision::GoodOld => OptionalFeatures::empty(),
+            Revision::Dynamic => OptionalFeatures::from_bits_truncate(self.optional_features),
+        }
+    }
+
+    pub fn set_optional_features(&mut self, features: OptionalFeatures) {
+        self.optional_features = features.bits();
     }
 
     pub fn required_features(&self) -> RequiredFeatures {
-        RequiredFeatures::from_bits_truncate(self.required_features)
+        match self.revision() {
+            Revision::GoodOld => RequiredFeatures::empty(),
+            Revision::Dynamic => RequiredFeatures::from_bits_truncate(self.required_features),
+        }
+    }
+
+    pub(crate) fn set_required_features(&mut self, features: RequiredFeatures) {
+        self.required_features = features.bits();
     }
 
     pub fn write_required_features(&self) -> ReadOnlyFeatures {
-        ReadOnlyFeatures::from_bits_truncate(self.write_required_features)
+        match self.revision() {
+            Revision::GoodOld => ReadOnlyFeatures::empty(),
+            Revision::Dynamic => ReadOnlyFeatures::from_bits_truncate(self.write_required_features),
+        }
+    }
+
+    pub fn set_write_required_features(&mut self, features: ReadOnlyFeatures) {
+        self.write_required_features = features.bits();
     }
 
     pub fn fsid(&self) -> Ext2FsId {
         Ext2FsId(self.fsid)
     }
 
-    pub fn volume_name(&self) -> &str {
-        core::str::from_utf8(&self.volume_name).unwrap()
+    pub fn set_fsid(&mut self, fsid: [u8; 16]) {
+        self.fsid = fsid;
+    }
+
+    /// The name of the file system, or `None` if it is not valid UTF-8.
+    pub fn volume_name(&self) -> Option<&str> {
+        core::str::from_utf8(self.volume_name_bytes()).ok()
+    }
+
+    /// The name of the file system, without the trailing NUL bytes.
+    pub fn volume_name_bytes(&self) -> &[u8] {
+        trim_nul(&self.volume_name)
+    }
+
+    pub fn set_volume_name(&mut self, name: [u8; 16]) {
+        self.volume_name = name;
     }
 
-    pub fn last_mount_path(&self) -> &str {
-        core::str::from_utf8(&self.last_mount_path).unwrap()
+    /// The directory that the file system was last mounted at, or `None` if it is
+    /// not valid UTF-8.
+    pub fn last_mount_path(&self) -> Option<&str> {
+        core::str::from_utf8(self.last_mount_path_bytes()).ok()
+    }
+
+    /// The directory that the file system was last mounted at, without the trailing NUL bytes.
+    pub fn last_mount_path_bytes(&self) -> &[u8] {
+        trim_nul(&self.last_mount_path)
+    }
+
+    pub fn set_last_mount_path(&mut self, path: [u8; 64]) {
+        self.last_mount_path = path;
     }
 
     pub fn compression(&self) -> u32 {
@@ -240,11 +510,219 @@ impl Superblock {
     pub fn num_preallocate_blocks_directory(&self) -> u8 {
         self.num_preallocate_blocks_directory
     }
+
+    pub fn num_reserved_gdt_blocks(&self) -> u16 {
+        self.num_reserved_gdt_blocks
+    }
+
+    pub(crate) fn set_num_reserved_gdt_blocks(&mut self, num_blocks: u16) {
+        self.num_reserved_gdt_blocks = num_blocks;
+    }
+
+    pub fn journal_id(&self) -> &[u8; 16] {
+        &self.journal_id
+    }
+
+    pub fn journal_inode(&self) -> u32 {
+        self.journal_inode
+    }
+
+    pub fn journal_device(&self) -> u32 {
+        self.journal_device
+    }
+
+    pub fn orphan_inode_list_head(&self) -> u32 {
+        self.orphan_inode_list_head
+    }
+
+    /// The seed for the hashes of hash indexed directories.
+    pub fn hash_seed(&self) -> [u32; 4] {
+        self.hash_seed
+    }
+
+    /// The hash version that is used for new hash indexed directories,
+    /// taking the signedness from the superblock flags into account.
+    pub fn default_hash_version(&self) -> Option<HashVersion> {
+        HashVersion::from_raw(self.default_hash_version, self.flags().contains(SuperblockFlags::UNSIGNED_HASH))
+    }
+
+    pub fn journal_backup_type(&self) -> u8 {
+        self.journal_backup_type
+    }
+
+    pub fn group_descriptor_size(&self) -> u16 {
+        self.group_descriptor_size
+    }
+
+    pub fn default_mount_options(&self) -> u32 {
+        self.default_mount_options
+    }
+
+    pub fn first_meta_block_group(&self) -> u32 {
+        self.first_meta_block_group
+    }
+
+    /// The backup of the journal inode's block pointers and size.
+    pub fn journal_blocks(&self) -> [u32; 17] {
+        self.journal_blocks
+    }
+
+    /// The upper 32 bits of the block counts, which are only used by 64bit file systems.
+    pub fn num_blocks_upper(&self) -> (u32, u32, u32) {
+        (self.num_blocks_upper, self.num_superuser_reserved_blocks_upper, self.num_unallocated_blocks_upper)
+    }
+
+    /// The size of the extra fields that all inodes have at least.
+    pub fn min_extra_inode_size(&self) -> u16 {
+        match self.revision() {
+            Revision::GoodOld => 0,
+            Revision::Dynamic => self.min_extra_inode_size,
+        }
+    }
+
+    /// The size of the extra fields that new inodes should have.
+    pub fn want_extra_inode_size(&self) -> u16 {
+        match self.revision() {
+            Revision::GoodOld => 0,
+            Revision::Dynamic => self.want_extra_inode_size,
+        }
+    }
+
+    pub fn creation_time(&self) -> u32 {
+        self.creation_time
+    }
+
+    pub fn flags(&self) -> SuperblockFlags {
+        SuperblockFlags::from_bits_truncate(self.flags)
+    }
+
+    /// The names of all enabled features, compatible ones first, like `dumpe2fs` prints
+    /// them. Features that this crate doesn't know about are named after their kind and
+    /// bit, like `FEATURE_I12`.
+    pub fn feature_names(&self) -> Vec<String> {
+        if self.revision() == Revision::GoodOld {
+            return Vec::new();
+        }
+        let kinds = [
+            ('C', self.optional_features, OPTIONAL_FEATURE_NAMES.as_slice()),
+            ('I', self.required_features, REQUIRED_FEATURE_NAMES.as_slice()),
+            ('R', self.write_required_features, READ_ONLY_FEATURE_NAMES.as_slice()),
+        ];
+        kinds
+            .into_iter()
+            .flat_map(|(kind, bits, names)| {
+                (0..32).filter(move |bit| bits & (1 << bit) != 0).map(move |bit| match names.get(bit) {
+                    Some(name) => String::from(*name),
+                    None => format!("FEATURE_{}{}", kind, bit),
+                })
+            })
+            .collect()
+    }
+
+    /// Whether the block group with the given index has a copy of the superblock
+    /// and the block group descriptor table.
+    pub fn group_has_superblock(&self, group_index: u32) -> bool {
+        let sparse = self.write_required_features().contains(ReadOnlyFeatures::SPARSE_SUPERBLOCK_AND_GDTS);
+        has_superblock_copy(sparse, group_index)
+    }
+}
+
+/// The names of the features by bit, as used by `mke2fs` and `dumpe2fs`.
+const OPTIONAL_FEATURE_NAMES: [&str; 6] = ["dir_prealloc", "imagic_inodes", "has_journal", "ext_attr", "resize_inode", "dir_index"];
+const REQUIRED_FEATURE_NAMES: [&str; 4] = ["compression", "filetype", "needs_recovery", "journal_dev"];
+const READ_ONLY_FEATURE_NAMES: [&str; 3] = ["sparse_super", "large_file", "btree_dir"];
+
+/// The given bytes up to the first NUL byte.
+fn trim_nul(bytes: &[u8]) -> &[u8] {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    &bytes[..len]
+}
+
+/// Serializes arrays that are too large for the implementations of serde.
+#[cfg(feature = "serde")]
+mod serde_array {
+    use core::fmt::Formatter;
+    use core::marker::PhantomData;
+
+    use serde::de::{Error, SeqAccess, Visitor};
+    use serde::ser::SerializeTuple;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S, T, const N: usize>(array: &[T; N], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        let mut tuple = serializer.serialize_tuple(N)?;
+        for element in array {
+            tuple.serialize_element(element)?;
+        }
+        tuple.end()
+    }
+
+    pub fn deserialize<'de, D, T, const N: usize>(deserializer: D) -> Result<[T; N], D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de> + Default + Copy,
+    {
+        struct ArrayVisitor<T, const N: usize>(PhantomData<T>);
+
+        impl<'de, T, const N: usize> Visitor<'de> for ArrayVisitor<T, N>
+        where
+            T: Deserialize<'de> + Default + Copy,
+        {
+            type Value = [T; N];
+
+            fn expecting(&self, formatter: &mut Formatter) -> core::fmt::Result {
+                write!(formatter, "an array of length {}", N)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut array = [T::default(); N];
+                for (i, element) in array.iter_mut().enumerate() {
+                    *element = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(i, &self))?;
+                }
+                Ok(array)
+            }
+        }
+
+        deserializer.deserialize_tuple(N, ArrayVisitor(PhantomData))
+    }
+}
+
+/// Whether the block group with the given index has a copy of the superblock. With
+/// sparse superblocks, only groups 0, 1 and powers of 3, 5 and 7 have a copy.
+pub(crate) fn has_superblock_copy(sparse: bool, group_index: u32) -> bool {
+    if !sparse || group_index <= 1 {
+        return true;
+    }
+    [3, 5, 7].into_iter().any(|base| {
+        let mut n = base;
+        while n < group_index {
+            n *= base;
+        }
+        n == group_index
+    })
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Ext2FsId([u8; 16]);
 
+impl Ext2FsId {
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct SuperblockFlags: u32 {
+        const SIGNED_HASH = 0x0001;
+        const UNSIGNED_HASH = 0x0002;
+        const TEST_FILESYSTEM = 0x0004;
+    }
+}
+
 bitflags! {
     #[derive(Debug, Copy, Clone, Eq, PartialEq)]
     pub struct OptionalFeatures: u32 {
@@ -414,15 +892,34 @@ mod tests {
                 write_required_features: 3,
                 fsid: [119, 75, 148, 206, 61, 5, 75, 113, 152, 252, 252, 246, 55, 253, 43, 72],
                 volume_name: [0_u8; 16],
-                last_mount_path: [0_u8; 16],
+                last_mount_path: [0_u8; 64],
                 compression: 0,
                 num_preallocate_blocks_file: 0,
                 num_preallocate_blocks_directory: 0,
+                num_reserved_gdt_blocks: 3,
+                journal_id: [0_u8; 16],
+                journal_inode: 0,
+                journal_device: 0,
+                orphan_inode_list_head: 0,
+                hash_seed: [0x060538E3, 0xCD44B272, 0xA14E20BB, 0x1DA669CB],
+                default_hash_version: 1,
+                journal_backup_type: 0,
+                group_descriptor_size: 0,
+                default_mount_options: 12,
+                first_meta_block_group: 0,
+                creation_time: 1686068677,
+                journal_blocks: [0_u32; 17],
+                num_blocks_upper: 0,
+                num_superuser_reserved_blocks_upper: 0,
+                num_unallocated_blocks_upper: 0,
+                min_extra_inode_size: 32,
+                want_extra_inode_size: 32,
+                flags: 1,
             },
             sb
         );
 
         let reversed = Into::<SuperblockArray>::into(sb);
-        assert_eq!(data[..206], reversed[..206]); // only check the actual superblock data
+        assert_eq!(data[..Superblock::LEN], reversed[..Superblock::LEN]); // only check the actual superblock data
     }
 }
@@ -0,0 +1,61 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use filesystem::BlockDevice;
+
+use crate::{Directory, Error, Ext2Fs, Inode, Permissions, SymLink, Type};
+
+/// Targets that are shorter than this are stored directly in the block
+/// pointers of the inode ("fast symlinks").
+const FAST_SYMLINK_MAX_LEN: usize = 60;
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Reads the target of the given symlink.
+    pub fn read_link(&self, link: &SymLink) -> Result<Vec<u8>, Error> {
+        let len = link.len() as usize;
+        if self.is_fast_symlink(link) {
+            return Ok(link.block_ptr_bytes()[..len].to_vec());
+        }
+
+        let mut target = vec![0_u8; len];
+        let read = self.read_from_inode(link, 0, &mut target)?;
+        target.truncate(read);
+        Ok(target)
+    }
+
+    /// Whether the target of the given symlink is stored in its block pointers
+    /// instead of a data block.
+    pub fn is_fast_symlink(&self, inode: &Inode) -> bool {
+        let xattr_sectors = if inode.extended_attribute_block().is_some() {
+            self.superblock.block_size() / 512
+        } else {
+            0
+        };
+        inode.len() < FAST_SYMLINK_MAX_LEN as u64 && inode.num_disk_sectors() == xattr_sectors
+    }
+
+    /// Creates a symlink with the given name in the given directory, that
+    /// points to the given target.
+    pub fn create_symlink(&mut self, parent: &mut Directory, name: impl AsRef<[u8]>, target: &[u8]) -> Result<SymLink, Error> {
+        if target.is_empty() || target.len() > self.superblock.block_size() as usize {
+            return Err(Error::InvalidSymLinkTarget);
+        }
+
+        let (inode_address, mut inode) = self.create_inode(parent, name, Type::SymLink)?;
+        inode.set_perm(Permissions::all() - Permissions::Sticky - Permissions::SetGID - Permissions::SetUID);
+        if target.len() < FAST_SYMLINK_MAX_LEN {
+            let mut bytes = [0_u8; FAST_SYMLINK_MAX_LEN];
+            bytes[..target.len()].copy_from_slice(target);
+            inode.set_block_ptr_bytes(bytes);
+            inode.set_len(target.len() as u64);
+            self.write_inode(inode_address, &inode)?;
+        } else {
+            self.write_to_inode(inode_address, &mut inode, 0, target)?;
+        }
+
+        Ok((inode_address, inode).try_into().unwrap()) // we just created a symlink, so this can't fail
+    }
+}
@@ -0,0 +1,196 @@
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+use filesystem::BlockDevice;
+
+use crate::{Directory, Error, ErrorPolicy, Ext2Fs, Flags, OptionalFeatures, ReadOnlyFeatures, RequiredFeatures, Revision, Type};
+
+/// A feature that can be enabled or disabled with [`Ext2Fs::tune`], without
+/// changing the layout of the file system.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Feature {
+    /// The hint to preallocate blocks for directories.
+    DirPrealloc,
+    /// Extended attributes. Can only be disabled if no inode has attributes.
+    ExtAttr,
+    /// Hash indexed directories. Disabling it turns all indexed directories into linear ones.
+    DirIndex,
+    /// File types in directory entries. Can only be enabled, which stores the type in
+    /// every existing entry.
+    FileType,
+    /// Files of 2GiB or more. Can only be disabled if there are no such files.
+    LargeFile,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 5] = [Feature::DirPrealloc, Feature::ExtAttr, Feature::DirIndex, Feature::FileType, Feature::LargeFile];
+
+    /// The name of the feature, as used by `mke2fs` and `tune2fs`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::DirPrealloc => "dir_prealloc",
+            Feature::ExtAttr => "ext_attr",
+            Feature::DirIndex => "dir_index",
+            Feature::FileType => "filetype",
+            Feature::LargeFile => "large_file",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|feature| feature.name() == name)
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The changes that [`Ext2Fs::tune`] makes. Fields that are `None` are not changed.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TuneOptions {
+    /// The name of the file system. Not stored by revision 0 file systems.
+    pub volume_name: Option<[u8; 16]>,
+    /// The id of the file system. Not stored by revision 0 file systems.
+    pub fsid: Option<[u8; 16]>,
+    /// The directory that the file system was last mounted at. Not stored by revision 0 file systems.
+    pub last_mount_path: Option<[u8; 64]>,
+    /// The number of mounts after which the file system should be checked, or `u16::MAX`
+    /// to not check it because of mounts.
+    pub max_mount_count: Option<u16>,
+    /// The number of mounts since the file system was last checked.
+    pub mount_count: Option<u16>,
+    /// The maximum number of seconds between checks, or `0` to not check it because of time.
+    pub check_interval: Option<u32>,
+    /// What the kernel does when it finds an error.
+    pub error_policy: Option<ErrorPolicy>,
+    /// The number of blocks that are reserved for the superuser, at most half of the blocks.
+    pub reserved_blocks: Option<u32>,
+    /// The user that may use the reserved blocks.
+    pub reserved_uid: Option<u16>,
+    /// The group that may use the reserved blocks.
+    pub reserved_gid: Option<u16>,
+    /// The features to enable (`true`) or disable (`false`), in order.
+    pub features: Vec<(Feature, bool)>,
+}
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Changes the parameters of the file system, like `tune2fs`, and writes the superblock
+    /// to every group that has a copy of it. All options are checked before anything is
+    /// written, so that an invalid option leaves the file system unchanged.
+    ///
+    /// Revision 0 file systems don't have a name, id, last mount path or features, so
+    /// setting them fails with [`Error::NotSupported`], like a feature that is in use and
+    /// can't be disabled.
+    pub fn tune(&mut self, options: &TuneOptions) -> Result<(), Error> {
+        let dynamic = self.superblock.revision() == Revision::Dynamic;
+        if !dynamic && (options.volume_name.is_some() || options.fsid.is_some() || options.last_mount_path.is_some() || !options.features.is_empty()) {
+            return Err(Error::NotSupported);
+        }
+        if options.reserved_blocks.is_some_and(|blocks| blocks > self.superblock.num_blocks() / 2) {
+            return Err(Error::InvalidArgument);
+        }
+        if options.error_policy.is_some_and(|policy| !ErrorPolicy::all().contains(policy) || policy.is_empty()) {
+            return Err(Error::InvalidArgument);
+        }
+        for &(feature, enable) in &options.features {
+            if !enable && self.is_feature_in_use(feature)? {
+                return Err(Error::NotSupported);
+            }
+        }
+
+        for &(feature, enable) in &options.features {
+            self.set_feature(feature, enable)?;
+        }
+        let superblock = &mut self.superblock;
+        if let Some(volume_name) = options.volume_name {
+            superblock.set_volume_name(volume_name);
+        }
+        if let Some(fsid) = options.fsid {
+            superblock.set_fsid(fsid);
+        }
+        if let Some(last_mount_path) = options.last_mount_path {
+            superblock.set_last_mount_path(last_mount_path);
+        }
+        if let Some(max_mount_count) = options.max_mount_count {
+            *superblock.mounts_allowed_before_fsck_mut() = max_mount_count;
+        }
+        if let Some(mount_count) = options.mount_count {
+            *superblock.mounts_since_fsck_mut() = mount_count;
+        }
+        if let Some(check_interval) = options.check_interval {
+            *superblock.fsck_force_interval_mut() = check_interval;
+        }
+        if let Some(error_policy) = options.error_policy {
+            superblock.set_error_policy(error_policy);
+        }
+        if let Some(reserved_blocks) = options.reserved_blocks {
+            superblock.set_num_superuser_reserved_blocks(reserved_blocks);
+        }
+        if let Some(reserved_uid) = options.reserved_uid {
+            *superblock.uid_for_reserved_blocks_mut() = reserved_uid;
+        }
+        if let Some(reserved_gid) = options.reserved_gid {
+            *superblock.gid_for_reserved_blocks_mut() = reserved_gid;
+        }
+        self.write_superblock_copies()
+    }
+
+    /// Whether the file system uses the given feature, so that it can't be disabled.
+    fn is_feature_in_use(&self, feature: Feature) -> Result<bool, Error> {
+        match feature {
+            Feature::DirPrealloc | Feature::DirIndex => Ok(false),
+            // the high byte of the name length would become the type of every entry
+            Feature::FileType => Ok(self.superblock.required_features().contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE)),
+            Feature::ExtAttr | Feature::LargeFile => {
+                for inode_address in self.used_inodes()? {
+                    let (_, inode) = self.read_inode(inode_address)?;
+                    let in_use = match feature {
+                        Feature::ExtAttr => inode.extended_attribute_block().is_some() || !self.list_xattr(&inode)?.is_empty(),
+                        _ => inode.typ() == Type::RegularFile && inode.len() > i32::MAX as u64,
+                    };
+                    if in_use {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    fn set_feature(&mut self, feature: Feature, enable: bool) -> Result<(), Error> {
+        let superblock = &mut self.superblock;
+        let optional = match feature {
+            Feature::DirPrealloc => OptionalFeatures::PREALLOCATE_FOR_DIRECTORY,
+            Feature::ExtAttr => OptionalFeatures::INODES_EXTENDED_ATTRIBUTES,
+            Feature::DirIndex => OptionalFeatures::DIRECTORIES_USE_HASH_INDEX,
+            Feature::FileType => {
+                // disabling the feature was rejected before
+                return self.enable_dir_entry_types();
+            }
+            Feature::LargeFile => {
+                let features = superblock.write_required_features();
+                superblock.set_write_required_features(if enable { features | ReadOnlyFeatures::USE_64BIT_FILE_SIZE } else { features - ReadOnlyFeatures::USE_64BIT_FILE_SIZE });
+                return Ok(());
+            }
+        };
+        let features = superblock.optional_features();
+        superblock.set_optional_features(if enable { features | optional } else { features - optional });
+
+        // indexed directories are still valid linear directories
+        if feature == Feature::DirIndex && !enable {
+            for inode_address in self.used_inodes()? {
+                let (_, inode) = self.read_inode(inode_address)?;
+                if inode.typ() == Type::Directory && inode.flags().contains(Flags::HashIndexedDirectory) {
+                    let mut dir = Directory::try_from((inode_address, inode)).unwrap(); // we checked the type
+                    self.drop_dir_index(&mut dir)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
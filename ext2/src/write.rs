@@ -1,8 +1,12 @@
 use alloc::vec;
+use alloc::vec::Vec;
 
 use filesystem::BlockDevice;
 
-use crate::{Error, Ext2Fs, RegularFile};
+use crate::{BlockAddress, Error, Ext2Fs, Inode, InodeAddress, ReadOnlyFeatures, RegularFile, Type};
+
+/// Files that are larger than this require [`ReadOnlyFeatures::USE_64BIT_FILE_SIZE`].
+const LARGE_FILE_SIZE: u64 = i32::MAX as u64;
 
 impl<T> Ext2Fs<T>
 where
@@ -11,15 +15,39 @@ where
     pub fn write_to_file(
         &mut self,
         file: &mut RegularFile,
-        offset: usize,
+        offset: u64,
+        buf: &[u8],
+    ) -> Result<usize, Error> {
+        let inode_address = file.inode_address();
+        self.write_to_inode(inode_address, file.inode_mut(), offset, buf)
+    }
+
+    pub(crate) fn write_to_inode(
+        &mut self,
+        inode_address: InodeAddress,
+        inode: &mut Inode,
+        offset: u64,
         buf: &[u8],
     ) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let end = offset.checked_add(buf.len() as u64).ok_or(Error::FileTooLarge)?;
+        if end > self.max_file_size() {
+            return Err(Error::FileTooLarge);
+        }
+        let grows = inode.len() < end;
+        if grows && inode.typ() == Type::RegularFile {
+            self.ensure_large_file_support(end)?;
+        }
+
         let block_size = self.superblock.block_size();
-        let offset = offset as u32;
 
-        let start_block = offset / block_size;
-        let end_block = (offset + buf.len() as u32 - 1) / block_size;
-        let relative_offset = (offset % block_size) as usize;
+        // the maximum file size guarantees that the block indices fit into u32
+        let start_block = (offset / block_size as u64) as u32;
+        let end_block = ((end - 1) / block_size as u64) as u32;
+        let relative_offset = (offset % block_size as u64) as usize;
         let block_count = (end_block - start_block + 1) as usize;
 
         // This is the data that we want to write. We pad the data with data from the disk
@@ -27,7 +55,7 @@ where
         // back to disk (block aligned) as is.
         let data = {
             let mut data = vec![0_u8; block_count * block_size as usize];
-            self.read_blocks_from_inode(&file, start_block as usize, end_block as usize, &mut data)?; // TODO: we don't need to read what will be overwritten anyways
+            self.read_blocks_from_inode(inode, start_block, end_block, &mut data)?; // TODO: we don't need to read what will be overwritten anyways
             // overwrite the part that should be written
             data[relative_offset..relative_offset + buf.len()].copy_from_slice(buf);
             data
@@ -35,30 +63,18 @@ where
 
         let mut num_new_allocated_blocks = 0;
         let mut chunks = data.chunks_exact(block_size as usize);
-        for ((_, block), chunk) in (start_block..=end_block).enumerate().zip(&mut chunks) {
+        for (block, chunk) in (start_block..=end_block).zip(&mut chunks) {
             let block_address =
-                if let Some(block_address) = self.resolve_block_index(file, block)? {
+                if let Some(block_address) = self.resolve_block_index(inode, block)? {
                     block_address
+                } else if self.skip_zero_blocks && chunk.iter().all(|&b| b == 0) {
+                    // leave a hole, which reads as zeros
+                    continue;
                 } else {
-                    // TODO: we don't need to allocate if the full content of this block would be zero if the fs allows sparse files
-                    let free_block_address = self.allocate_block()?;
-                    if free_block_address.is_none() {
-                        return Err(Error::NoSpace);
-                    }
-                    let free_block_address = free_block_address.unwrap();
+                    let free_block_address = self.allocate_data_block(inode_address, inode)?;
                     num_new_allocated_blocks += 1;
 
-                    let inode = file.inode_mut();
-                    let free_slot = inode.direct_ptrs()
-                        .enumerate()
-                        .find(|(_, ptr)| ptr.is_none())
-                        .map(|(i, _)| i);
-                    if let Some(free_slot) = free_slot {
-                        inode.set_direct_ptr(free_slot, Some(free_block_address));
-                        self.write_inode(file.inode_address(), file)?;
-                    } else {
-                        todo!("allocate indirect block");
-                    }
+                    num_new_allocated_blocks += self.set_block_index(inode, block, free_block_address)?;
 
                     free_block_address
                 };
@@ -67,20 +83,210 @@ where
         }
         debug_assert_eq!(chunks.remainder().len(), 0, "data to write was not block aligned");
 
-        if file.len() < offset as usize + buf.len() {
-            let new_size = offset as usize + buf.len();
-            let new_size_lower = new_size as u32;
-            let new_size_upper = (new_size >> 32) as u32;
+        if grows {
+            inode.set_len(end);
+        }
+        // the number of disk sectors is counted in 512 byte sectors, not in blocks
+        *inode.num_disk_sectors_mut() += num_new_allocated_blocks * (block_size / 512);
+        if grows || num_new_allocated_blocks > 0 {
+            self.write_inode(inode_address, inode)?;
+        }
 
-            let inode = file.inode_mut();
-            inode.set_file_size_lower(new_size_lower);
-            inode.set_file_size_upper(new_size_upper);
+        Ok(buf.len())
+    }
 
-            *inode.num_disk_sectors_mut() += num_new_allocated_blocks;
+    /// Changes the size of the file to `len`, like `ftruncate(2)`. Shrinking frees the
+    /// blocks after the new end, growing leaves a hole, which reads as zeros.
+    pub fn truncate(&mut self, file: &mut RegularFile, len: u64) -> Result<(), Error> {
+        if len > self.max_file_size() {
+            return Err(Error::FileTooLarge);
+        }
+        if len > file.len() {
+            self.ensure_large_file_support(len)?;
+        }
+
+        // the part of the last block after the end is zeroed, so that it reads as zeros if the file grows again
+        self.punch_hole(file, len, u64::MAX - len)?;
+        file.inode_mut().set_len(len);
+        self.write_inode(file.inode_address(), file)
+    }
+
+    /// The maximum size of a file in bytes. This is limited by the number of blocks
+    /// that the block pointers of an inode can address, and by the number of 512 byte
+    /// sectors that an inode can count, which includes the indirect blocks.
+    pub fn max_file_size(&self) -> u64 {
+        let block_size = self.superblock.block_size() as u64;
+        let pointers_per_block = block_size / 4;
+
+        let addressable_blocks = 12 + pointers_per_block + pointers_per_block.pow(2) + pointers_per_block.pow(3);
+        let countable_blocks = u32::MAX as u64 / (block_size / 512);
+        // leave room for the indirect blocks that are needed to address the data blocks
+        let indirect_blocks = countable_blocks / pointers_per_block + countable_blocks / pointers_per_block.pow(2) + 3;
+        addressable_blocks
+            .min(countable_blocks - indirect_blocks)
+            .min(u32::MAX as u64)
+            * block_size
+    }
 
-            self.write_inode(file.inode_address(), file)?;
+    /// Sets [`ReadOnlyFeatures::USE_64BIT_FILE_SIZE`] if a regular file with the given
+    /// size requires it, which is the case for files larger than 2GiB. Fails if the
+    /// file system doesn't support features.
+    pub(crate) fn ensure_large_file_support(&mut self, len: u64) -> Result<(), Error> {
+        if len <= LARGE_FILE_SIZE {
+            return Ok(());
         }
 
-        Ok(buf.len())
+        let features = self.superblock.write_required_features();
+        if features.contains(ReadOnlyFeatures::USE_64BIT_FILE_SIZE) {
+            return Ok(());
+        }
+        // revision 0 file systems don't have feature flags
+        if self.superblock.version_major() < 1 {
+            return Err(Error::FileTooLarge);
+        }
+
+        self.superblock.set_write_required_features(features | ReadOnlyFeatures::USE_64BIT_FILE_SIZE);
+        self.write_superblock()
+    }
+
+    /// Maps the block with the given index in the given inode to the given block
+    /// address. Indirect blocks are allocated as needed. The inode is not written
+    /// to the device. Returns the number of newly allocated indirect blocks.
+    pub(crate) fn set_block_index(&mut self, inode: &mut Inode, block_index: u32, block: BlockAddress) -> Result<u32, Error> {
+        let (direct_limit, _, _) = self.indirect_pointer_limits();
+
+        if block_index < direct_limit {
+            inode.set_direct_ptr(block_index as usize, Some(block));
+            return Ok(0);
+        }
+
+        let (root, indices) = self.indirect_indices(inode, block_index);
+
+        let mut num_allocated = 0;
+        let root = match root {
+            Some(root) => root,
+            None => {
+                let root = self.allocate_zeroed_block()?;
+                num_allocated += 1;
+                match indices.len() {
+                    1 => inode.set_single_indirect_ptr(Some(root)),
+                    2 => inode.set_double_indirect_ptr(Some(root)),
+                    _ => inode.set_triple_indirect_ptr(Some(root)),
+                }
+                root
+            }
+        };
+
+        let mut table = root;
+        let mut table_data = vec![0_u8; self.superblock.block_size() as usize];
+        for (level, &index) in indices.iter().enumerate() {
+            self.read_block(table, &mut table_data)?;
+            let entry = &mut table_data[index as usize * 4..index as usize * 4 + 4];
+
+            if level == indices.len() - 1 {
+                entry.copy_from_slice(&block.into_u32().to_le_bytes());
+                self.write_block(table, &table_data)?;
+                break;
+            }
+
+            table = match BlockAddress::new(u32::from_le_bytes(entry.try_into().unwrap())) {
+                Some(next) => next,
+                None => {
+                    let next = self.allocate_zeroed_block()?;
+                    num_allocated += 1;
+                    entry.copy_from_slice(&next.into_u32().to_le_bytes());
+                    self.write_block(table, &table_data)?;
+                    next
+                }
+            };
+        }
+
+        Ok(num_allocated)
+    }
+
+    /// Removes the mapping of the block with the given index in the given inode, and
+    /// frees all indirect blocks that don't point to anything anymore. The data block
+    /// itself is not freed, and the inode is not written to the device. Returns the
+    /// block that was mapped, and the number of freed indirect blocks.
+    pub(crate) fn clear_block_index(&mut self, inode: &mut Inode, block_index: u32) -> Result<(Option<BlockAddress>, u32), Error> {
+        let (direct_limit, _, _) = self.indirect_pointer_limits();
+
+        if block_index < direct_limit {
+            let block = inode.direct_ptrs().nth(block_index as usize).flatten();
+            inode.set_direct_ptr(block_index as usize, None);
+            return Ok((block, 0));
+        }
+
+        let (root, indices) = self.indirect_indices(inode, block_index);
+        let Some(root) = root else {
+            return Ok((None, 0));
+        };
+
+        // walk down to the data block, and remember the tables on the way
+        let mut tables = Vec::with_capacity(indices.len());
+        let mut table = root;
+        let mut block = None;
+        for (level, &index) in indices.iter().enumerate() {
+            let mut table_data = vec![0_u8; self.superblock.block_size() as usize];
+            self.read_block(table, &mut table_data)?;
+            let entry = &table_data[index as usize * 4..index as usize * 4 + 4];
+            let next = BlockAddress::new(u32::from_le_bytes(entry.try_into().unwrap()));
+            tables.push((table, index, table_data));
+
+            match next {
+                Some(next) if level == indices.len() - 1 => block = Some(next),
+                Some(next) => table = next,
+                None => return Ok((None, 0)),
+            }
+        }
+
+        // clear the pointer, and free the tables that became empty, from the innermost table outwards
+        let mut num_freed = 0;
+        while let Some((table, index, mut table_data)) = tables.pop() {
+            table_data[index as usize * 4..index as usize * 4 + 4].fill(0);
+            if table_data.iter().any(|&b| b != 0) {
+                self.write_block(table, &table_data)?;
+                return Ok((block, num_freed));
+            }
+            self.free_block(table)?;
+            num_freed += 1;
+        }
+
+        // the outermost table was freed as well
+        match indices.len() {
+            1 => inode.set_single_indirect_ptr(None),
+            2 => inode.set_double_indirect_ptr(None),
+            _ => inode.set_triple_indirect_ptr(None),
+        }
+        Ok((block, num_freed))
+    }
+
+    /// Returns the outermost indirect block for the block with the given index,
+    /// and the indices into the indirect blocks, starting at the outermost one.
+    /// The block index must not be one of the direct blocks.
+    fn indirect_indices(&self, inode: &Inode, block_index: u32) -> (Option<BlockAddress>, Vec<u32>) {
+        let (direct_limit, indirect_limit, double_indirect_limit) = self.indirect_pointer_limits();
+        let pointers_per_block = self.superblock.block_size() / 4;
+        debug_assert!(block_index >= direct_limit, "block index is a direct block");
+
+        if block_index < indirect_limit {
+            (inode.single_indirect_ptr(), vec![block_index - direct_limit])
+        } else if block_index < double_indirect_limit {
+            let index = block_index - indirect_limit;
+            (inode.double_indirect_ptr(), vec![index / pointers_per_block, index % pointers_per_block])
+        } else {
+            let index = block_index - double_indirect_limit;
+            (inode.triple_indirect_ptr(), vec![
+                index / (pointers_per_block * pointers_per_block),
+                (index / pointers_per_block) % pointers_per_block,
+                index % pointers_per_block,
+            ])
+        }
+    }
+
+    pub(crate) fn allocate_zeroed_block(&mut self) -> Result<BlockAddress, Error> {
+        let block = self.allocate_block()?.ok_or(Error::NoSpace)?;
+        self.write_block(block, &vec![0_u8; self.superblock.block_size() as usize])?;
+        Ok(block)
     }
 }
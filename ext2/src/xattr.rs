@@ -0,0 +1,479 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use filesystem::BlockDevice;
+
+use crate::{BlockAddress, Error, Ext2Fs, Inode, InodeAddress, OptionalFeatures};
+
+/// The magic number at the start of every extended attribute block, and of the
+/// extended attributes in the extra area of large inodes.
+const XATTR_MAGIC: u32 = 0xEA02_0000;
+/// The size of the header of an extended attribute block.
+const HEADER_LEN: usize = 32;
+/// The size of an entry without its name.
+const ENTRY_HEADER_LEN: usize = 16;
+/// The maximum number of inodes that can share an extended attribute block.
+const REFCOUNT_MAX: u32 = 1024;
+
+/// The prefixes of attribute names, and the index that is stored on disk instead
+/// of the prefix. The ACL names consist only of their prefix, so they must be
+/// matched before the more general `system.` prefix.
+const NAME_PREFIXES: [(u8, &str); 6] = [
+    (1, "user."),
+    (2, "system.posix_acl_access"),
+    (3, "system.posix_acl_default"),
+    (4, "trusted."),
+    (6, "security."),
+    (7, "system."),
+];
+
+/// An extended attribute as it is stored on disk, with the prefix of the
+/// name replaced by its index.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct XattrEntry {
+    name_index: u8,
+    name: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl XattrEntry {
+    /// The size of the entry in the entry table, without the value.
+    fn entry_len(&self) -> usize {
+        (ENTRY_HEADER_LEN + self.name.len() + 3) & !3
+    }
+
+    /// The size of the value, padded to 4 bytes.
+    fn value_len(&self) -> usize {
+        (self.value.len() + 3) & !3
+    }
+
+    fn full_name(&self) -> Option<String> {
+        let (_, prefix) = NAME_PREFIXES.iter().find(|(index, _)| *index == self.name_index)?;
+        let suffix = core::str::from_utf8(&self.name).ok()?;
+        Some([prefix, suffix].concat())
+    }
+
+    /// The hash of the entry, which is computed over the name and the value.
+    fn hash(&self) -> u32 {
+        let mut hash = 0_u32;
+        for &c in &self.name {
+            hash = (hash << 5) ^ (hash >> 27) ^ (c as i8 as i32 as u32);
+        }
+        for word in self.value.chunks(4) {
+            let mut bytes = [0_u8; 4];
+            bytes[..word.len()].copy_from_slice(word);
+            hash = (hash << 16) ^ (hash >> 16) ^ u32::from_le_bytes(bytes);
+        }
+        hash
+    }
+}
+
+/// Splits the given attribute name into the index of its prefix and the rest of the name.
+fn split_name(name: &str) -> Result<(u8, &[u8]), Error> {
+    for (index, prefix) in NAME_PREFIXES {
+        let Some(suffix) = name.strip_prefix(prefix) else {
+            continue;
+        };
+        // the ACL names are complete, all other prefixes need a name after them
+        let is_acl = matches!(index, 2 | 3);
+        if is_acl != suffix.is_empty() || suffix.len() > u8::MAX as usize {
+            return Err(Error::NotSupported);
+        }
+        return Ok((index, suffix.as_bytes()));
+    }
+    Err(Error::NotSupported)
+}
+
+impl<T> Ext2Fs<T>
+where
+    T: BlockDevice,
+{
+    /// Returns the value of the extended attribute with the given name, including
+    /// its prefix, e.g. `user.comment`.
+    pub fn get_xattr(&self, inode: &Inode, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        let (name_index, name) = split_name(name)?;
+        Ok(self.read_xattrs(inode)?
+            .into_iter()
+            .find(|e| e.name_index == name_index && e.name == name)
+            .map(|e| e.value))
+    }
+
+    /// Returns the names of all extended attributes of the given inode, including
+    /// their prefixes. Attributes in unknown namespaces are not listed.
+    pub fn list_xattr(&self, inode: &Inode) -> Result<Vec<String>, Error> {
+        Ok(self.read_xattrs(inode)?
+            .iter()
+            .filter_map(XattrEntry::full_name)
+            .collect())
+    }
+
+    /// Sets the extended attribute with the given name to the given value, and writes
+    /// the inode. Supported are the `user.`, `trusted.`, `security.` and `system.`
+    /// namespaces. Large inodes store attributes after their extra fields as long as
+    /// there is space, all other attributes of an inode must fit into a single block.
+    ///
+    /// If the attribute block of the inode is shared with other inodes, the inode
+    /// gets its own copy. If another block with exactly the same attributes was
+    /// read or written before, that block is shared instead.
+    ///
+    /// Fails with [`Error::NotSupported`] on revision 0 file systems, which predate
+    /// extended attributes.
+    pub fn set_xattr(&mut self, inode_address: InodeAddress, inode: &mut Inode, name: &str, value: &[u8]) -> Result<(), Error> {
+        if self.superblock.version_major() == 0 {
+            return Err(Error::NotSupported);
+        }
+        let (name_index, name) = split_name(name)?;
+        let entry = XattrEntry {
+            name_index,
+            name: name.to_vec(),
+            value: value.to_vec(),
+        };
+        let mut inode_entries = read_inode_xattrs(inode)?;
+        let old_block_entries = self.read_block_xattrs(inode)?;
+        let mut block_entries = old_block_entries.clone();
+        inode_entries.retain(|e| e.name_index != name_index || e.name != name);
+        block_entries.retain(|e| e.name_index != name_index || e.name != name);
+
+        // the attribute goes into the inode if it fits, and into the block otherwise
+        inode_entries.push(entry);
+        if !write_inode_xattrs(inode, &inode_entries) {
+            let entry = inode_entries.pop().unwrap();
+            write_inode_xattrs(inode, &inode_entries);
+            block_entries.push(entry);
+        }
+        if block_entries != old_block_entries {
+            self.write_xattrs(inode_address, inode, block_entries)?;
+        } else {
+            self.write_inode(inode_address, inode)?;
+        }
+
+        if !self.superblock.optional_features().contains(OptionalFeatures::INODES_EXTENDED_ATTRIBUTES) {
+            let features = self.superblock.optional_features() | OptionalFeatures::INODES_EXTENDED_ATTRIBUTES;
+            self.superblock.set_optional_features(features);
+            self.write_superblock()?;
+        }
+        Ok(())
+    }
+
+    /// Removes the extended attribute with the given name, and writes the inode.
+    /// The attribute block is freed once no inode uses it anymore.
+    pub fn remove_xattr(&mut self, inode_address: InodeAddress, inode: &mut Inode, name: &str) -> Result<(), Error> {
+        let (name_index, name) = split_name(name)?;
+        let mut inode_entries = read_inode_xattrs(inode)?;
+        let len = inode_entries.len();
+        inode_entries.retain(|e| e.name_index != name_index || e.name != name);
+        if inode_entries.len() != len {
+            write_inode_xattrs(inode, &inode_entries);
+            return self.write_inode(inode_address, inode);
+        }
+
+        let mut entries = self.read_block_xattrs(inode)?;
+        let len = entries.len();
+        entries.retain(|e| e.name_index != name_index || e.name != name);
+        if entries.len() == len {
+            return Err(Error::NotFound);
+        }
+        self.write_xattrs(inode_address, inode, entries)
+    }
+
+    /// Reads all extended attributes of the given inode, first the ones in the inode,
+    /// then the ones in the attribute block.
+    fn read_xattrs(&self, inode: &Inode) -> Result<Vec<XattrEntry>, Error> {
+        let mut entries = read_inode_xattrs(inode)?;
+        entries.extend(self.read_block_xattrs(inode)?);
+        Ok(entries)
+    }
+
+    /// Reads all extended attributes from the attribute block of the given inode.
+    fn read_block_xattrs(&self, inode: &Inode) -> Result<Vec<XattrEntry>, Error> {
+        let Some(block) = inode.extended_attribute_block() else {
+            return Ok(Vec::new());
+        };
+        let mut data = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(block, &mut data)?;
+        parse_xattr_block(&data).ok_or(Error::CorruptedXattrBlock)
+    }
+
+    /// Stores the given attributes for the given inode, and releases the old attribute
+    /// block of the inode. The inode is written as well.
+    fn write_xattrs(&mut self, inode_address: InodeAddress, inode: &mut Inode, mut entries: Vec<XattrEntry>) -> Result<(), Error> {
+        let block_size = self.superblock.block_size() as usize;
+        let old_block = inode.extended_attribute_block();
+
+        let new_block = if entries.is_empty() {
+            None
+        } else {
+            // the entries are sorted like the kernel sorts them
+            entries.sort_by(|a, b| (a.name_index, a.name.len(), &a.name).cmp(&(b.name_index, b.name.len(), &b.name)));
+            let data = serialize_xattr_block(&entries, block_size).ok_or(Error::NoSpace)?;
+            let hash = u32::from_le_bytes(data[12..16].try_into().unwrap());
+
+            if let Some(old_block) = old_block {
+                self.cache_xattr_block(old_block)?;
+            }
+            match self.find_shared_xattr_block(hash, &data)? {
+                Some(shared) if Some(shared) == old_block => Some(shared),
+                Some(shared) => {
+                    self.change_xattr_refcount(shared, 1)?;
+                    Some(shared)
+                }
+                None => match old_block {
+                    // nobody else uses the old block, so it can be overwritten
+                    Some(old_block) if self.xattr_refcount(old_block)? == 1 => {
+                        self.uncache_xattr_block(old_block)?;
+                        self.write_block(old_block, &data)?;
+                        self.xattr_block_cache.entry(hash).or_default().push(old_block);
+                        Some(old_block)
+                    }
+                    _ => {
+                        let block = self.allocate_block()?.ok_or(Error::NoSpace)?;
+                        self.write_block(block, &data)?;
+                        self.xattr_block_cache.entry(hash).or_default().push(block);
+                        Some(block)
+                    }
+                },
+            }
+        };
+
+        let sectors_per_block = block_size as u32 / 512;
+        if old_block != new_block {
+            if let Some(old_block) = old_block {
+                self.change_xattr_refcount(old_block, -1)?;
+                *inode.num_disk_sectors_mut() = inode.num_disk_sectors().saturating_sub(sectors_per_block);
+            }
+            if new_block.is_some() {
+                *inode.num_disk_sectors_mut() += sectors_per_block;
+            }
+        }
+        inode.set_extended_attribute_block(new_block);
+        self.write_inode(inode_address, inode)
+    }
+
+    /// Finds a known attribute block with the given hash and the same attributes as the
+    /// given block data, which can take another reference.
+    fn find_shared_xattr_block(&self, hash: u32, data: &[u8]) -> Result<Option<BlockAddress>, Error> {
+        // blocks with a hash of 0 must not be shared
+        if hash == 0 {
+            return Ok(None);
+        }
+        let mut candidate = vec![0_u8; data.len()];
+        for &block in self.xattr_block_cache.get(&hash).into_iter().flatten() {
+            self.read_block(block, &mut candidate)?;
+            let refcount = u32::from_le_bytes(candidate[4..8].try_into().unwrap());
+            // the refcount is the only difference between blocks with the same attributes
+            if refcount < REFCOUNT_MAX && candidate[..4] == data[..4] && candidate[8..] == data[8..] {
+                return Ok(Some(block));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remembers the given attribute block, so that it can be shared.
+    fn cache_xattr_block(&mut self, block: BlockAddress) -> Result<(), Error> {
+        let mut data = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(block, &mut data)?;
+        let hash = u32::from_le_bytes(data[12..16].try_into().unwrap());
+        let blocks = self.xattr_block_cache.entry(hash).or_default();
+        if !blocks.contains(&block) {
+            blocks.push(block);
+        }
+        Ok(())
+    }
+
+    /// Forgets the given attribute block, because its attributes change or it is freed.
+    fn uncache_xattr_block(&mut self, block: BlockAddress) -> Result<(), Error> {
+        let mut data = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(block, &mut data)?;
+        let hash = u32::from_le_bytes(data[12..16].try_into().unwrap());
+        if let Some(blocks) = self.xattr_block_cache.get_mut(&hash) {
+            blocks.retain(|&b| b != block);
+        }
+        Ok(())
+    }
+
+    fn xattr_refcount(&self, block: BlockAddress) -> Result<u32, Error> {
+        let mut data = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(block, &mut data)?;
+        Ok(u32::from_le_bytes(data[4..8].try_into().unwrap()))
+    }
+
+    /// Adds the given delta to the refcount of the given attribute block, and frees
+    /// the block if the refcount drops to zero.
+    pub(crate) fn change_xattr_refcount(&mut self, block: BlockAddress, delta: i32) -> Result<(), Error> {
+        let mut data = vec![0_u8; self.superblock.block_size() as usize];
+        self.read_block(block, &mut data)?;
+        let refcount = u32::from_le_bytes(data[4..8].try_into().unwrap()).saturating_add_signed(delta);
+        if refcount == 0 {
+            self.uncache_xattr_block(block)?;
+            return self.free_block(block);
+        }
+        data[4..8].copy_from_slice(&refcount.to_le_bytes());
+        self.write_block(block, &data)?;
+        Ok(())
+    }
+}
+
+/// Reads the extended attributes that are stored in the extra area of the given inode.
+fn read_inode_xattrs(inode: &Inode) -> Result<Vec<XattrEntry>, Error> {
+    if inode.extra_isize() == 0 {
+        return Ok(Vec::new());
+    }
+    let area = inode.xattr_area();
+    match area.get(0..4) {
+        Some(magic) if magic == XATTR_MAGIC.to_le_bytes() => parse_xattr_entries(&area[4..], 0).ok_or(Error::CorruptedXattrBlock),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Stores the given attributes in the extra area of the given inode, without writing the
+/// inode. Returns `false` and leaves the inode unchanged if the attributes don't fit.
+fn write_inode_xattrs(inode: &mut Inode, entries: &[XattrEntry]) -> bool {
+    if inode.extra_isize() == 0 {
+        return entries.is_empty();
+    }
+    let area = inode.xattr_area_mut();
+    if entries.is_empty() {
+        area.fill(0);
+        return true;
+    }
+    if area.len() < 4 {
+        return false;
+    }
+    let mut data = vec![0_u8; area.len() - 4];
+    if !serialize_xattr_entries(entries, &mut data, 0) {
+        return false;
+    }
+    area[0..4].copy_from_slice(&XATTR_MAGIC.to_le_bytes());
+    area[4..].copy_from_slice(&data);
+    true
+}
+
+/// Returns the refcount of the given attribute block, or `None` if the block is not valid.
+pub(crate) fn xattr_block_refcount(data: &[u8]) -> Option<u32> {
+    parse_xattr_block(data)?;
+    Some(u32::from_le_bytes(data[4..8].try_into().unwrap()))
+}
+
+/// Parses the entries of an attribute block. Returns `None` if the block is not valid.
+fn parse_xattr_block(data: &[u8]) -> Option<Vec<XattrEntry>> {
+    let magic = u32::from_le_bytes(data[0..4].try_into().unwrap());
+    let num_blocks = u32::from_le_bytes(data[8..12].try_into().unwrap());
+    if magic != XATTR_MAGIC || num_blocks != 1 {
+        return None;
+    }
+    parse_xattr_entries(data, HEADER_LEN)
+}
+
+/// Parses the entry table that starts at the given offset. The value offsets are relative
+/// to the start of `data`. Returns `None` if the entries are not valid.
+fn parse_xattr_entries(data: &[u8], table_offset: usize) -> Option<Vec<XattrEntry>> {
+    let mut entries = Vec::new();
+    let mut offset = table_offset;
+    // the entry table ends with four zero bytes
+    while data.get(offset..offset + 4)? != [0; 4] {
+        let header = data.get(offset..offset + ENTRY_HEADER_LEN)?;
+        let name_len = header[0] as usize;
+        let name_index = header[1];
+        let value_offset = u16::from_le_bytes([header[2], header[3]]) as usize;
+        let value_inode = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let value_len = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        if value_inode != 0 {
+            // values in separate inodes are an ext4 feature
+            return None;
+        }
+
+        let name = data.get(offset + ENTRY_HEADER_LEN..offset + ENTRY_HEADER_LEN + name_len)?;
+        let value = data.get(value_offset..value_offset.checked_add(value_len)?)?;
+        let entry = XattrEntry {
+            name_index,
+            name: name.to_vec(),
+            value: value.to_vec(),
+        };
+        offset += entry.entry_len();
+        entries.push(entry);
+    }
+    Some(entries)
+}
+
+/// Serializes the given entries into an attribute block with a refcount of one. The
+/// values are stored at the end of the block. Returns `None` if the entries don't fit.
+fn serialize_xattr_block(entries: &[XattrEntry], block_size: usize) -> Option<Vec<u8>> {
+    let mut data = vec![0_u8; block_size];
+    if !serialize_xattr_entries(entries, &mut data, HEADER_LEN) {
+        return None;
+    }
+    data[0..4].copy_from_slice(&XATTR_MAGIC.to_le_bytes());
+    data[4..8].copy_from_slice(&1_u32.to_le_bytes()); // refcount
+    data[8..12].copy_from_slice(&1_u32.to_le_bytes()); // number of blocks
+
+    let mut block_hash = 0_u32;
+    for entry in entries {
+        block_hash = (block_hash << 16) ^ (block_hash >> 16) ^ entry.hash();
+    }
+    // a block with an entry hash of 0 has a block hash of 0, and is never shared
+    if entries.iter().any(|e| e.hash() == 0) {
+        block_hash = 0;
+    }
+    data[12..16].copy_from_slice(&block_hash.to_le_bytes());
+    Some(data)
+}
+
+/// Serializes the entry table into `data`, starting at the given offset, and stores the
+/// values at the end of `data`. The value offsets are relative to the start of `data`.
+/// Returns `false` if the entries don't fit.
+fn serialize_xattr_entries(entries: &[XattrEntry], data: &mut [u8], table_offset: usize) -> bool {
+    let table_len = table_offset + entries.iter().map(XattrEntry::entry_len).sum::<usize>() + 4;
+    let values_len = entries.iter().map(XattrEntry::value_len).sum::<usize>();
+    if table_len + values_len > data.len() {
+        return false;
+    }
+
+    let mut offset = table_offset;
+    let mut value_end = data.len();
+    for entry in entries {
+        let value_offset = value_end - entry.value_len();
+        let entry_hash = entry.hash();
+
+        let header = &mut data[offset..offset + ENTRY_HEADER_LEN];
+        header[0] = entry.name.len() as u8;
+        header[1] = entry.name_index;
+        header[2..4].copy_from_slice(&(value_offset as u16).to_le_bytes());
+        header[8..12].copy_from_slice(&(entry.value.len() as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&entry_hash.to_le_bytes());
+        data[offset + ENTRY_HEADER_LEN..offset + ENTRY_HEADER_LEN + entry.name.len()].copy_from_slice(&entry.name);
+        data[value_offset..value_offset + entry.value.len()].copy_from_slice(&entry.value);
+
+        offset += entry.entry_len();
+        value_end = value_offset;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_name() {
+        assert_eq!(Ok((1, &b"comment"[..])), split_name("user.comment"));
+        assert_eq!(Ok((6, &b"selinux"[..])), split_name("security.selinux"));
+        assert_eq!(Ok((2, &b""[..])), split_name("system.posix_acl_access"));
+        assert_eq!(Ok((7, &b"data"[..])), split_name("system.data"));
+        assert_eq!(Err(Error::NotSupported), split_name("user."));
+        assert_eq!(Err(Error::NotSupported), split_name("unknown.name"));
+    }
+
+    #[test]
+    fn test_serialize_and_parse() {
+        let entries = vec![
+            XattrEntry { name_index: 1, name: b"a".to_vec(), value: b"hello".to_vec() },
+            XattrEntry { name_index: 6, name: b"selinux".to_vec(), value: b"system_u:object_r:etc_t:s0\0".to_vec() },
+        ];
+        let data = serialize_xattr_block(&entries, 1024).unwrap();
+        assert_eq!(Some(entries), parse_xattr_block(&data));
+
+        let too_large = vec![XattrEntry { name_index: 1, name: b"a".to_vec(), value: vec![0; 1024] }];
+        assert_eq!(None, serialize_xattr_block(&too_large, 1024));
+    }
+}
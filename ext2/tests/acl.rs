@@ -0,0 +1,110 @@
+use ext2::{Acl, AclEntry, AclPerm, AclTag, AclType, Error, Ext2Fs, Permissions, Type};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+fn entry(tag: AclTag, perm: AclPerm) -> AclEntry {
+    AclEntry { tag, perm }
+}
+
+fn extended_acl() -> Acl {
+    Acl::new(vec![
+        entry(AclTag::UserObj, AclPerm::all()),
+        entry(AclTag::User(1000), AclPerm::Read | AclPerm::Write),
+        entry(AclTag::GroupObj, AclPerm::Read),
+        entry(AclTag::Group(100), AclPerm::Read),
+        entry(AclTag::Mask, AclPerm::Read | AclPerm::Write),
+        entry(AclTag::Other, AclPerm::empty()),
+    ]).unwrap()
+}
+
+generate_tests!(
+    test_set_and_get_acl:
+    512 - test_set_and_get_acl_standard,
+    1 - test_set_and_get_acl_tiny,
+    32 - test_set_and_get_acl_small,
+    32768 - test_set_and_get_acl_large,
+    1048576 - test_set_and_get_acl_huge,
+);
+
+fn test_set_and_get_acl(sector_size: usize) {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", sector_size);
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.txt").unwrap();
+    assert_eq!(None, fs.get_acl(&file, AclType::Access).unwrap());
+
+    // the permission bits follow the ACL, with the mask as group permissions
+    let acl = extended_acl();
+    fs.set_acl(file.inode_address(), file.inode_mut(), AclType::Access, Some(&acl)).unwrap();
+    assert_eq!(Permissions::from_bits_truncate(0o760), file.perm());
+    assert_eq!(Some(acl.clone()), fs.get_acl(&file, AclType::Access).unwrap());
+    assert_eq!(vec!["system.posix_acl_access"], fs.list_xattr(&file).unwrap());
+    assert_eq!(Error::NotDirectory, fs.set_acl(file.inode_address(), file.inode_mut(), AclType::Default, Some(&acl)).unwrap_err());
+
+    // the changes must be persisted
+    let data = fs.block_device().data().clone();
+    let mut fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, data).unwrap()).unwrap();
+    let mut file = fs.open_file("file.txt").unwrap();
+    assert_eq!(Some(acl), fs.get_acl(&file, AclType::Access).unwrap());
+    assert_eq!(Permissions::from_bits_truncate(0o760), file.perm());
+
+    // an ACL that is equivalent to the permission bits is not stored
+    let minimal = Acl::from_perm(Permissions::from_bits_truncate(0o640));
+    fs.set_acl(file.inode_address(), file.inode_mut(), AclType::Access, Some(&minimal)).unwrap();
+    assert_eq!(None, fs.get_acl(&file, AclType::Access).unwrap());
+    assert_eq!(None, file.extended_attribute_block());
+    assert_eq!(Permissions::from_bits_truncate(0o640), file.perm());
+}
+
+#[test]
+fn test_default_acl_inheritance() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+
+    let mut root = fs.read_root_inode().unwrap();
+    let root_len = root.len();
+    let default_acl = Acl::new(vec![
+        entry(AclTag::UserObj, AclPerm::all()),
+        entry(AclTag::User(1000), AclPerm::all()),
+        entry(AclTag::GroupObj, AclPerm::Read | AclPerm::Execute),
+        entry(AclTag::Mask, AclPerm::all()),
+        entry(AclTag::Other, AclPerm::Read | AclPerm::Execute),
+    ]).unwrap();
+    let address = root.inode_address();
+    fs.set_acl(address, root.inode_mut(), AclType::Default, Some(&default_acl)).unwrap();
+    assert_eq!(Some(default_acl.clone()), fs.get_acl(&root, AclType::Default).unwrap());
+    // the ACL of a directory doesn't change its size
+    assert_eq!(root_len, root.len());
+
+    // the permissions of the new file restrict the inherited ACL
+    let perm = Permissions::from_bits_truncate(0o666);
+    let (_, file) = fs.create_inode_with_perm(&mut root, "file.txt", Type::RegularFile, perm).unwrap();
+    assert_eq!(Permissions::from_bits_truncate(0o664), file.perm());
+    assert_eq!(None, fs.get_acl(&file, AclType::Default).unwrap());
+    let access_acl = fs.get_acl(&file, AclType::Access).unwrap().unwrap();
+    let expected = Acl::new(vec![
+        entry(AclTag::UserObj, AclPerm::Read | AclPerm::Write),
+        entry(AclTag::User(1000), AclPerm::all()),
+        entry(AclTag::GroupObj, AclPerm::Read | AclPerm::Execute),
+        entry(AclTag::Mask, AclPerm::Read | AclPerm::Write),
+        entry(AclTag::Other, AclPerm::Read),
+    ]).unwrap();
+    assert_eq!(expected, access_acl);
+
+    // creating the file again fails before an inode or an attribute block is allocated
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    let free_inodes = fs.superblock().num_unallocated_inodes();
+    assert_eq!(Error::EntryExists, fs.create_inode_with_perm(&mut root, "file.txt", Type::RegularFile, perm).unwrap_err());
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+    assert_eq!(free_inodes, fs.superblock().num_unallocated_inodes());
+
+    // symlinks don't have ACLs
+    let symlink = fs.create_symlink(&mut root, "link", b"file.txt").unwrap();
+    assert!(fs.list_xattr(&symlink).unwrap().is_empty());
+
+    // removing the default ACL stops the inheritance
+    fs.set_acl(address, root.inode_mut(), AclType::Default, None).unwrap();
+    let (_, file) = fs.create_inode_with_perm(&mut root, "other.txt", Type::RegularFile, perm).unwrap();
+    assert_eq!(perm, file.perm());
+    assert_eq!(None, fs.get_acl(&file, AclType::Access).unwrap());
+}
@@ -0,0 +1,58 @@
+use ext2::Ext2Fs;
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+fn reopen(fs: &Ext2Fs<MemoryBlockDevice<Vec<u8>>>) -> Ext2Fs<MemoryBlockDevice<Vec<u8>>> {
+    let data = fs.block_device().data().clone();
+    Ext2Fs::try_new(MemoryBlockDevice::try_new(512, data).unwrap()).unwrap()
+}
+
+#[test]
+fn test_allocate_block() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    let free_inodes = fs.superblock().num_unallocated_inodes();
+
+    // the block bitmap starts at block 1 for 1KiB blocks, and blocks up to 56 are used
+    assert_eq!(Some(57), fs.allocate_block().unwrap().map(|block| block.get()));
+    assert_eq!(Some(58), fs.allocate_block().unwrap().map(|block| block.get()));
+
+    // the bitmap and the free block counts are written back
+    let mut fs = reopen(&fs);
+    assert_eq!(free_blocks - 2, fs.superblock().num_unallocated_blocks());
+    assert_eq!(free_inodes, fs.superblock().num_unallocated_inodes());
+    assert_eq!(Some(59), fs.allocate_block().unwrap().map(|block| block.get()));
+}
+
+#[test]
+fn test_allocate_inode() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    let free_inodes = fs.superblock().num_unallocated_inodes();
+
+    // inode numbers start at 1, and inodes up to 14 are used
+    assert_eq!(Some(15), fs.allocate_inode().unwrap().map(|inode| inode.get()));
+    assert_eq!(Some(16), fs.allocate_inode().unwrap().map(|inode| inode.get()));
+
+    let mut fs = reopen(&fs);
+    assert_eq!(free_inodes - 2, fs.superblock().num_unallocated_inodes());
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+    assert_eq!(Some(17), fs.allocate_inode().unwrap().map(|inode| inode.get()));
+}
+
+#[test]
+fn test_allocate_all_blocks() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    let num_blocks = fs.superblock().num_blocks();
+
+    let mut allocated = 0;
+    while let Some(block) = fs.allocate_block().unwrap() {
+        // the bitmap has more bits than the group has blocks
+        assert!(block.get() < num_blocks);
+        allocated += 1;
+    }
+    assert_eq!(free_blocks, allocated);
+    assert_eq!(0, fs.superblock().num_unallocated_blocks());
+}
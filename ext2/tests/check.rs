@@ -0,0 +1,178 @@
+use ext2::{BlockAddress, BlockOwner, DirType, Ext2Fs, Finding, InodeAddress};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+generate_tests!(
+    test_check_clean:
+    512 - test_check_clean_standard,
+    1 - test_check_clean_tiny,
+    32 - test_check_clean_small,
+    32768 - test_check_clean_large,
+    1048576 - test_check_clean_huge,
+);
+
+fn test_check_clean(sector_size: usize) {
+    for image in [
+        common::load_copy_of_image("tests/filesystems/read.img"),
+        common::load_copy_of_image("tests/filesystems/htree.img"),
+        common::load_copy_of_image("tests/filesystems/rev0.img"),
+        common::load_copy_of_image("tests/filesystems/nofiletype.img"),
+    ] {
+        let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, image).unwrap()).unwrap();
+        assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    }
+}
+
+#[test]
+fn test_check_after_writes() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let mut dir = fs.create_dir(&mut root, "dir").unwrap();
+    let mut file = fs.create_regular_file(&mut dir, "file").unwrap();
+    fs.write_to_file(&mut file, 0, &vec![1; 300_000]).unwrap();
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "user.large", &[2; 200]).unwrap();
+    fs.create_symlink(&mut dir, "link", b"file").unwrap();
+    fs.sync().unwrap();
+
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+}
+
+#[test]
+fn test_check_wrong_link_count() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut file = fs.open_file("/hello.txt").unwrap();
+    *file.inode_mut().num_hard_links_mut() = 3;
+    fs.write_inode(file.inode_address(), file.inode()).unwrap();
+
+    assert_eq!(
+        vec![Finding::WrongLinkCount { inode: file.inode_address(), stored: 3, actual: 1 }],
+        fs.check().unwrap()
+    );
+}
+
+#[test]
+fn test_check_unattached_inode() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let entry = fs.remove_entry_from_dir(&mut root, "hello.txt").unwrap();
+
+    assert_eq!(vec![Finding::UnattachedInode { inode: entry.inode() }], fs.check().unwrap());
+}
+
+#[test]
+fn test_check_invalid_block_pointer() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut file = fs.open_file("/hello.txt").unwrap();
+    let block = file.direct_ptrs().next().unwrap().unwrap();
+    file.inode_mut().set_direct_ptr(0, BlockAddress::new(100_000));
+    fs.write_inode(file.inode_address(), file.inode()).unwrap();
+
+    let findings = fs.check().unwrap();
+    let inode = file.inode_address();
+    assert!(findings.contains(&Finding::InvalidBlockPointer { inode, block: 100_000 }));
+    assert!(findings.contains(&Finding::WrongSectorCount { inode, stored: 2, actual: 0 }));
+    // the old block is not used anymore, but still marked as used
+    assert!(findings.contains(&Finding::BlockBitmapDifference { blocks: block.get()..block.get() + 1, used: false }));
+    assert!(findings.iter().all(|f| f.pass() != 2));
+}
+
+#[test]
+fn test_check_duplicate_block() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let hello = fs.open_file("/hello.txt").unwrap();
+    let block = hello.direct_ptrs().next().unwrap().unwrap();
+    let mut root = fs.read_root_inode().unwrap();
+    let mut copy = fs.create_regular_file(&mut root, "copy.txt").unwrap();
+    copy.inode_mut().set_direct_ptr(0, Some(block));
+    copy.inode_mut().set_len(14);
+    *copy.inode_mut().num_disk_sectors_mut() = 2;
+    fs.write_inode(copy.inode_address(), copy.inode()).unwrap();
+
+    assert_eq!(
+        vec![Finding::DuplicateBlock {
+            block,
+            owners: vec![BlockOwner::Inode(hello.inode_address()), BlockOwner::Inode(copy.inode_address())],
+        }],
+        fs.check().unwrap()
+    );
+}
+
+#[test]
+fn test_check_invalid_inode_table() {
+    // the inode table of the only group is outside of the file system, or even outside
+    // of the address space of the device
+    for inode_table in [5000_u32, 0xffff_fff0] {
+        let mut image = common::load_copy_of_image("tests/filesystems/read.img");
+        image[2048 + 8..2048 + 12].copy_from_slice(&inode_table.to_le_bytes());
+        let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, image).unwrap()).unwrap();
+
+        let findings = fs.check().unwrap();
+        assert!(findings.contains(&Finding::InvalidGroupDescriptor { group: 0 }));
+        assert!(findings.contains(&Finding::RootNotDirectory));
+        assert!(fs.read_root_inode().is_err());
+    }
+}
+
+#[test]
+fn test_check_bitmaps() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let hello = fs.open_file("/hello.txt").unwrap();
+    let block = hello.direct_ptrs().next().unwrap().unwrap();
+    fs.free_block(block).unwrap();
+
+    // freeing a block updates the counters as well, so they are wrong now
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    assert_eq!(
+        vec![
+            Finding::BlockBitmapDifference { blocks: block.get()..block.get() + 1, used: true },
+            Finding::WrongGroupFreeBlocks { group: 0, stored: free_blocks, actual: free_blocks - 1 },
+            Finding::WrongFreeBlocks { stored: free_blocks, actual: free_blocks - 1 },
+        ],
+        fs.check().unwrap()
+    );
+}
+
+#[test]
+fn test_check_unconnected_directory() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let mut a = fs.create_dir(&mut root, "a").unwrap();
+    let b = fs.create_dir(&mut a, "b").unwrap();
+    fs.remove_entry_from_dir(&mut a, "b").unwrap();
+
+    assert_eq!(
+        vec![
+            Finding::UnconnectedDirectory { dir: b.inode_address(), parent: Some(a.inode_address()) },
+            Finding::WrongLinkCount { inode: a.inode_address(), stored: 3, actual: 2 },
+        ],
+        fs.check().unwrap()
+    );
+}
+
+#[test]
+fn test_check_directory_entries() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let hello = fs.open_file("/hello.txt").unwrap().inode_address();
+    let some = fs.open_dir("/some").unwrap().inode_address();
+    fs.add_entry_to_dir(&mut root, "wrong_type", hello, DirType::Directory).unwrap();
+    fs.add_entry_to_dir(&mut root, "dir_link", some, DirType::Directory).unwrap();
+    fs.add_entry_to_dir(&mut root, "unused", InodeAddress::new(100).unwrap(), DirType::RegularFile).unwrap();
+    fs.add_entry_to_dir(&mut root, "reserved", InodeAddress::new(7).unwrap(), DirType::RegularFile).unwrap();
+
+    let root = root.inode_address();
+    let findings = fs.check().unwrap();
+    assert_eq!(
+        vec![
+            Finding::WrongEntryType { dir: root, name: b"wrong_type".to_vec(), inode: hello, stored: DirType::Directory, actual: DirType::RegularFile },
+            Finding::DirectoryHardLink { dir: root, name: b"dir_link".to_vec(), inode: some },
+            Finding::EntryToUnusedInode { dir: root, name: b"unused".to_vec(), inode: InodeAddress::new(100).unwrap() },
+            Finding::InvalidEntryInode { dir: root, name: b"reserved".to_vec(), inode: 7 },
+            Finding::WrongLinkCount { inode: hello, stored: 1, actual: 2 },
+            Finding::WrongLinkCount { inode: some, stored: 2, actual: 3 },
+        ],
+        findings
+    );
+    assert_eq!(vec![2, 2, 2, 2, 4, 4], findings.iter().map(Finding::pass).collect::<Vec<_>>());
+}
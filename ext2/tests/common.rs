@@ -2,7 +2,7 @@ use std::env::temp_dir;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use rand::distributions::Alphanumeric;
+use rand::distr::Alphanumeric;
 use rand::Rng;
 
 #[macro_export]
@@ -31,7 +31,7 @@ macro_rules! generate_tests {
 
 pub fn copy_test_image_for_use(test_image: impl AsRef<Path>) -> PathBuf {
     let temp_dir = temp_dir();
-    let name = rand::thread_rng().sample_iter(&Alphanumeric).take(10).map(char::from).collect::<String>();
+    let name = rand::rng().sample_iter(&Alphanumeric).take(10).map(char::from).collect::<String>();
     let temp_image = temp_dir.join(format!("{}.img", name));
 
     fs::copy(test_image, &temp_image).unwrap();
@@ -0,0 +1,113 @@
+use ext2::{Error, Ext2Fs, SeekFrom};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+#[test]
+fn test_file_handle() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+
+    let mut root = fs.read_root_inode().unwrap();
+    let file = fs.create_regular_file(&mut root, "file.txt").unwrap();
+    let mut handle = fs.file_handle(file);
+
+    // write past the direct blocks, so that indirect blocks are used
+    let data = (0..20 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    for chunk in data.chunks(1000) {
+        assert_eq!(chunk.len(), handle.write(chunk).unwrap());
+    }
+    assert_eq!(data.len() as u64, handle.position());
+    assert_eq!(data.len() as u64, handle.len());
+
+    assert_eq!(0, handle.seek(SeekFrom::Start(0)).unwrap());
+    let mut read_data = vec![0_u8; data.len()];
+    let mut total = 0;
+    while total < read_data.len() {
+        let read = handle.read(&mut read_data[total..]).unwrap();
+        assert_ne!(0, read);
+        total += read;
+    }
+    assert_eq!(data, read_data);
+    assert_eq!(0, handle.read(&mut read_data).unwrap());
+
+    assert_eq!(data.len() as u64 - 10, handle.seek(SeekFrom::End(-10)).unwrap());
+    assert_eq!(data.len() as u64 - 15, handle.seek(SeekFrom::Current(-5)).unwrap());
+    let mut buf = [0_u8; 15];
+    assert_eq!(15, handle.read(&mut buf).unwrap());
+    assert_eq!(&data[data.len() - 15..], &buf);
+    assert_eq!(Error::InvalidSeek, handle.seek(SeekFrom::Current(-(data.len() as i64) - 1)).unwrap_err());
+
+    // the data must also be readable from the file system after the handle is gone
+    let file = fs.open_file("/file.txt").unwrap();
+    assert_eq!(data.len() as u64, file.len());
+    let mut read_data = vec![0_u8; data.len()];
+    assert_eq!(data.len(), fs.read_from_file(&file, 0, &mut read_data).unwrap());
+    assert_eq!(data, read_data);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_file_handle_std_io() {
+    use std::io::{Read, Seek, Write};
+
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_regular_file(&mut root, "file.txt").unwrap();
+    let mut handle = fs.open_file_handle("/file.txt").unwrap();
+
+    let data = (0..50 * 1024).map(|i| (i % 13) as u8).collect::<Vec<_>>();
+    assert_eq!(data.len() as u64, std::io::copy(&mut data.as_slice(), &mut handle).unwrap());
+    handle.flush().unwrap();
+
+    // the inherent methods of the handle take precedence over the trait methods
+    Seek::seek(&mut handle, std::io::SeekFrom::Start(0)).unwrap();
+    let mut read_data = Vec::new();
+    handle.read_to_end(&mut read_data).unwrap();
+    assert_eq!(data, read_data);
+
+    assert_eq!(data.len() as u64 - 1, Seek::seek(&mut handle, std::io::SeekFrom::End(-1)).unwrap());
+    assert_eq!(std::io::ErrorKind::InvalidInput, Seek::seek(&mut handle, std::io::SeekFrom::End(-(data.len() as i64) - 1)).unwrap_err().kind());
+}
+
+#[cfg(feature = "embedded-io")]
+#[test]
+fn test_file_handle_embedded_io() {
+    use embedded_io::{Read, Seek, Write};
+
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_regular_file(&mut root, "file.txt").unwrap();
+    let mut handle = fs.open_file_handle("/file.txt").unwrap();
+
+    let data = (0..20 * 1024).map(|i| (i % 13) as u8).collect::<Vec<_>>();
+    Write::write_all(&mut handle, &data).unwrap();
+
+    Seek::seek(&mut handle, embedded_io::SeekFrom::Start(0)).unwrap();
+    let mut read_data = vec![0_u8; data.len()];
+    Read::read_exact(&mut handle, &mut read_data).unwrap();
+    assert_eq!(data, read_data);
+}
+
+#[cfg(feature = "embedded-io")]
+#[test]
+fn test_embedded_io_block_device() {
+    use filesystem::EmbeddedIoBlockDevice;
+
+    // a file handle in one file system can be used as the storage of another one
+    let mut outer = cow_fs!("tests/filesystems/empty.img", 512);
+    let mut root = outer.read_root_inode().unwrap();
+    outer.create_regular_file(&mut root, "inner.img").unwrap();
+    let mut handle = outer.open_file_handle("/inner.img").unwrap();
+    let mut read_image = common::load_copy_of_image("tests/filesystems/read.img");
+    read_image.truncate(256 * 1024); // all used blocks of the image are at the start
+    embedded_io::Write::write_all(&mut handle, &read_image).unwrap();
+
+    let device = EmbeddedIoBlockDevice::try_new(512, handle).unwrap();
+    let inner = Ext2Fs::try_new(device).unwrap();
+    let hello_txt = inner.open_file("/hello.txt").unwrap();
+    let mut buf = [0_u8; 14];
+    inner.read_from_file(&hello_txt, 0, &mut buf).unwrap();
+    assert_eq!(b"Hello, World!\n", &buf);
+}
@@ -0,0 +1,101 @@
+use ext2::{DirType, Error, Ext2Fs, FormatOptions, RequiredFeatures, Revision};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+generate_tests!(
+    test_read_without_file_types:
+    512 - test_read_without_file_types_standard,
+    1 - test_read_without_file_types_tiny,
+    32 - test_read_without_file_types_small,
+    32768 - test_read_without_file_types_large,
+    1048576 - test_read_without_file_types_huge,
+);
+
+fn test_read_without_file_types(sector_size: usize) {
+    let fs = cow_fs!("tests/filesystems/nofiletype.img", sector_size);
+    assert!(!fs.superblock().required_features().contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE));
+
+    let hello = fs.open_file("/hello.txt").unwrap();
+    let mut buf = vec![0_u8; hello.len() as usize];
+    fs.read_from_file(&hello, 0, &mut buf).unwrap();
+    assert_eq!(b"Hello, World!\n", buf.as_slice());
+
+    // the entries don't have a type, so it comes from the inode
+    let dir = fs.open_dir("/dir").unwrap();
+    let entries = fs.list_dir(&dir).unwrap();
+    assert!(entries.iter().all(|e| e.typ().is_none()));
+    let typ = |name: &str| fs.dir_entry_type(entries.iter().find(|e| e.name() == Some(name)).unwrap()).unwrap();
+    assert_eq!(DirType::Directory, typ("."));
+    assert_eq!(DirType::Directory, typ("sub"));
+    assert_eq!(DirType::SymLink, typ("link"));
+    assert_eq!(DirType::RegularFile, typ("numbers.txt"));
+    assert_eq!(hello.inode_address(), fs.resolve_path("/dir/link").unwrap().0);
+
+    // the directory with many entries spans multiple blocks
+    let sub = fs.open_dir("/dir/sub").unwrap();
+    assert!(sub.len() > fs.superblock().block_size() as u64);
+    assert_eq!(63, fs.list_dir(&sub).unwrap().len());
+    let root = fs.read_root_inode().unwrap();
+    let fifo = fs.find_entry(&root, |e| e.name() == Some("fifo")).unwrap().unwrap();
+    assert_eq!(DirType::FIFO, fs.dir_entry_type(&fifo).unwrap());
+}
+
+#[test]
+fn test_write_without_file_types() {
+    let mut fs = cow_fs!("tests/filesystems/nofiletype.img", 512);
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut dir = fs.create_dir(&mut root, "new_dir").unwrap();
+    let mut file = fs.create_regular_file(&mut dir, "new.txt").unwrap();
+    fs.write_to_file(&mut file, 0, b"written").unwrap();
+    let mut sub = fs.open_dir("/dir/sub").unwrap();
+    fs.remove_entry_from_dir(&mut sub, "entry_with_a_longer_name_1").unwrap();
+
+    let data = fs.block_device().data().clone();
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, data).unwrap()).unwrap();
+    let dir = fs.open_dir("/new_dir").unwrap();
+    let mut names = fs.list_dir(&dir).unwrap().iter().map(|e| e.name().unwrap().to_string()).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(vec![".", "..", "new.txt"], names);
+    let file = fs.open_file("/new_dir/new.txt").unwrap();
+    let mut buf = [0_u8; 7];
+    fs.read_from_file(&file, 0, &mut buf).unwrap();
+    assert_eq!(b"written", &buf);
+    assert_eq!(62, fs.list_dir(&fs.open_dir("/dir/sub").unwrap()).unwrap().len());
+}
+
+#[test]
+fn test_enable_dir_entry_types() {
+    let mut fs = cow_fs!("tests/filesystems/nofiletype.img", 512);
+    fs.enable_dir_entry_types().unwrap();
+    assert!(fs.superblock().required_features().contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE));
+
+    // the types are stored in the entries, and the names are unchanged
+    let data = fs.block_device().data().clone();
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, data).unwrap()).unwrap();
+    let dir = fs.open_dir("/dir").unwrap();
+    let entries = fs.list_dir(&dir).unwrap();
+    let typ = |name: &str| entries.iter().find(|e| e.name() == Some(name)).unwrap().typ();
+    assert_eq!(Some(DirType::Directory), typ(".."));
+    assert_eq!(Some(DirType::Directory), typ("sub"));
+    assert_eq!(Some(DirType::SymLink), typ("link"));
+    assert_eq!(Some(DirType::RegularFile), typ("numbers.txt"));
+    let sub = fs.open_dir("/dir/sub").unwrap();
+    let entries = fs.list_dir(&sub).unwrap();
+    assert_eq!(63, entries.len());
+    assert!(entries.iter().filter(|e| e.name().unwrap().starts_with("entry_")).all(|e| e.typ() == Some(DirType::RegularFile)));
+    assert_eq!(b"sub\n", fs.open_file("/dir/sub/file").map(|f| {
+        let mut buf = [0_u8; 4];
+        fs.read_from_file(&f, 0, &mut buf).unwrap();
+        buf
+    }).unwrap().as_slice());
+}
+
+#[test]
+fn test_enable_dir_entry_types_rev0() {
+    let device = MemoryBlockDevice::try_new(512, vec![0; 1024 * 1024]).unwrap();
+    let options = FormatOptions { revision: Revision::GoodOld, ..Default::default() };
+    let mut fs = Ext2Fs::format(device, &options).unwrap();
+    assert_eq!(Err(Error::NotSupported), fs.enable_dir_entry_types());
+}
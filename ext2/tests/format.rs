@@ -0,0 +1,132 @@
+use ext2::{DirType, Error, Ext2Fs, FormatOptions, InodeAddress, ReadOnlyFeatures, RequiredFeatures, Revision, State};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+const IMAGE_SIZE: usize = 8 * 1024 * 1024;
+/// The offsets of fields of the dynamic revision in the superblock.
+const FIRST_INODE_OFFSET: usize = 1024 + 84;
+const INODE_SIZE_OFFSET: usize = 1024 + 88;
+
+generate_tests!(
+    test_format:
+    512 - test_format_standard,
+    1 - test_format_tiny,
+    32 - test_format_small,
+    32768 - test_format_large,
+    1048576 - test_format_huge,
+);
+
+fn test_format(sector_size: usize) {
+    let device = MemoryBlockDevice::try_new(sector_size, vec![0_u8; IMAGE_SIZE]).unwrap();
+    let options = FormatOptions {
+        time: 1_700_000_000,
+        ..Default::default()
+    };
+    let mut fs = Ext2Fs::format(device, &options).unwrap();
+
+    let superblock = fs.superblock();
+    assert_eq!(Revision::Dynamic, superblock.revision());
+    assert_eq!(8192, superblock.num_blocks());
+    assert_eq!(1024, superblock.block_size());
+    assert_eq!(256, superblock.inode_size());
+    assert_eq!(2048, superblock.num_inodes());
+    assert_eq!(1_700_000_000, superblock.creation_time());
+    assert_eq!(State::CLEAN, superblock.state());
+    assert!(superblock.required_features().contains(RequiredFeatures::DIRECTORY_ENTRIES_HAVE_TYPE));
+    assert!(superblock.write_required_features().contains(ReadOnlyFeatures::SPARSE_SUPERBLOCK_AND_GDTS));
+    // all but the reserved inodes, the root directory and lost+found are free
+    assert_eq!(2048 - 11, superblock.num_unallocated_inodes());
+
+    let mut root = fs.read_root_inode().unwrap();
+    assert_eq!(3, root.num_hard_links());
+    let entries = fs.list_dir(&root).unwrap();
+    let entries = entries.iter().map(|e| (e.inode().get(), e.name().unwrap(), e.typ())).collect::<Vec<_>>();
+    assert_eq!(vec![
+        (2, ".", Some(DirType::Directory)),
+        (2, "..", Some(DirType::Directory)),
+        (11, "lost+found", Some(DirType::Directory)),
+    ], entries);
+    let lost_and_found = fs.open_dir("/lost+found").unwrap();
+    assert_eq!(2, fs.list_dir(&lost_and_found).unwrap().len());
+    assert_eq!(32, lost_and_found.extra_isize());
+
+    // the new file system can be used right away
+    let mut dir = fs.create_dir(&mut root, "dir").unwrap();
+    let mut file = fs.create_regular_file(&mut dir, "file.txt").unwrap();
+    fs.write_to_file(&mut file, 0, b"Hello").unwrap();
+    let data = fs.block_device().data().clone();
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, data).unwrap()).unwrap();
+    assert_eq!(4, fs.read_root_inode().unwrap().num_hard_links());
+    assert_eq!(5, fs.open_file("/dir/file.txt").unwrap().len());
+    assert_eq!(2, fs.open_dir("/dir").unwrap().num_hard_links());
+}
+
+#[test]
+fn test_format_rev0() {
+    let device = MemoryBlockDevice::try_new(512, vec![0_u8; IMAGE_SIZE]).unwrap();
+    let options = FormatOptions {
+        revision: Revision::GoodOld,
+        inode_size: 256, // ignored for revision 0
+        ..Default::default()
+    };
+    let mut fs = Ext2Fs::format(device, &options).unwrap();
+    assert_eq!(Revision::GoodOld, fs.superblock().revision());
+    assert_eq!(128, fs.superblock().inode_size());
+    assert_eq!(RequiredFeatures::empty(), fs.superblock().required_features());
+    // the fields of the dynamic revision are not written
+    let data = fs.block_device().data();
+    assert_eq!([0; 4], data[FIRST_INODE_OFFSET..FIRST_INODE_OFFSET + 4]);
+    assert_eq!([0; 2], data[INODE_SIZE_OFFSET..INODE_SIZE_OFFSET + 2]);
+
+    // the directory entries don't have file types
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_regular_file(&mut root, "file.txt").unwrap();
+    let entries = fs.list_dir(&root).unwrap();
+    assert_eq!(4, entries.len());
+    assert!(entries.iter().all(|e| e.typ().is_none()));
+    assert_eq!(12, fs.open_file("/file.txt").unwrap().inode_address().get());
+}
+
+#[test]
+fn test_format_block_sizes() {
+    for block_size in [1024, 2048, 4096] {
+        let device = MemoryBlockDevice::try_new(512, vec![0_u8; IMAGE_SIZE * 4]).unwrap();
+        let options = FormatOptions {
+            block_size,
+            ..Default::default()
+        };
+        let fs = Ext2Fs::format(device, &options).unwrap();
+        assert_eq!(block_size, fs.superblock().block_size());
+        assert_eq!((IMAGE_SIZE * 4) as u32 / block_size, fs.superblock().num_blocks());
+        assert!(fs.open_dir("/lost+found").is_ok());
+    }
+}
+
+#[test]
+fn test_format_errors() {
+    let options = FormatOptions::default();
+    let device = MemoryBlockDevice::try_new(512, vec![0_u8; 4096]).unwrap();
+    assert_eq!(Error::NoSpace, Ext2Fs::format(device, &options).err().unwrap());
+
+    let device = MemoryBlockDevice::try_new(512, vec![0_u8; IMAGE_SIZE]).unwrap();
+    let options = FormatOptions {
+        block_size: 1000,
+        ..Default::default()
+    };
+    assert_eq!(Error::NotSupported, Ext2Fs::format(device, &options).err().unwrap());
+}
+
+#[test]
+fn test_format_over_existing_file_system() {
+    let image_data = common::load_copy_of_image("tests/filesystems/htree.img");
+    let device = MemoryBlockDevice::try_new(512, image_data).unwrap();
+    let fs = Ext2Fs::format(device, &FormatOptions::default()).unwrap();
+
+    // nothing of the old file system is left
+    let root = fs.read_root_inode().unwrap();
+    assert_eq!(3, fs.list_dir(&root).unwrap().len());
+    assert_eq!(Error::NotFound, fs.open_dir("/big").unwrap_err());
+    let (_, inode) = fs.read_inode(InodeAddress::new(12).unwrap()).unwrap();
+    assert_eq!(0, inode.num_hard_links());
+}
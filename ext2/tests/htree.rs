@@ -0,0 +1,191 @@
+use ext2::{DirType, Error, Ext2Fs, Flags, HashVersion};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+/// The offset of the hash version in the first block of a hash indexed directory.
+const DX_ROOT_HASH_VERSION_OFFSET: usize = 28;
+/// The offset of the number of interior index levels in the first block of a hash indexed directory.
+const DX_ROOT_INDIRECT_LEVELS_OFFSET: usize = 30;
+
+generate_tests!(
+    test_indexed_lookup:
+    512 - test_indexed_lookup_standard,
+    1 - test_indexed_lookup_tiny,
+    32 - test_indexed_lookup_small,
+    32768 - test_indexed_lookup_large,
+    1048576 - test_indexed_lookup_huge,
+);
+
+fn test_indexed_lookup(sector_size: usize) {
+    let fs = cow_fs!("tests/filesystems/htree.img", sector_size);
+    assert_eq!(Some(HashVersion::HalfMd4), fs.superblock().default_hash_version());
+
+    // /big has an index with an interior level, /small only has the root
+    let big = fs.open_dir("/big").unwrap();
+    assert!(big.flags().contains(Flags::HashIndexedDirectory));
+    for i in (0..10000).step_by(37) {
+        let name = format!("file_{}", i);
+        let entry = fs.find_entry_by_name(&big, name.as_bytes()).unwrap().unwrap();
+        assert_eq!(Some(name.as_str()), entry.name());
+    }
+    assert!(fs.find_entry_by_name(&big, b"file_10000").unwrap().is_none());
+
+    let small = fs.open_dir("/small").unwrap();
+    assert!(small.flags().contains(Flags::HashIndexedDirectory));
+    for i in 0..200 {
+        let name = format!("entry_{}", i);
+        assert!(fs.find_entry_by_name(&small, name.as_bytes()).unwrap().is_some());
+    }
+    // names with non-ASCII bytes hash differently with signed and unsigned characters
+    assert!(fs.find_entry_by_name(&small, b"h\xE4llo").unwrap().is_some());
+    assert!(fs.find_entry_by_name(&small, "Ünïcödé".as_bytes()).unwrap().is_some());
+    assert!(fs.find_entry_by_name(&small, b"entry_200").unwrap().is_none());
+
+    // paths are resolved through the index as well
+    let target = fs.open_file("/target").unwrap();
+    assert_eq!(target.inode_address(), fs.open_file("/big/file_9999").unwrap().inode_address());
+    assert_eq!(target.inode_address(), fs.open_file("/small/Ünïcödé").unwrap().inode_address());
+}
+
+#[test]
+fn test_invalid_index_falls_back_to_linear_lookup() {
+    let mut image_data = common::load_copy_of_image("tests/filesystems/htree.img");
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, image_data.clone()).unwrap()).unwrap();
+    let block_size = fs.superblock().block_size() as usize;
+    let small = fs.open_dir("/small").unwrap();
+    let root_block = fs.resolve_block_index(&small, 0).unwrap().unwrap();
+
+    // an unknown hash version makes the index unusable
+    image_data[root_block.get() as usize * block_size + DX_ROOT_HASH_VERSION_OFFSET] = 0xFF;
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, image_data).unwrap()).unwrap();
+    let small = fs.open_dir("/small").unwrap();
+    for i in 0..200 {
+        let name = format!("entry_{}", i);
+        assert!(fs.find_entry_by_name(&small, name.as_bytes()).unwrap().is_some());
+    }
+    assert!(fs.find_entry_by_name(&small, b"entry_200").unwrap().is_none());
+}
+
+#[test]
+fn test_add_entries_to_indexed_dir() {
+    let mut fs = cow_fs!("tests/filesystems/htree.img", 512);
+    let target = fs.open_file("/target").unwrap().inode_address();
+
+    // long names fill the leaves quickly, so that leaves and interior index blocks are split
+    let mut big = fs.open_dir("/big").unwrap();
+    for i in 0..300 {
+        fs.add_entry_to_dir(&mut big, long_name(i), target, DirType::RegularFile).unwrap();
+    }
+
+    let big = fs.open_dir("/big").unwrap();
+    assert!(big.flags().contains(Flags::HashIndexedDirectory));
+    for i in 0..300 {
+        assert!(fs.find_entry_by_name(&big, long_name(i).as_bytes()).unwrap().is_some());
+    }
+    for i in (0..10000).step_by(37) {
+        assert!(fs.find_entry_by_name(&big, format!("file_{}", i).as_bytes()).unwrap().is_some());
+    }
+    assert_eq!(10000 + 300 + 2, fs.list_dir(&big).unwrap().len());
+}
+
+#[test]
+fn test_add_index_level() {
+    let mut fs = cow_fs!("tests/filesystems/htree.img", 512);
+    let block_size = fs.superblock().block_size() as usize;
+    let target = fs.open_file("/target").unwrap().inode_address();
+
+    // the root of /small has no interior index blocks, until it is full
+    let mut small = fs.open_dir("/small").unwrap();
+    for i in 0..400 {
+        fs.add_entry_to_dir(&mut small, long_name(i), target, DirType::RegularFile).unwrap();
+    }
+
+    let small = fs.open_dir("/small").unwrap();
+    assert!(small.flags().contains(Flags::HashIndexedDirectory));
+    let root_block = fs.resolve_block_index(&small, 0).unwrap().unwrap();
+    let indirect_levels = fs.block_device().data()[root_block.get() as usize * block_size + DX_ROOT_INDIRECT_LEVELS_OFFSET];
+    assert_eq!(1, indirect_levels);
+    for i in 0..400 {
+        assert!(fs.find_entry_by_name(&small, long_name(i).as_bytes()).unwrap().is_some());
+    }
+    for i in 0..200 {
+        assert!(fs.find_entry_by_name(&small, format!("entry_{}", i).as_bytes()).unwrap().is_some());
+    }
+}
+
+#[test]
+fn test_convert_to_indexed_dir() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let block_size = fs.superblock().block_size() as u64;
+
+    let mut root = fs.read_root_inode().unwrap();
+    assert_eq!(block_size, root.len());
+    assert!(!root.flags().contains(Flags::HashIndexedDirectory));
+
+    // once the first block is full, the directory gets an index
+    for i in 0..60 {
+        fs.create_regular_file(&mut root, format!("some_file_with_a_long_name_{}", i)).unwrap();
+    }
+    let root = fs.read_root_inode().unwrap();
+    assert!(root.flags().contains(Flags::HashIndexedDirectory));
+    assert!(root.len() > 2 * block_size);
+    for i in 0..60 {
+        let name = format!("some_file_with_a_long_name_{}", i);
+        assert!(fs.find_entry_by_name(&root, name.as_bytes()).unwrap().is_some());
+    }
+    assert!(fs.find_entry_by_name(&root, b"lost+found").unwrap().is_some());
+    assert_eq!(60 + 3, fs.list_dir(&root).unwrap().len());
+}
+
+#[test]
+fn test_remove_entries() {
+    let mut fs = cow_fs!("tests/filesystems/htree.img", 512);
+
+    let mut small = fs.open_dir("/small").unwrap();
+    for i in (0..200).step_by(3) {
+        let entry = fs.remove_entry_from_dir(&mut small, format!("entry_{}", i)).unwrap();
+        assert_eq!(Some(format!("entry_{}", i).as_str()), entry.name());
+    }
+    assert_eq!(Error::NotFound, fs.remove_entry_from_dir(&mut small, "entry_0").unwrap_err());
+    assert_eq!(Error::NotSupported, fs.remove_entry_from_dir(&mut small, "..").unwrap_err());
+
+    // the index doesn't change when entries are removed
+    let small = fs.open_dir("/small").unwrap();
+    assert!(small.flags().contains(Flags::HashIndexedDirectory));
+    for i in 0..200 {
+        let found = fs.find_entry_by_name(&small, format!("entry_{}", i).as_bytes()).unwrap().is_some();
+        assert_eq!(i % 3 != 0, found);
+    }
+    assert_eq!(2 + 202 - 67, fs.list_dir(&small).unwrap().len());
+
+    // entries are removed from linear directories as well
+    let mut root = fs.read_root_inode().unwrap();
+    fs.remove_entry_from_dir(&mut root, "target").unwrap();
+    assert_eq!(Error::NotFound, fs.open_file("/target").unwrap_err());
+    assert!(fs.open_dir("/small").is_ok());
+}
+
+#[test]
+fn test_invalid_index_is_dropped_on_insert() {
+    let mut image_data = common::load_copy_of_image("tests/filesystems/htree.img");
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, image_data.clone()).unwrap()).unwrap();
+    let block_size = fs.superblock().block_size() as usize;
+    let small = fs.open_dir("/small").unwrap();
+    let root_block = fs.resolve_block_index(&small, 0).unwrap().unwrap();
+
+    image_data[root_block.get() as usize * block_size + DX_ROOT_HASH_VERSION_OFFSET] = 0xFF;
+    let mut fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, image_data).unwrap()).unwrap();
+    let mut small = fs.open_dir("/small").unwrap();
+    fs.create_regular_file(&mut small, "new_file").unwrap();
+
+    // the directory is used without its index from now on
+    let small = fs.open_dir("/small").unwrap();
+    assert!(!small.flags().contains(Flags::HashIndexedDirectory));
+    assert!(fs.find_entry_by_name(&small, b"new_file").unwrap().is_some());
+    assert!(fs.find_entry_by_name(&small, b"entry_42").unwrap().is_some());
+}
+
+fn long_name(i: usize) -> String {
+    format!("{:0>250}", i)
+}
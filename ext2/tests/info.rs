@@ -0,0 +1,57 @@
+use ext2::{Ext2Fs, FormatOptions, Revision};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+generate_tests!(
+    test_free_ranges:
+    512 - test_free_ranges_standard,
+    1 - test_free_ranges_tiny,
+    32 - test_free_ranges_small,
+    32768 - test_free_ranges_large,
+    1048576 - test_free_ranges_huge,
+);
+
+fn test_free_ranges(sector_size: usize) {
+    let fs = cow_fs!("tests/filesystems/read.img", sector_size);
+    let descriptors = fs.block_group_descriptors();
+    assert_eq!(1, descriptors.len());
+    assert_eq!(6, descriptors[0].block_usage_bitmap_block());
+    assert_eq!(7, descriptors[0].inode_usage_bitmap_block());
+    assert_eq!(8, descriptors[0].inode_table_starting_block());
+
+    // the ranges add up to the free counts of the descriptor
+    let free_blocks = fs.free_block_ranges(0).unwrap();
+    let free_inodes = fs.free_inode_ranges(0).unwrap();
+    assert_eq!(vec![57..1024], free_blocks);
+    assert_eq!(vec![15..129], free_inodes);
+    assert_eq!(descriptors[0].num_unallocated_blocks() as usize, free_blocks.iter().map(|range| range.len()).sum::<usize>());
+    assert_eq!(descriptors[0].num_unallocated_inodes() as usize, free_inodes.iter().map(|range| range.len()).sum::<usize>());
+}
+
+#[test]
+fn test_free_ranges_multiple_groups() {
+    let device = MemoryBlockDevice::try_new(512, vec![0_u8; 20 * 1024 * 1024]).unwrap();
+    let fs = Ext2Fs::format(device, &FormatOptions::default()).unwrap();
+    let inodes_per_group = fs.superblock().inodes_per_group();
+    for group_index in 1..fs.block_group_descriptors().len() {
+        // groups start at block 1, and the last group ends with the file system
+        let first_block = 1 + 8192 * group_index as u32;
+        let free_blocks = fs.free_block_ranges(group_index).unwrap();
+        assert_eq!((first_block + 8192).min(20480), free_blocks.last().unwrap().end);
+        assert!(free_blocks[0].start > first_block);
+
+        // no inodes are used outside of the first group
+        assert_eq!(vec![inodes_per_group * group_index as u32 + 1..inodes_per_group * (group_index as u32 + 1) + 1], fs.free_inode_ranges(group_index).unwrap());
+    }
+}
+
+#[test]
+fn test_feature_names() {
+    let fs = cow_fs!("tests/filesystems/read.img", 512);
+    assert_eq!(vec!["ext_attr", "resize_inode", "dir_index", "filetype", "sparse_super", "large_file"], fs.superblock().feature_names());
+
+    let fs = cow_fs!("tests/filesystems/rev0.img", 512);
+    assert_eq!(Revision::GoodOld, fs.superblock().revision());
+    assert!(fs.superblock().feature_names().is_empty());
+}
@@ -0,0 +1,33 @@
+use ext2::Ext2Fs;
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+#[test]
+fn test_create_links_inode() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let file = fs.create_regular_file(&mut root, "file.txt").unwrap();
+    assert_eq!(1, file.num_hard_links());
+
+    // the directory entry is the only link, on disk as well
+    let (_, inode) = fs.read_inode(file.inode_address()).unwrap();
+    assert_eq!(1, inode.num_hard_links());
+}
+
+#[test]
+fn test_write_counts_disk_sectors() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let sectors_per_block = fs.superblock().block_size() / 512;
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.txt").unwrap();
+
+    fs.write_to_file(&mut file, 0, &[1; 1500]).unwrap();
+    let blocks = 1500_u32.div_ceil(fs.superblock().block_size());
+    assert_eq!(blocks * sectors_per_block, file.num_disk_sectors());
+
+    // overwriting doesn't allocate, so the count stays the same
+    fs.write_to_file(&mut file, 0, &[2; 1500]).unwrap();
+    let (_, inode) = fs.read_inode(file.inode_address()).unwrap();
+    assert_eq!(blocks * sectors_per_block, inode.num_disk_sectors());
+}
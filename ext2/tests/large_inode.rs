@@ -0,0 +1,117 @@
+use ext2::{Error, Ext2Fs, Timestamp};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+/// The offset of `i_version_hi`, an extra field that is not interpreted by this crate.
+const VERSION_HI_OFFSET: usize = 152;
+
+generate_tests!(
+    test_timestamps:
+    512 - test_timestamps_standard,
+    1 - test_timestamps_tiny,
+    32 - test_timestamps_small,
+    32768 - test_timestamps_large,
+    1048576 - test_timestamps_huge,
+);
+
+fn test_timestamps(sector_size: usize) {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", sector_size);
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.txt").unwrap();
+    assert_eq!(32, file.extra_isize());
+
+    // times after 2038 and before 1970 need the epoch bits of the extra fields
+    let access = Timestamp { seconds: 5_000_000_000, nanoseconds: 123_456_789 };
+    let modification = Timestamp { seconds: -5, nanoseconds: 999_999_999 };
+    let change = Timestamp { seconds: 1_700_000_000, nanoseconds: 1 };
+    let birth = Timestamp { seconds: 1_600_000_000, nanoseconds: 500 };
+    file.inode_mut().set_last_access_timestamp(access);
+    file.inode_mut().set_last_modification_timestamp(modification);
+    file.inode_mut().set_creation_timestamp(change);
+    file.inode_mut().set_birth_timestamp(birth);
+    fs.write_inode(file.inode_address(), file.inode()).unwrap();
+
+    // the changes must be persisted
+    let data = fs.block_device().data().clone();
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, data).unwrap()).unwrap();
+    let file = fs.open_file("file.txt").unwrap();
+    assert_eq!(access, file.last_access_timestamp());
+    assert_eq!(modification, file.last_modification_timestamp());
+    assert_eq!(change, file.creation_timestamp());
+    assert_eq!(Some(birth), file.birth_timestamp());
+    assert_eq!(5_000_000_000_u64 as u32, file.last_access_time());
+}
+
+#[test]
+fn test_unknown_extra_bytes_are_preserved() {
+    let mut image_data = common::load_copy_of_image("tests/filesystems/empty.img");
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, image_data.clone()).unwrap()).unwrap();
+    let block_size = fs.superblock().block_size() as usize;
+    let inode_size = fs.superblock().inode_size() as usize;
+
+    // the root inode is the second inode in the inode table of the first group
+    let descriptor_table = (fs.superblock().superblock_block_number() as usize + 1) * block_size;
+    let inode_table = u32::from_le_bytes(image_data[descriptor_table + 8..descriptor_table + 12].try_into().unwrap()) as usize;
+    let root_offset = inode_table * block_size + inode_size;
+    image_data[root_offset + VERSION_HI_OFFSET..root_offset + VERSION_HI_OFFSET + 4].copy_from_slice(&[1, 2, 3, 4]);
+
+    let mut fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, image_data).unwrap()).unwrap();
+    let mut root = fs.read_root_inode().unwrap();
+    root.inode_mut().set_last_modification_timestamp(Timestamp { seconds: 42, nanoseconds: 42 });
+    fs.write_inode(root.inode_address(), root.inode()).unwrap();
+
+    let data = fs.block_device().data();
+    assert_eq!([1, 2, 3, 4], data[root_offset + VERSION_HI_OFFSET..root_offset + VERSION_HI_OFFSET + 4]);
+    assert_eq!(Timestamp { seconds: 42, nanoseconds: 42 }, fs.read_root_inode().unwrap().last_modification_timestamp());
+}
+
+#[test]
+fn test_xattrs_in_inode() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.txt").unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+
+    // small attributes are stored in the inode, and don't need a block
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "user.a", b"1").unwrap();
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "security.selinux", b"system_u:object_r:etc_t:s0\0").unwrap();
+    assert_eq!(None, file.extended_attribute_block());
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+
+    // once the inode is full, attributes go into the block
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "user.b", &[b'b'; 64]).unwrap();
+    assert!(file.extended_attribute_block().is_some());
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+
+    let file = fs.open_file("file.txt").unwrap();
+    assert_eq!(vec!["user.a", "security.selinux", "user.b"], fs.list_xattr(&file).unwrap());
+    assert_eq!(Some(b"1".to_vec()), fs.get_xattr(&file, "user.a").unwrap());
+    assert_eq!(Some(vec![b'b'; 64]), fs.get_xattr(&file, "user.b").unwrap());
+
+    // removing an attribute from the inode leaves the block alone
+    let mut file = fs.open_file("file.txt").unwrap();
+    fs.remove_xattr(file.inode_address(), file.inode_mut(), "user.a").unwrap();
+    assert_eq!(None, fs.get_xattr(&file, "user.a").unwrap());
+    assert!(file.extended_attribute_block().is_some());
+
+    // a value that moves into the inode is removed from the block
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "user.b", b"short").unwrap();
+    assert_eq!(None, file.extended_attribute_block());
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+    assert_eq!(Some(b"short".to_vec()), fs.get_xattr(&file, "user.b").unwrap());
+}
+
+#[test]
+fn test_open_invalid_inode_size() {
+    const INODE_SIZE_OFFSET: usize = 1024 + 88;
+
+    for inode_size in [0_u16, 64, 200, 2048] {
+        let mut image_data = common::load_copy_of_image("tests/filesystems/empty.img");
+        image_data[INODE_SIZE_OFFSET..INODE_SIZE_OFFSET + 2].copy_from_slice(&inode_size.to_le_bytes());
+        let device = MemoryBlockDevice::try_new(512, image_data).unwrap();
+        assert_eq!(Error::InvalidSuperblock, Ext2Fs::try_new(device).err().unwrap(), "inode size {inode_size}");
+    }
+}
@@ -0,0 +1,80 @@
+use ext2::{Ext2Fs, State};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+generate_tests!(
+    test_mount_and_unmount:
+    512 - test_mount_and_unmount_standard,
+    1 - test_mount_and_unmount_tiny,
+    32 - test_mount_and_unmount_small,
+    32768 - test_mount_and_unmount_large,
+    1048576 - test_mount_and_unmount_huge,
+);
+
+fn test_mount_and_unmount(sector_size: usize) {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", sector_size);
+    fs.set_clock(|| 1700000000);
+    assert_eq!(State::CLEAN, fs.superblock().state());
+    let mounts = fs.superblock().mounts_since_fsck();
+
+    fs.mount_read_write().unwrap();
+    assert!(fs.is_mounted_read_write());
+    assert_eq!(1700000000, fs.superblock().last_mount_time());
+
+    // while mounted, the file system must be marked as not clean on disk
+    let data = fs.block_device().data().clone();
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, data).unwrap()).unwrap();
+    assert!(!fs.superblock().state().contains(State::CLEAN));
+    assert_eq!(mounts + 1, fs.superblock().mounts_since_fsck());
+}
+
+generate_tests!(
+    test_unmount_marks_clean:
+    512 - test_unmount_marks_clean_standard,
+    1 - test_unmount_marks_clean_tiny,
+    32 - test_unmount_marks_clean_small,
+    32768 - test_unmount_marks_clean_large,
+    1048576 - test_unmount_marks_clean_huge,
+);
+
+fn test_unmount_marks_clean(sector_size: usize) {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", sector_size);
+    fs.set_clock(|| 1700000000);
+    fs.mount_read_write().unwrap();
+
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_regular_file(&mut root, "file.txt").unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+
+    let device = fs.unmount().unwrap();
+    let fs = Ext2Fs::try_new(device).unwrap();
+    assert_eq!(State::CLEAN, fs.superblock().state());
+    assert_eq!(1700000000, fs.superblock().last_mount_time());
+    assert_eq!(1700000000, fs.superblock().last_written_time());
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+}
+
+#[test]
+fn test_write_without_mount_marks_dirty() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    assert_eq!(State::CLEAN, fs.superblock().state());
+    let mounts = fs.superblock().mounts_since_fsck();
+
+    // reading doesn't change anything
+    fs.read_root_inode().unwrap();
+    assert!(!fs.is_mounted_read_write());
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.txt").unwrap();
+    fs.write_to_file(&mut file, 0, b"Hello").unwrap();
+    assert!(fs.is_mounted_read_write());
+
+    // the file system was not unmounted, so it is not clean on disk
+    let data = fs.block_device().data().clone();
+    drop(fs);
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, data).unwrap()).unwrap();
+    assert!(!fs.superblock().state().contains(State::CLEAN));
+    assert_eq!(mounts + 1, fs.superblock().mounts_since_fsck());
+    assert_eq!(5, fs.open_file("/file.txt").unwrap().len());
+}
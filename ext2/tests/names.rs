@@ -0,0 +1,56 @@
+use ext2::{DirType, Error, Ext2Fs, Type, MAX_NAME_LEN};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+#[test]
+fn test_non_utf8_names() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+
+    // latin-1 and invalid bytes are fine in names, as long as they are not `/` or NUL
+    let mut root = fs.read_root_inode().unwrap();
+    let mut dir = fs.create_dir(&mut root, b"\xff\xfe").unwrap();
+    let mut file = fs.create_regular_file(&mut dir, b"caf\xe9.txt").unwrap();
+    fs.write_to_file(&mut file, 0, b"coffee").unwrap();
+    fs.create_symlink(&mut dir, b"link\x80", b"caf\xe9.txt").unwrap();
+
+    let data = fs.block_device().data().clone();
+    let mut fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, data).unwrap()).unwrap();
+    let dir = fs.open_dir(b"/\xff\xfe").unwrap();
+    let entries = fs.list_dir(&dir).unwrap();
+    let file_entry = entries.iter().find(|e| e.name_bytes() == b"caf\xe9.txt").unwrap();
+    assert_eq!(None, file_entry.name());
+    assert_eq!("caf\u{FFFD}.txt", file_entry.name_lossy());
+
+    let file = fs.open_file(b"/\xff\xfe/link\x80").unwrap();
+    let mut buf = [0_u8; 6];
+    fs.read_from_file(&file, 0, &mut buf).unwrap();
+    assert_eq!(b"coffee", &buf);
+    assert_eq!(Type::SymLink, fs.symlink_metadata(b"\xff\xfe/link\x80").unwrap().typ());
+
+    let mut dir = fs.open_dir(b"/\xff\xfe").unwrap();
+    fs.remove_entry_from_dir(&mut dir, b"link\x80").unwrap();
+    assert_eq!(Err(Error::NotFound), fs.resolve_path(b"/\xff\xfe/link\x80").map(|_| ()));
+}
+
+#[test]
+fn test_invalid_names() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let root_address = root.inode_address();
+    let free_inodes = fs.superblock().num_unallocated_inodes();
+
+    let too_long = vec![b'a'; MAX_NAME_LEN + 1];
+    let invalid: [&[u8]; 6] = [b"", b"a/b", b"a\0b", b".", b"..", &too_long];
+    for name in invalid {
+        assert_eq!(Err(Error::InvalidName), fs.create_regular_file(&mut root, name).map(|_| ()), "{:?}", name);
+        assert_eq!(Err(Error::InvalidName), fs.add_entry_to_dir(&mut root, name, root_address, DirType::Directory));
+    }
+    // no inode was allocated for the invalid names
+    assert_eq!(free_inodes, fs.superblock().num_unallocated_inodes());
+
+    let longest = vec![b'a'; MAX_NAME_LEN];
+    fs.create_regular_file(&mut root, &longest).unwrap();
+    assert_eq!(Type::RegularFile, fs.metadata(&longest).unwrap().typ());
+    assert!(fs.list_dir(&root).unwrap().iter().any(|e| e.name_bytes() == longest.as_slice()));
+}
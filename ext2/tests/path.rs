@@ -0,0 +1,99 @@
+use ext2::{Error, Ext2Fs, Type};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+#[test]
+fn test_open_paths() {
+    let fs = cow_fs!("tests/filesystems/read.img", 512);
+
+    let hello_txt = fs.open_file("/hello.txt").unwrap();
+    assert_eq!(14, hello_txt.len());
+    assert_eq!(hello_txt.inode_address(), fs.open_file("hello.txt").unwrap().inode_address());
+    assert_eq!(hello_txt.inode_address(), fs.open_file("/some/../hello.txt").unwrap().inode_address());
+    assert_eq!(hello_txt.inode_address(), fs.open_file("/./some/./.././hello.txt").unwrap().inode_address());
+
+    let some = fs.open_dir("/some").unwrap();
+    assert_eq!(13, some.inode_address().get());
+    assert_eq!(2, fs.open_dir("/").unwrap().inode_address().get());
+    assert_eq!(2, fs.open_dir("/..").unwrap().inode_address().get());
+    assert_eq!(Type::Directory, fs.metadata("/lost+found").unwrap().typ());
+}
+
+#[test]
+fn test_open_paths_errors() {
+    let fs = cow_fs!("tests/filesystems/read.img", 512);
+
+    assert_eq!(Error::NotFound, fs.metadata("/does_not_exist").unwrap_err());
+    assert_eq!(Error::NotFound, fs.metadata("/some/does_not_exist").unwrap_err());
+    assert_eq!(Error::NotDirectory, fs.metadata("/hello.txt/foo").unwrap_err());
+    assert_eq!(Error::NotRegularFile, fs.open_file("/some").unwrap_err());
+    assert_eq!(Error::NotDirectory, fs.open_dir("/hello.txt").unwrap_err());
+}
+
+generate_tests!(
+    test_follow_symlinks:
+    512 - test_follow_symlinks_standard,
+    1 - test_follow_symlinks_tiny,
+    32 - test_follow_symlinks_small,
+    32768 - test_follow_symlinks_large,
+    1048576 - test_follow_symlinks_huge,
+);
+
+fn test_follow_symlinks(sector_size: usize) {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", sector_size);
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.txt").unwrap();
+    fs.write_to_file(&mut file, 0, b"Hello, World!").unwrap();
+
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_symlink(&mut root, "relative", b"file.txt").unwrap();
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_symlink(&mut root, "absolute", b"/lost+found/../file.txt").unwrap();
+    let long_target = format!("/{}/../file.txt", "x".repeat(100));
+    let mut root = fs.read_root_inode().unwrap();
+    let long = fs.create_symlink(&mut root, "long", long_target.as_bytes()).unwrap();
+    assert_eq!(long_target.as_bytes(), fs.read_link(&long).unwrap());
+
+    for path in ["/relative", "/absolute"] {
+        let resolved = fs.open_file(path).unwrap();
+        assert_eq!(file.inode_address(), resolved.inode_address());
+        assert_eq!(Type::SymLink, fs.symlink_metadata(path).unwrap().typ());
+    }
+    // the directory in the long target doesn't exist
+    assert_eq!(Error::NotFound, fs.metadata("/long").unwrap_err());
+}
+
+#[test]
+fn test_symlink_loop() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_symlink(&mut root, "a", b"b").unwrap();
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_symlink(&mut root, "b", b"/a").unwrap();
+
+    assert_eq!(Error::TooManySymLinks, fs.metadata("/a").unwrap_err());
+    assert_eq!(Type::SymLink, fs.symlink_metadata("/a").unwrap().typ());
+}
+
+#[test]
+fn test_trailing_slash() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_regular_file(&mut root, "file.txt").unwrap();
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_symlink(&mut root, "file_link", b"file.txt").unwrap();
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_symlink(&mut root, "dir_link", b"lost+found").unwrap();
+
+    // a trailing slash only resolves to directories, and follows a symlink in the last component
+    let lost_found = fs.open_dir("/lost+found").unwrap().inode_address();
+    assert_eq!(lost_found, fs.open_dir("/lost+found/").unwrap().inode_address());
+    assert_eq!(lost_found, fs.resolve_path_no_follow("/dir_link/").unwrap().0);
+    assert_eq!(Error::NotDirectory, fs.metadata("/file.txt/").unwrap_err());
+    assert_eq!(Error::NotDirectory, fs.symlink_metadata("/file_link/").unwrap_err());
+    assert_eq!(Type::SymLink, fs.symlink_metadata("/file_link").unwrap().typ());
+}
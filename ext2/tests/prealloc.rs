@@ -0,0 +1,229 @@
+use ext2::{Error, Ext2Fs, Finding};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+const PREALLOCATE_BLOCKS_FILE_OFFSET: usize = 1024 + 204;
+const PREALLOCATE_BLOCKS_DIRECTORY_OFFSET: usize = 1024 + 205;
+const OPTIONAL_FEATURES_OFFSET: usize = 1024 + 92;
+
+generate_tests!(
+    test_fallocate:
+    512 - test_fallocate_standard,
+    1 - test_fallocate_tiny,
+    32 - test_fallocate_small,
+    32768 - test_fallocate_large,
+    1048576 - test_fallocate_huge,
+);
+
+fn test_fallocate(sector_size: usize) {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", sector_size);
+    let block_size = fs.superblock().block_size() as u64;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.bin").unwrap();
+    fs.write_to_file(&mut file, 0, b"Hello").unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+
+    // the first block is already allocated, and its data must not change
+    fs.fallocate(&mut file, 2, 4 * block_size, false).unwrap();
+    assert_eq!(2 + 4 * block_size, file.len());
+    assert_eq!(free_blocks - 4, fs.superblock().num_unallocated_blocks());
+    assert_eq!(5 * (block_size as u32 / 512), file.num_disk_sectors());
+
+    let mut buf = vec![0xFF_u8; file.len() as usize];
+    assert_eq!(buf.len(), fs.read_from_file(&file, 0, &mut buf).unwrap());
+    assert_eq!(b"Hello", &buf[..5]);
+    assert!(buf[5..].iter().all(|&b| b == 0));
+    assert_eq!(Some(file.len()), fs.seek_hole(&file, 0).unwrap());
+
+    // with keep size, the size doesn't change, but the blocks are allocated nevertheless
+    let len = file.len();
+    fs.fallocate(&mut file, len, 2 * block_size, true).unwrap();
+    assert_eq!(len, file.len());
+    assert_eq!(free_blocks - 6, fs.superblock().num_unallocated_blocks());
+
+    // the changes must be persisted
+    let data = fs.block_device().data().clone();
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, data).unwrap()).unwrap();
+    let file = fs.open_file("file.bin").unwrap();
+    assert_eq!(len, file.len());
+    assert_eq!(7 * (block_size as u32 / 512), file.num_disk_sectors());
+}
+
+#[test]
+fn test_fallocate_no_space() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let block_size = fs.superblock().block_size() as u64;
+    let num_blocks = fs.superblock().num_blocks() as u64;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.bin").unwrap();
+    assert_eq!(Err(Error::NoSpace), fs.fallocate(&mut file, 0, num_blocks * block_size, false));
+    assert_eq!(0, fs.superblock().num_unallocated_blocks());
+
+    // the blocks that were allocated before running out of space belong to the file
+    assert!(!file.is_empty() && file.len() < num_blocks * block_size);
+    assert_eq!(Some(file.len()), fs.seek_hole(&file, 0).unwrap());
+    fs.sync().unwrap();
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+}
+
+generate_tests!(
+    test_punch_hole:
+    512 - test_punch_hole_standard,
+    1 - test_punch_hole_tiny,
+    32 - test_punch_hole_small,
+    32768 - test_punch_hole_large,
+    1048576 - test_punch_hole_huge,
+);
+
+fn test_punch_hole(sector_size: usize) {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", sector_size);
+    let block_size = fs.superblock().block_size() as usize;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    let mut file = fs.create_regular_file(&mut root, "file.bin").unwrap();
+
+    // 20 blocks need the single indirect block
+    let data = vec![0xAB_u8; 20 * block_size];
+    fs.write_to_file(&mut file, 0, &data).unwrap();
+    assert_eq!(free_blocks - 21, fs.superblock().num_unallocated_blocks());
+
+    // punch out everything but the first 100 bytes, which frees the indirect block as well
+    fs.punch_hole(&mut file, 100, data.len() as u64).unwrap();
+    assert_eq!(data.len() as u64, file.len());
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+    assert_eq!(block_size as u32 / 512, file.num_disk_sectors());
+    assert_eq!(None, file.single_indirect_ptr());
+
+    let mut buf = vec![0xFF_u8; data.len()];
+    assert_eq!(data.len(), fs.read_from_file(&file, 0, &mut buf).unwrap());
+    assert!(buf[..100].iter().all(|&b| b == 0xAB));
+    assert!(buf[100..].iter().all(|&b| b == 0));
+    assert_eq!(Some(block_size as u64), fs.seek_hole(&file, 0).unwrap());
+}
+
+generate_tests!(
+    test_punch_hole_within_block:
+    512 - test_punch_hole_within_block_standard,
+    1 - test_punch_hole_within_block_tiny,
+    32 - test_punch_hole_within_block_small,
+    32768 - test_punch_hole_within_block_large,
+    1048576 - test_punch_hole_within_block_huge,
+);
+
+fn test_punch_hole_within_block(sector_size: usize) {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", sector_size);
+    let block_size = fs.superblock().block_size() as usize;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.bin").unwrap();
+    fs.write_to_file(&mut file, 0, &vec![0xAB_u8; block_size]).unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+
+    fs.punch_hole(&mut file, 10, 20).unwrap();
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+
+    let mut buf = vec![0_u8; block_size];
+    fs.read_from_file(&file, 0, &mut buf).unwrap();
+    assert!(buf[..10].iter().all(|&b| b == 0xAB));
+    assert!(buf[10..30].iter().all(|&b| b == 0));
+    assert!(buf[30..].iter().all(|&b| b == 0xAB));
+}
+
+generate_tests!(
+    test_preallocate_for_file:
+    512 - test_preallocate_for_file_standard,
+    1 - test_preallocate_for_file_tiny,
+    32 - test_preallocate_for_file_small,
+    32768 - test_preallocate_for_file_large,
+    1048576 - test_preallocate_for_file_huge,
+);
+
+fn test_preallocate_for_file(sector_size: usize) {
+    let mut image_data = common::load_copy_of_image("tests/filesystems/empty.img");
+    image_data[PREALLOCATE_BLOCKS_FILE_OFFSET] = 8;
+    let mut fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, image_data).unwrap()).unwrap();
+    let block_size = fs.superblock().block_size() as usize;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.bin").unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+
+    // the first block reserves 7 more blocks for the file, and the second block uses one of them
+    fs.write_to_file(&mut file, 0, &vec![0xAB_u8; block_size]).unwrap();
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+    let mut root = fs.read_root_inode().unwrap();
+    let mut other = fs.create_regular_file(&mut root, "other.bin").unwrap();
+    fs.write_to_file(&mut other, 0, &vec![0xCD_u8; block_size]).unwrap();
+    fs.write_to_file(&mut file, block_size as u64, &vec![0xAB_u8; block_size]).unwrap();
+    assert_eq!(free_blocks - 3, fs.superblock().num_unallocated_blocks());
+    let first = file.direct_ptrs().next().flatten().unwrap();
+    let second = file.direct_ptrs().nth(1).flatten().unwrap();
+    assert_eq!(first.get() + 1, second.get());
+    // other files don't get the reserved blocks
+    let other_first = other.direct_ptrs().next().flatten().unwrap();
+    assert_eq!(first.get() + 8, other_first.get());
+
+    // reservations are dropped when syncing
+    fs.sync().unwrap();
+    assert_eq!(free_blocks - 3, fs.superblock().num_unallocated_blocks());
+    assert_eq!(Vec::<ext2::Finding>::new(), fs.check().unwrap());
+}
+
+#[test]
+fn test_preallocate_without_sync() {
+    let mut image_data = common::load_copy_of_image("tests/filesystems/empty.img");
+    image_data[PREALLOCATE_BLOCKS_FILE_OFFSET] = 8;
+    let mut fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, image_data).unwrap()).unwrap();
+    let block_size = fs.superblock().block_size() as usize;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.bin").unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    fs.write_to_file(&mut file, 0, &vec![0xAB_u8; 3 * block_size]).unwrap();
+
+    // the reserved blocks are only reserved in memory, so dropping the file system doesn't leak them
+    let data = fs.block_device().data().clone();
+    drop(fs);
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, data).unwrap()).unwrap();
+    assert_eq!(free_blocks - 3, fs.superblock().num_unallocated_blocks());
+    assert_eq!(Vec::<ext2::Finding>::new(), fs.check().unwrap());
+}
+
+generate_tests!(
+    test_preallocate_for_directory:
+    512 - test_preallocate_for_directory_standard,
+    1 - test_preallocate_for_directory_tiny,
+    32 - test_preallocate_for_directory_small,
+    32768 - test_preallocate_for_directory_large,
+    1048576 - test_preallocate_for_directory_huge,
+);
+
+fn test_preallocate_for_directory(sector_size: usize) {
+    let mut image_data = common::load_copy_of_image("tests/filesystems/empty.img");
+    image_data[PREALLOCATE_BLOCKS_DIRECTORY_OFFSET] = 3;
+    image_data[OPTIONAL_FEATURES_OFFSET] |= 0x01; // PREALLOCATE_FOR_DIRECTORY
+    image_data[OPTIONAL_FEATURES_OFFSET] &= !0x20; // DIRECTORIES_USE_HASH_INDEX, otherwise the root would get an index
+    let mut fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, image_data).unwrap()).unwrap();
+    let block_size = fs.superblock().block_size() as usize;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let initial_len = root.len();
+    // every entry with a 100 byte name takes 108 bytes, so the root directory has to grow
+    let count = block_size / 108 + 1;
+    for i in 0..count {
+        let name = format!("{:0100}", i);
+        fs.create_regular_file(&mut root, &name).unwrap();
+    }
+    assert_eq!(initial_len + 3 * block_size as u64, root.len());
+
+    let names = fs.list_dir(&root).unwrap()
+        .into_iter()
+        .filter_map(|e| e.name().map(str::to_string))
+        .filter(|name| name.len() == 100)
+        .count();
+    assert_eq!(count, names);
+}
@@ -2,7 +2,7 @@ use std::env;
 use std::fs::File;
 use std::io::Read;
 
-use ext2::{DirType, Ext2Fs, RegularFile, Type};
+use ext2::{DirPosition, DirType, Ext2Fs, RegularFile, Type};
 use filesystem::MemoryBlockDevice;
 
 #[test]
@@ -32,7 +32,7 @@ fn test_list_directory_huge_device_block_size() {
 
 fn do_test_list_directory(sector_size: usize) {
     let mut image = env::current_dir().unwrap();
-    image.push(&"tests/filesystems/read.img");
+    image.push("tests/filesystems/read.img");
 
     let mut data = Vec::new();
 
@@ -45,7 +45,7 @@ fn do_test_list_directory(sector_size: usize) {
     let device = MemoryBlockDevice::try_new(sector_size, data).unwrap();
 
     let fs = Ext2Fs::try_new(device).unwrap();
-    let root = fs.read_root_inode().unwrap().try_into().unwrap();
+    let root = fs.read_root_inode().unwrap().into();
     let entries = fs.list_dir(&root).unwrap();
 
     let expected_entries = [
@@ -95,7 +95,7 @@ fn test_read_file_huge_device_block_size() {
 
 fn do_test_read_file(sector_size: usize) {
     let mut image = env::current_dir().unwrap();
-    image.push(&"tests/filesystems/read.img");
+    image.push("tests/filesystems/read.img");
 
     let mut data = Vec::new();
 
@@ -106,7 +106,7 @@ fn do_test_read_file(sector_size: usize) {
     let device = MemoryBlockDevice::try_new(sector_size, data).unwrap();
 
     let fs = Ext2Fs::try_new(device).unwrap();
-    let root = fs.read_root_inode().unwrap().try_into().unwrap();
+    let root = fs.read_root_inode().unwrap();
 
     let hello_txt: RegularFile = fs
         .find_and_resolve_entry(&root, |e| e.name().is_some_and(|n| n == "hello.txt"))
@@ -137,3 +137,72 @@ fn do_test_read_file(sector_size: usize) {
         assert_eq!(b"World", &hello_txt_data[..]);
     }
 }
+
+#[test]
+fn test_read_dir_resume() {
+    let mut image = env::current_dir().unwrap();
+    image.push("tests/filesystems/read.img");
+    let data = std::fs::read(&image).unwrap();
+    let device = MemoryBlockDevice::try_new(512, data).unwrap();
+
+    let fs = Ext2Fs::try_new(device).unwrap();
+    let root = fs.read_root_inode().unwrap();
+    let all_names = fs.read_dir(&root).unwrap()
+        .map(|e| e.unwrap().name().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(vec![".", "..", "lost+found", "hello.txt", "some"], all_names);
+
+    // read one entry at a time, and continue from the returned position every time
+    let mut names = Vec::new();
+    let mut position = DirPosition::START;
+    loop {
+        let mut read_dir = fs.read_dir_at(&root, position).unwrap();
+        match read_dir.next() {
+            Some(entry) => names.push(entry.unwrap().name().unwrap().to_string()),
+            None => break,
+        }
+        position = DirPosition::from_raw(read_dir.position().into_raw());
+    }
+    assert_eq!(all_names, names);
+}
+
+#[test]
+fn test_read_dir_skips_unused_entries() {
+    let mut image = env::current_dir().unwrap();
+    image.push("tests/filesystems/read.img");
+    let data = std::fs::read(&image).unwrap();
+    let device = MemoryBlockDevice::try_new(512, data).unwrap();
+
+    // fill the root directory until it has a second block
+    let mut fs = Ext2Fs::try_new(device).unwrap();
+    let block_size = fs.superblock().block_size() as u64;
+    let mut root = fs.read_root_inode().unwrap();
+    for i in 0..40 {
+        fs.create_regular_file(&mut root, format!("a-file-with-a-rather-long-name-{i:02}")).unwrap();
+    }
+    assert!(root.len() > block_size);
+
+    // the first entry of a block can't be merged into the previous entry, so removing it leaves an unused entry
+    let mut read_dir = fs.read_dir(&root).unwrap();
+    while read_dir.position().into_raw() < block_size {
+        read_dir.next().unwrap().unwrap();
+    }
+    assert_eq!(block_size, read_dir.position().into_raw());
+    let first_in_block = read_dir.next().unwrap().unwrap().name().unwrap().to_string();
+    let mut expected_names = fs.read_dir(&root).unwrap()
+        .map(|e| e.unwrap().name().unwrap().to_string())
+        .collect::<Vec<_>>();
+    let removed_index = expected_names.iter().position(|name| *name == first_in_block).unwrap();
+    expected_names.remove(removed_index);
+    fs.remove_entry_from_dir(&mut root, &first_in_block).unwrap();
+
+    let names = fs.read_dir(&root).unwrap()
+        .map(|e| e.unwrap().name().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(expected_names, names);
+
+    // resuming at the unused entry continues with the next entry in use
+    let mut read_dir = fs.read_dir_at(&root, DirPosition::from_raw(block_size)).unwrap();
+    let next = read_dir.next().unwrap().unwrap();
+    assert_eq!(Some(expected_names[removed_index].as_str()), next.name());
+}
@@ -0,0 +1,86 @@
+use ext2::{BlockAddress, DirType, Error, Ext2Fs, Finding};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+#[test]
+fn test_unlink() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    let free_inodes = fs.superblock().num_unallocated_inodes();
+
+    // large enough for double indirect blocks, with an attribute block
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "large").unwrap();
+    let data = (0..400 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    fs.write_to_file(&mut file, 0, &data).unwrap();
+    assert!(file.double_indirect_ptr().is_some());
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "user.comment", &[1; 200]).unwrap();
+    fs.add_entry_to_dir(&mut root, "link", file.inode_address(), DirType::RegularFile).unwrap();
+    *file.inode_mut().num_hard_links_mut() += 1;
+    fs.write_inode(file.inode_address(), file.inode()).unwrap();
+
+    // the other link keeps the inode alive
+    fs.unlink(&mut root, "large").unwrap();
+    assert_eq!(Err(Error::NotFound), fs.open_file("/large").map(|_| ()));
+    let link = fs.open_file("/link").unwrap();
+    assert_eq!(1, link.num_hard_links());
+    let mut buf = vec![0_u8; data.len()];
+    fs.read_from_file(&link, 0, &mut buf).unwrap();
+    assert_eq!(data, buf);
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+
+    // removing the last link frees everything
+    fs.unlink(&mut root, "link").unwrap();
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+    assert_eq!(free_inodes, fs.superblock().num_unallocated_inodes());
+    let (_, inode) = fs.read_inode(link.inode_address()).unwrap();
+    assert_eq!(0, inode.num_hard_links());
+    assert_ne!(0, inode.deletion_time());
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+}
+
+#[test]
+fn test_unlink_symlinks() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    let mut root = fs.read_root_inode().unwrap();
+    fs.create_symlink(&mut root, "fast", b"hello.txt").unwrap();
+    fs.create_symlink(&mut root, "slow", &[b'a'; 100]).unwrap();
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+
+    fs.unlink(&mut root, "fast").unwrap();
+    fs.unlink(&mut root, "slow").unwrap();
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+    // the target of the link is not affected
+    assert_eq!(14, fs.open_file("/hello.txt").unwrap().len());
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+}
+
+#[test]
+fn test_unlink_invalid() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    assert_eq!(Err(Error::NotFound), fs.unlink(&mut root, "missing"));
+    assert_eq!(Err(Error::NotSupported), fs.unlink(&mut root, "some"));
+    assert_eq!(Err(Error::NotSupported), fs.unlink(&mut root, "."));
+    assert!(fs.open_dir("/some").is_ok());
+}
+
+#[test]
+fn test_reserve_block() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    let block = BlockAddress::new(100).unwrap();
+
+    fs.reserve_block(block).unwrap();
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+    assert_eq!(free_blocks - 1, fs.block_group_descriptors()[0].num_unallocated_blocks() as u32);
+    assert!(!fs.free_block_ranges(0).unwrap().iter().any(|range| range.contains(&100)));
+    assert_eq!(Err(Error::InvalidBlockAddress(100)), fs.reserve_block(block));
+    assert_eq!(Err(Error::InvalidBlockAddress(5000)), fs.reserve_block(BlockAddress::new(5000).unwrap()));
+
+    fs.free_block(block).unwrap();
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+}
@@ -0,0 +1,218 @@
+use ext2::{BlockAddress, DirType, Ext2Fs, Finding, InodeAddress, Repair};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+#[test]
+fn test_repair_clean() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let data = fs.block_device().data().clone();
+    assert_eq!(Vec::<Repair>::new(), fs.repair().unwrap());
+    assert!(data == *fs.block_device().data());
+}
+
+#[test]
+fn test_repair_invalid_inode_table() {
+    let mut image = common::load_copy_of_image("tests/filesystems/read.img");
+    image[2048 + 8..2048 + 12].copy_from_slice(&5000_u32.to_le_bytes());
+    let mut fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, image).unwrap()).unwrap();
+    let data = fs.block_device().data().clone();
+
+    // the inodes of the group are unknown, so nothing is repaired
+    assert_eq!(Vec::<Repair>::new(), fs.repair().unwrap());
+    assert!(data == *fs.block_device().data());
+}
+
+#[test]
+fn test_repair_link_count() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut file = fs.open_file("/hello.txt").unwrap();
+    *file.inode_mut().num_hard_links_mut() = 3;
+    fs.write_inode(file.inode_address(), file.inode()).unwrap();
+
+    assert_eq!(vec![Repair::SetLinkCount { inode: file.inode_address(), links: 1 }], fs.repair().unwrap());
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    assert_eq!(1, fs.open_file("/hello.txt").unwrap().num_hard_links());
+}
+
+#[test]
+fn test_repair_unattached_inode() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let entry = fs.remove_entry_from_dir(&mut root, "hello.txt").unwrap();
+
+    assert_eq!(vec![Repair::Reconnect { inode: entry.inode(), name: b"#12".to_vec() }], fs.repair().unwrap());
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    let file = fs.open_file("/lost+found/#12").unwrap();
+    let mut buf = [0_u8; 14];
+    fs.read_from_file(&file, 0, &mut buf).unwrap();
+    assert_eq!(b"Hello, World!\n", &buf);
+}
+
+#[test]
+fn test_repair_unattached_empty_file() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let empty = fs.create_regular_file(&mut root, "empty").unwrap();
+    fs.remove_entry_from_dir(&mut root, "empty").unwrap();
+    let free_inodes = fs.superblock().num_unallocated_inodes();
+
+    // empty files are not worth keeping, so they are cleared instead of reconnected
+    let inode = empty.inode_address();
+    let repairs = fs.repair().unwrap();
+    assert_eq!(Repair::ClearInode { inode }, repairs[0]);
+    assert!(repairs.contains(&Repair::MarkInodes { inodes: inode.get()..inode.get() + 1, used: false }));
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    assert_eq!(free_inodes + 1, fs.superblock().num_unallocated_inodes());
+}
+
+#[test]
+fn test_repair_invalid_block_pointer() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut file = fs.open_file("/hello.txt").unwrap();
+    file.inode_mut().set_direct_ptr(0, BlockAddress::new(100_000));
+    fs.write_inode(file.inode_address(), file.inode()).unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+
+    // the inode is cleared, and then the entry that points to it is removed
+    let inode = file.inode_address();
+    let repairs = fs.repair().unwrap();
+    assert_eq!(Repair::ClearInode { inode }, repairs[0]);
+    assert_eq!(Repair::RemoveEntry { dir: InodeAddress::new(2).unwrap(), name: b"hello.txt".to_vec() }, repairs[1]);
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    assert_eq!(free_blocks + 1, fs.superblock().num_unallocated_blocks());
+    assert!(fs.open_file("/hello.txt").is_err());
+}
+
+#[test]
+fn test_repair_duplicate_block() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let hello = fs.open_file("/hello.txt").unwrap();
+    let block = hello.direct_ptrs().next().unwrap().unwrap();
+    let mut root = fs.read_root_inode().unwrap();
+    let mut copy = fs.create_regular_file(&mut root, "copy.txt").unwrap();
+    copy.inode_mut().set_direct_ptr(0, Some(block));
+    copy.inode_mut().set_len(14);
+    *copy.inode_mut().num_disk_sectors_mut() = 2;
+    fs.write_inode(copy.inode_address(), copy.inode()).unwrap();
+
+    // the first owner keeps the block
+    let inode = copy.inode_address();
+    assert_eq!(
+        vec![Repair::RemoveBlockPointer { inode, block }, Repair::SetSectorCount { inode, sectors: 0 }],
+        fs.repair().unwrap()
+    );
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    assert_eq!(Some(block), fs.open_file("/hello.txt").unwrap().direct_ptrs().next().unwrap());
+}
+
+#[test]
+fn test_repair_bitmaps() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let hello = fs.open_file("/hello.txt").unwrap();
+    let block = hello.direct_ptrs().next().unwrap().unwrap();
+    fs.free_block(block).unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+
+    assert_eq!(
+        vec![
+            Repair::MarkBlocks { blocks: block.get()..block.get() + 1, used: true },
+            Repair::SetGroupFreeBlocks { group: 0, count: free_blocks - 1 },
+            Repair::SetFreeBlocks { count: free_blocks - 1 },
+        ],
+        fs.repair().unwrap()
+    );
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    // the block is not allocated again
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "new.txt").unwrap();
+    fs.write_to_file(&mut file, 0, b"new").unwrap();
+    assert_ne!(Some(block), file.direct_ptrs().next().unwrap());
+}
+
+#[test]
+fn test_repair_unconnected_directory() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let mut a = fs.create_dir(&mut root, "a").unwrap();
+    let b = fs.create_dir(&mut a, "b").unwrap();
+    fs.remove_entry_from_dir(&mut a, "b").unwrap();
+
+    let lost_and_found = fs.open_dir("/lost+found").unwrap();
+    assert_eq!(
+        vec![
+            Repair::Reconnect { inode: b.inode_address(), name: format!("#{}", b.inode_address()).into_bytes() },
+            Repair::SetLinkCount { inode: a.inode_address(), links: 2 },
+        ],
+        fs.repair().unwrap()
+    );
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    let b = fs.open_dir(format!("/lost+found/#{}", b.inode_address())).unwrap();
+    let parent = fs.find_entry(&b, |e| e.name() == Some("..")).unwrap().unwrap();
+    assert_eq!(lost_and_found.inode_address(), parent.inode());
+    assert_eq!(3, fs.open_dir("/lost+found").unwrap().num_hard_links());
+}
+
+#[test]
+fn test_repair_creates_lost_and_found() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let old = fs.remove_entry_from_dir(&mut root, "lost+found").unwrap().inode();
+    fs.remove_entry_from_dir(&mut root, "hello.txt").unwrap();
+
+    // the old lost+found is not connected anymore, so it ends up in the new one
+    let repairs = fs.repair().unwrap();
+    let lost_and_found = fs.open_dir("/lost+found").unwrap();
+    assert_ne!(old, lost_and_found.inode_address());
+    assert!(repairs.contains(&Repair::CreateLostAndFound { inode: lost_and_found.inode_address() }));
+    assert!(repairs.contains(&Repair::Reconnect { inode: old, name: format!("#{}", old).into_bytes() }));
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    fs.open_file("/lost+found/#12").unwrap();
+    fs.open_dir(format!("/lost+found/#{}", old)).unwrap();
+}
+
+#[test]
+fn test_repair_directory_entries() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let hello = fs.open_file("/hello.txt").unwrap().inode_address();
+    let some = fs.open_dir("/some").unwrap().inode_address();
+    fs.add_entry_to_dir(&mut root, "wrong_type", hello, DirType::Directory).unwrap();
+    fs.add_entry_to_dir(&mut root, "dir_link", some, DirType::Directory).unwrap();
+    fs.add_entry_to_dir(&mut root, "unused", InodeAddress::new(100).unwrap(), DirType::RegularFile).unwrap();
+
+    let root = root.inode_address();
+    assert_eq!(
+        vec![
+            Repair::SetEntryType { dir: root, name: b"wrong_type".to_vec(), typ: DirType::RegularFile },
+            Repair::RemoveEntry { dir: root, name: b"dir_link".to_vec() },
+            Repair::RemoveEntry { dir: root, name: b"unused".to_vec() },
+            Repair::SetLinkCount { inode: hello, links: 2 },
+        ],
+        fs.repair().unwrap()
+    );
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    let root = fs.read_root_inode().unwrap();
+    let names = fs.list_dir(&root).unwrap().iter().map(|e| e.name().unwrap().to_string()).collect::<Vec<_>>();
+    assert_eq!(vec![".", "..", "lost+found", "hello.txt", "some", "wrong_type"], names);
+}
+
+#[test]
+fn test_repair_corrupted_directory() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let some = fs.open_dir("/some").unwrap();
+    let file = fs.open_file("/some/file.txt").unwrap().inode_address();
+    let block = some.direct_ptrs().next().unwrap().unwrap();
+    let mut data = vec![0_u8; fs.superblock().block_size() as usize];
+    data[0] = some.inode_address().get() as u8;
+    data[4] = 3; // entries can't be shorter than 8 bytes
+    fs.write_block(block, &data).unwrap();
+
+    // the block gets new `.` and `..` entries, and the file in it goes to lost+found
+    let repairs = fs.repair().unwrap();
+    assert_eq!(Repair::ClearDirectoryBlock { dir: some.inode_address(), block_index: 0 }, repairs[0]);
+    assert!(repairs.contains(&Repair::Reconnect { inode: file, name: format!("#{}", file).into_bytes() }));
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    let names = fs.list_dir(&fs.open_dir("/some").unwrap()).unwrap().iter().map(|e| e.name().unwrap().to_string()).collect::<Vec<_>>();
+    assert_eq!(vec![".", ".."], names);
+}
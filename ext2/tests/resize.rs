@@ -0,0 +1,158 @@
+use ext2::{Error, Ext2Fs, Finding};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+const GROWN_SIZE: usize = 20 * 1024 * 1024;
+
+/// Loads the given image into a device that has space for the grown file system.
+fn grown_device(path: &str, sector_size: usize) -> Ext2Fs<MemoryBlockDevice<Vec<u8>>> {
+    let mut image_data = common::load_copy_of_image(path);
+    image_data.resize(GROWN_SIZE, 0);
+    let device = MemoryBlockDevice::try_new(sector_size, image_data).unwrap();
+    Ext2Fs::try_new(device).unwrap()
+}
+
+fn read_file(fs: &Ext2Fs<MemoryBlockDevice<Vec<u8>>>, path: &str) -> Vec<u8> {
+    let file = fs.open_file(path).unwrap();
+    let mut buf = vec![0_u8; file.len() as usize];
+    fs.read_from_file(&file, 0, &mut buf).unwrap();
+    buf
+}
+
+generate_tests!(
+    test_resize_grow:
+    512 - test_resize_grow_standard,
+    1 - test_resize_grow_tiny,
+    32 - test_resize_grow_small,
+    32768 - test_resize_grow_large,
+    1048576 - test_resize_grow_huge,
+);
+
+fn test_resize_grow(sector_size: usize) {
+    let mut fs = grown_device("tests/filesystems/read.img", sector_size);
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+
+    assert_eq!(20480, fs.resize(20480).unwrap());
+    let superblock = fs.superblock();
+    assert_eq!(20480, superblock.num_blocks());
+    assert_eq!(3 * superblock.inodes_per_group(), superblock.num_inodes());
+    assert!(superblock.num_unallocated_blocks() > free_blocks + 19000);
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    assert_eq!(b"Hello, World!\n", read_file(&fs, "/hello.txt").as_slice());
+
+    // the new groups can be used
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "large").unwrap();
+    let data = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    fs.write_to_file(&mut file, 0, &data).unwrap();
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    assert_eq!(data, read_file(&fs, "/large"));
+}
+
+#[test]
+fn test_resize_grow_drops_small_group() {
+    let mut fs = grown_device("tests/filesystems/read.img", 512);
+    // the third group would only have a few blocks, which is not enough for its metadata
+    assert_eq!(16385, fs.resize(16390).unwrap());
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+}
+
+#[test]
+fn test_resize_grow_device_too_small() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let data = fs.block_device().data().clone();
+    assert_eq!(Err(Error::NoSpace), fs.resize(20480));
+    assert!(data == *fs.block_device().data());
+}
+
+#[test]
+fn test_resize_shrink() {
+    let mut fs = grown_device("tests/filesystems/read.img", 512);
+    fs.resize(20480).unwrap();
+    let inodes_per_group = fs.superblock().inodes_per_group();
+
+    // fill the first group, so that the files below end up in the other groups
+    let mut root = fs.read_root_inode().unwrap();
+    let mut filler = fs.create_regular_file(&mut root, "filler").unwrap();
+    fs.write_to_file(&mut filler, 0, &vec![1_u8; 9 * 1024 * 1024]).unwrap();
+    let mut dir = fs.create_dir(&mut root, "dir").unwrap();
+    for i in 0..inodes_per_group {
+        fs.create_regular_file(&mut dir, format!("empty{}", i)).unwrap();
+    }
+    let mut moved = fs.create_regular_file(&mut dir, "moved").unwrap();
+    let data = (0..300 * 1024).map(|i| (i % 253) as u8).collect::<Vec<_>>();
+    fs.write_to_file(&mut moved, 0, &data).unwrap();
+    let mut sub = fs.create_dir(&mut dir, "sub").unwrap();
+    fs.create_symlink(&mut sub, "link", b"../moved").unwrap();
+    assert!(moved.inode_address().get() > inodes_per_group);
+    assert!(moved.direct_ptrs().next().unwrap().unwrap().get() > 8192);
+
+    // make space in the first group
+    fs.punch_hole(&mut filler, 0, 9 * 1024 * 1024).unwrap();
+    for i in 0..inodes_per_group {
+        fs.remove_entry_from_dir(&mut dir, format!("empty{}", i)).unwrap();
+    }
+    fs.repair().unwrap();
+
+    assert_eq!(8193, fs.resize(8193).unwrap());
+    assert_eq!(inodes_per_group, fs.superblock().num_inodes());
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    assert_eq!(data, read_file(&fs, "/dir/moved"));
+    assert_eq!(data, read_file(&fs, "/dir/sub/link"));
+    assert!(fs.open_file("/dir/moved").unwrap().inode_address().get() <= inodes_per_group);
+    let sub = fs.open_dir("/dir/sub").unwrap();
+    let parent = fs.find_entry(&sub, |e| e.name() == Some("..")).unwrap().unwrap();
+    assert_eq!(fs.open_dir("/dir").unwrap().inode_address(), parent.inode());
+}
+
+#[test]
+fn test_resize_minimize() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let min_num_blocks = fs.min_num_blocks().unwrap();
+    assert!(min_num_blocks < fs.superblock().num_blocks());
+
+    let data = fs.block_device().data().clone();
+    assert_eq!(Err(Error::NoSpace), fs.resize(min_num_blocks - 1));
+    assert!(data == *fs.block_device().data());
+
+    assert_eq!(min_num_blocks, fs.resize(min_num_blocks).unwrap());
+    assert_eq!(0, fs.superblock().num_unallocated_blocks());
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+    assert_eq!(b"Hello, World!\n", read_file(&fs, "/hello.txt").as_slice());
+    assert_eq!(min_num_blocks, fs.min_num_blocks().unwrap());
+}
+
+#[test]
+fn test_resize_inconsistent() {
+    let mut fs = grown_device("tests/filesystems/read.img", 512);
+    let mut file = fs.open_file("/hello.txt").unwrap();
+    *file.inode_mut().num_hard_links_mut() = 3;
+    fs.write_inode(file.inode_address(), file.inode()).unwrap();
+
+    assert_eq!(Err(Error::Inconsistent), fs.resize(20480));
+    assert_eq!(Err(Error::Inconsistent), fs.min_num_blocks());
+}
+
+#[test]
+fn test_open_invalid_geometry() {
+    const NUM_BLOCKS_OFFSET: usize = 1024 + 4;
+    const FIRST_DATA_BLOCK_OFFSET: usize = 1024 + 20;
+    const LOG2_BLOCK_SIZE_OFFSET: usize = 1024 + 24;
+    const BLOCKS_PER_GROUP_OFFSET: usize = 1024 + 32;
+    const INODES_PER_GROUP_OFFSET: usize = 1024 + 40;
+
+    let corruptions: [(usize, u32); 5] = [
+        (NUM_BLOCKS_OFFSET, 1),
+        (FIRST_DATA_BLOCK_OFFSET, 5000),
+        (LOG2_BLOCK_SIZE_OFFSET, 40),
+        (BLOCKS_PER_GROUP_OFFSET, 0),
+        (INODES_PER_GROUP_OFFSET, 0),
+    ];
+    for (offset, value) in corruptions {
+        let mut image_data = common::load_copy_of_image("tests/filesystems/empty.img");
+        image_data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        let device = MemoryBlockDevice::try_new(512, image_data).unwrap();
+        assert_eq!(Error::InvalidSuperblock, Ext2Fs::try_new(device).err().unwrap(), "offset {offset}");
+    }
+}
@@ -0,0 +1,65 @@
+use ext2::{Error, Ext2Fs, OptionalFeatures, Revision, Type};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+generate_tests!(
+    test_read_rev0:
+    512 - test_read_rev0_standard,
+    1 - test_read_rev0_tiny,
+    32 - test_read_rev0_small,
+    32768 - test_read_rev0_large,
+    1048576 - test_read_rev0_huge,
+);
+
+fn test_read_rev0(sector_size: usize) {
+    // the fields of the dynamic revision are zero in this image
+    let fs = cow_fs!("tests/filesystems/rev0.img", sector_size);
+    assert_eq!(Revision::GoodOld, fs.superblock().revision());
+    assert_eq!(128, fs.superblock().inode_size());
+    assert_eq!(11, fs.superblock().first_non_reserved_inode());
+    assert_eq!(OptionalFeatures::empty(), fs.superblock().optional_features());
+
+    let hello = fs.open_file("/hello.txt").unwrap();
+    let mut buf = vec![0_u8; hello.len() as usize];
+    fs.read_from_file(&hello, 0, &mut buf).unwrap();
+    assert_eq!(b"Hello, World!\n", buf.as_slice());
+
+    // the numbers need an indirect block
+    let numbers = fs.open_file("/dir/numbers.txt").unwrap();
+    let mut buf = vec![0_u8; numbers.len() as usize];
+    fs.read_from_file(&numbers, 0, &mut buf).unwrap();
+    let expected = (1..=3000).map(|i| format!("{}\n", i)).collect::<String>();
+    assert_eq!(expected.as_bytes(), buf.as_slice());
+
+    // without file types in the directory entries, the type comes from the inode
+    let dir = fs.open_dir("/dir").unwrap();
+    let mut names = fs.list_dir(&dir).unwrap().iter().map(|e| e.name().unwrap().to_string()).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(vec![".", "..", "link", "numbers.txt", "sub"], names);
+    assert_eq!(Type::SymLink, fs.symlink_metadata("/dir/link").unwrap().typ());
+    assert_eq!(hello.inode_address(), fs.resolve_path("/dir/link").unwrap().0);
+}
+
+#[test]
+fn test_write_rev0() {
+    let mut fs = cow_fs!("tests/filesystems/rev0.img", 512);
+
+    let mut dir = fs.open_dir("/dir/sub").unwrap();
+    let mut file = fs.create_regular_file(&mut dir, "new.txt").unwrap();
+    fs.write_to_file(&mut file, 0, b"written").unwrap();
+    // revision 0 predates extended attributes
+    assert_eq!(Err(Error::NotSupported), fs.set_xattr(file.inode_address(), file.inode_mut(), "user.a", b"1"));
+
+    let data = fs.block_device().data().clone();
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(512, data).unwrap()).unwrap();
+    let file = fs.open_file("/dir/sub/new.txt").unwrap();
+    let mut buf = [0_u8; 7];
+    fs.read_from_file(&file, 0, &mut buf).unwrap();
+    assert_eq!(b"written", &buf);
+    // inodes of revision 0 file systems don't have extra fields, and features can't be enabled
+    assert_eq!(0, file.extra_isize());
+    assert_eq!(None, file.extended_attribute_block());
+    assert_eq!(OptionalFeatures::empty(), fs.superblock().optional_features());
+    assert_eq!(0, fs.block_device().data()[1024 + 76]);
+}
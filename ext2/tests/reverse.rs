@@ -0,0 +1,90 @@
+use ext2::{BlockAddress, BlockRef, BlockUse, DirType, Ext2Fs, InodeAddress};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+fn inode(address: u32) -> InodeAddress {
+    InodeAddress::new(address).unwrap()
+}
+
+fn block(address: u32) -> BlockAddress {
+    BlockAddress::new(address).unwrap()
+}
+
+#[test]
+fn test_block_map() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let map = fs.block_map().unwrap();
+    assert_eq!(Some(&vec![BlockRef { inode: inode(12), usage: BlockUse::Data(0) }]), map.get(&block(54)));
+    assert_eq!(Some(&vec![BlockRef { inode: inode(2), usage: BlockUse::Data(0) }]), map.get(&block(40)));
+    // bitmaps and free blocks don't belong to an inode
+    assert_eq!(None, map.get(&block(6)));
+    assert_eq!(None, map.get(&block(100)));
+
+    // the uses of the blocks match the block indices of the file
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "large").unwrap();
+    let data = vec![1_u8; 400 * 1024];
+    fs.write_to_file(&mut file, 0, &data).unwrap();
+    let blocks = fs.inode_blocks(&file).unwrap();
+    assert_eq!(Some(&(file.single_indirect_ptr().unwrap(), BlockUse::Indirect(1))), blocks.get(12));
+    assert_eq!(400 + 3, blocks.len(), "a single indirect block, and a double indirect block with one single indirect block");
+    for &(block, usage) in &blocks {
+        if let BlockUse::Data(index) = usage {
+            assert_eq!(Some(block), fs.resolve_block_index(&file, index as u32).unwrap());
+        }
+    }
+
+    let map = fs.block_map().unwrap();
+    for (block, usage) in blocks {
+        assert_eq!(Some(&vec![BlockRef { inode: file.inode_address(), usage }]), map.get(&block));
+    }
+    let free_ranges = fs.free_block_ranges(0).unwrap();
+    assert!(map.keys().all(|block| !free_ranges.iter().any(|range| range.contains(&block.get()))));
+}
+
+#[test]
+fn test_block_map_shared_xattr_block() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut first = fs.open_file("/hello.txt").unwrap();
+    let mut second = fs.open_file("/some/file.txt").unwrap();
+    fs.set_xattr(first.inode_address(), first.inode_mut(), "user.comment", &[1; 200]).unwrap();
+    fs.set_xattr(second.inode_address(), second.inode_mut(), "user.comment", &[1; 200]).unwrap();
+    let xattr_block = first.extended_attribute_block().unwrap();
+    assert_eq!(Some(xattr_block), second.extended_attribute_block());
+
+    let map = fs.block_map().unwrap();
+    let expected = vec![BlockRef { inode: inode(12), usage: BlockUse::Xattr }, BlockRef { inode: inode(14), usage: BlockUse::Xattr }];
+    assert_eq!(Some(&expected), map.get(&xattr_block));
+}
+
+#[test]
+fn test_inode_blocks_fast_symlink() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut root = fs.read_root_inode().unwrap();
+    let fast = fs.create_symlink(&mut root, "fast", b"hello.txt").unwrap();
+    let slow = fs.create_symlink(&mut root, "slow", &[b'a'; 100]).unwrap();
+    assert!(fs.inode_blocks(&fast).unwrap().is_empty());
+    assert_eq!(1, fs.inode_blocks(&slow).unwrap().len());
+}
+
+#[test]
+fn test_inode_paths() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let paths = fs.inode_paths().unwrap();
+    assert_eq!(Some(&vec![b"/".to_vec()]), paths.get(&inode(2)));
+    assert_eq!(Some(&vec![b"/hello.txt".to_vec()]), paths.get(&inode(12)));
+    assert_eq!(Some(&vec![b"/some/file.txt".to_vec()]), paths.get(&inode(14)));
+    assert_eq!(Some(&vec![b"/lost+found".to_vec()]), paths.get(&inode(11)));
+    // reserved inodes are not in any directory
+    assert_eq!(None, paths.get(&inode(7)));
+
+    // hard links have more than one path, and unreachable inodes have none
+    let mut some = fs.open_dir("/some").unwrap();
+    fs.add_entry_to_dir(&mut some, "another", inode(12), DirType::RegularFile).unwrap();
+    let mut root = fs.read_root_inode().unwrap();
+    fs.remove_entry_from_dir(&mut root, "lost+found").unwrap();
+    let paths = fs.inode_paths().unwrap();
+    assert_eq!(Some(&vec![b"/hello.txt".to_vec(), b"/some/another".to_vec()]), paths.get(&inode(12)));
+    assert_eq!(None, paths.get(&inode(11)));
+}
@@ -0,0 +1,69 @@
+use ext2::{Ext2Fs, SeekFrom};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+#[test]
+fn test_write_past_end_leaves_hole() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let block_size = fs.superblock().block_size() as u64;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "sparse.bin").unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+
+    // this is beyond the direct blocks, so the single indirect block is needed as well
+    let offset = 100 * 1024;
+    fs.write_to_file(&mut file, offset as u64, b"Hello").unwrap();
+    assert_eq!(offset as u64 + 5, file.len());
+    assert_eq!(free_blocks - 2, fs.superblock().num_unallocated_blocks());
+
+    // holes read as zeros
+    let mut buf = vec![0xFF_u8; offset + 10];
+    assert_eq!(offset + 5, fs.read_from_file(&file, 0, &mut buf).unwrap());
+    assert!(buf[..offset].iter().all(|&b| b == 0));
+    assert_eq!(b"Hello", &buf[offset..offset + 5]);
+
+    let data_start = offset as u64 / block_size * block_size;
+    assert_eq!(Some(data_start), fs.seek_data(&file, 0).unwrap());
+    assert_eq!(Some(offset as u64), fs.seek_data(&file, offset as u64).unwrap());
+    assert_eq!(Some(0), fs.seek_hole(&file, 0).unwrap());
+    assert_eq!(Some(offset as u64 + 5), fs.seek_hole(&file, data_start).unwrap());
+    assert_eq!(None, fs.seek_data(&file, offset as u64 + 5).unwrap());
+    assert_eq!(None, fs.seek_hole(&file, offset as u64 + 5).unwrap());
+}
+
+#[test]
+fn test_skip_zero_blocks() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    fs.set_skip_zero_blocks(true);
+    let block_size = fs.superblock().block_size() as usize;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let file = fs.create_regular_file(&mut root, "sparse.bin").unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+
+    // data, zeros, data, zeros
+    let mut data = vec![0_u8; 8 * block_size];
+    data[..block_size].fill(1);
+    data[4 * block_size..5 * block_size].fill(2);
+
+    let mut handle = fs.file_handle(file);
+    handle.write(&data).unwrap();
+    assert_eq!(data.len() as u64, handle.len());
+
+    handle.seek(SeekFrom::Start(0)).unwrap();
+    assert_eq!(Some(block_size as u64), handle.seek_hole().unwrap());
+    assert_eq!(Some(4 * block_size as u64), handle.seek_data().unwrap());
+    assert_eq!(Some(5 * block_size as u64), handle.seek_hole().unwrap());
+    assert_eq!(None, handle.seek_data().unwrap());
+
+    handle.seek(SeekFrom::Start(0)).unwrap();
+    let mut read_data = vec![0xFF_u8; data.len()];
+    assert_eq!(data.len(), handle.read(&mut read_data).unwrap());
+    assert_eq!(data, read_data);
+
+    let file = handle.into_inner();
+    assert_eq!(free_blocks - 2, fs.superblock().num_unallocated_blocks());
+    assert_eq!(2 * block_size as u32 / 512, file.num_disk_sectors());
+}
@@ -0,0 +1,35 @@
+use ext2::{Error, Ext2Fs, SymLink, Type};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+#[test]
+fn test_create_symlink() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let block_size = fs.superblock().block_size();
+
+    // short targets are stored in the block pointers, long ones in a data block
+    let mut root = fs.read_root_inode().unwrap();
+    let fast = fs.create_symlink(&mut root, "fast", b"file.txt").unwrap();
+    let long_target = vec![b'a'; 100];
+    let mut root = fs.read_root_inode().unwrap();
+    let slow = fs.create_symlink(&mut root, "slow", &long_target).unwrap();
+    assert_eq!(0, fast.num_disk_sectors());
+    assert_eq!(block_size / 512, slow.num_disk_sectors());
+
+    for (link, target) in [(fast, b"file.txt".to_vec()), (slow, long_target)] {
+        let link = SymLink::try_from(fs.read_inode(link.inode_address()).unwrap()).unwrap();
+        assert_eq!(Type::SymLink, link.typ());
+        assert_eq!(1, link.num_hard_links());
+        assert_eq!(target, fs.read_link(&link).unwrap());
+    }
+}
+
+#[test]
+fn test_create_symlink_invalid_target() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let too_long = vec![b'a'; fs.superblock().block_size() as usize + 1];
+    let mut root = fs.read_root_inode().unwrap();
+    assert_eq!(Error::InvalidSymLinkTarget, fs.create_symlink(&mut root, "empty", b"").unwrap_err());
+    assert_eq!(Error::InvalidSymLinkTarget, fs.create_symlink(&mut root, "long", &too_long).unwrap_err());
+}
@@ -0,0 +1,151 @@
+use ext2::{Error, ErrorPolicy, Ext2Fs, Feature, Finding, Flags, FormatOptions, OptionalFeatures, TuneOptions};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+const IMAGE_SIZE: usize = 20 * 1024 * 1024;
+/// The offset of the backup superblock in the second group of a file system with 1KiB blocks.
+const BACKUP_SUPERBLOCK_OFFSET: usize = 8193 * 1024;
+/// The offset of the volume name in the superblock.
+const VOLUME_NAME_OFFSET: usize = 120;
+
+fn padded<const N: usize>(s: &[u8]) -> [u8; N] {
+    let mut field = [0_u8; N];
+    field[..s.len()].copy_from_slice(s);
+    field
+}
+
+generate_tests!(
+    test_tune:
+    512 - test_tune_standard,
+    1 - test_tune_tiny,
+    32 - test_tune_small,
+    32768 - test_tune_large,
+    1048576 - test_tune_huge,
+);
+
+fn test_tune(sector_size: usize) {
+    let device = MemoryBlockDevice::try_new(sector_size, vec![0_u8; IMAGE_SIZE]).unwrap();
+    let mut fs = Ext2Fs::format(device, &FormatOptions::default()).unwrap();
+    let options = TuneOptions {
+        volume_name: Some(padded(b"data")),
+        fsid: Some([7; 16]),
+        last_mount_path: Some(padded(b"/mnt/a/rather/long/path/to/the/data")),
+        max_mount_count: Some(20),
+        mount_count: Some(3),
+        check_interval: Some(86400),
+        error_policy: Some(ErrorPolicy::REMOUNT_READ_ONLY),
+        reserved_blocks: Some(1000),
+        reserved_uid: Some(1000),
+        reserved_gid: Some(100),
+        features: vec![(Feature::DirPrealloc, true), (Feature::ExtAttr, false)],
+    };
+    fs.tune(&options).unwrap();
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+
+    // the changes are written to the device
+    let data = fs.unmount().unwrap().data().clone();
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, data.clone()).unwrap()).unwrap();
+    let superblock = fs.superblock();
+    assert_eq!(Some("data"), superblock.volume_name());
+    assert_eq!(&[7; 16], superblock.fsid().as_bytes());
+    assert_eq!(Some("/mnt/a/rather/long/path/to/the/data"), superblock.last_mount_path());
+    assert_eq!(20, superblock.mounts_allowed_before_fsck());
+    assert_eq!(3, superblock.mounts_since_fsck());
+    assert_eq!(86400, superblock.fsck_force_interval());
+    assert_eq!(ErrorPolicy::REMOUNT_READ_ONLY, superblock.error_policy());
+    assert_eq!(1000, superblock.num_superuser_reserved_blocks());
+    assert_eq!(1000, superblock.uid_for_reserved_blocks());
+    assert_eq!(100, superblock.gid_for_reserved_blocks());
+    assert!(superblock.optional_features().contains(OptionalFeatures::PREALLOCATE_FOR_DIRECTORY));
+    assert!(!superblock.optional_features().contains(OptionalFeatures::INODES_EXTENDED_ATTRIBUTES));
+
+    // and to the backup superblocks
+    let backup = &data[BACKUP_SUPERBLOCK_OFFSET..BACKUP_SUPERBLOCK_OFFSET + 1024];
+    assert_eq!(b"data\0", &backup[VOLUME_NAME_OFFSET..VOLUME_NAME_OFFSET + 5]);
+}
+
+#[test]
+fn test_tune_invalid_options() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let data = fs.block_device().data().clone();
+    let num_blocks = fs.superblock().num_blocks();
+
+    // nothing is written if any option is invalid
+    let options = TuneOptions {
+        volume_name: Some(padded(b"data")),
+        reserved_blocks: Some(num_blocks / 2 + 1),
+        ..Default::default()
+    };
+    assert_eq!(Err(Error::InvalidArgument), fs.tune(&options));
+    let options = TuneOptions {
+        error_policy: Some(ErrorPolicy::empty()),
+        ..Default::default()
+    };
+    assert_eq!(Err(Error::InvalidArgument), fs.tune(&options));
+    let options = TuneOptions {
+        volume_name: Some(padded(b"data")),
+        features: vec![(Feature::FileType, false)],
+        ..Default::default()
+    };
+    assert_eq!(Err(Error::NotSupported), fs.tune(&options));
+    assert!(data == *fs.block_device().data());
+}
+
+#[test]
+fn test_tune_revision_0() {
+    let mut fs = cow_fs!("tests/filesystems/rev0.img", 512);
+    let options = TuneOptions {
+        volume_name: Some(padded(b"data")),
+        ..Default::default()
+    };
+    assert_eq!(Err(Error::NotSupported), fs.tune(&options));
+
+    let options = TuneOptions {
+        max_mount_count: Some(10),
+        ..Default::default()
+    };
+    fs.tune(&options).unwrap();
+    assert_eq!(10, fs.superblock().mounts_allowed_before_fsck());
+}
+
+#[test]
+fn test_tune_disable_ext_attr_in_use() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut file = fs.open_file("/hello.txt").unwrap();
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "user.comment", b"hello").unwrap();
+
+    let options = TuneOptions {
+        features: vec![(Feature::ExtAttr, false)],
+        ..Default::default()
+    };
+    assert_eq!(Err(Error::NotSupported), fs.tune(&options));
+    fs.remove_xattr(file.inode_address(), file.inode_mut(), "user.comment").unwrap();
+    fs.tune(&options).unwrap();
+    assert!(!fs.superblock().optional_features().contains(OptionalFeatures::INODES_EXTENDED_ATTRIBUTES));
+}
+
+#[test]
+fn test_tune_disable_dir_index() {
+    let mut fs = cow_fs!("tests/filesystems/htree.img", 512);
+    let options = TuneOptions {
+        features: vec![(Feature::DirIndex, false)],
+        ..Default::default()
+    };
+    fs.tune(&options).unwrap();
+
+    // the directories are linear now, and still contain all entries
+    assert!(!fs.superblock().optional_features().contains(OptionalFeatures::DIRECTORIES_USE_HASH_INDEX));
+    let big = fs.open_dir("/big").unwrap();
+    assert!(!big.flags().contains(Flags::HashIndexedDirectory));
+    assert!(fs.find_entry_by_name(&big, b"file_9999").unwrap().is_some());
+    assert_eq!(Vec::<Finding>::new(), fs.check().unwrap());
+}
+
+#[test]
+fn test_feature_names() {
+    for feature in Feature::ALL {
+        assert_eq!(Some(feature), Feature::from_name(feature.name()));
+    }
+    assert_eq!(None, Feature::from_name("sparse_super"));
+}
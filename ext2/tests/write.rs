@@ -1,4 +1,4 @@
-use ext2::{Error, Ext2Fs};
+use ext2::{Error, Ext2Fs, FormatOptions, ReadOnlyFeatures, Revision};
 use filesystem::MemoryBlockDevice;
 
 mod common;
@@ -24,7 +24,7 @@ fn test_create_and_write_file(sector_size: usize) {
     let data = b"Hello, world!";
     // write `data` until all direct pointers are used
     for i in 0..((1024 * 12) / data.len()) {
-        assert_eq!(fs.write_to_file(&mut file, i * data.len(), data).unwrap(), data.len());
+        assert_eq!(fs.write_to_file(&mut file, (i * data.len()) as u64, data).unwrap(), data.len());
     }
 }
 
@@ -70,4 +70,194 @@ fn test_create_file_collision(sector_size: usize) {
     let mut root = fs.read_root_inode().unwrap();
     let result = fs.create_regular_file(&mut root, file_name);
     assert_eq!(result.unwrap_err(), Error::EntryExists);
-}
\ No newline at end of file
+}
+
+const WRITE_REQUIRED_FEATURES_OFFSET: usize = 1024 + 100;
+
+generate_tests!(
+    test_write_large_file:
+    512 - test_write_large_file_standard,
+    1 - test_write_large_file_tiny,
+    32 - test_write_large_file_small,
+    32768 - test_write_large_file_large,
+    1048576 - test_write_large_file_huge,
+);
+
+fn test_write_large_file(sector_size: usize) {
+    let mut image_data = common::load_copy_of_image("tests/filesystems/empty.img");
+    image_data[WRITE_REQUIRED_FEATURES_OFFSET] &= !0x02; // USE_64BIT_FILE_SIZE
+    let mut fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, image_data).unwrap()).unwrap();
+    assert!(!fs.superblock().write_required_features().contains(ReadOnlyFeatures::USE_64BIT_FILE_SIZE));
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "large.bin").unwrap();
+
+    // this is beyond 4GiB, so the offset doesn't fit into 32 bits
+    let offset = 5 * 1024 * 1024 * 1024;
+    fs.write_to_file(&mut file, offset, b"Hello").unwrap();
+    assert_eq!(offset + 5, file.len());
+
+    let data = fs.block_device().data().clone();
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, data).unwrap()).unwrap();
+    assert!(fs.superblock().write_required_features().contains(ReadOnlyFeatures::USE_64BIT_FILE_SIZE));
+    let file = fs.open_file("large.bin").unwrap();
+    assert_eq!(offset + 5, file.len());
+
+    let mut buf = [0xFF_u8; 10];
+    assert_eq!(5, fs.read_from_file(&file, offset, &mut buf).unwrap());
+    assert_eq!(b"Hello", &buf[..5]);
+    assert_eq!(2, fs.read_from_file(&file, offset - 2, &mut buf[..2]).unwrap());
+    assert_eq!([0, 0], buf[..2]);
+}
+
+generate_tests!(
+    test_write_beyond_max_file_size:
+    512 - test_write_beyond_max_file_size_standard,
+    1 - test_write_beyond_max_file_size_tiny,
+    32 - test_write_beyond_max_file_size_small,
+    32768 - test_write_beyond_max_file_size_large,
+    1048576 - test_write_beyond_max_file_size_huge,
+);
+
+fn test_write_beyond_max_file_size(sector_size: usize) {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", sector_size);
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "large.bin").unwrap();
+    let max_file_size = fs.max_file_size();
+    assert_eq!(Error::FileTooLarge, fs.write_to_file(&mut file, max_file_size - 1, b"Hello").unwrap_err());
+    assert_eq!(Error::FileTooLarge, fs.write_to_file(&mut file, u64::MAX, b"Hello").unwrap_err());
+    assert_eq!(0, file.len());
+}
+
+generate_tests!(
+    test_write_large_file_without_features:
+    512 - test_write_large_file_without_features_standard,
+    1 - test_write_large_file_without_features_tiny,
+    32 - test_write_large_file_without_features_small,
+    32768 - test_write_large_file_without_features_large,
+    1048576 - test_write_large_file_without_features_huge,
+);
+
+fn test_write_large_file_without_features(sector_size: usize) {
+    // revision 0 file systems don't have features
+    let device = MemoryBlockDevice::try_new(sector_size, vec![0_u8; 1024 * 1024]).unwrap();
+    let options = FormatOptions {
+        revision: Revision::GoodOld,
+        ..Default::default()
+    };
+    let mut fs = Ext2Fs::format(device, &options).unwrap();
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "large.bin").unwrap();
+    // files up to 2GiB are fine
+    fs.write_to_file(&mut file, (1 << 31) - 6, b"Hello").unwrap();
+    assert_eq!(Error::FileTooLarge, fs.write_to_file(&mut file, 1 << 31, b"Hello").unwrap_err());
+    assert_eq!((1 << 31) - 1, file.len());
+}
+
+#[test]
+fn test_write_owner() {
+    let mut fs = cow_fs!("tests/filesystems/read.img", 512);
+    let mut file = fs.open_file("/hello.txt").unwrap();
+
+    // ids above 65535 keep their upper half in the OS dependent values
+    file.inode_mut().set_user_id(100_000);
+    file.inode_mut().set_group_id(1000);
+    fs.write_inode(file.inode_address(), file.inode()).unwrap();
+
+    let file = fs.open_file("/hello.txt").unwrap();
+    assert_eq!(100_000, file.user_id());
+    assert_eq!(1000, file.group_id());
+    assert_eq!(&[0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0], file.os_dependent_values().1);
+}
+
+generate_tests!(
+    test_truncate:
+    512 - test_truncate_standard,
+    1 - test_truncate_tiny,
+    32 - test_truncate_small,
+    32768 - test_truncate_large,
+    1048576 - test_truncate_huge,
+);
+
+fn test_truncate(sector_size: usize) {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", sector_size);
+    let block_size = fs.superblock().block_size() as usize;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    let mut file = fs.create_regular_file(&mut root, "file.bin").unwrap();
+    let data = vec![0xAB_u8; 20 * block_size];
+    fs.write_to_file(&mut file, 0, &data).unwrap();
+
+    // shrinking frees the blocks after the end, including the indirect block
+    fs.truncate(&mut file, 100).unwrap();
+    assert_eq!(100, file.len());
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+    assert_eq!(None, file.single_indirect_ptr());
+
+    // growing leaves a hole, and the old data after the end is gone
+    fs.truncate(&mut file, 2 * block_size as u64).unwrap();
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+    let mut buf = vec![0xFF_u8; 2 * block_size];
+    assert_eq!(buf.len(), fs.read_from_file(&file, 0, &mut buf).unwrap());
+    assert!(buf[..100].iter().all(|&b| b == 0xAB));
+    assert!(buf[100..].iter().all(|&b| b == 0));
+
+    fs.truncate(&mut file, 0).unwrap();
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+    assert_eq!(0, file.num_disk_sectors());
+    assert_eq!(Vec::<ext2::Finding>::new(), fs.check().unwrap());
+}
+
+#[test]
+fn test_truncate_at_block_boundary() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let block_size = fs.superblock().block_size() as usize;
+    let sectors_per_block = block_size as u32 / 512;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    let mut file = fs.create_regular_file(&mut root, "file.bin").unwrap();
+    fs.write_to_file(&mut file, 0, &vec![0xAB_u8; 3 * block_size]).unwrap();
+    assert_eq!(3 * sectors_per_block, file.num_disk_sectors());
+
+    // the last remaining block is full, so it is kept as it is
+    fs.truncate(&mut file, 2 * block_size as u64).unwrap();
+    assert_eq!(2 * block_size as u64, file.len());
+    assert_eq!(2 * sectors_per_block, file.num_disk_sectors());
+    assert_eq!(free_blocks - 2, fs.superblock().num_unallocated_blocks());
+    let mut buf = vec![0_u8; 2 * block_size];
+    assert_eq!(buf.len(), fs.read_from_file(&file, 0, &mut buf).unwrap());
+    assert!(buf.iter().all(|&b| b == 0xAB));
+    assert_eq!(Vec::<ext2::Finding>::new(), fs.check().unwrap());
+}
+
+#[test]
+fn test_truncate_grow() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let block_size = fs.superblock().block_size() as usize;
+    let sectors_per_block = block_size as u32 / 512;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    let mut file = fs.create_regular_file(&mut root, "file.bin").unwrap();
+    fs.write_to_file(&mut file, 0, b"Hello").unwrap();
+
+    // growing past the end doesn't allocate anything
+    let len = 20 * block_size as u64 + 10;
+    fs.truncate(&mut file, len).unwrap();
+    assert_eq!(len, file.len());
+    assert_eq!(sectors_per_block, file.num_disk_sectors());
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+    let file = fs.open_file("/file.bin").unwrap();
+    assert_eq!(len, file.len());
+    assert_eq!(sectors_per_block, file.num_disk_sectors());
+
+    let mut buf = vec![0xFF_u8; len as usize];
+    assert_eq!(buf.len(), fs.read_from_file(&file, 0, &mut buf).unwrap());
+    assert_eq!(b"Hello", &buf[..5]);
+    assert!(buf[5..].iter().all(|&b| b == 0));
+    assert_eq!(Vec::<ext2::Finding>::new(), fs.check().unwrap());
+}
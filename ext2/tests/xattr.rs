@@ -0,0 +1,118 @@
+use ext2::{Error, Ext2Fs, OptionalFeatures};
+use filesystem::MemoryBlockDevice;
+
+mod common;
+
+/// A value that doesn't fit into the space after the extra fields of an inode, so
+/// that it is stored in the attribute block.
+fn block_value(c: u8) -> Vec<u8> {
+    vec![c; 100]
+}
+
+generate_tests!(
+    test_set_and_get_xattr:
+    512 - test_set_and_get_xattr_standard,
+    1 - test_set_and_get_xattr_tiny,
+    32 - test_set_and_get_xattr_small,
+    32768 - test_set_and_get_xattr_large,
+    1048576 - test_set_and_get_xattr_huge,
+);
+
+fn test_set_and_get_xattr(sector_size: usize) {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", sector_size);
+    let block_size = fs.superblock().block_size();
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.txt").unwrap();
+    assert_eq!(None, fs.get_xattr(&file, "user.comment").unwrap());
+    assert!(fs.list_xattr(&file).unwrap().is_empty());
+
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "user.comment", b"hello").unwrap();
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "security.selinux", b"system_u:object_r:etc_t:s0\0").unwrap();
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "user.comment", b"hello, world").unwrap();
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "user.large", &block_value(b'x')).unwrap();
+    assert!(file.extended_attribute_block().is_some());
+    assert_eq!(block_size / 512, file.num_disk_sectors());
+    assert!(fs.superblock().optional_features().contains(OptionalFeatures::INODES_EXTENDED_ATTRIBUTES));
+
+    // the changes must be persisted
+    let data = fs.block_device().data().clone();
+    let fs = Ext2Fs::try_new(MemoryBlockDevice::try_new(sector_size, data).unwrap()).unwrap();
+    let file = fs.open_file("file.txt").unwrap();
+    assert_eq!(Some(b"hello, world".to_vec()), fs.get_xattr(&file, "user.comment").unwrap());
+    assert_eq!(Some(b"system_u:object_r:etc_t:s0\0".to_vec()), fs.get_xattr(&file, "security.selinux").unwrap());
+    assert_eq!(Some(block_value(b'x')), fs.get_xattr(&file, "user.large").unwrap());
+    assert_eq!(None, fs.get_xattr(&file, "trusted.comment").unwrap());
+    // the attributes in the inode are listed before the ones in the block
+    assert_eq!(vec!["security.selinux", "user.comment", "user.large"], fs.list_xattr(&file).unwrap());
+}
+
+#[test]
+fn test_remove_xattr() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.txt").unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "user.a", &block_value(b'1')).unwrap();
+    fs.set_xattr(file.inode_address(), file.inode_mut(), "user.b", &block_value(b'2')).unwrap();
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+
+    fs.remove_xattr(file.inode_address(), file.inode_mut(), "user.a").unwrap();
+    assert_eq!(None, fs.get_xattr(&file, "user.a").unwrap());
+    assert_eq!(Some(block_value(b'2')), fs.get_xattr(&file, "user.b").unwrap());
+    assert_eq!(Error::NotFound, fs.remove_xattr(file.inode_address(), file.inode_mut(), "user.a").unwrap_err());
+
+    // without attributes, the block is freed
+    fs.remove_xattr(file.inode_address(), file.inode_mut(), "user.b").unwrap();
+    assert_eq!(None, file.extended_attribute_block());
+    assert_eq!(0, file.num_disk_sectors());
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+}
+
+#[test]
+fn test_shared_xattr_block() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut first = fs.create_regular_file(&mut root, "first.txt").unwrap();
+    let mut second = fs.create_regular_file(&mut root, "second.txt").unwrap();
+    let free_blocks = fs.superblock().num_unallocated_blocks();
+
+    // inodes with the same attributes share a block
+    fs.set_xattr(first.inode_address(), first.inode_mut(), "user.a", &block_value(b'1')).unwrap();
+    fs.set_xattr(second.inode_address(), second.inode_mut(), "user.a", &block_value(b'1')).unwrap();
+    assert_eq!(first.extended_attribute_block(), second.extended_attribute_block());
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+
+    // changing a shared block gives the inode its own copy
+    fs.set_xattr(second.inode_address(), second.inode_mut(), "user.b", &block_value(b'2')).unwrap();
+    assert_ne!(first.extended_attribute_block(), second.extended_attribute_block());
+    assert_eq!(free_blocks - 2, fs.superblock().num_unallocated_blocks());
+    assert_eq!(None, fs.get_xattr(&first, "user.b").unwrap());
+    assert_eq!(Some(block_value(b'2')), fs.get_xattr(&second, "user.b").unwrap());
+
+    // once the attributes are the same again, the block is shared again
+    fs.remove_xattr(second.inode_address(), second.inode_mut(), "user.b").unwrap();
+    assert_eq!(first.extended_attribute_block(), second.extended_attribute_block());
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+
+    // the block is freed once the last inode doesn't use it anymore
+    fs.remove_xattr(first.inode_address(), first.inode_mut(), "user.a").unwrap();
+    assert_eq!(free_blocks - 1, fs.superblock().num_unallocated_blocks());
+    fs.remove_xattr(second.inode_address(), second.inode_mut(), "user.a").unwrap();
+    assert_eq!(free_blocks, fs.superblock().num_unallocated_blocks());
+}
+
+#[test]
+fn test_xattr_errors() {
+    let mut fs = cow_fs!("tests/filesystems/empty.img", 512);
+    let block_size = fs.superblock().block_size() as usize;
+
+    let mut root = fs.read_root_inode().unwrap();
+    let mut file = fs.create_regular_file(&mut root, "file.txt").unwrap();
+    assert_eq!(Error::NotSupported, fs.set_xattr(file.inode_address(), file.inode_mut(), "unknown.a", b"1").unwrap_err());
+    assert_eq!(Error::NotSupported, fs.set_xattr(file.inode_address(), file.inode_mut(), "user.", b"1").unwrap_err());
+    assert_eq!(Error::NoSpace, fs.set_xattr(file.inode_address(), file.inode_mut(), "user.a", &vec![0; block_size]).unwrap_err());
+    assert_eq!(None, file.extended_attribute_block());
+}
@@ -0,0 +1,187 @@
+use core::cell::RefCell;
+
+use embedded_io::{Read, ReadExactError, Seek, SeekFrom, Write};
+
+use crate::BlockDevice;
+
+/// A [`BlockDevice`] over any storage that implements the `embedded-io`
+/// [`Read`], [`Write`] and [`Seek`] traits.
+pub struct EmbeddedIoBlockDevice<S> {
+    sector_size: usize,
+    sector_count: usize,
+    storage: RefCell<S>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EmbeddedIoError<E> {
+    /// The underlying storage returned an error.
+    Io(E),
+    /// The storage ended before a whole sector could be read.
+    UnexpectedEof,
+    /// The sector is not within the bounds of the device.
+    OutOfBounds,
+    /// The sector size is zero, or the size of the storage is not a multiple of it.
+    InvalidSize,
+}
+
+impl<E> From<ReadExactError<E>> for EmbeddedIoError<E> {
+    fn from(value: ReadExactError<E>) -> Self {
+        match value {
+            ReadExactError::UnexpectedEof => Self::UnexpectedEof,
+            ReadExactError::Other(e) => Self::Io(e),
+        }
+    }
+}
+
+impl<S> EmbeddedIoBlockDevice<S>
+where
+    S: Read + Write + Seek,
+{
+    /// Creates a new block device over the given storage. The size of the
+    /// device is determined by seeking to the end of the storage, and must be
+    /// a multiple of the sector size.
+    pub fn try_new(sector_size: usize, storage: S) -> Result<Self, EmbeddedIoError<S::Error>> {
+        let mut storage = storage;
+        let len = storage.seek(SeekFrom::End(0)).map_err(EmbeddedIoError::Io)?;
+        if sector_size == 0 || len % sector_size as u64 != 0 {
+            return Err(EmbeddedIoError::InvalidSize);
+        }
+        Ok(Self {
+            sector_size,
+            sector_count: len as usize / sector_size,
+            storage: RefCell::new(storage),
+        })
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage.into_inner()
+    }
+}
+
+impl<S> BlockDevice for EmbeddedIoBlockDevice<S>
+where
+    S: Read + Write + Seek,
+{
+    type Error = EmbeddedIoError<S::Error>;
+
+    fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn sector_count(&self) -> usize {
+        self.sector_count
+    }
+
+    fn read_sector(&self, sector_index: usize, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        debug_assert_eq!(self.sector_size(), buf.len());
+        if sector_index >= self.sector_count {
+            return Err(EmbeddedIoError::OutOfBounds);
+        }
+
+        let mut storage = self.storage.borrow_mut();
+        storage.seek(SeekFrom::Start((sector_index * self.sector_size) as u64))
+            .map_err(EmbeddedIoError::Io)?;
+        storage.read_exact(buf)?;
+        Ok(buf.len())
+    }
+
+    fn write_sector(&mut self, sector_index: usize, buf: &[u8]) -> Result<usize, Self::Error> {
+        debug_assert_eq!(self.sector_size(), buf.len());
+        if sector_index >= self.sector_count {
+            return Err(EmbeddedIoError::OutOfBounds);
+        }
+
+        let storage = self.storage.get_mut();
+        storage.seek(SeekFrom::Start((sector_index * self.sector_size) as u64))
+            .map_err(EmbeddedIoError::Io)?;
+        storage.write_all(buf).map_err(EmbeddedIoError::Io)?;
+        Ok(buf.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::convert::Infallible;
+
+    use embedded_io::{ErrorType, Read, Seek, SeekFrom, Write};
+
+    use crate::block::io::{EmbeddedIoBlockDevice, EmbeddedIoError};
+    use crate::BlockDevice;
+
+    struct Cursor {
+        data: Vec<u8>,
+        position: usize,
+    }
+
+    impl ErrorType for Cursor {
+        type Error = Infallible;
+    }
+
+    impl Read for Cursor {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let len = buf.len().min(self.data.len() - self.position);
+            buf[..len].copy_from_slice(&self.data[self.position..self.position + len]);
+            self.position += len;
+            Ok(len)
+        }
+    }
+
+    impl Write for Cursor {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            let len = buf.len().min(self.data.len() - self.position);
+            self.data[self.position..self.position + len].copy_from_slice(&buf[..len]);
+            self.position += len;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Cursor {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+            self.position = match pos {
+                SeekFrom::Start(offset) => offset as usize,
+                SeekFrom::End(offset) => (self.data.len() as i64 + offset) as usize,
+                SeekFrom::Current(offset) => (self.position as i64 + offset) as usize,
+            };
+            Ok(self.position as u64)
+        }
+    }
+
+    #[test]
+    fn test_read_write_at() {
+        let data = vec![1_u8, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8];
+        let mut device = EmbeddedIoBlockDevice::try_new(4, Cursor { data, position: 0 }).unwrap();
+        assert_eq!(4, device.sector_count());
+
+        let mut buf = [0_u8; 6];
+        device.read_at(3, &mut buf).unwrap();
+        assert_eq!([2, 3, 3, 4, 4, 5], buf);
+
+        device.write_at(5, &[0xFF, 0xFF, 0xFF]).unwrap();
+        let data = device.into_inner().data;
+        assert_eq!(&[1_u8, 1, 2, 2, 3, 0xFF, 0xFF, 0xFF, 5, 5, 6, 6, 7, 7, 8, 8], data.as_slice());
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let data = vec![0_u8; 8];
+        let mut device = EmbeddedIoBlockDevice::try_new(4, Cursor { data, position: 0 }).unwrap();
+
+        let mut buf = [0_u8; 4];
+        assert_eq!(Err(EmbeddedIoError::OutOfBounds), device.read_sector(2, &mut buf));
+        assert_eq!(Err(EmbeddedIoError::OutOfBounds), device.write_sector(2, &buf));
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        let cursor = || Cursor { data: vec![0_u8; 9], position: 0 };
+        assert!(matches!(EmbeddedIoBlockDevice::try_new(0, cursor()), Err(EmbeddedIoError::InvalidSize)));
+        assert!(matches!(EmbeddedIoBlockDevice::try_new(4, cursor()), Err(EmbeddedIoError::InvalidSize)));
+        assert!(EmbeddedIoBlockDevice::try_new(3, cursor()).is_ok());
+    }
+}
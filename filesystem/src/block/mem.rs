@@ -11,7 +11,7 @@ where
 {
     pub fn try_new(sector_size: usize, data: T) -> Option<Self> {
         let mut data = data;
-        if data.as_mut().len() % sector_size != 0 {
+        if sector_size == 0 || data.as_mut().len() % sector_size != 0 {
             return None;
         }
         Some(Self { sector_size, data })
@@ -33,7 +33,7 @@ where
     }
 
     fn sector_count(&self) -> usize {
-        self.data.as_ref().len()
+        self.data.as_ref().len() / self.sector_size
     }
 
     fn read_sector(&self, sector_index: usize, buf: &mut [u8]) -> Result<usize, Self::Error> {
@@ -62,6 +62,12 @@ mod tests {
     use crate::block::mem::MemoryBlockDevice;
     use crate::BlockDevice;
 
+    #[test]
+    fn test_try_new_invalid_size() {
+        assert!(MemoryBlockDevice::try_new(0, vec![0_u8; 8]).is_none());
+        assert!(MemoryBlockDevice::try_new(3, vec![0_u8; 8]).is_none());
+    }
+
     #[test]
     fn test_read_at_short() {
         let data = vec![1_u8, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8];
@@ -165,4 +171,18 @@ mod tests {
         device.write_at(0, &[1, 2, 3, 4]).unwrap();
         assert_eq!(&[1, 2, 3, 4, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], device.data().as_slice());
     }
+
+    #[test]
+    fn test_access_at_end() {
+        let data = vec![0xFF_u8; 16];
+        let mut device = MemoryBlockDevice::try_new(4, data).unwrap();
+
+        // unaligned accesses that end with the last sector must not touch the sector after it
+        device.write_at(10, &[1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 1, 2, 3, 4, 5, 6], device.data().as_slice());
+        device.write_at(8, &[7; 8]).unwrap();
+        let mut buf = [0_u8; 6];
+        device.read_at(10, &mut buf).unwrap();
+        assert_eq!([7; 6], buf);
+    }
 }
@@ -1,7 +1,11 @@
 use alloc::vec;
 
+#[cfg(feature = "embedded-io")]
+pub use io::*;
 pub use mem::*;
 
+#[cfg(feature = "embedded-io")]
+mod io;
 mod mem;
 
 pub trait BlockDevice {
@@ -43,25 +47,16 @@ pub trait BlockDevice {
 
         let sector_size = self.sector_size();
 
-        if offset % buf.len() == 0 && buf.len() == sector_size {
+        if offset.is_multiple_of(buf.len()) && buf.len() == sector_size {
             // if we read exactly one sector, and that read is aligned, delegate to the device impl
             return self.read_sector(offset / sector_size, buf);
         }
 
         let start_sector = offset / sector_size;
         let relative_offset = offset % sector_size;
-        let end_sector = if relative_offset + buf.len() <= sector_size {
-            start_sector
-        } else {
-            (offset + buf.len()) / sector_size
-        };
-        let sector_count = end_sector - start_sector
-            + if relative_offset == 0 && buf.len() % sector_size == 0 && start_sector != end_sector
-        {
-            0
-        } else {
-            1
-        };
+        // the last sector that contains data of the read
+        let end_sector = (offset + buf.len() - 1) / sector_size;
+        let sector_count = end_sector - start_sector + 1;
 
         // read sectors
         let mut data = vec![0_u8; sector_count * sector_size];
@@ -89,19 +84,17 @@ pub trait BlockDevice {
 
         let sector_size = self.sector_size();
 
-        if offset % buf.len() == 0 && buf.len() == sector_size {
+        if offset.is_multiple_of(buf.len()) && buf.len() == sector_size {
             // if we write exactly one sector, and that write is aligned, delegate to the device impl
             return self.write_sector(offset / sector_size, buf);
         }
 
         let start_sector = offset / sector_size;
         let relative_start_offset = offset % sector_size;
-        let end_sector = if relative_start_offset + buf.len() <= sector_size {
-            start_sector
-        } else {
-            (offset + buf.len()) / sector_size
-        };
-        let relative_end_offset = (relative_start_offset + buf.len()) % sector_size;
+        // the last sector that contains data of the write, and the end of the write within
+        // that sector, which is the whole sector if the write ends at a sector boundary
+        let end_sector = (offset + buf.len() - 1) / sector_size;
+        let relative_end_offset = offset + buf.len() - end_sector * sector_size;
 
         // The write is not aligned, so we have to read the first and last sector, merge
         // the data with the given buffer, and write the merged data back to the device.
@@ -112,9 +105,7 @@ pub trait BlockDevice {
             // and write it back.
             let mut first_sector = vec![0_u8; sector_size];
             self.read_sector(start_sector, &mut first_sector)?;
-            // if we have a 1 sector write and a relative_end_offset of 0, that means we need to write until the end of the sector
-            let actual_end_offset = if relative_end_offset == 0 { sector_size } else { relative_end_offset };
-            first_sector.as_mut_slice()[relative_start_offset..actual_end_offset].copy_from_slice(&buf);
+            first_sector.as_mut_slice()[relative_start_offset..relative_end_offset].copy_from_slice(buf);
             return self.write_sector(start_sector, &first_sector);
         }
 
@@ -0,0 +1,1447 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Display;
+use std::fs::{self, FileTimes, Metadata};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::hash::{BuildHasher, Hasher};
+use std::ops::Range;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{lchown, symlink, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::{Parser, ValueEnum};
+use ext2::{
+    BlockUse, DirType, Directory, ErrorPolicy, Ext2Fs, Feature, FormatOptions, HashVersion, Inode, InodeAddress, Permissions, RegularFile, Revision, State, Superblock, SuperblockFlags, SymLink, Timestamp, TuneOptions, Type,
+};
+use filesystem::MemoryBlockDevice;
+use serde_json::json;
+
+use crate::ext2_cli::shell::Session;
+
+mod shell;
+
+/// A file system that was read from an image into memory.
+type ImageFs = Ext2Fs<MemoryBlockDevice<Vec<u8>>>;
+
+#[derive(Debug, Parser)]
+pub struct Ext2 {
+    #[command(subcommand)]
+    command: Ext2Command,
+}
+
+#[derive(Debug, Parser)]
+pub enum Ext2Command {
+    /// Create a new, empty ext2 file system
+    Create(Create),
+    /// Check the consistency of an ext2 file system, like e2fsck, and optionally repair it
+    Check(Check),
+    /// Grow or shrink an ext2 file system, like resize2fs
+    Resize(Resize),
+    /// Change the parameters of an ext2 file system, like tune2fs
+    Tune(Tune),
+    /// Print the superblock and the block group descriptors of an ext2 file system, like dumpe2fs
+    Info(Info),
+    /// List the contents of a directory in an ext2 file system
+    Ls(Ls),
+    /// Print the contents of a file in an ext2 file system
+    Cat(Cat),
+    /// Print the fields and the blocks of an inode in an ext2 file system
+    Stat(Stat),
+    /// Print the contents of a directory in an ext2 file system recursively, as a tree
+    Tree(Tree),
+    /// Copy a file or directory from the host into an ext2 file system
+    Cp(Cp),
+    /// Copy a path or the whole ext2 file system into a directory on the host
+    Extract(Extract),
+    /// Inspect and change an ext2 file system with interactive commands, like debugfs
+    Shell(Shell),
+}
+
+#[derive(Debug, Parser)]
+pub struct Create {
+    #[arg(long, help = "The file that the file system will be written into")]
+    out: PathBuf,
+    #[arg(short, long, help = "Overwrite the file if it already exists")]
+    force: bool,
+    #[arg(long, value_parser = parse_size, help = "The size of the file system, e.g. 1MB or 512K")]
+    size: u64,
+    #[arg(long, default_value_t = 1024, help = "The block size in bytes")]
+    block_size: u32,
+    #[arg(long, default_value_t = 256, help = "The size of an inode in bytes, ignored for revision 0")]
+    inode_size: u16,
+    #[arg(long, value_enum, default_value_t = RevisionArg::Dynamic, help = "The revision of the file system")]
+    revision: RevisionArg,
+}
+
+#[derive(Debug, Parser)]
+pub struct Check {
+    #[arg(help = "The file that contains the file system")]
+    image: PathBuf,
+    #[arg(long, help = "Repair the problems that were found")]
+    repair: bool,
+    #[arg(long, requires = "repair", help = "Print the repairs without changing the file")]
+    dry_run: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct Resize {
+    #[arg(help = "The file that contains the file system")]
+    image: PathBuf,
+    #[arg(long, value_parser = parse_size, required_unless_present = "minimize", conflicts_with = "minimize", help = "The new size of the file system, e.g. 64MB")]
+    size: Option<u64>,
+    #[arg(long, help = "Shrink the file system to the smallest possible size")]
+    minimize: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct Tune {
+    #[arg(help = "The file that contains the file system")]
+    image: PathBuf,
+    #[arg(long, help = "The name of the file system, at most 16 bytes")]
+    label: Option<String>,
+    #[arg(long, value_parser = parse_uuid, help = "The id of the file system, a UUID, 'random' or 'clear'")]
+    uuid: Option<[u8; 16]>,
+    #[arg(long, help = "The directory that the file system was last mounted at, at most 64 bytes")]
+    last_mount_path: Option<String>,
+    #[arg(long, allow_negative_numbers = true, value_parser = parse_max_mount_count, help = "The number of mounts after which the file system should be checked, 0 or -1 to disable")]
+    max_mount_count: Option<u16>,
+    #[arg(long, help = "The number of mounts since the last check")]
+    mount_count: Option<u16>,
+    #[arg(long, value_parser = parse_interval, help = "The maximum time between checks, in days or with a suffix like 2w or 6m, 0 to disable")]
+    interval: Option<u32>,
+    #[arg(long, value_enum, help = "What the kernel does when it finds an error")]
+    errors: Option<ErrorsArg>,
+    #[arg(long, help = "The number of blocks that are reserved for the superuser")]
+    reserved_blocks: Option<u32>,
+    #[arg(long, conflicts_with = "reserved_blocks", value_parser = clap::value_parser!(u8).range(0..=50), help = "The percentage of blocks that is reserved for the superuser")]
+    reserved_percent: Option<u8>,
+    #[arg(long, help = "The user that may use the reserved blocks")]
+    reserved_uid: Option<u16>,
+    #[arg(long, help = "The group that may use the reserved blocks")]
+    reserved_gid: Option<u16>,
+    #[arg(long, value_delimiter = ',', value_parser = parse_feature, help = "The features to enable, or to disable with a leading ^, e.g. dir_index,^ext_attr")]
+    features: Vec<(Feature, bool)>,
+}
+
+#[derive(Debug, Parser)]
+pub struct Info {
+    #[arg(help = "The file that contains the file system")]
+    image: PathBuf,
+    #[arg(long, help = "Also list the ranges of free blocks and inodes of every group")]
+    free_ranges: bool,
+    #[arg(long, help = "Print JSON instead of text")]
+    json: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct Ls {
+    #[arg(help = "The file that contains the file system")]
+    image: PathBuf,
+    #[arg(default_value = "/", help = "The directory to list, or a file")]
+    path: OsString,
+    #[arg(short, long, help = "Print the inode, mode, links, owner, size and modification time of every entry")]
+    long: bool,
+    #[arg(short, long, help = "Also list the entries '.' and '..'")]
+    all: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct Cat {
+    #[arg(help = "The file that contains the file system")]
+    image: PathBuf,
+    #[arg(help = "The file to print")]
+    path: OsString,
+}
+
+#[derive(Debug, Parser)]
+pub struct Stat {
+    #[arg(help = "The file that contains the file system")]
+    image: PathBuf,
+    #[arg(help = "The file, directory or symlink to print the inode of")]
+    path: OsString,
+}
+
+#[derive(Debug, Parser)]
+pub struct Tree {
+    #[arg(help = "The file that contains the file system")]
+    image: PathBuf,
+    #[arg(default_value = "/", help = "The directory to print")]
+    path: OsString,
+}
+
+#[derive(Debug, Parser)]
+pub struct Cp {
+    #[arg(help = "The file that contains the file system")]
+    image: PathBuf,
+    #[arg(help = "The file, directory or symlink on the host to copy")]
+    source: PathBuf,
+    #[arg(help = "The path to copy to. Missing parent directories are created, an existing file is overwritten, and an existing directory gets the source inside it")]
+    dest: OsString,
+    #[arg(long, help = "The owner of the copies, instead of the owner on the host")]
+    uid: Option<u32>,
+    #[arg(long, help = "The group of the copies, instead of the group on the host")]
+    gid: Option<u32>,
+}
+
+#[derive(Debug, Parser)]
+pub struct Extract {
+    #[arg(help = "The file that contains the file system")]
+    image: PathBuf,
+    #[arg(help = "The directory on the host to copy into, which is created if it doesn't exist")]
+    dest: PathBuf,
+    #[arg(long, default_value = "/", help = "The path to copy, by default the whole file system. Only the contents of the root directory are copied, other paths are copied into the directory by name")]
+    path: OsString,
+}
+
+#[derive(Debug, Parser)]
+pub struct Shell {
+    #[arg(help = "The file that contains the file system")]
+    image: PathBuf,
+    #[arg(short, long, help = "Allow commands that change the file system. The changes are written to the file when the shell exits")]
+    write: bool,
+    #[arg(short, long, help = "Run the given command instead of reading commands from stdin, can be given multiple times")]
+    command: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+enum ErrorsArg {
+    /// Ignore the error
+    Continue,
+    /// Remount the file system read-only
+    RemountRo,
+    /// Panic
+    Panic,
+}
+
+impl From<ErrorsArg> for ErrorPolicy {
+    fn from(value: ErrorsArg) -> Self {
+        match value {
+            ErrorsArg::Continue => ErrorPolicy::IGNORE,
+            ErrorsArg::RemountRo => ErrorPolicy::REMOUNT_READ_ONLY,
+            ErrorsArg::Panic => ErrorPolicy::KERNEL_PANIC,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+enum RevisionArg {
+    /// Revision 0, with 128 byte inodes and no features, for maximal compatibility
+    #[value(name = "0")]
+    GoodOld,
+    /// Revision 1, with variable inode sizes and features
+    #[value(name = "1")]
+    Dynamic,
+}
+
+impl From<RevisionArg> for Revision {
+    fn from(value: RevisionArg) -> Self {
+        match value {
+            RevisionArg::GoodOld => Revision::GoodOld,
+            RevisionArg::Dynamic => Revision::Dynamic,
+        }
+    }
+}
+
+pub fn handle_ext2(ext2: Ext2) -> Result<(), Box<dyn Error>> {
+    match ext2.command {
+        Ext2Command::Create(create) => handle_create(create),
+        Ext2Command::Check(check) => handle_check(check),
+        Ext2Command::Resize(resize) => handle_resize(resize),
+        Ext2Command::Tune(tune) => handle_tune(tune),
+        Ext2Command::Info(info) => handle_info(info),
+        Ext2Command::Ls(ls) => handle_ls(ls),
+        Ext2Command::Cat(cat) => handle_cat(cat),
+        Ext2Command::Stat(stat) => handle_stat(stat),
+        Ext2Command::Tree(tree) => handle_tree(tree),
+        Ext2Command::Cp(cp) => handle_cp(cp),
+        Ext2Command::Extract(extract) => handle_extract(extract),
+        Ext2Command::Shell(shell) => handle_shell(shell),
+    }
+}
+
+fn handle_create(create: Create) -> Result<(), Box<dyn Error>> {
+    if create.out.exists() && !create.force {
+        return Err(format!("{} already exists, use --force to overwrite it", create.out.display()).into());
+    }
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32;
+    let options = FormatOptions {
+        revision: create.revision.into(),
+        block_size: create.block_size,
+        inode_size: create.inode_size,
+        fsid: random_uuid(),
+        time,
+        ..Default::default()
+    };
+    let device = MemoryBlockDevice::try_new(512, vec![0_u8; create.size as usize])
+        .ok_or("the size must be a multiple of 512 bytes")?;
+    let fs = Ext2Fs::format(device, &options)?;
+    let device = fs.unmount()?;
+    fs::write(&create.out, device.data())?;
+    Ok(())
+}
+
+fn handle_check(check: Check) -> Result<(), Box<dyn Error>> {
+    let mut fs = open_image(&check.image)?;
+    let findings = fs.check()?;
+    for finding in &findings {
+        println!("pass {}: {}", finding.pass(), finding);
+    }
+    if findings.is_empty() {
+        println!("{}: no problems found", check.image.display());
+        return Ok(());
+    }
+    if !check.repair {
+        return Err(format!("{}: found {} problems", check.image.display(), findings.len()).into());
+    }
+
+    // the image is repaired in memory, so a dry run just doesn't write it back
+    let repairs = fs.repair()?;
+    let prefix = if check.dry_run { "would " } else { "" };
+    for repair in &repairs {
+        println!("{}{}", prefix, repair);
+    }
+    let remaining = fs.check()?;
+    for finding in &remaining {
+        println!("not repaired: {}", finding);
+    }
+    if check.dry_run {
+        return Err(format!("{}: found {} problems, the file was not changed", check.image.display(), findings.len()).into());
+    }
+    let device = fs.unmount()?;
+    fs::write(&check.image, device.data())?;
+    if !remaining.is_empty() {
+        return Err(format!("{}: {} problems could not be repaired", check.image.display(), remaining.len()).into());
+    }
+    println!("{}: repaired {} problems", check.image.display(), findings.len());
+    Ok(())
+}
+
+fn handle_resize(resize: Resize) -> Result<(), Box<dyn Error>> {
+    let mut data = fs::read(&resize.image)?;
+    let device = MemoryBlockDevice::try_new(512, data.clone()).ok_or("the size of the image must be a multiple of 512 bytes")?;
+    let fs = Ext2Fs::try_new(device)?;
+    let block_size = fs.superblock().block_size() as u64;
+    let num_blocks = match resize.size {
+        Some(size) => (size / block_size).min(u32::MAX as u64) as u32,
+        None => fs.min_num_blocks()?,
+    };
+
+    // the image has to be large enough before the file system can grow into it
+    let size = num_blocks as u64 * block_size;
+    if size > data.len() as u64 {
+        data.resize(size as usize, 0);
+    }
+    let device = MemoryBlockDevice::try_new(512, data).ok_or("the size must be a multiple of 512 bytes")?;
+    let mut fs = Ext2Fs::try_new(device)?;
+    fs.set_clock(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs() as u32));
+    let old_num_blocks = fs.superblock().num_blocks();
+    let num_blocks = fs.resize(num_blocks).map_err(|e| match e {
+        ext2::Error::Inconsistent => format!("{} has problems, run the check command first", resize.image.display()),
+        e => format!("unable to resize {}: {}", resize.image.display(), e),
+    })?;
+
+    let mut data = fs.unmount()?.data().clone();
+    data.truncate((num_blocks as u64 * block_size) as usize);
+    fs::write(&resize.image, data)?;
+    println!("{}: resized from {} to {} blocks of {} bytes", resize.image.display(), old_num_blocks, num_blocks, block_size);
+    Ok(())
+}
+
+fn handle_tune(tune: Tune) -> Result<(), Box<dyn Error>> {
+    let mut fs = open_image(&tune.image)?;
+    let reserved_blocks = tune
+        .reserved_percent
+        .map(|percent| (fs.superblock().num_blocks() as u64 * percent as u64 / 100) as u32)
+        .or(tune.reserved_blocks);
+    let options = TuneOptions {
+        volume_name: tune.label.map(|label| padded::<16>(&label, "label")).transpose()?,
+        fsid: tune.uuid,
+        last_mount_path: tune.last_mount_path.map(|path| padded::<64>(&path, "last mount path")).transpose()?,
+        max_mount_count: tune.max_mount_count,
+        mount_count: tune.mount_count,
+        check_interval: tune.interval,
+        error_policy: tune.errors.map(Into::into),
+        reserved_blocks,
+        reserved_uid: tune.reserved_uid,
+        reserved_gid: tune.reserved_gid,
+        features: tune.features,
+    };
+    fs.tune(&options).map_err(|e| match e {
+        ext2::Error::NotSupported => format!("unable to tune {}: not supported by the file system, or a feature that is in use", tune.image.display()),
+        ext2::Error::InvalidArgument => format!("unable to tune {}: invalid value", tune.image.display()),
+        e => format!("unable to tune {}: {}", tune.image.display(), e),
+    })?;
+    let device = fs.unmount()?;
+    fs::write(&tune.image, device.data())?;
+    Ok(())
+}
+
+fn handle_info(info: Info) -> Result<(), Box<dyn Error>> {
+    let fs = open_image(&info.image)?;
+    let superblock = fs.superblock();
+    if info.json {
+        let mut groups = Vec::new();
+        for (group_index, descriptor) in fs.block_group_descriptors().iter().enumerate() {
+            let mut group = json!({
+                "index": group_index,
+                "descriptor": descriptor,
+            });
+            if info.free_ranges {
+                group["free_blocks"] = json!(fs.free_block_ranges(group_index)?);
+                group["free_inodes"] = json!(fs.free_inode_ranges(group_index)?);
+            }
+            groups.push(group);
+        }
+        let value = json!({
+            "superblock": superblock,
+            "uuid": format_uuid(superblock.fsid().as_bytes()),
+            "volume_name": String::from_utf8_lossy(superblock.volume_name_bytes()),
+            "last_mount_path": String::from_utf8_lossy(superblock.last_mount_path_bytes()),
+            "features": superblock.feature_names(),
+            "groups": groups,
+        });
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+
+    print_superblock(superblock);
+    let block_size = superblock.block_size();
+    let num_groups = fs.block_group_descriptors().len() as u32;
+    let gdt_blocks = (num_groups * 32).div_ceil(block_size);
+    for (group_index, descriptor) in fs.block_group_descriptors().iter().enumerate() {
+        let first_block = superblock.superblock_block_number() + group_index as u32 * superblock.blocks_per_group();
+        let last_block = (first_block + superblock.blocks_per_group()).min(superblock.num_blocks()) - 1;
+        println!();
+        println!("Group {}: (Blocks {}-{})", group_index, first_block, last_block);
+        if superblock.group_has_superblock(group_index as u32) {
+            let kind = if group_index == 0 { "Primary" } else { "Backup" };
+            println!("  {} superblock at {}, Group descriptors at {}-{}", kind, first_block, first_block + 1, first_block + gdt_blocks);
+            let reserved_gdt_blocks = superblock.num_reserved_gdt_blocks() as u32;
+            if reserved_gdt_blocks > 0 {
+                println!("  Reserved GDT blocks at {}-{}", first_block + gdt_blocks + 1, first_block + gdt_blocks + reserved_gdt_blocks);
+            }
+        }
+        let inode_table_blocks = (superblock.inodes_per_group() * superblock.inode_size() as u32).div_ceil(block_size);
+        println!("  Block bitmap at {}, Inode bitmap at {}", descriptor.block_usage_bitmap_block(), descriptor.inode_usage_bitmap_block());
+        println!("  Inode table at {}-{}", descriptor.inode_table_starting_block(), descriptor.inode_table_starting_block() + inode_table_blocks - 1);
+        println!("  {} free blocks, {} free inodes, {} directories", descriptor.num_unallocated_blocks(), descriptor.num_unallocated_inodes(), descriptor.num_directories());
+        if info.free_ranges {
+            println!("  Free blocks: {}", format_ranges(&fs.free_block_ranges(group_index)?));
+            println!("  Free inodes: {}", format_ranges(&fs.free_inode_ranges(group_index)?));
+        }
+    }
+    Ok(())
+}
+
+fn handle_ls(ls: Ls) -> Result<(), Box<dyn Error>> {
+    let fs = open_image(&ls.image)?;
+    let path = ls.path.as_bytes();
+    let (inode_address, inode) = fs.resolve_path(path).map_err(|e| format!("{}: {}", ls.path.to_string_lossy(), e))?;
+    print_dir(&fs, path, inode_address, inode, ls.long, ls.all)
+}
+
+/// Prints the entries of the directory with the given path sorted by name, or the
+/// entry of the file if it is not a directory.
+fn print_dir(fs: &ImageFs, path: &[u8], inode_address: InodeAddress, inode: Inode, long: bool, all: bool) -> Result<(), Box<dyn Error>> {
+    if inode.typ() != Type::Directory {
+        let name = path.rsplit(|&b| b == b'/').next().unwrap_or_default();
+        return print_entry(fs, inode_address, inode, name, long);
+    }
+
+    let mut entries = fs.list_dir(&inode)?;
+    entries.retain(|entry| all || !matches!(entry.name_bytes(), b"." | b".."));
+    entries.sort_by(|a, b| a.name_bytes().cmp(b.name_bytes()));
+    for entry in entries {
+        let (inode_address, inode) = fs.read_inode(entry.inode())?;
+        print_entry(fs, inode_address, inode, entry.name_bytes(), long)?;
+    }
+    Ok(())
+}
+
+fn handle_cat(cat: Cat) -> Result<(), Box<dyn Error>> {
+    let fs = open_image(&cat.image)?;
+    let file = fs.open_file(cat.path.as_bytes()).map_err(|e| format!("{}: {}", cat.path.to_string_lossy(), e))?;
+    print_file(&fs, &file)
+}
+
+/// Writes the contents of the given file to stdout.
+fn print_file(fs: &ImageFs, file: &RegularFile) -> Result<(), Box<dyn Error>> {
+    let mut stdout = io::stdout().lock();
+    let mut buf = vec![0_u8; 64 * fs.superblock().block_size() as usize];
+    let mut offset = 0;
+    while offset < file.len() {
+        let read = fs.read_from_file(file, offset, &mut buf)?;
+        stdout.write_all(&buf[..read])?;
+        offset += read as u64;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn handle_stat(stat: Stat) -> Result<(), Box<dyn Error>> {
+    let fs = open_image(&stat.image)?;
+    let (inode_address, inode) = fs.resolve_path_no_follow(stat.path.as_bytes()).map_err(|e| format!("{}: {}", stat.path.to_string_lossy(), e))?;
+    print_inode(&fs, inode_address, inode)
+}
+
+fn handle_tree(tree: Tree) -> Result<(), Box<dyn Error>> {
+    let fs = open_image(&tree.image)?;
+    let dir = fs.open_dir(tree.path.as_bytes()).map_err(|e| format!("{}: {}", tree.path.to_string_lossy(), e))?;
+    println!("{}", tree.path.to_string_lossy());
+    let mut counts = (0, 0);
+    let mut visited = HashSet::from([dir.inode_address()]);
+    print_tree(&fs, &dir, "", &mut visited, &mut counts)?;
+    println!();
+    println!("{} directories, {} files", counts.0, counts.1);
+    Ok(())
+}
+
+fn handle_cp(cp: Cp) -> Result<(), Box<dyn Error>> {
+    let mut fs = open_image(&cp.image)?;
+    // zeros in the source become holes
+    fs.set_skip_zero_blocks(true);
+
+    let dest = cp.dest.as_bytes();
+    let source_name = cp.source.file_name().map(|name| name.as_bytes());
+    let (mut parent, name) = match fs.open_dir(dest) {
+        Ok(dir) => (dir, source_name.ok_or_else(|| format!("{}: can't copy into an existing directory without a name", cp.source.display()))?.to_vec()),
+        Err(ext2::Error::NotFound | ext2::Error::NotDirectory) => {
+            let (parent, name) = split_path(dest);
+            if name.is_empty() {
+                return Err(format!("{}: not a valid destination", cp.dest.to_string_lossy()).into());
+            }
+            (create_parents(&mut fs, parent)?, name.to_vec())
+        }
+        Err(e) => return Err(format!("{}: {}", cp.dest.to_string_lossy(), e).into()),
+    };
+
+    let mut copy = CopyIn {
+        uid: cp.uid,
+        gid: cp.gid,
+        links: HashMap::new(),
+    };
+    copy.copy(&mut fs, &mut parent, &name, &cp.source)?;
+    let device = fs.unmount()?;
+    fs::write(&cp.image, device.data())?;
+    Ok(())
+}
+
+/// Splits a path in the file system into the path of the parent and the last component,
+/// ignoring trailing slashes.
+fn split_path(path: &[u8]) -> (&[u8], &[u8]) {
+    let path = &path[..path.iter().rposition(|&b| b != b'/').map_or(0, |i| i + 1)];
+    match path.iter().rposition(|&b| b == b'/') {
+        Some(i) => (&path[..i], &path[i + 1..]),
+        None => (b"", path),
+    }
+}
+
+/// Opens the directory with the given path, and creates it and its parents if they
+/// don't exist, like `mkdir -p`.
+fn create_parents(fs: &mut ImageFs, path: &[u8]) -> Result<Directory, Box<dyn Error>> {
+    let mut dir = fs.read_root_inode()?;
+    let mut prefix = Vec::new();
+    for component in path.split(|&b| b == b'/').filter(|component| !component.is_empty()) {
+        prefix.push(b'/');
+        prefix.extend_from_slice(component);
+        dir = match fs.open_dir(&prefix) {
+            Ok(dir) => dir,
+            Err(ext2::Error::NotFound) => make_dir(fs, &mut dir, component)?,
+            Err(e) => return Err(format!("{}: {}", String::from_utf8_lossy(&prefix), e).into()),
+        };
+    }
+    Ok(dir)
+}
+
+/// Creates a directory with the permissions `0755`, owned by root, like `mkdir`.
+fn make_dir(fs: &mut ImageFs, parent: &mut Directory, name: &[u8]) -> Result<Directory, Box<dyn Error>> {
+    let mut dir = fs.create_dir(parent, name)?;
+    let now = now();
+    dir.inode_mut().set_perm(Permissions::from_bits_truncate(0o755));
+    dir.inode_mut().set_last_access_timestamp(now);
+    dir.inode_mut().set_last_modification_timestamp(now);
+    dir.inode_mut().set_creation_timestamp(now);
+    dir.inode_mut().set_birth_timestamp(now);
+    fs.write_inode(dir.inode_address(), dir.inode())?;
+    Ok(dir)
+}
+
+/// The state of copying files from the host into a file system.
+struct CopyIn {
+    uid: Option<u32>,
+    gid: Option<u32>,
+    /// The inodes of the files that were copied and have more than one link on the
+    /// host, by their device and inode number on the host.
+    links: HashMap<(u64, u64), InodeAddress>,
+}
+
+impl CopyIn {
+    /// Copies the given file, directory or symlink on the host to the entry with the
+    /// given name in the given directory. Existing directories are merged and existing
+    /// files are overwritten. Other existing entries can't be replaced.
+    fn copy(&mut self, fs: &mut ImageFs, parent: &mut Directory, name: &[u8], source: &Path) -> Result<(), Box<dyn Error>> {
+        let metadata = fs::symlink_metadata(source)?;
+        let file_type = metadata.file_type();
+        let existing = match fs.find_entry_by_name(parent, name)? {
+            Some(entry) => Some(fs.resolve_dir_entry(entry)?),
+            None => None,
+        };
+        let exists = || format!("{}: a different kind of file with the same name already exists", source.display());
+
+        if file_type.is_dir() {
+            let mut dir = match existing {
+                Some(inode) => Directory::try_from(inode).map_err(|_| exists())?,
+                None => fs.create_dir(parent, name)?,
+            };
+            let mut entries = fs::read_dir(source)?.collect::<Result<Vec<_>, _>>()?;
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                self.copy(fs, &mut dir, entry.file_name().as_bytes(), &entry.path())?;
+            }
+            self.set_metadata(fs, dir.inode_address(), dir.inode_mut(), &metadata, source)?;
+        } else if file_type.is_file() {
+            let key = (metadata.dev(), metadata.ino());
+            if let Some(&inode_address) = self.links.get(&key) {
+                if existing.is_some() {
+                    return Err(exists().into());
+                }
+                fs.add_entry_to_dir(parent, name, inode_address, DirType::RegularFile)?;
+                let (_, mut inode) = fs.read_inode(inode_address)?;
+                *inode.num_hard_links_mut() += 1;
+                fs.write_inode(inode_address, &inode)?;
+                return Ok(());
+            }
+
+            let mut file = match existing {
+                Some(inode) => {
+                    let mut file = RegularFile::try_from(inode).map_err(|_| exists())?;
+                    fs.truncate(&mut file, 0)?;
+                    file
+                }
+                None => fs.create_regular_file(parent, name)?,
+            };
+            let mut source_file = fs::File::open(source)?;
+            let mut buf = vec![0_u8; 1024 * 1024];
+            let mut offset = 0;
+            loop {
+                let read = source_file.read(&mut buf)?;
+                if read == 0 {
+                    break;
+                }
+                fs.write_to_file(&mut file, offset, &buf[..read])?;
+                offset += read as u64;
+            }
+            // trailing zeros are skipped, but still count for the size
+            if file.len() < offset {
+                fs.truncate(&mut file, offset)?;
+            }
+            let inode_address = file.inode_address();
+            self.set_metadata(fs, inode_address, file.inode_mut(), &metadata, source)?;
+            if metadata.nlink() > 1 {
+                self.links.insert(key, inode_address);
+            }
+        } else if file_type.is_symlink() {
+            if existing.is_some() {
+                return Err(exists().into());
+            }
+            let target = fs::read_link(source)?;
+            let mut link = fs.create_symlink(parent, name, target.as_os_str().as_bytes())?;
+            let inode_address = link.inode_address();
+            self.set_metadata(fs, inode_address, link.inode_mut(), &metadata, source)?;
+        } else {
+            eprintln!("{}: skipping special file", source.display());
+        }
+        Ok(())
+    }
+
+    /// Copies the permissions, the owner, the timestamps and the extended attributes of a file
+    /// on the host to the given inode.
+    fn set_metadata(&self, fs: &mut ImageFs, inode_address: InodeAddress, inode: &mut Inode, metadata: &Metadata, source: &Path) -> Result<(), Box<dyn Error>> {
+        inode.set_perm(Permissions::from_bits_truncate(metadata.mode() as u16 & 0o7777));
+        inode.set_user_id(self.uid.unwrap_or(metadata.uid()));
+        inode.set_group_id(self.gid.unwrap_or(metadata.gid()));
+        inode.set_last_access_timestamp(Timestamp { seconds: metadata.atime(), nanoseconds: metadata.atime_nsec() as u32 });
+        inode.set_last_modification_timestamp(Timestamp { seconds: metadata.mtime(), nanoseconds: metadata.mtime_nsec() as u32 });
+        inode.set_creation_timestamp(Timestamp { seconds: metadata.ctime(), nanoseconds: metadata.ctime_nsec() as u32 });
+        if inode.birth_timestamp().is_some_and(|birth| birth.seconds == 0) {
+            inode.set_birth_timestamp(now());
+        }
+        fs.write_inode(inode_address, inode)?;
+        copy_xattrs(fs, inode_address, inode, source)
+    }
+}
+
+/// Copies the `security.*` and `user.*` extended attributes of a file on the host to the
+/// given inode, so that SELinux labels are kept. Symlinks are not followed. Attributes in
+/// other namespaces, like ACLs, are skipped with a warning.
+fn copy_xattrs(fs: &mut ImageFs, inode_address: InodeAddress, inode: &mut Inode, source: &Path) -> Result<(), Box<dyn Error>> {
+    let names = match xattr::list(source) {
+        Ok(names) => names,
+        // a host file system without extended attributes has none to copy
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => return Ok(()),
+        Err(e) => return Err(format!("{}: {}", source.display(), e).into()),
+    };
+    for name in names {
+        let Some(name) = name.to_str().filter(|name| name.starts_with("security.") || name.starts_with("user.")) else {
+            eprintln!("{}: skipping extended attribute {}", source.display(), name.to_string_lossy());
+            continue;
+        };
+        let Some(value) = xattr::get(source, name)? else {
+            continue;
+        };
+        match fs.set_xattr(inode_address, inode, name, &value) {
+            // revision 0 file systems have no extended attributes at all
+            Err(ext2::Error::NotSupported) => {
+                eprintln!("{}: skipping extended attributes, the file system doesn't support them", source.display());
+                break;
+            }
+            result => result.map_err(|e| format!("{}: {}: {}", source.display(), name, e))?,
+        }
+    }
+    Ok(())
+}
+
+fn handle_extract(extract: Extract) -> Result<(), Box<dyn Error>> {
+    let fs = open_image(&extract.image)?;
+    let path = extract.path.as_bytes();
+    let (inode_address, inode) = fs.resolve_path_no_follow(path).map_err(|e| format!("{}: {}", extract.path.to_string_lossy(), e))?;
+    fs::create_dir_all(&extract.dest)?;
+
+    let mut copy = CopyOut {
+        links: HashMap::new(),
+        visited: HashSet::new(),
+    };
+    let (_, name) = split_path(path);
+    let dest = if name.is_empty() { extract.dest.clone() } else { extract.dest.join(OsStr::from_bytes(name)) };
+    copy.copy(&fs, inode_address, inode, &dest)
+}
+
+fn handle_shell(shell: Shell) -> Result<(), Box<dyn Error>> {
+    let fs = open_image(&shell.image)?;
+    let mut session = Session::new(fs, shell.write);
+    if shell.command.is_empty() {
+        session.run(io::stdin().lock())?;
+    } else {
+        for command in &shell.command {
+            if !session.run_command(command.as_bytes()) {
+                break;
+            }
+        }
+    }
+
+    let failed = session.failed_commands();
+    if let Some(fs) = session.into_changed_fs() {
+        let device = fs.unmount()?;
+        fs::write(&shell.image, device.data())?;
+    }
+    if !shell.command.is_empty() && failed > 0 {
+        return Err(format!("{} commands failed", failed).into());
+    }
+    Ok(())
+}
+
+/// The state of copying files from a file system to the host.
+struct CopyOut {
+    /// The paths on the host of the inodes with more than one link that were copied.
+    links: HashMap<InodeAddress, PathBuf>,
+    /// The directories that were copied, to not copy a directory into itself in
+    /// corrupted file systems.
+    visited: HashSet<InodeAddress>,
+}
+
+impl CopyOut {
+    /// Copies the given inode to the given path on the host, including the contents
+    /// of directories. Directories are merged with existing directories, other files
+    /// replace existing files. The timestamps of symlinks are not copied, since the
+    /// standard library can't set them.
+    fn copy(&mut self, fs: &ImageFs, inode_address: InodeAddress, inode: Inode, dest: &Path) -> Result<(), Box<dyn Error>> {
+        let typ = inode.typ();
+        if typ != Type::Directory && inode.num_hard_links() > 1 {
+            if let Some(existing) = self.links.get(&inode_address) {
+                remove_non_dir(dest)?;
+                fs::hard_link(existing, dest)?;
+                return Ok(());
+            }
+            self.links.insert(inode_address, dest.to_path_buf());
+        }
+
+        match typ {
+            Type::Directory => {
+                if !self.visited.insert(inode_address) {
+                    return Ok(());
+                }
+                // a symlink to a directory is replaced, so that nothing is written outside of the destination
+                if !fs::symlink_metadata(dest).is_ok_and(|metadata| metadata.is_dir()) {
+                    remove_non_dir(dest)?;
+                    fs::create_dir(dest)?;
+                }
+                for entry in fs.list_dir(&inode)? {
+                    if matches!(entry.name_bytes(), b"." | b"..") {
+                        continue;
+                    }
+                    let (child_address, child) = fs.read_inode(entry.inode())?;
+                    self.copy(fs, child_address, child, &dest.join(OsStr::from_bytes(entry.name_bytes())))?;
+                }
+            }
+            Type::RegularFile => {
+                remove_non_dir(dest)?;
+                let file = RegularFile::try_from((inode_address, inode)).unwrap(); // we checked the type
+                let mut out = fs::File::create(dest)?;
+                // only the data is written, so that holes stay holes
+                let mut buf = vec![0_u8; 1024 * 1024];
+                let mut offset = 0;
+                while let Some(data) = fs.seek_data(&file, offset)? {
+                    let hole = fs.seek_hole(&file, data)?.unwrap_or(file.len());
+                    out.seek(SeekFrom::Start(data))?;
+                    let mut position = data;
+                    while position < hole {
+                        let len = buf.len().min((hole - position) as usize);
+                        let read = fs.read_from_file(&file, position, &mut buf[..len])?;
+                        out.write_all(&buf[..read])?;
+                        position += read as u64;
+                    }
+                    offset = hole;
+                }
+                out.set_len(file.len())?;
+                set_host_metadata(dest, file.inode(), Some(&out))?;
+                return Ok(());
+            }
+            Type::SymLink => {
+                remove_non_dir(dest)?;
+                let link = SymLink::try_from((inode_address, inode)).unwrap(); // we checked the type
+                symlink(OsStr::from_bytes(&fs.read_link(&link)?), dest)?;
+                return set_host_owner(dest, link.inode());
+            }
+            _ => {
+                eprintln!("{}: skipping special file", dest.display());
+                return Ok(());
+            }
+        }
+
+        // the times of directories are set after their contents are copied
+        let dir = fs::File::open(dest)?;
+        set_host_metadata(dest, &inode, Some(&dir))
+    }
+}
+
+/// Removes the file or symlink at the given path on the host, if there is one.
+fn remove_non_dir(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.is_dir() => fs::remove_file(path),
+        _ => Ok(()),
+    }
+}
+
+/// Sets the owner of the given path on the host to the owner of the inode. Changing
+/// the owner needs root privileges, so it is silently skipped without them.
+fn set_host_owner(path: &Path, inode: &Inode) -> Result<(), Box<dyn Error>> {
+    match lchown(path, Some(inode.user_id()), Some(inode.group_id())) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// Copies the owner, permissions and timestamps of the inode to the given path on the host.
+fn set_host_metadata(path: &Path, inode: &Inode, file: Option<&fs::File>) -> Result<(), Box<dyn Error>> {
+    // changing the owner clears the set-user-ID and set-group-ID bits, so it comes first
+    set_host_owner(path, inode)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(inode.perm().bits() as u32))?;
+    let system_time = |timestamp: Timestamp| match u64::try_from(timestamp.seconds) {
+        Ok(seconds) => UNIX_EPOCH + Duration::new(seconds, timestamp.nanoseconds),
+        Err(_) => UNIX_EPOCH - Duration::new(timestamp.seconds.unsigned_abs(), 0) + Duration::new(0, timestamp.nanoseconds),
+    };
+    let times = FileTimes::new()
+        .set_accessed(system_time(inode.last_access_timestamp()))
+        .set_modified(system_time(inode.last_modification_timestamp()));
+    if let Some(file) = file {
+        file.set_times(times)?;
+    }
+    Ok(())
+}
+
+/// The current time as a timestamp.
+fn now() -> Timestamp {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Timestamp { seconds: time.as_secs() as i64, nanoseconds: time.subsec_nanos() }
+}
+
+/// Prints the entries of the given directory and its subdirectories, indented by the
+/// given prefix. Directories that were already printed are not entered again, which
+/// only happens in corrupted file systems.
+fn print_tree(fs: &ImageFs, dir: &Inode, prefix: &str, visited: &mut HashSet<InodeAddress>, counts: &mut (usize, usize)) -> Result<(), Box<dyn Error>> {
+    let mut entries = fs.list_dir(dir)?;
+    entries.retain(|entry| !matches!(entry.name_bytes(), b"." | b".."));
+    entries.sort_by(|a, b| a.name_bytes().cmp(b.name_bytes()));
+    for (i, entry) in entries.iter().enumerate() {
+        let last = i == entries.len() - 1;
+        let (inode_address, inode) = fs.read_inode(entry.inode())?;
+        let mut line = format!("{}{}{}", prefix, if last { "└── " } else { "├── " }, entry.name_lossy());
+        if inode.typ() == Type::SymLink {
+            let link = SymLink::try_from((inode_address, inode)).unwrap(); // we checked the type
+            line = format!("{} -> {}", line, String::from_utf8_lossy(&fs.read_link(&link)?));
+            println!("{}", line);
+            counts.1 += 1;
+            continue;
+        }
+        println!("{}", line);
+        if inode.typ() != Type::Directory {
+            counts.1 += 1;
+            continue;
+        }
+        counts.0 += 1;
+        if visited.insert(inode_address) {
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            print_tree(fs, &inode, &prefix, visited, counts)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the name of a directory entry, or a line like `ls -il` if `long` is set.
+fn print_entry(fs: &ImageFs, inode_address: InodeAddress, inode: Inode, name: &[u8], long: bool) -> Result<(), Box<dyn Error>> {
+    let name = String::from_utf8_lossy(name);
+    if !long {
+        println!("{}", name);
+        return Ok(());
+    }
+    let mut line = format!(
+        "{:>7} {} {:>3} {:>5} {:>5} {:>10} {} {}",
+        inode_address.get(),
+        format_mode(&inode),
+        inode.num_hard_links(),
+        inode.user_id(),
+        inode.group_id(),
+        inode.len(),
+        format_time(inode.last_modification_time() as i64),
+        name,
+    );
+    if inode.typ() == Type::SymLink {
+        let link = SymLink::try_from((inode_address, inode)).unwrap(); // we checked the type
+        line = format!("{} -> {}", line, String::from_utf8_lossy(&fs.read_link(&link)?));
+    }
+    println!("{}", line);
+    Ok(())
+}
+
+/// Prints every field of the given inode and the blocks that it uses, like `debugfs stat`.
+fn print_inode(fs: &ImageFs, inode_address: InodeAddress, inode: Inode) -> Result<(), Box<dyn Error>> {
+    let typ = match inode.typ() {
+        Type::RegularFile => "regular",
+        Type::Directory => "directory",
+        Type::SymLink => "symlink",
+        Type::CharacterDevice => "character special",
+        Type::BlockDevice => "block special",
+        Type::FIFO => "FIFO",
+        Type::UnixSocket => "socket",
+        _ => "bad type",
+    };
+    println!("Inode: {}   Type: {}   Mode: {:04o}   Flags: {:#x}", inode_address.get(), typ, inode.perm().bits(), inode.flags().bits());
+    println!("Generation: {}   Links: {}   Blockcount: {}", inode.generation(), inode.num_hard_links(), inode.num_disk_sectors());
+    println!("User: {}   Group: {}   Size: {}", inode.user_id(), inode.group_id(), inode.len());
+    println!("File ACL: {}   Fragment address: {}", inode.extended_attribute_block().map_or(0, |block| block.get()), inode.fragment_block_address());
+    let (os_val_1, os_val_2) = inode.os_dependent_values();
+    println!("OS dependent values: {:02x?} {:02x?}", os_val_1, os_val_2);
+    println!(" ctime: {}", format_timestamp(inode.creation_timestamp()));
+    println!(" atime: {}", format_timestamp(inode.last_access_timestamp()));
+    println!(" mtime: {}", format_timestamp(inode.last_modification_timestamp()));
+    if let Some(birth) = inode.birth_timestamp() {
+        println!("crtime: {}", format_timestamp(birth));
+    }
+    if inode.deletion_time() != 0 {
+        println!(" dtime: {}", format_time(inode.deletion_time() as i64));
+    }
+    if inode.extra_isize() != 0 {
+        println!("Size of extra inode fields: {}", inode.extra_isize());
+    }
+    let xattrs = fs.list_xattr(&inode)?;
+    if !xattrs.is_empty() {
+        println!("Extended attributes:");
+        for name in xattrs {
+            let value = fs.get_xattr(&inode, &name)?.unwrap_or_default();
+            println!("  {} ({} bytes)", name, value.len());
+        }
+    }
+
+    if inode.typ() == Type::SymLink && fs.is_fast_symlink(&inode) {
+        let link = SymLink::try_from((inode_address, inode)).unwrap(); // we checked the type
+        println!("Fast link dest: \"{}\"", String::from_utf8_lossy(&fs.read_link(&link)?));
+        return Ok(());
+    }
+    // consecutive data blocks are merged into runs, like `debugfs` does
+    let mut entries: Vec<String> = Vec::new();
+    let mut run: Option<(u64, u64, u32)> = None;
+    let mut total = 0;
+    let format_run = |(first_index, last_index, first_block): (u64, u64, u32)| {
+        if first_index == last_index {
+            format!("({}):{}", first_index, first_block)
+        } else {
+            format!("({}-{}):{}-{}", first_index, last_index, first_block, first_block as u64 + last_index - first_index)
+        }
+    };
+    for (block, usage) in fs.inode_blocks(&inode)? {
+        total += 1;
+        let block = block.get();
+        match (usage, &mut run) {
+            (BlockUse::Data(index), Some((first_index, last_index, first_block))) if index == *last_index + 1 && block as u64 == *first_block as u64 + index - *first_index => {
+                *last_index = index;
+            }
+            (BlockUse::Data(index), _) => {
+                entries.extend(run.replace((index, index, block)).map(format_run));
+            }
+            (BlockUse::Indirect(level), _) => {
+                entries.extend(run.take().map(format_run));
+                entries.push(format!("({}):{}", ["IND", "DIND", "TIND"][level as usize - 1], block));
+            }
+            (BlockUse::Xattr, _) => {}
+        }
+    }
+    entries.extend(run.map(format_run));
+    println!("BLOCKS:");
+    println!("{}", entries.join(", "));
+    println!("TOTAL: {}", total);
+    Ok(())
+}
+
+/// Formats the type and permissions of an inode like `ls -l`, e.g. `drwxr-xr-x`.
+fn format_mode(inode: &Inode) -> String {
+    let typ = match inode.typ() {
+        Type::Directory => 'd',
+        Type::SymLink => 'l',
+        Type::CharacterDevice => 'c',
+        Type::BlockDevice => 'b',
+        Type::FIFO => 'p',
+        Type::UnixSocket => 's',
+        _ => '-',
+    };
+    let perm = inode.perm();
+    let special = |exec: Permissions, special: Permissions, set: char, unset: char| match (perm.contains(exec), perm.contains(special)) {
+        (true, true) => set,
+        (false, true) => unset,
+        (true, false) => 'x',
+        (false, false) => '-',
+    };
+    let bit = |permission: Permissions, c: char| if perm.contains(permission) { c } else { '-' };
+    [
+        typ,
+        bit(Permissions::UserRead, 'r'),
+        bit(Permissions::UserWrite, 'w'),
+        special(Permissions::UserExec, Permissions::SetUID, 's', 'S'),
+        bit(Permissions::GroupRead, 'r'),
+        bit(Permissions::GroupWrite, 'w'),
+        special(Permissions::GroupExec, Permissions::SetGID, 's', 'S'),
+        bit(Permissions::OtherRead, 'r'),
+        bit(Permissions::OtherWrite, 'w'),
+        special(Permissions::OtherExec, Permissions::Sticky, 't', 'T'),
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// Prints every field of the superblock, with the names that `dumpe2fs` uses.
+fn print_superblock(superblock: &Superblock) {
+    let field = |name: &str, value: &dyn Display| println!("{:<32}{}", format!("{}:", name), value);
+    let volume_name = superblock.volume_name_bytes();
+    field("Filesystem volume name", &if volume_name.is_empty() { "<none>".into() } else { String::from_utf8_lossy(volume_name) });
+    let last_mount_path = superblock.last_mount_path_bytes();
+    field("Last mounted on", &if last_mount_path.is_empty() { "<not available>".into() } else { String::from_utf8_lossy(last_mount_path) });
+    field("Filesystem UUID", &format_uuid(superblock.fsid().as_bytes()));
+    field("Filesystem magic number", &format!("{:#06X}", superblock.magic_number()));
+    let revision = match superblock.revision() {
+        Revision::GoodOld => "(original)",
+        Revision::Dynamic => "(dynamic)",
+    };
+    field("Filesystem revision #", &format!("{} {}", superblock.version_major(), revision));
+    field("Filesystem minor revision", &superblock.version_minor());
+    field("Filesystem features", &or_none(superblock.feature_names().join(" ")));
+    let optional = superblock.optional_features().bits();
+    let required = superblock.required_features().bits();
+    let read_only = superblock.write_required_features().bits();
+    field("Feature bits", &format!("compat {:#x}, incompat {:#x}, ro_compat {:#x}", optional, required, read_only));
+    let flags = superblock.flags();
+    let flag_names = [
+        (SuperblockFlags::SIGNED_HASH, "signed_directory_hash"),
+        (SuperblockFlags::UNSIGNED_HASH, "unsigned_directory_hash"),
+        (SuperblockFlags::TEST_FILESYSTEM, "test_filesystem"),
+    ];
+    field("Filesystem flags", &or_none(flag_names.iter().filter(|(flag, _)| flags.contains(*flag)).map(|(_, name)| *name).collect::<Vec<_>>().join(" ")));
+    field("Default mount options", &format!("{:#x}", superblock.default_mount_options()));
+    let state = superblock.state();
+    field("Filesystem state", &match (state.contains(State::CLEAN), state.contains(State::ERRONOUS)) {
+        (true, false) => "clean",
+        (true, true) => "clean with errors",
+        (false, false) => "not clean",
+        (false, true) => "not clean with errors",
+    });
+    field("Errors behavior", &match superblock.error_policy().bits() {
+        1 => "Continue",
+        2 => "Remount read-only",
+        3 => "Panic",
+        _ => "Unknown (continue)",
+    });
+    field("Filesystem OS type", &match superblock.os_id() {
+        0 => "Linux".to_string(),
+        1 => "Hurd".to_string(),
+        2 => "Masix".to_string(),
+        3 => "FreeBSD".to_string(),
+        4 => "Lites".to_string(),
+        os => format!("(unknown os {})", os),
+    });
+    field("Inode count", &superblock.num_inodes());
+    field("Block count", &superblock.num_blocks());
+    field("Reserved block count", &superblock.num_superuser_reserved_blocks());
+    let (blocks_upper, reserved_upper, free_upper) = superblock.num_blocks_upper();
+    field("Block counts (upper 32 bits)", &format!("{} blocks, {} reserved, {} free", blocks_upper, reserved_upper, free_upper));
+    field("Free blocks", &superblock.num_unallocated_blocks());
+    field("Free inodes", &superblock.num_unallocated_inodes());
+    field("First block", &superblock.superblock_block_number());
+    field("Block size", &superblock.block_size());
+    field("Fragment size", &superblock.fragment_size());
+    field("Reserved GDT blocks", &superblock.num_reserved_gdt_blocks());
+    field("Group descriptor size", &superblock.group_descriptor_size());
+    field("First meta block group", &superblock.first_meta_block_group());
+    field("Blocks per group", &superblock.blocks_per_group());
+    field("Fragments per group", &superblock.fragments_per_group());
+    field("Inodes per group", &superblock.inodes_per_group());
+    field("Inode blocks per group", &(superblock.inodes_per_group() * superblock.inode_size() as u32).div_ceil(superblock.block_size()));
+    field("This superblock's group", &superblock.this_superblock_block_group());
+    field("Filesystem created", &format_time(superblock.creation_time() as i64));
+    field("Last mount time", &format_time(superblock.last_mount_time() as i64));
+    field("Last write time", &format_time(superblock.last_written_time() as i64));
+    field("Mount count", &superblock.mounts_since_fsck());
+    field("Maximum mount count", &(superblock.mounts_allowed_before_fsck() as i16));
+    field("Last checked", &format_time(superblock.last_fsck() as i64));
+    field("Check interval", &superblock.fsck_force_interval());
+    field("Reserved blocks uid", &superblock.uid_for_reserved_blocks());
+    field("Reserved blocks gid", &superblock.gid_for_reserved_blocks());
+    field("First inode", &superblock.first_non_reserved_inode());
+    field("Inode size", &superblock.inode_size());
+    field("Required extra isize", &superblock.min_extra_inode_size());
+    field("Desired extra isize", &superblock.want_extra_inode_size());
+    field("Compression algorithms", &format!("{:#x}", superblock.compression()));
+    field("Preallocated file blocks", &superblock.num_preallocate_blocks_file());
+    field("Preallocated directory blocks", &superblock.num_preallocate_blocks_directory());
+    field("Journal UUID", &format_uuid(superblock.journal_id()));
+    field("Journal inode", &superblock.journal_inode());
+    field("Journal device", &format!("{:#06x}", superblock.journal_device()));
+    field("Journal backup type", &superblock.journal_backup_type());
+    field("Journal backup", &superblock.journal_blocks().iter().map(u32::to_string).collect::<Vec<_>>().join(" "));
+    field("First orphan inode", &superblock.orphan_inode_list_head());
+    field("Default directory hash", &match superblock.default_hash_version() {
+        Some(HashVersion::Legacy | HashVersion::LegacyUnsigned) => "legacy",
+        Some(HashVersion::HalfMd4 | HashVersion::HalfMd4Unsigned) => "half_md4",
+        Some(HashVersion::Tea | HashVersion::TeaUnsigned) => "tea",
+        None => "unknown",
+    });
+    let hash_seed = superblock.hash_seed().iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>();
+    field("Directory Hash Seed", &format_uuid(&hash_seed.try_into().unwrap()));
+}
+
+/// Formats ranges of blocks or inodes like `dumpe2fs`, e.g. `3-7, 9`.
+fn format_ranges(ranges: &[Range<u32>]) -> String {
+    let ranges = ranges
+        .iter()
+        .map(|range| if range.len() == 1 { range.start.to_string() } else { format!("{}-{}", range.start, range.end - 1) })
+        .collect::<Vec<_>>();
+    ranges.join(", ")
+}
+
+fn format_uuid(uuid: &[u8; 16]) -> String {
+    if uuid == &[0; 16] {
+        return "<none>".to_string();
+    }
+    let hex = uuid.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Formats a timestamp with nanoseconds, like `format_time`.
+fn format_timestamp(timestamp: Timestamp) -> String {
+    if timestamp.nanoseconds == 0 {
+        return format_time(timestamp.seconds);
+    }
+    format!("{} ({}.{:09})", format_time(timestamp.seconds), timestamp.seconds, timestamp.nanoseconds)
+}
+
+/// Formats seconds since the unix epoch as a UTC date and time, or `n/a` for `0`.
+fn format_time(seconds: i64) -> String {
+    if seconds == 0 {
+        return "n/a".to_string();
+    }
+    // the conversion from days to a civil date by Howard Hinnant
+    let days = seconds.div_euclid(86400);
+    let time = seconds.rem_euclid(86400);
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+fn or_none(s: String) -> String {
+    if s.is_empty() {
+        "(none)".to_string()
+    } else {
+        s
+    }
+}
+
+/// Pads the given string with NUL bytes to a field of the superblock.
+fn padded<const N: usize>(s: &str, what: &str) -> Result<[u8; N], String> {
+    if s.len() > N {
+        return Err(format!("the {} must be at most {} bytes", what, N));
+    }
+    let mut field = [0_u8; N];
+    field[..s.len()].copy_from_slice(s.as_bytes());
+    Ok(field)
+}
+
+/// Reads the file system in the given file into memory.
+fn open_image(path: &Path) -> Result<ImageFs, Box<dyn Error>> {
+    let data = fs::read(path)?;
+    let device = MemoryBlockDevice::try_new(512, data).ok_or("the size of the image must be a multiple of 512 bytes")?;
+    let mut fs = Ext2Fs::try_new(device)?;
+    fs.set_clock(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs() as u32));
+    Ok(fs)
+}
+
+/// Parses a size in bytes, with an optional binary suffix like `K`, `KB` or `KiB`.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, suffix) = s.split_at(digits);
+    let number = number.parse::<u64>().map_err(|e| format!("invalid size '{}': {}", s, e))?;
+    let factor = match suffix.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return Err(format!("invalid size suffix '{}'", suffix)),
+    };
+    number.checked_mul(factor).ok_or_else(|| format!("size '{}' is too large", s))
+}
+
+/// Parses a UUID with or without dashes, or `random` for a new random UUID, or `clear`
+/// for the zero UUID.
+fn parse_uuid(s: &str) -> Result<[u8; 16], String> {
+    match s {
+        "random" => return Ok(random_uuid()),
+        "clear" => return Ok([0; 16]),
+        _ => {}
+    }
+    let digits = s.chars().filter(|&c| c != '-').collect::<Vec<_>>();
+    if digits.len() != 32 {
+        return Err(format!("invalid UUID '{}'", s));
+    }
+    let mut uuid = [0_u8; 16];
+    for (byte, pair) in uuid.iter_mut().zip(digits.chunks(2)) {
+        let pair = pair.iter().collect::<String>();
+        *byte = u8::from_str_radix(&pair, 16).map_err(|_| format!("invalid UUID '{}'", s))?;
+    }
+    Ok(uuid)
+}
+
+/// Parses a maximum mount count, where `0` and `-1` disable checks because of mounts.
+fn parse_max_mount_count(s: &str) -> Result<u16, String> {
+    match s.parse::<i32>().map_err(|e| format!("invalid mount count '{}': {}", s, e))? {
+        -1 | 0 => Ok(u16::MAX),
+        count @ 1..=16000 => Ok(count as u16),
+        _ => Err(format!("invalid mount count '{}', must be between -1 and 16000", s)),
+    }
+}
+
+/// Parses a time interval in seconds, from a number with an optional suffix `d` for
+/// days, `w` for weeks or `m` for months. Numbers without a suffix are days.
+fn parse_interval(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, suffix) = s.split_at(digits);
+    let number = number.parse::<u32>().map_err(|e| format!("invalid interval '{}': {}", s, e))?;
+    let factor = match suffix {
+        "" | "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "m" => 30 * 24 * 60 * 60,
+        _ => return Err(format!("invalid interval suffix '{}'", suffix)),
+    };
+    number.checked_mul(factor).ok_or_else(|| format!("interval '{}' is too large", s))
+}
+
+/// Parses a feature name, with a leading `^` if it should be disabled.
+fn parse_feature(s: &str) -> Result<(Feature, bool), String> {
+    let (name, enable) = match s.strip_prefix('^') {
+        Some(name) => (name, false),
+        None => (s, true),
+    };
+    let names = Feature::ALL.iter().map(|feature| feature.name()).collect::<Vec<_>>().join(", ");
+    let feature = Feature::from_name(name).ok_or_else(|| format!("unknown feature '{}', must be one of {}", name, names))?;
+    Ok((feature, enable))
+}
+
+/// Generates a random version 4 UUID, from the random keys of the standard library's hasher.
+fn random_uuid() -> [u8; 16] {
+    let mut uuid = [0_u8; 16];
+    for chunk in uuid.chunks_mut(8) {
+        let random = RandomState::new().build_hasher().finish();
+        chunk.copy_from_slice(&random.to_le_bytes());
+    }
+    uuid[6] = (uuid[6] & 0x0F) | 0x40;
+    uuid[8] = (uuid[8] & 0x3F) | 0x80;
+    uuid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(Ok(1024), parse_size("1024"));
+        assert_eq!(Ok(512 * 1024), parse_size("512K"));
+        assert_eq!(Ok(1024 * 1024), parse_size("1MB"));
+        assert_eq!(Ok(2 * 1024 * 1024 * 1024), parse_size("2GiB"));
+        assert!(parse_size("1TB").is_err());
+        assert!(parse_size("MB").is_err());
+    }
+
+    #[test]
+    fn test_parse_uuid() {
+        let uuid = [0x77, 0x4b, 0x94, 0xce, 0x3d, 0x05, 0x4b, 0x71, 0x98, 0xfc, 0xfc, 0xf6, 0x37, 0xfd, 0x2b, 0x48];
+        assert_eq!(Ok(uuid), parse_uuid("774b94ce-3d05-4b71-98fc-fcf637fd2b48"));
+        assert_eq!(Ok(uuid), parse_uuid("774b94ce3d054b7198fcfcf637fd2b48"));
+        assert_eq!(Ok([0; 16]), parse_uuid("clear"));
+        assert!(parse_uuid("774b94ce-3d05-4b71-98fc").is_err());
+        assert!(parse_uuid("774b94ce-3d05-4b71-98fc-fcf637fd2bxx").is_err());
+    }
+
+    #[test]
+    fn test_format_ranges() {
+        assert_eq!("", format_ranges(&[]));
+        assert_eq!("3-7, 9", format_ranges(&[3..8, 9..10]));
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!("n/a", format_time(0));
+        assert_eq!("1970-01-01 00:00:01 UTC", format_time(1));
+        assert_eq!("2023-06-08 17:46:40 UTC", format_time(1686246400));
+        assert_eq!("1969-12-31 23:59:59 UTC", format_time(-1));
+    }
+
+    #[test]
+    fn test_copy_in_and_out() {
+        let host = std::env::temp_dir().join(format!("mkfs-copy-{}", std::process::id()));
+        let source = host.join("source");
+        fs::create_dir_all(source.join("dir/sub")).unwrap();
+        fs::write(source.join("dir/file"), b"data").unwrap();
+        fs::hard_link(source.join("dir/file"), source.join("link")).unwrap();
+        symlink("dir/file", source.join("symlink")).unwrap();
+        fs::write(source.join(OsStr::from_bytes(b"\xFFname")), b"").unwrap();
+        fs::set_permissions(source.join("dir/sub"), fs::Permissions::from_mode(0o700)).unwrap();
+
+        let device = MemoryBlockDevice::try_new(512, vec![0_u8; 4 * 1024 * 1024]).unwrap();
+        let mut fs = Ext2Fs::format(device, &FormatOptions::default()).unwrap();
+        let mut parent = create_parents(&mut fs, b"/a/b").unwrap();
+        let mut copy = CopyIn { uid: Some(0), gid: Some(0), links: HashMap::new() };
+        copy.copy(&mut fs, &mut parent, b"copy", &source).unwrap();
+        assert!(fs.check().unwrap().is_empty());
+        assert_eq!(2, fs.open_file("/a/b/copy/link").unwrap().num_hard_links());
+        assert_eq!(0o700, fs.open_dir("/a/b/copy/dir/sub").unwrap().perm().bits());
+
+        let dest = host.join("dest");
+        let (inode_address, inode) = fs.resolve_path_no_follow("/a/b/copy").unwrap();
+        let mut copy = CopyOut { links: HashMap::new(), visited: HashSet::new() };
+        copy.copy(&fs, inode_address, inode, &dest).unwrap();
+        assert_eq!(b"data", fs::read(dest.join("link")).unwrap().as_slice());
+        assert_eq!(fs::metadata(dest.join("link")).unwrap().ino(), fs::metadata(dest.join("dir/file")).unwrap().ino());
+        assert_eq!(Path::new("dir/file"), fs::read_link(dest.join("symlink")).unwrap());
+        assert!(dest.join(OsStr::from_bytes(b"\xFFname")).exists());
+        assert_eq!(0o700, fs::metadata(dest.join("dir/sub")).unwrap().mode() & 0o7777);
+        fs::remove_dir_all(host).unwrap();
+    }
+
+    #[test]
+    fn test_copy_in_xattrs() {
+        let host = std::env::temp_dir().join(format!("mkfs-xattr-{}", std::process::id()));
+        fs::create_dir_all(&host).unwrap();
+        let source = host.join("file");
+        fs::write(&source, b"data").unwrap();
+        if let Err(e) = xattr::set(&source, "user.test", b"value") {
+            // the file system of the temporary directory doesn't support user attributes
+            fs::remove_dir_all(host).unwrap();
+            assert_eq!(io::ErrorKind::Unsupported, e.kind());
+            return;
+        }
+
+        let device = MemoryBlockDevice::try_new(512, vec![0_u8; 4 * 1024 * 1024]).unwrap();
+        let mut fs = Ext2Fs::format(device, &FormatOptions::default()).unwrap();
+        let mut root = fs.read_root_inode().unwrap();
+        let mut copy = CopyIn { uid: Some(0), gid: Some(0), links: HashMap::new() };
+        copy.copy(&mut fs, &mut root, b"file", &source).unwrap();
+        fs::remove_dir_all(host).unwrap();
+
+        let file = fs.open_file("/file").unwrap();
+        assert_eq!(Some(b"value".to_vec()), fs.get_xattr(&file, "user.test").unwrap());
+        assert!(fs.check().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_copy_in_xattrs_rev0() {
+        let host = std::env::temp_dir().join(format!("mkfs-xattr-rev0-{}", std::process::id()));
+        fs::create_dir_all(&host).unwrap();
+        let source = host.join("file");
+        fs::write(&source, b"data").unwrap();
+        if let Err(e) = xattr::set(&source, "user.test", b"value") {
+            // the file system of the temporary directory doesn't support user attributes
+            fs::remove_dir_all(host).unwrap();
+            assert_eq!(io::ErrorKind::Unsupported, e.kind());
+            return;
+        }
+
+        let device = MemoryBlockDevice::try_new(512, vec![0_u8; 4 * 1024 * 1024]).unwrap();
+        let options = FormatOptions { revision: Revision::GoodOld, ..FormatOptions::default() };
+        let mut fs = Ext2Fs::format(device, &options).unwrap();
+        let mut root = fs.read_root_inode().unwrap();
+        let mut copy = CopyIn { uid: Some(0), gid: Some(0), links: HashMap::new() };
+        copy.copy(&mut fs, &mut root, b"file", &source).unwrap();
+        fs::remove_dir_all(host).unwrap();
+
+        // the file is copied without its attributes
+        let file = fs.open_file("/file").unwrap();
+        assert_eq!(None, file.extended_attribute_block());
+        assert!(fs.check().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!((&b"/etc"[..], &b"hosts"[..]), split_path(b"/etc/hosts"));
+        assert_eq!((&b"/etc"[..], &b"ssh"[..]), split_path(b"/etc/ssh/"));
+        assert_eq!((&b""[..], &b"file"[..]), split_path(b"/file"));
+        assert_eq!((&b""[..], &b"file"[..]), split_path(b"file"));
+        assert_eq!((&b""[..], &b""[..]), split_path(b"/"));
+    }
+
+    #[test]
+    fn test_format_mode() {
+        let mut inode = Inode::new(Type::Directory);
+        inode.set_perm(Permissions::from_bits_truncate(0o1755));
+        assert_eq!("drwxr-xr-t", format_mode(&inode));
+        let mut inode = Inode::new(Type::RegularFile);
+        inode.set_perm(Permissions::from_bits_truncate(0o4640));
+        assert_eq!("-rwSr-----", format_mode(&inode));
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(Ok(86400), parse_interval("1"));
+        assert_eq!(Ok(2 * 7 * 86400), parse_interval("2w"));
+        assert_eq!(Ok(6 * 30 * 86400), parse_interval("6m"));
+        assert!(parse_interval("1y").is_err());
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::OsStr;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use ext2::{BlockAddress, BlockUse, Flags, Inode, InodeAddress, Permissions, RegularFile, Timestamp, Type};
+
+use super::{make_dir, print_dir, print_file, print_inode, split_path, CopyIn, CopyOut, ImageFs};
+
+/// The commands of the shell, with their arguments and a description, for `help`.
+const COMMANDS: &[(&str, &str, &str)] = &[
+    ("cd", "[DIR]", "Change the current directory, to the root directory without an argument"),
+    ("pwd", "", "Print the current directory"),
+    ("ls", "[-l] [-a] [PATH]", "List a directory, with the inode, mode, links, owner, size and time of every entry with -l"),
+    ("cat", "FILE", "Print the contents of a file"),
+    ("stat", "PATH", "Print the fields and the blocks of an inode"),
+    ("blocks", "PATH", "Print the data blocks of an inode"),
+    ("icheck", "BLOCK...", "Print the inodes that use the given blocks"),
+    ("ncheck", "INODE...", "Print the paths that lead to the given inodes"),
+    ("dump_inode", "PATH HOST_PATH", "Copy a file, symlink or directory to the host"),
+    ("set_inode_field", "PATH FIELD VALUE", "Change a field of an inode, 'help set_inode_field' lists the fields"),
+    ("freeb", "BLOCK [COUNT]", "Mark blocks as free in the block bitmap"),
+    ("setb", "BLOCK [COUNT]", "Mark blocks as used in the block bitmap"),
+    ("mkdir", "PATH", "Create a directory"),
+    ("rm", "PATH", "Remove a file or symlink, the inode is freed with its last link"),
+    ("write", "HOST_FILE PATH", "Copy a file from the host into a new file"),
+    ("help", "[COMMAND]", "Print the commands"),
+    ("quit", "", "Leave the shell"),
+];
+
+/// The fields that `set_inode_field` can change, with the names that `debugfs` uses.
+const INODE_FIELDS: &[(&str, &str)] = &[
+    ("mode", "the type and the permissions"),
+    ("uid", "the owner"),
+    ("gid", "the group"),
+    ("size", "the size in bytes"),
+    ("atime", "the access time in seconds"),
+    ("ctime", "the change time in seconds"),
+    ("mtime", "the modification time in seconds"),
+    ("crtime", "the creation time in seconds, only in large inodes"),
+    ("dtime", "the deletion time in seconds"),
+    ("links_count", "the number of hard links"),
+    ("blocks", "the number of 512 byte sectors"),
+    ("flags", "the flags"),
+    ("generation", "the generation"),
+    ("file_acl", "the extended attribute block"),
+    ("block[N]", "the block pointer N from 0 to 11, or IND, DIND or TIND for the indirect blocks"),
+];
+
+/// An interactive session over a file system, like `debugfs`. Paths are relative to the
+/// current directory, and an inode can be given by its number as `<12>` instead of a path.
+pub(super) struct Session {
+    fs: ImageFs,
+    /// The absolute path of the current directory, without `.` and `..` components.
+    cwd: Vec<u8>,
+    writable: bool,
+    changed: bool,
+    failed: usize,
+}
+
+impl Session {
+    pub(super) fn new(mut fs: ImageFs, writable: bool) -> Self {
+        // zeros in files that are written become holes, like with the cp command
+        fs.set_skip_zero_blocks(true);
+        Self {
+            fs,
+            cwd: b"/".to_vec(),
+            writable,
+            changed: false,
+            failed: 0,
+        }
+    }
+
+    /// Runs the commands from the given input, one per line, until the input ends or
+    /// the `quit` command. A prompt is only printed for terminals.
+    pub(super) fn run(&mut self, mut input: impl BufRead) -> Result<(), Box<dyn Error>> {
+        let interactive = io::stdin().is_terminal();
+        let mut line = Vec::new();
+        loop {
+            if interactive {
+                print!("ext2:{}> ", String::from_utf8_lossy(&self.cwd));
+                io::stdout().flush()?;
+            }
+            line.clear();
+            if input.read_until(b'\n', &mut line)? == 0 || !self.run_command(&line) {
+                break;
+            }
+        }
+        if interactive {
+            println!();
+        }
+        Ok(())
+    }
+
+    /// Runs a single command line, and prints the error if it fails. Returns `false`
+    /// if the shell should exit.
+    pub(super) fn run_command(&mut self, line: &[u8]) -> bool {
+        let args = match split_args(line) {
+            Ok(args) => args,
+            Err(e) => {
+                eprintln!("{}", e);
+                self.failed += 1;
+                return true;
+            }
+        };
+        let Some((command, args)) = args.split_first() else {
+            return true;
+        };
+        let command = String::from_utf8_lossy(command).into_owned();
+        match self.execute(&command, args) {
+            Ok(running) => running,
+            Err(e) => {
+                eprintln!("{}: {}", command, e);
+                self.failed += 1;
+                true
+            }
+        }
+    }
+
+    /// The number of commands that failed so far.
+    pub(super) fn failed_commands(&self) -> usize {
+        self.failed
+    }
+
+    /// Returns the file system if a command changed it.
+    pub(super) fn into_changed_fs(self) -> Option<ImageFs> {
+        self.changed.then_some(self.fs)
+    }
+
+    fn execute(&mut self, command: &str, args: &[Vec<u8>]) -> Result<bool, Box<dyn Error>> {
+        let changes = matches!(command, "set_inode_field" | "freeb" | "setb" | "mkdir" | "rm" | "write");
+        if changes && !self.writable {
+            return Err("the file system is read-only, start the shell with --write to change it".into());
+        }
+        match (command, args) {
+            ("quit" | "q" | "exit", []) => return Ok(false),
+            ("help", []) => {
+                for (name, args, description) in COMMANDS {
+                    println!("{:<36} {}", format!("{} {}", name, args), description);
+                }
+                println!("Paths are relative to the current directory, an inode can also be given as <NUMBER>.");
+            }
+            ("help", [name]) if name == b"set_inode_field" => {
+                for (field, description) in INODE_FIELDS {
+                    println!("{:<12} {}", field, description);
+                }
+                println!("Numbers can be decimal, hexadecimal with 0x or octal with a leading 0.");
+            }
+            ("help", [name]) => {
+                let (name, args, description) = COMMANDS.iter().find(|(command, ..)| command.as_bytes() == name).ok_or_else(|| format!("unknown command '{}'", String::from_utf8_lossy(name)))?;
+                println!("{} {}", name, args);
+                println!("{}", description);
+            }
+            ("cd", []) => self.cwd = b"/".to_vec(),
+            ("cd", [path]) => {
+                let path = self.absolute_path(path);
+                let (_, inode) = self.fs.resolve_path(&path).map_err(|e| format!("{}: {}", String::from_utf8_lossy(&path), e))?;
+                if inode.typ() != Type::Directory {
+                    return Err(format!("{}: not a directory", String::from_utf8_lossy(&path)).into());
+                }
+                self.cwd = path;
+            }
+            ("pwd", []) => println!("{}", String::from_utf8_lossy(&self.cwd)),
+            ("ls", args) => {
+                let (flags, paths) = args.iter().partition::<Vec<_>, _>(|arg| arg.starts_with(b"-") && arg.len() > 1);
+                let mut long = false;
+                let mut all = false;
+                for &flag in flags.iter().flat_map(|flag| &flag[1..]) {
+                    match flag {
+                        b'l' => long = true,
+                        b'a' => all = true,
+                        _ => return Err(format!("unknown option '-{}'", flag as char).into()),
+                    }
+                }
+                let path = match paths.as_slice() {
+                    [] => b".".as_slice(),
+                    [path] => path.as_slice(),
+                    _ => return Err(usage(command)),
+                };
+                let (inode_address, inode) = self.resolve(path, true)?;
+                print_dir(&self.fs, path, inode_address, inode, long, all)?;
+            }
+            ("cat", [path]) => {
+                let file = RegularFile::try_from(self.resolve(path, true)?).map_err(|_| format!("{}: not a regular file", String::from_utf8_lossy(path)))?;
+                print_file(&self.fs, &file)?;
+            }
+            ("stat", [path]) => {
+                let (inode_address, inode) = self.resolve(path, false)?;
+                print_inode(&self.fs, inode_address, inode)?;
+            }
+            ("blocks", [path]) => {
+                let (_, inode) = self.resolve(path, false)?;
+                let blocks = self.fs.inode_blocks(&inode)?;
+                let data = blocks.iter().filter(|(_, usage)| matches!(usage, BlockUse::Data(_))).map(|(block, _)| block.to_string());
+                println!("{}", data.collect::<Vec<_>>().join(" "));
+            }
+            ("icheck", blocks) if !blocks.is_empty() => {
+                let blocks = blocks.iter().map(|block| parse_number(block)).collect::<Result<Vec<_>, _>>()?;
+                let map = self.fs.block_map()?;
+                println!("Block\tInode number");
+                for block in blocks {
+                    let refs = BlockAddress::new(block).and_then(|block| map.get(&block)).map(Vec::as_slice).unwrap_or_default();
+                    // an inode can use a block more than once in corrupted file systems
+                    let mut inodes = refs.iter().map(|block_ref| block_ref.inode).collect::<Vec<_>>();
+                    inodes.dedup();
+                    if inodes.is_empty() {
+                        println!("{}\t<block not found>", block);
+                    }
+                    for inode in inodes {
+                        println!("{}\t{}", block, inode);
+                    }
+                }
+            }
+            ("ncheck", inodes) if !inodes.is_empty() => {
+                let inodes = inodes.iter().map(|inode| parse_number(inode)).collect::<Result<Vec<_>, _>>()?;
+                let paths = self.fs.inode_paths()?;
+                println!("Inode\tPathname");
+                for inode in inodes {
+                    let found = InodeAddress::new(inode).and_then(|inode| paths.get(&inode));
+                    match found {
+                        Some(paths) => paths.iter().for_each(|path| println!("{}\t{}", inode, String::from_utf8_lossy(path))),
+                        None => println!("{}\t<inode not found>", inode),
+                    }
+                }
+            }
+            ("dump_inode", [path, host_path]) => {
+                let (inode_address, inode) = self.resolve(path, false)?;
+                let mut copy = CopyOut {
+                    links: HashMap::new(),
+                    visited: HashSet::new(),
+                };
+                copy.copy(&self.fs, inode_address, inode, Path::new(OsStr::from_bytes(host_path)))?;
+            }
+            ("set_inode_field", [path, field, value]) => {
+                let (inode_address, mut inode) = self.resolve(path, false)?;
+                set_inode_field(&mut inode, &String::from_utf8_lossy(field), value)?;
+                self.fs.write_inode(inode_address, &inode)?;
+                self.changed = true;
+            }
+            ("freeb" | "setb", [block, count @ ..]) if count.len() <= 1 => {
+                let first = parse_number::<u32>(block)?;
+                let count = count.first().map(|count| parse_number(count)).transpose()?.unwrap_or(1);
+                for block in first..first.saturating_add(count) {
+                    let addr = BlockAddress::new(block).ok_or("block 0 is not part of the file system")?;
+                    let result = if command == "freeb" { self.fs.free_block(addr) } else { self.fs.reserve_block(addr) };
+                    match result {
+                        Ok(()) => self.changed = true,
+                        Err(ext2::Error::InvalidBlockAddress(_)) if block < self.fs.superblock().num_blocks() => {
+                            eprintln!("{}: block {} is already {}", command, block, if command == "freeb" { "free" } else { "used" });
+                        }
+                        Err(e) => return Err(format!("block {}: {}", block, e).into()),
+                    }
+                }
+            }
+            ("mkdir", [path]) => {
+                let (mut parent, name) = self.parent_dir(path)?;
+                make_dir(&mut self.fs, &mut parent, &name)?;
+                self.changed = true;
+            }
+            ("rm", [path]) => {
+                let (mut parent, name) = self.parent_dir(path)?;
+                self.fs.unlink(&mut parent, &name).map_err(|e| match e {
+                    ext2::Error::NotSupported => format!("{}: is a directory", String::from_utf8_lossy(path)),
+                    e => format!("{}: {}", String::from_utf8_lossy(path), e),
+                })?;
+                self.changed = true;
+            }
+            ("write", [host_file, path]) => {
+                let (mut parent, name) = self.parent_dir(path)?;
+                if self.fs.find_entry_by_name(&parent, &name)?.is_some() {
+                    return Err(format!("{}: already exists", String::from_utf8_lossy(path)).into());
+                }
+                let mut copy = CopyIn {
+                    uid: None,
+                    gid: None,
+                    links: HashMap::new(),
+                };
+                // the entry is created before the copy can fail
+                self.changed = true;
+                copy.copy(&mut self.fs, &mut parent, &name, Path::new(OsStr::from_bytes(host_file)))?;
+            }
+            _ if COMMANDS.iter().any(|(name, ..)| *name == command) => return Err(usage(command)),
+            _ => return Err("unknown command, 'help' lists the commands".into()),
+        }
+        Ok(true)
+    }
+
+    /// Turns a path that may be relative to the current directory into an absolute path.
+    fn absolute_path(&self, path: &[u8]) -> Vec<u8> {
+        if path.starts_with(b"/") {
+            normalize_path(path)
+        } else {
+            normalize_path(&[self.cwd.as_slice(), b"/", path].concat())
+        }
+    }
+
+    /// Resolves a path or an inode number like `<12>`. The last component of the path
+    /// is only followed if it is a symlink and `follow` is set.
+    fn resolve(&self, path: &[u8], follow: bool) -> Result<(InodeAddress, Inode), Box<dyn Error>> {
+        if let Some(number) = path.strip_prefix(b"<").and_then(|path| path.strip_suffix(b">")) {
+            let inode_address = InodeAddress::new(parse_number(number)?).ok_or("inode 0 does not exist")?;
+            if inode_address.get() > self.fs.superblock().num_inodes() {
+                return Err(format!("inode {} does not exist", inode_address.get()).into());
+            }
+            return Ok(self.fs.read_inode(inode_address)?);
+        }
+        let path = self.absolute_path(path);
+        let result = if follow { self.fs.resolve_path(&path) } else { self.fs.resolve_path_no_follow(&path) };
+        result.map_err(|e| format!("{}: {}", String::from_utf8_lossy(&path), e).into())
+    }
+
+    /// Opens the parent directory of the given path, and returns it with the last component.
+    fn parent_dir(&self, path: &[u8]) -> Result<(ext2::Directory, Vec<u8>), Box<dyn Error>> {
+        let path = self.absolute_path(path);
+        let (parent, name) = split_path(&path);
+        if name.is_empty() {
+            return Err("the root directory can't be changed".into());
+        }
+        let parent = self.fs.open_dir(parent).map_err(|e| format!("{}: {}", String::from_utf8_lossy(parent), e))?;
+        Ok((parent, name.to_vec()))
+    }
+}
+
+fn usage(command: &str) -> Box<dyn Error> {
+    let (name, args, _) = COMMANDS.iter().find(|(name, ..)| *name == command).unwrap(); // only called for known commands
+    format!("usage: {} {}", name, args).into()
+}
+
+/// Splits a command line into its arguments at whitespace. Arguments can be quoted with
+/// `"` or `'`, and a backslash escapes the next byte. Lines that start with `#` are comments.
+fn split_args(line: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let mut args = Vec::new();
+    let mut arg: Option<Vec<u8>> = None;
+    let mut quote = None;
+    let mut bytes = line.iter().copied();
+    while let Some(b) = bytes.next() {
+        match (quote, b) {
+            (None, b'#') if args.is_empty() && arg.is_none() => break,
+            (_, b'\\') => arg.get_or_insert_default().push(bytes.next().ok_or("unfinished escape at the end of the line")?),
+            (Some(q), b) if b == q => quote = None,
+            (Some(_), b) => arg.get_or_insert_default().push(b),
+            (None, b'"' | b'\'') => {
+                quote = Some(b);
+                arg.get_or_insert_default();
+            }
+            (None, b) if b.is_ascii_whitespace() => args.extend(arg.take()),
+            (None, b) => arg.get_or_insert_default().push(b),
+        }
+    }
+    if quote.is_some() {
+        return Err("unfinished quote".to_string());
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+/// Removes empty, `.` and `..` components from an absolute path. `..` is resolved
+/// without following symlinks, like `cd` in a shell does.
+fn normalize_path(path: &[u8]) -> Vec<u8> {
+    let mut components: Vec<&[u8]> = Vec::new();
+    for component in path.split(|&b| b == b'/') {
+        match component {
+            b"" | b"." => {}
+            b".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    if components.is_empty() {
+        return b"/".to_vec();
+    }
+    components.iter().flat_map(|component| [b"/".as_slice(), component]).flatten().copied().collect()
+}
+
+/// Parses a decimal number, a hexadecimal number with `0x` or an octal number with a leading `0`.
+fn parse_number<T: TryFrom<u64>>(s: &[u8]) -> Result<T, String> {
+    let s = String::from_utf8_lossy(s);
+    let number = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(octal) = s.strip_prefix('0').filter(|octal| !octal.is_empty()) {
+        u64::from_str_radix(octal, 8)
+    } else {
+        s.parse::<u64>()
+    };
+    let number = number.map_err(|e| format!("invalid number '{}': {}", s, e))?;
+    T::try_from(number).map_err(|_| format!("number '{}' is too large", s))
+}
+
+/// Changes the field of the inode with the given name, see [`INODE_FIELDS`].
+fn set_inode_field(inode: &mut Inode, field: &str, value: &[u8]) -> Result<(), String> {
+    let time = || parse_number::<u32>(value).map(|seconds| Timestamp { seconds: seconds as i32 as i64, nanoseconds: 0 });
+    match field {
+        "mode" => {
+            let mode = parse_number::<u16>(value)?;
+            inode.set_typ(Type::from_bits_truncate(mode));
+            inode.set_perm(Permissions::from_bits_truncate(mode));
+        }
+        "uid" => inode.set_user_id(parse_number(value)?),
+        "gid" => inode.set_group_id(parse_number(value)?),
+        "size" => inode.set_len(parse_number(value)?),
+        "atime" => inode.set_last_access_timestamp(time()?),
+        "ctime" => inode.set_creation_timestamp(time()?),
+        "mtime" => inode.set_last_modification_timestamp(time()?),
+        "crtime" => {
+            if inode.birth_timestamp().is_none() {
+                return Err("the inode has no creation time".to_string());
+            }
+            inode.set_birth_timestamp(time()?);
+        }
+        "dtime" => *inode.deletion_time_mut() = parse_number(value)?,
+        "links_count" => *inode.num_hard_links_mut() = parse_number(value)?,
+        "blocks" => *inode.num_disk_sectors_mut() = parse_number(value)?,
+        "flags" => inode.set_flags(Flags::from_bits_retain(parse_number(value)?)),
+        "generation" => *inode.generation_mut() = parse_number(value)?,
+        "file_acl" => inode.set_extended_attribute_block(BlockAddress::new(parse_number(value)?)),
+        _ => {
+            let index = field
+                .strip_prefix("block[")
+                .and_then(|field| field.strip_suffix(']'))
+                .ok_or_else(|| format!("unknown field '{}', 'help set_inode_field' lists the fields", field))?;
+            let ptr = BlockAddress::new(parse_number(value)?);
+            match index {
+                "IND" => inode.set_single_indirect_ptr(ptr),
+                "DIND" => inode.set_double_indirect_ptr(ptr),
+                "TIND" => inode.set_triple_indirect_ptr(ptr),
+                index => match index.parse::<usize>() {
+                    Ok(index @ 0..12) => inode.set_direct_ptr(index, ptr),
+                    _ => return Err(format!("invalid block pointer '{}', must be 0 to 11, IND, DIND or TIND", index)),
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ext2::{Ext2Fs, FormatOptions};
+    use filesystem::MemoryBlockDevice;
+
+    use super::*;
+
+    #[test]
+    fn test_split_args() {
+        let args = |line: &[u8]| split_args(line).unwrap();
+        assert_eq!(vec![b"ls".to_vec(), b"-l".to_vec()], args(b"  ls \t-l\n"));
+        assert_eq!(vec![b"cd".to_vec(), b"a b".to_vec(), b"c\"d".to_vec(), b"".to_vec()], args(br#"cd "a b" c\"d ''"#));
+        assert!(args(b"# comment").is_empty());
+        assert_eq!(vec![b"ls".to_vec(), b"#name".to_vec()], args(b"ls #name"));
+        assert!(split_args(b"cd \"a").is_err());
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(b"/".to_vec(), normalize_path(b"/"));
+        assert_eq!(b"/".to_vec(), normalize_path(b"/a/../.."));
+        assert_eq!(b"/a/c".to_vec(), normalize_path(b"//a/./b/../c/"));
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(Ok(42_u32), parse_number(b"42"));
+        assert_eq!(Ok(42_u32), parse_number(b"0x2a"));
+        assert_eq!(Ok(0o755_u16), parse_number(b"0755"));
+        assert_eq!(Ok(0_u32), parse_number(b"0"));
+        assert!(parse_number::<u16>(b"65536").is_err());
+        assert!(parse_number::<u32>(b"09").is_err());
+    }
+
+    #[test]
+    fn test_set_inode_field() {
+        let mut inode = Inode::new(Type::RegularFile);
+        set_inode_field(&mut inode, "mode", b"040700").unwrap();
+        assert_eq!(Type::Directory, inode.typ());
+        assert_eq!(0o700, inode.perm().bits());
+        set_inode_field(&mut inode, "uid", b"100000").unwrap();
+        assert_eq!(100000, inode.user_id());
+        set_inode_field(&mut inode, "block[3]", b"77").unwrap();
+        assert_eq!(Some(77), inode.direct_ptrs().nth(3).unwrap().map(|block| block.get()));
+        set_inode_field(&mut inode, "block[TIND]", b"0x10").unwrap();
+        assert_eq!(Some(16), inode.triple_indirect_ptr().map(|block| block.get()));
+        assert!(set_inode_field(&mut inode, "block[12]", b"1").is_err());
+        assert!(set_inode_field(&mut inode, "color", b"1").is_err());
+        assert!(set_inode_field(&mut inode, "links_count", b"70000").is_err());
+    }
+
+    #[test]
+    fn test_session() {
+        let format = || Ext2Fs::format(MemoryBlockDevice::try_new(512, vec![0_u8; 4 * 1024 * 1024]).unwrap(), &FormatOptions::default()).unwrap();
+        let mut session = Session::new(format(), false);
+        assert!(session.run_command(b"mkdir a"));
+        assert_eq!(1, session.failed_commands());
+        assert!(session.into_changed_fs().is_none());
+
+        let mut session = Session::new(format(), true);
+        for command in ["mkdir a", "cd a", "mkdir b", "cd b/..", "set_inode_field b uid 7", "rm b"] {
+            assert!(session.run_command(command.as_bytes()));
+        }
+        assert_eq!(b"/a".to_vec(), session.cwd);
+        assert_eq!(1, session.failed_commands(), "b is a directory");
+        assert_eq!(7, session.fs.open_dir("/a/b").unwrap().user_id());
+
+        let free_blocks = session.fs.superblock().num_unallocated_blocks();
+        assert!(session.run_command(b"setb 1000 3"));
+        assert_eq!(free_blocks - 3, session.fs.superblock().num_unallocated_blocks());
+        assert!(session.run_command(b"freeb 1000 3"));
+        assert!(!session.run_command(b"quit"));
+        assert!(session.into_changed_fs().unwrap().check().unwrap().is_empty());
+    }
+}
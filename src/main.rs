@@ -1,5 +1,10 @@
+use std::process::ExitCode;
+
 use clap::Parser;
-use std::path::PathBuf;
+
+use crate::ext2_cli::{handle_ext2, Ext2};
+
+mod ext2_cli;
 
 #[derive(Debug, Parser)]
 pub struct Args {
@@ -17,33 +22,35 @@ pub enum Subcommand {
     Ext4(Ext4),
 }
 
-#[derive(Debug, Parser)]
-pub struct Ext2 {
-    #[arg(long, help = "The file that the file system will be written into")]
-    out: PathBuf,
-    #[arg(short, long, help = "Overwrite the file if it already exists")]
-    force: bool,
-}
-
 #[derive(Debug, Parser)]
 pub struct Ext3 {}
 
 #[derive(Debug, Parser)]
 pub struct Ext4 {}
 
-fn main() {
+fn main() -> ExitCode {
     let args = Args::parse();
-    println!("args={:?}", args);
 
-    match args.subcommand {
+    let result = match args.subcommand {
         Subcommand::Ext2(ext2) => handle_ext2(ext2),
-        Subcommand::Ext3(ext3) => handle_ext3(ext3),
-        Subcommand::Ext4(ext4) => handle_ext4(ext4),
+        Subcommand::Ext3(ext3) => {
+            handle_ext3(ext3);
+            Ok(())
+        }
+        Subcommand::Ext4(ext4) => {
+            handle_ext4(ext4);
+            Ok(())
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
     }
 }
 
-fn handle_ext2(_ext2: Ext2) {}
-
 fn handle_ext3(_ext3: Ext3) {}
 
 fn handle_ext4(_ext4: Ext4) {}